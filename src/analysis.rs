@@ -0,0 +1,293 @@
+//! Which functions a program calls and which globals it uses, for dead code
+//! elimination, the linter and embedders checking what a script can do
+//! before running it.
+//!
+//! ```
+//! use reaper::analysis::{analyze, SCRIPT};
+//! use reaper::parser::Parser;
+//! use reaper::tokenizer::Tokenizer;
+//! use reaper::Capability;
+//!
+//! let src = "fn now() { return time.clock(); } fn unused() {} t = now();";
+//! let ast = Parser::new().parse(Tokenizer::new(src).collect());
+//! let graph = analyze(&ast);
+//! assert!(graph.reachable(SCRIPT).contains("now"));
+//! assert!(!graph.reachable(SCRIPT).contains("unused"));
+//! assert!(graph.capabilities(SCRIPT).allows(Capability::Time));
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::builtins::{self, Capabilities, BUILTINS};
+use crate::parser::{Expression, Statement};
+use crate::prelude;
+
+/// Name of the top level code of a program in a [`CallGraph`], one no
+/// function can have.
+pub const SCRIPT: &str = "<script>";
+
+/// What a function, or the top level code, uses directly.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Uses {
+    /// Functions of the program and the prelude it calls. Functions it
+    /// passes as values count as called, they may be.
+    pub calls: BTreeSet<String>,
+    /// Builtins it calls, `math.sqrt`.
+    pub builtins: BTreeSet<String>,
+    /// Globals it reads, variables of the top level and those declared by
+    /// the embedder. Function bodies can't see globals, only the top level
+    /// code reads and writes them.
+    pub reads: BTreeSet<String>,
+    pub writes: BTreeSet<String>,
+}
+
+/// The functions of a program, the prelude's included, and what each uses.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CallGraph {
+    /// By function name, the top level code as [`SCRIPT`].
+    pub functions: BTreeMap<String, Uses>,
+}
+
+impl CallGraph {
+    /// `function` and the functions it calls, directly or not.
+    pub fn reachable(&self, function: &str) -> BTreeSet<String> {
+        let mut reached = BTreeSet::new();
+        let mut pending = vec![function.to_string()];
+        while let Some(function) = pending.pop() {
+            if let Some(uses) = self.functions.get(&function) {
+                pending.extend(uses.calls.iter().filter(|f| !reached.contains(*f)).cloned());
+            }
+            reached.insert(function);
+        }
+        reached
+    }
+
+    /// The capabilities the builtins `function` calls need, directly or
+    /// through other functions.
+    pub fn capabilities(&self, function: &str) -> Capabilities {
+        let mut capabilities = Capabilities::none();
+        for reached in self.reachable(function) {
+            let Some(uses) = self.functions.get(&reached) else {
+                continue;
+            };
+            for name in &uses.builtins {
+                let builtin = &BUILTINS[builtins::lookup(name).unwrap()];
+                if let Some(capability) = builtin.capability {
+                    capabilities = capabilities.with(capability);
+                }
+            }
+        }
+        capabilities
+    }
+}
+
+/// The call graph of `ast`, a parsed program without the prelude. Calls of
+/// undefined functions are left out, the resolver reports them.
+pub fn analyze(ast: &[Statement]) -> CallGraph {
+    let prelude = prelude::statements();
+    let mut functions = BTreeSet::new();
+    for statement in prelude.iter().chain(ast) {
+        collect_functions(statement, &mut functions);
+    }
+    let mut analyzer = Analyzer {
+        functions,
+        graph: CallGraph::default(),
+        current: SCRIPT.to_string(),
+        scopes: vec![vec![]],
+    };
+    analyzer
+        .graph
+        .functions
+        .insert(SCRIPT.to_string(), Uses::default());
+    for statement in prelude.iter().chain(ast) {
+        analyzer.statement(statement);
+    }
+    analyzer.graph
+}
+
+/// Adds the names of the functions `statement` declares, also nested ones.
+pub(crate) fn collect_functions(statement: &Statement, functions: &mut impl Extend<String>) {
+    match statement {
+        Statement::Fn(s) => {
+            functions.extend([s.name.clone()]);
+            collect_functions(&s.body, functions);
+        }
+        Statement::Block(s) => {
+            for statement in &s.body {
+                collect_functions(statement, functions);
+            }
+        }
+        Statement::If(s) => {
+            for (_, body) in &s.branches {
+                collect_functions(body, functions);
+            }
+            if let Some(body) = &s.else_body {
+                collect_functions(body, functions);
+            }
+        }
+        Statement::While(s) => collect_functions(&s.body, functions),
+        Statement::For(s) => collect_functions(&s.body, functions),
+        Statement::DoWhile(s) => collect_functions(&s.body, functions),
+        _ => {}
+    }
+}
+
+struct Analyzer {
+    functions: BTreeSet<String>,
+    graph: CallGraph,
+    /// The function being analyzed.
+    current: String,
+    /// Variables declared in the blocks of the current function, the
+    /// outermost first. Those of the top level code's outermost scope are
+    /// the globals.
+    scopes: Vec<Vec<String>>,
+}
+
+impl Analyzer {
+    fn uses(&mut self) -> &mut Uses {
+        self.graph.functions.get_mut(&self.current).unwrap()
+    }
+
+    fn is_global(&self, name: &str) -> bool {
+        if self.current != SCRIPT {
+            return false;
+        }
+        // Undeclared names are declared by the embedder.
+        self.scopes
+            .iter()
+            .rposition(|scope| scope.iter().any(|n| n == name))
+            .is_none_or(|scope| scope == 0)
+    }
+
+    fn is_variable(&self, name: &str) -> bool {
+        self.scopes.iter().flatten().any(|n| n == name)
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        self.scopes.push(vec![]);
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Dummy | Statement::Break(_) | Statement::Continue(_) => {}
+            Statement::Print(s) => self.expression(&s.expression),
+            Statement::Expression(s) => self.expression(&s.expression),
+            Statement::Return(s) => self.expression(&s.expression),
+            Statement::Defer(s) => self.expression(&s.expression),
+            Statement::Block(s) => self.block(&s.body),
+            Statement::Fn(s) => {
+                let outer = std::mem::replace(&mut self.current, s.name.clone());
+                let outer_scopes = std::mem::replace(
+                    &mut self.scopes,
+                    vec![s.arguments.iter().map(|a| a.name.clone()).collect()],
+                );
+                self.graph.functions.entry(s.name.clone()).or_default();
+                match &*s.body {
+                    Statement::Block(block) => self.block(&block.body),
+                    body => self.statement(body),
+                }
+                self.current = outer;
+                self.scopes = outer_scopes;
+            }
+            Statement::If(s) => {
+                for (condition, body) in &s.branches {
+                    self.expression(condition);
+                    self.statement(body);
+                }
+                if let Some(body) = &s.else_body {
+                    self.statement(body);
+                }
+            }
+            Statement::While(s) => {
+                self.expression(&s.condition);
+                self.statement(&s.body);
+            }
+            Statement::For(s) => {
+                // The initializer's variables are the loop's.
+                self.scopes.push(vec![]);
+                let clauses = [&s.initializer, &s.condition];
+                for expression in clauses.into_iter().flatten() {
+                    self.expression(expression);
+                }
+                self.statement(&s.body);
+                if let Some(increment) = &s.increment {
+                    self.expression(increment);
+                }
+                self.scopes.pop();
+            }
+            Statement::DoWhile(s) => {
+                self.statement(&s.body);
+                self.expression(&s.condition);
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Literal(_) => {}
+            Expression::Variable(e) => {
+                if self.is_variable(&e.value) || !self.functions.contains(&e.value) {
+                    if self.is_global(&e.value) {
+                        self.uses().reads.insert(e.value.clone());
+                    }
+                } else {
+                    self.uses().calls.insert(e.value.clone());
+                }
+            }
+            Expression::Call(e) => {
+                match &*e.callee {
+                    Expression::Variable(callee)
+                        if !self.is_variable(&callee.value)
+                            && !self.functions.contains(&callee.value) =>
+                    {
+                        if builtins::lookup(&callee.value).is_some() {
+                            self.uses().builtins.insert(callee.value.clone());
+                        }
+                    }
+                    callee => self.expression(callee),
+                }
+                for argument in &e.arguments {
+                    self.expression(argument);
+                }
+            }
+            Expression::Assign(e) => {
+                self.expression(&e.rhs);
+                // The variable itself, or a list in it an element is set of.
+                let mut target = &*e.lhs;
+                while let Expression::Index(index) = target {
+                    self.expression(&index.index);
+                    if let Some(end) = &index.end {
+                        self.expression(end);
+                    }
+                    target = &index.expr;
+                }
+                if let Expression::Variable(variable) = target {
+                    if !self.is_variable(&variable.value) {
+                        let name = variable.value.clone();
+                        self.scopes.last_mut().unwrap().push(name);
+                    }
+                    if self.is_global(&variable.value) {
+                        self.uses().writes.insert(variable.value.clone());
+                    }
+                }
+            }
+            Expression::Binary(e) => {
+                self.expression(&e.lhs);
+                self.expression(&e.rhs);
+            }
+            Expression::Unary(e) => self.expression(&e.expr),
+            Expression::Index(e) => {
+                self.expression(&e.expr);
+                self.expression(&e.index);
+                if let Some(end) = &e.end {
+                    self.expression(end);
+                }
+            }
+            Expression::TypeTest(e) => self.expression(&e.expr),
+        }
+    }
+}
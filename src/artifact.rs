@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::builtins::{self, BUILTINS};
+use crate::compiler::Opcode;
+use crate::program::{Function, LocalName, Program};
+use crate::tokenizer::Span;
+use crate::typecheck::Type;
+use crate::vm::Object;
+
+/// Start of every `.reapc` file, followed by the format version.
+const MAGIC: &[u8] = b"REAPC";
+/// Version 2 added the source spans, version 3 the names of locals,
+/// version 4 byte string constants, version 5 the `<=`, `>` and `>=`
+/// opcodes and version 6 negation, older files are still read.
+const VERSION: u8 = 6;
+
+/// Encodes `program` for writing to a `.reapc` file. Builtins are stored
+/// by name, so an artifact runs on builds with a different builtin set as
+/// long as it has the builtins that are called.
+pub fn write(program: &Program) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.u8(VERSION);
+
+    // `Program::new` appends the final `EndOfProgram` again.
+    let code = &program.code()[..program.code().len() - 1];
+    writer.len(code.len());
+    for op in code {
+        writer.opcode(op);
+    }
+    writer.len(program.constants().len());
+    for constant in program.constants() {
+        writer.object(constant);
+    }
+    let mut functions: Vec<_> = program.functions().collect();
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+    writer.len(functions.len());
+    for function in functions {
+        writer.function(function);
+    }
+    writer.len(program.slots());
+    let mut globals: Vec<_> = program.globals().collect();
+    globals.sort();
+    writer.len(globals.len());
+    for (name, slot) in globals {
+        writer.str(name);
+        writer.len(slot);
+    }
+    writer.len(program.spans().len());
+    for &(at, span) in program.spans() {
+        writer.len(at);
+        writer.len(span.start);
+        writer.len(span.end);
+    }
+    writer.len(program.locals().len());
+    for local in program.locals() {
+        match &local.function {
+            Some(function) => {
+                writer.u8(1);
+                writer.str(function);
+            }
+            None => writer.u8(0),
+        }
+        writer.len(local.slot);
+        writer.len(local.live.start);
+        writer.len(local.live.end);
+        writer.str(&local.name);
+    }
+    writer.bytes
+}
+
+/// Decodes a program written by [`write`], checked by [`Program::new`]
+/// like one compiled from source.
+pub fn read(bytes: &[u8]) -> Result<Program, String> {
+    let mut reader = Reader { bytes, at: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("not a reaper artifact".to_string());
+    }
+    let version = reader.u8()?;
+    if !(1..=VERSION).contains(&version) {
+        return Err(format!("unsupported artifact version {}", version));
+    }
+
+    let code = (0..reader.len()?)
+        .map(|_| reader.opcode())
+        .collect::<Result<Vec<_>, _>>()?;
+    let constants = (0..reader.len()?)
+        .map(|_| reader.object())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut functions = HashMap::new();
+    for _ in 0..reader.len()? {
+        let function = reader.function()?;
+        functions.insert(function.name.clone(), Arc::new(function));
+    }
+    let slots = reader.len()?;
+    let mut globals = HashMap::new();
+    for _ in 0..reader.len()? {
+        let name = reader.str()?;
+        globals.insert(name, reader.len()?);
+    }
+    let mut spans = vec![];
+    if version >= 2 {
+        for _ in 0..reader.len()? {
+            let at = reader.len()?;
+            let span = Span {
+                start: reader.len()?,
+                end: reader.len()?,
+            };
+            spans.push((at, span));
+        }
+    }
+    let mut locals = vec![];
+    if version >= 3 {
+        for _ in 0..reader.len()? {
+            let function = match reader.u8()? {
+                0 => None,
+                _ => Some(reader.str()?),
+            };
+            locals.push(LocalName {
+                function,
+                slot: reader.len()?,
+                live: reader.len()?..reader.len()?,
+                name: reader.str()?,
+            });
+        }
+    }
+    if reader.at != bytes.len() {
+        return Err("trailing bytes after the program".to_string());
+    }
+
+    Ok(Program::new(code, constants, functions)?
+        .with_globals(slots, globals)
+        .with_spans(spans)
+        .with_locals(locals))
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, value: usize) {
+        self.bytes.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn opcode(&mut self, op: &Opcode) {
+        match *op {
+            Opcode::Print => self.u8(0),
+            Opcode::Const(idx) => {
+                self.u8(1);
+                self.u32(idx);
+            }
+            Opcode::Add => self.u8(2),
+            Opcode::Sub => self.u8(3),
+            Opcode::Mul => self.u8(4),
+            Opcode::Div => self.u8(5),
+            Opcode::Null => self.u8(6),
+            Opcode::Not => self.u8(7),
+            Opcode::False => self.u8(8),
+            Opcode::Eq => self.u8(9),
+            Opcode::Jmp(offset) => {
+                self.u8(10);
+                self.u32(offset as u32);
+            }
+            Opcode::Jz(offset) => {
+                self.u8(11);
+                self.u32(offset as u32);
+            }
+            Opcode::Ret => self.u8(12),
+            Opcode::Less => self.u8(13),
+            Opcode::GetLocal(slot) => {
+                self.u8(14);
+                self.u32(slot);
+            }
+            Opcode::SetLocal(slot) => {
+                self.u8(15);
+                self.u32(slot);
+            }
+            Opcode::Pop => self.u8(16),
+            Opcode::Invoke(argc, slots) => {
+                self.u8(17);
+                self.u16(argc);
+                self.u32(slots);
+            }
+            Opcode::Call(argc) => {
+                self.u8(18);
+                self.u16(argc);
+            }
+            Opcode::Native(builtin, argc) => {
+                self.u8(19);
+                self.str(BUILTINS[builtin as usize].name);
+                self.u16(argc);
+            }
+            Opcode::Strcat => self.u8(20),
+            Opcode::Index => self.u8(21),
+            Opcode::Slice => self.u8(22),
+            Opcode::SetIndex(slot, n) => {
+                self.u8(23);
+                self.u32(slot);
+                self.u16(n);
+            }
+            Opcode::EndOfProgram => self.u8(24),
+            Opcode::Neq => self.u8(25),
+            Opcode::TypeTest(ty) => {
+                self.u8(26);
+                self.str(&ty.to_string());
+            }
+            Opcode::Take(slot) => {
+                self.u8(27);
+                self.u32(slot);
+            }
+            Opcode::LessEqual => self.u8(28),
+            Opcode::Greater => self.u8(29),
+            Opcode::GreaterEqual => self.u8(30),
+            Opcode::Neg => self.u8(31),
+        }
+    }
+
+    fn object(&mut self, object: &Object) {
+        match object {
+            Object::Number(n) => {
+                self.u8(0);
+                self.bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            Object::Bool(b) => {
+                self.u8(1);
+                self.u8(*b as u8);
+            }
+            Object::String(s) => {
+                self.u8(2);
+                self.str(s);
+            }
+            Object::List(l) => {
+                let items = l.items();
+                self.u8(3);
+                self.len(items.len());
+                for element in items.iter() {
+                    self.object(element);
+                }
+            }
+            Object::Function(f) => {
+                self.u8(4);
+                self.function(f);
+            }
+            Object::Null => self.u8(5),
+            Object::Bytes(b) => {
+                self.u8(6);
+                self.len(b.len());
+                self.bytes.extend_from_slice(b);
+            }
+            // Only the host and builtins create them, while a program runs.
+            Object::Set(_) => unreachable!("set in the constant pool"),
+            Object::Native(_) => unreachable!("native object in the constant pool"),
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.str(&function.name);
+        self.len(function.arity);
+        self.len(function.addr);
+        self.len(function.slots);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .at
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or("unexpected end of artifact")?;
+        let bytes = &self.bytes[self.at..end];
+        self.at = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        usize::try_from(u64::from_le_bytes(self.array()?)).map_err(|e| e.to_string())
+    }
+
+    fn str(&mut self) -> Result<String, String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn opcode(&mut self) -> Result<Opcode, String> {
+        Ok(match self.u8()? {
+            0 => Opcode::Print,
+            1 => Opcode::Const(self.u32()?),
+            2 => Opcode::Add,
+            3 => Opcode::Sub,
+            4 => Opcode::Mul,
+            5 => Opcode::Div,
+            6 => Opcode::Null,
+            7 => Opcode::Not,
+            8 => Opcode::False,
+            9 => Opcode::Eq,
+            10 => Opcode::Jmp(self.u32()? as i32),
+            11 => Opcode::Jz(self.u32()? as i32),
+            12 => Opcode::Ret,
+            13 => Opcode::Less,
+            14 => Opcode::GetLocal(self.u32()?),
+            15 => Opcode::SetLocal(self.u32()?),
+            16 => Opcode::Pop,
+            17 => Opcode::Invoke(self.u16()?, self.u32()?),
+            18 => Opcode::Call(self.u16()?),
+            19 => {
+                let name = self.str()?;
+                let builtin =
+                    builtins::lookup(&name).ok_or_else(|| format!("unknown builtin '{}'", name))?;
+                Opcode::Native(builtin as u16, self.u16()?)
+            }
+            20 => Opcode::Strcat,
+            21 => Opcode::Index,
+            22 => Opcode::Slice,
+            23 => Opcode::SetIndex(self.u32()?, self.u16()?),
+            24 => Opcode::EndOfProgram,
+            25 => Opcode::Neq,
+            26 => {
+                let name = self.str()?;
+                let ty =
+                    Type::from_name(&name).ok_or_else(|| format!("unknown type '{}'", name))?;
+                Opcode::TypeTest(ty)
+            }
+            27 => Opcode::Take(self.u32()?),
+            28 => Opcode::LessEqual,
+            29 => Opcode::Greater,
+            30 => Opcode::GreaterEqual,
+            31 => Opcode::Neg,
+            tag => return Err(format!("unknown opcode {}", tag)),
+        })
+    }
+
+    fn object(&mut self) -> Result<Object, String> {
+        Ok(match self.u8()? {
+            0 => Object::Number(f64::from_le_bytes(self.array()?)),
+            1 => Object::Bool(self.u8()? != 0),
+            2 => self.str()?.into(),
+            3 => {
+                let elements = (0..self.len()?)
+                    .map(|_| self.object())
+                    .collect::<Result<Vec<_>, _>>()?;
+                Object::List(Arc::new(elements.into()))
+            }
+            4 => Object::Function(Arc::new(self.function()?)),
+            5 => Object::Null,
+            6 => {
+                let len = self.len()?;
+                Object::Bytes(self.take(len)?.to_vec().into())
+            }
+            tag => return Err(format!("unknown constant {}", tag)),
+        })
+    }
+
+    fn function(&mut self) -> Result<Function, String> {
+        Ok(Function {
+            name: self.str()?,
+            arity: self.len()?,
+            addr: self.len()?,
+            slots: self.len()?,
+        })
+    }
+}
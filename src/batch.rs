@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::program::Program;
+use crate::vm::{Object, RuntimeError, VM};
+
+/// Globals of one run, by name.
+pub type Globals = HashMap<String, Object>;
+
+/// Runs `program` once per entry of `inputs` on up to `threads` threads.
+/// Each run starts with its input globals set and yields all globals once
+/// the script is done, in the order of `inputs`.
+pub fn run_many(
+    program: &Arc<Program>,
+    inputs: Vec<Globals>,
+    threads: usize,
+) -> Vec<Result<Globals, RuntimeError>> {
+    let runs = inputs.len();
+    let inputs: Vec<_> = inputs.into_iter().map(|i| Mutex::new(Some(i))).collect();
+    let results: Vec<_> = (0..runs).map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, runs.max(1)) {
+            scope.spawn(|| {
+                let mut vm = VM::new();
+                loop {
+                    let run = next.fetch_add(1, Ordering::Relaxed);
+                    if run >= runs {
+                        break;
+                    }
+                    let input = inputs[run].lock().unwrap().take().unwrap();
+                    let result = run_one(&mut vm, program, input);
+                    *results[run].lock().unwrap() = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_iter()
+        .map(|r| r.into_inner().unwrap().unwrap())
+        .collect()
+}
+
+fn run_one(vm: &mut VM, program: &Arc<Program>, input: Globals) -> Result<Globals, RuntimeError> {
+    vm.load(Arc::clone(program));
+    for (name, value) in input {
+        vm.set_global(&name, value)?;
+    }
+    vm.execute()?;
+    Ok(vm.globals())
+}
@@ -0,0 +1,82 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::compiler::CompileOptions;
+use crate::driver::{compile_files, Error};
+use crate::util::SourceManager;
+use crate::vm::VM;
+
+/// Timing of one benchmark script.
+#[derive(Debug)]
+pub struct BenchResult {
+    pub name: String,
+    pub instructions: u64,
+    /// Run time without compilation.
+    pub elapsed: Duration,
+}
+
+/// Compiles and runs every `.reap` script in `dir`, in name order. Stops at
+/// the first script that fails.
+pub fn run(
+    dir: &Path,
+    compile_options: impl Into<CompileOptions>,
+) -> Result<Vec<BenchResult>, Error> {
+    let compile_options = compile_options.into();
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.retain(|p| p.extension().is_some_and(|e| e == "reap"));
+    paths.sort();
+
+    let mut results = vec![];
+    for path in paths {
+        let mut sources = SourceManager::new();
+        let file = sources.load(&path.to_string_lossy())?;
+        let program = compile_files(&sources, &[file], compile_options)?;
+        let mut vm = VM::new();
+        vm.load(Arc::new(program));
+        let start = Instant::now();
+        let instructions = vm.run_counted().map_err(|e| {
+            let source = sources.get(file);
+            let location = vm
+                .span()
+                .map(|span| (source.name().to_string(), source.location(span.start)));
+            Error::Runtime(e, location)
+        })?;
+        results.push(BenchResult {
+            name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+            instructions,
+            elapsed: start.elapsed(),
+        });
+    }
+    Ok(results)
+}
+
+/// Formats `results` as a table with one script per line.
+pub fn table(results: &[BenchResult]) -> String {
+    let width = results
+        .iter()
+        .map(|r| r.name.len())
+        .fold("script".len(), usize::max);
+    let mut out = format!(
+        "{:<width$}  {:>14}  {:>10}  {:>10}\n",
+        "script", "instructions", "ms", "Minstr/s"
+    );
+    for result in results {
+        let seconds = result.elapsed.as_secs_f64();
+        let rate = if seconds > 0.0 {
+            result.instructions as f64 / seconds / 1e6
+        } else {
+            0.0
+        };
+        out += &format!(
+            "{:<width$}  {:>14}  {:>10.1}  {:>10.1}\n",
+            result.name,
+            result.instructions,
+            seconds * 1e3,
+            rate
+        );
+    }
+    out
+}
@@ -0,0 +1,615 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::list::List;
+use crate::typecheck::Type;
+use crate::vm::{runtime_error, Object, RuntimeError, VM};
+
+mod bytes;
+mod csv;
+mod debug;
+mod digest;
+mod fs;
+mod list;
+mod math;
+#[cfg(feature = "net")]
+mod net;
+mod num;
+mod re;
+mod set;
+mod time;
+
+/// Access to the world outside the VM that builtins may need. Embedders
+/// running untrusted scripts take away what they don't want to grant.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    /// Reading the clock.
+    Time,
+    /// Network access, only used with the `net` feature.
+    Net,
+    /// Reading and writing files.
+    Files,
+}
+
+impl Capability {
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Time => "time",
+            Capability::Net => "net",
+            Capability::Files => "files",
+        }
+    }
+
+    /// Whether builtins needing it give the same results on every run of
+    /// a [deterministic](VM::set_deterministic) VM.
+    pub fn is_deterministic(self) -> bool {
+        match self {
+            Capability::Time => true,
+            Capability::Net | Capability::Files => false,
+        }
+    }
+}
+
+/// A set of [`Capability`]s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capabilities {
+    bits: u32,
+}
+
+impl Capabilities {
+    pub fn all() -> Capabilities {
+        Capabilities { bits: u32::MAX }
+    }
+
+    pub fn none() -> Capabilities {
+        Capabilities { bits: 0 }
+    }
+
+    pub fn with(self, capability: Capability) -> Capabilities {
+        Capabilities {
+            bits: self.bits | 1 << capability as u32,
+        }
+    }
+
+    pub fn without(self, capability: Capability) -> Capabilities {
+        Capabilities {
+            bits: self.bits & !(1 << capability as u32),
+        }
+    }
+
+    pub fn allows(self, capability: Capability) -> bool {
+        self.bits & 1 << capability as u32 != 0
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities::all()
+    }
+}
+
+/// A function implemented in Rust and callable from scripts by name. All
+/// but the most basic ones are grouped in namespaces, `re.match`.
+pub struct Builtin {
+    pub name: &'static str,
+    pub parameters: &'static [Type],
+    pub result: Type,
+    /// Needed to call it at all.
+    pub capability: Option<Capability>,
+    pub function: fn(&mut VM, Vec<Object>) -> Result<Object, RuntimeError>,
+}
+
+pub static BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "len",
+        parameters: &[Type::Any],
+        result: Type::Num,
+        capability: None,
+        function: len,
+    },
+    Builtin {
+        name: "on",
+        parameters: &[Type::Str, Type::Any],
+        result: Type::Null,
+        capability: None,
+        function: on,
+    },
+    Builtin {
+        name: "memoize",
+        parameters: &[Type::Any],
+        result: Type::Any,
+        capability: None,
+        function: memoize,
+    },
+    Builtin {
+        name: "clone",
+        parameters: &[Type::Any],
+        result: Type::Any,
+        capability: None,
+        function: clone,
+    },
+    Builtin {
+        name: "freeze",
+        parameters: &[Type::Any],
+        result: Type::Any,
+        capability: None,
+        function: freeze,
+    },
+    Builtin {
+        name: "sort_by",
+        parameters: &[Type::List, Type::Any],
+        result: Type::List,
+        capability: None,
+        function: list::sort_by,
+    },
+    Builtin {
+        name: "min_by",
+        parameters: &[Type::List, Type::Any],
+        result: Type::Any,
+        capability: None,
+        function: list::min_by,
+    },
+    Builtin {
+        name: "max_by",
+        parameters: &[Type::List, Type::Any],
+        result: Type::Any,
+        capability: None,
+        function: list::max_by,
+    },
+    Builtin {
+        name: "bytes",
+        parameters: &[Type::Num],
+        result: Type::Bytes,
+        capability: None,
+        function: bytes::new,
+    },
+    Builtin {
+        name: "math.sqrt",
+        parameters: &[Type::Num],
+        result: Type::Num,
+        capability: None,
+        function: math::sqrt,
+    },
+    Builtin {
+        name: "math.floor",
+        parameters: &[Type::Num],
+        result: Type::Num,
+        capability: None,
+        function: math::floor,
+    },
+    Builtin {
+        name: "math.abs",
+        parameters: &[Type::Num],
+        result: Type::Num,
+        capability: None,
+        function: math::abs,
+    },
+    Builtin {
+        name: "math.min",
+        parameters: &[Type::List],
+        result: Type::Num,
+        capability: None,
+        function: math::min,
+    },
+    Builtin {
+        name: "math.max",
+        parameters: &[Type::List],
+        result: Type::Num,
+        capability: None,
+        function: math::max,
+    },
+    Builtin {
+        name: "math.sum",
+        parameters: &[Type::List],
+        result: Type::Num,
+        capability: None,
+        function: math::sum,
+    },
+    Builtin {
+        name: "math.avg",
+        parameters: &[Type::List],
+        result: Type::Num,
+        capability: None,
+        function: math::avg,
+    },
+    Builtin {
+        name: "num.parse_int",
+        parameters: &[Type::Str, Type::Num],
+        result: Type::Num,
+        capability: None,
+        function: num::parse_int,
+    },
+    Builtin {
+        name: "num.parse_float",
+        parameters: &[Type::Str],
+        result: Type::Num,
+        capability: None,
+        function: num::parse_float,
+    },
+    Builtin {
+        name: "num.to_fixed",
+        parameters: &[Type::Num, Type::Num],
+        result: Type::Str,
+        capability: None,
+        function: num::to_fixed,
+    },
+    Builtin {
+        name: "re.match",
+        parameters: &[Type::Str, Type::Str],
+        result: Type::Bool,
+        capability: None,
+        function: re::re_match,
+    },
+    Builtin {
+        name: "re.find_all",
+        parameters: &[Type::Str, Type::Str],
+        result: Type::List,
+        capability: None,
+        function: re::re_find_all,
+    },
+    Builtin {
+        name: "re.replace",
+        parameters: &[Type::Str, Type::Str, Type::Str],
+        result: Type::Str,
+        capability: None,
+        function: re::re_replace,
+    },
+    Builtin {
+        name: "list.new",
+        parameters: &[],
+        result: Type::List,
+        capability: None,
+        function: list::new,
+    },
+    Builtin {
+        name: "list.push",
+        parameters: &[Type::List, Type::Any],
+        result: Type::List,
+        capability: None,
+        function: list::push,
+    },
+    Builtin {
+        name: "list.unshift",
+        parameters: &[Type::List, Type::Any],
+        result: Type::List,
+        capability: None,
+        function: list::unshift,
+    },
+    Builtin {
+        name: "list.shift",
+        parameters: &[Type::List],
+        result: Type::List,
+        capability: None,
+        function: list::shift,
+    },
+    Builtin {
+        name: "list.peek",
+        parameters: &[Type::List],
+        result: Type::Any,
+        capability: None,
+        function: list::peek,
+    },
+    Builtin {
+        name: "set.new",
+        parameters: &[],
+        result: Type::Set,
+        capability: None,
+        function: set::new,
+    },
+    Builtin {
+        name: "set.of",
+        parameters: &[Type::List],
+        result: Type::Set,
+        capability: None,
+        function: set::of,
+    },
+    Builtin {
+        name: "set.insert",
+        parameters: &[Type::Set, Type::Any],
+        result: Type::Set,
+        capability: None,
+        function: set::insert,
+    },
+    Builtin {
+        name: "set.remove",
+        parameters: &[Type::Set, Type::Any],
+        result: Type::Set,
+        capability: None,
+        function: set::remove,
+    },
+    Builtin {
+        name: "set.contains",
+        parameters: &[Type::Set, Type::Any],
+        result: Type::Bool,
+        capability: None,
+        function: set::contains,
+    },
+    Builtin {
+        name: "set.union",
+        parameters: &[Type::Set, Type::Set],
+        result: Type::Set,
+        capability: None,
+        function: set::union,
+    },
+    Builtin {
+        name: "set.intersect",
+        parameters: &[Type::Set, Type::Set],
+        result: Type::Set,
+        capability: None,
+        function: set::intersect,
+    },
+    Builtin {
+        name: "bytes.from_str",
+        parameters: &[Type::Str],
+        result: Type::Bytes,
+        capability: None,
+        function: bytes::from_str,
+    },
+    Builtin {
+        name: "bytes.to_str",
+        parameters: &[Type::Bytes],
+        result: Type::Str,
+        capability: None,
+        function: bytes::to_str,
+    },
+    Builtin {
+        name: "bytes.from_hex",
+        parameters: &[Type::Str],
+        result: Type::Bytes,
+        capability: None,
+        function: bytes::from_hex,
+    },
+    Builtin {
+        name: "bytes.to_hex",
+        parameters: &[Type::Any],
+        result: Type::Str,
+        capability: None,
+        function: bytes::to_hex,
+    },
+    Builtin {
+        name: "bytes.from_base64",
+        parameters: &[Type::Str],
+        result: Type::Bytes,
+        capability: None,
+        function: bytes::from_base64,
+    },
+    Builtin {
+        name: "bytes.to_base64",
+        parameters: &[Type::Any],
+        result: Type::Str,
+        capability: None,
+        function: bytes::to_base64,
+    },
+    Builtin {
+        name: "digest.md5",
+        parameters: &[Type::Any],
+        result: Type::Str,
+        capability: None,
+        function: digest::md5,
+    },
+    Builtin {
+        name: "digest.sha256",
+        parameters: &[Type::Any],
+        result: Type::Str,
+        capability: None,
+        function: digest::sha256,
+    },
+    Builtin {
+        name: "digest.crc32",
+        parameters: &[Type::Any],
+        result: Type::Num,
+        capability: None,
+        function: digest::crc32,
+    },
+    Builtin {
+        name: "csv.parse",
+        parameters: &[Type::Str],
+        result: Type::List,
+        capability: None,
+        function: csv::parse,
+    },
+    Builtin {
+        name: "csv.stringify",
+        parameters: &[Type::List],
+        result: Type::Str,
+        capability: None,
+        function: csv::stringify,
+    },
+    Builtin {
+        name: "debug.stack",
+        parameters: &[],
+        result: Type::List,
+        capability: None,
+        function: debug::stack,
+    },
+    Builtin {
+        name: "debug.locals",
+        parameters: &[],
+        result: Type::List,
+        capability: None,
+        function: debug::locals,
+    },
+    Builtin {
+        name: "time.now",
+        parameters: &[],
+        result: Type::Num,
+        capability: Some(Capability::Time),
+        function: time::now,
+    },
+    Builtin {
+        name: "time.clock",
+        parameters: &[],
+        result: Type::Num,
+        capability: Some(Capability::Time),
+        function: time::clock,
+    },
+    Builtin {
+        name: "time.format",
+        parameters: &[Type::Num, Type::Str],
+        result: Type::Str,
+        capability: Some(Capability::Time),
+        function: time::format_time,
+    },
+    Builtin {
+        name: "time.parse",
+        parameters: &[Type::Str, Type::Str],
+        result: Type::Num,
+        capability: Some(Capability::Time),
+        function: time::parse_time,
+    },
+    Builtin {
+        name: "fs.read_bytes",
+        parameters: &[Type::Str],
+        result: Type::Bytes,
+        capability: Some(Capability::Files),
+        function: fs::read_bytes,
+    },
+    Builtin {
+        name: "fs.write_bytes",
+        parameters: &[Type::Str, Type::Bytes],
+        result: Type::Null,
+        capability: Some(Capability::Files),
+        function: fs::write_bytes,
+    },
+    #[cfg(feature = "net")]
+    Builtin {
+        name: "net.get",
+        parameters: &[Type::Str],
+        result: Type::List,
+        capability: Some(Capability::Net),
+        function: net::http_get,
+    },
+    #[cfg(feature = "net")]
+    Builtin {
+        name: "net.post",
+        parameters: &[Type::Str, Type::Str],
+        result: Type::List,
+        capability: Some(Capability::Net),
+        function: net::http_post,
+    },
+];
+
+/// Index of the builtin called `name` in [`BUILTINS`].
+pub fn lookup(name: &str) -> Option<usize> {
+    BUILTINS.iter().position(|b| b.name == name)
+}
+
+fn string(object: &Object, what: &str) -> Result<String, RuntimeError> {
+    match object {
+        Object::String(s) => Ok(s.to_string()),
+        _ => runtime_error!(format!("{} must be a string.", what)),
+    }
+}
+
+fn byte_string<'a>(object: &'a Object, what: &str) -> Result<&'a [u8], RuntimeError> {
+    match object {
+        Object::Bytes(b) => Ok(b),
+        _ => runtime_error!(format!("{} must be a byte string.", what)),
+    }
+}
+
+/// The bytes of a string, in UTF-8, or of a byte string.
+fn data<'a>(object: &'a Object, what: &str) -> Result<&'a [u8], RuntimeError> {
+    match object {
+        Object::String(s) => Ok(s.as_bytes()),
+        Object::Bytes(b) => Ok(b),
+        _ => runtime_error!(format!("{} must be a string or a byte string.", what)),
+    }
+}
+
+fn number(object: &Object, what: &str) -> Result<f64, RuntimeError> {
+    match object {
+        Object::Number(n) => Ok(*n),
+        _ => runtime_error!(format!("{} must be a number.", what)),
+    }
+}
+
+fn list<'a>(object: &'a Object, what: &str) -> Result<&'a List, RuntimeError> {
+    match object {
+        Object::List(l) => Ok(l),
+        _ => runtime_error!(format!("{} must be a list.", what)),
+    }
+}
+
+fn len(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    match &arguments[0] {
+        Object::String(s) => Ok((s.chars().count() as f64).into()),
+        Object::Bytes(b) => Ok((b.len() as f64).into()),
+        Object::List(l) => Ok((l.len() as f64).into()),
+        Object::Set(s) => Ok((s.len() as f64).into()),
+        _ => runtime_error!("Argument of 'len' must be a string, a byte string, a list or a set."),
+    }
+}
+
+/// Attaches the function `handler` to an event the host registered, see
+/// [`VM::emit`].
+fn on(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let event = string(&arguments[0], "Event of 'on'")?;
+    let Object::Function(handler) = &arguments[1] else {
+        runtime_error!("Handler of 'on' must be a function.");
+    };
+    vm.subscribe(&event, handler.clone())?;
+    Ok(Object::Null)
+}
+
+/// Caches the results of the function by its arguments, also for the
+/// calls it makes of itself, and returns it. Calls with arguments that
+/// aren't [keys](crate::key) fail.
+fn memoize(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let Object::Function(function) = &arguments[0] else {
+        runtime_error!("Argument of 'memoize' must be a function.");
+    };
+    vm.memoize(function.addr);
+    Ok(arguments[0].clone())
+}
+
+/// A deep copy of the argument, sharing nothing with it but functions and
+/// native objects, which can't be copied. Lists in it more than once, or
+/// in themselves, are copied once and the copy is shared the same way.
+/// Copies of frozen values can be changed.
+fn clone(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    deep_copy(vm, &arguments[0], &mut HashMap::new())
+}
+
+/// Copies `object`, `copies` holding those of the lists copied so far by
+/// their address.
+pub(crate) fn deep_copy(
+    vm: &mut VM,
+    object: &Object,
+    copies: &mut HashMap<*const List, Arc<List>>,
+) -> Result<Object, RuntimeError> {
+    match object {
+        Object::String(s) => vm.new_string(s.to_string()),
+        Object::Bytes(b) => vm.new_bytes(b.to_vec()),
+        Object::List(l) => {
+            if let Some(copy) = copies.get(&Arc::as_ptr(l)) {
+                return Ok(Object::List(copy.clone()));
+            }
+            // Known before its items are copied, for those that hold it.
+            let Object::List(copy) = vm.new_list(vec![])? else {
+                unreachable!();
+            };
+            copies.insert(Arc::as_ptr(l), copy.clone());
+            let items = l.items();
+            vm.size_limits().check_list(items.len())?;
+            vm.heap_mut().grow(items.len() * size_of::<Object>())?;
+            let copied = items
+                .iter()
+                .map(|item| deep_copy(vm, item, copies))
+                .collect::<Result<_, _>>()?;
+            *copy.items_mut()? = copied;
+            Ok(Object::List(copy))
+        }
+        Object::Set(s) => vm.heap_mut().set(s.thawed()),
+        _ => Ok(object.clone()),
+    }
+}
+
+/// The argument with lists and sets, also those in it, made unchangeable:
+/// `a = freeze(a)`. Changing them fails, copies made with `clone` can be
+/// changed.
+fn freeze(_vm: &mut VM, mut arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let object = arguments.pop().unwrap();
+    object.freeze();
+    Ok(object)
+}
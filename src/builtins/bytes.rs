@@ -0,0 +1,106 @@
+use super::{byte_string, data, number, string};
+use crate::vm::{runtime_error, Object, RuntimeError, VM};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A byte string of `n` zero bytes, `bytes(16)`.
+pub(super) fn new(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let n = number(&arguments[0], "Length of 'bytes'")?;
+    if n.fract() != 0.0 || n < 0.0 {
+        runtime_error!("Length of 'bytes' must be a whole number of at least 0.");
+    }
+    // Checked before the bytes are allocated.
+    vm.size_limits().check_string(n as usize)?;
+    vm.new_bytes(vec![0; n as usize])
+}
+
+/// The UTF-8 encoding of the string.
+pub(super) fn from_str(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let s = string(&arguments[0], "Argument of 'bytes.from_str'")?;
+    vm.new_bytes(s.into_bytes())
+}
+
+/// The string the byte string is the UTF-8 encoding of, an error if it
+/// isn't one.
+pub(super) fn to_str(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let b = byte_string(&arguments[0], "Argument of 'bytes.to_str'")?;
+    match String::from_utf8(b.to_vec()) {
+        Ok(s) => vm.new_string(s),
+        Err(e) => runtime_error!(format!(
+            "Argument of 'bytes.to_str' is not UTF-8, byte {} is invalid.",
+            e.utf8_error().valid_up_to()
+        )),
+    }
+}
+
+/// Parses two hex digits per byte, in either case: `bytes.from_hex("ff00")`.
+pub(super) fn from_hex(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let s = string(&arguments[0], "Argument of 'bytes.from_hex'")?;
+    let digits: Vec<_> = s.chars().map(|c| c.to_digit(16)).collect();
+    let bytes = match digits.len() % 2 {
+        0 => digits
+            .chunks(2)
+            .map(|pair| Some((pair[0]? * 16 + pair[1]?) as u8))
+            .collect(),
+        _ => None,
+    };
+    match bytes {
+        Some(bytes) => vm.new_bytes(bytes),
+        None => runtime_error!(format!("'{}' is not hex, two digits per byte.", s)),
+    }
+}
+
+/// Two lowercase hex digits per byte of the byte string, or of the UTF-8
+/// of the string.
+pub(super) fn to_hex(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let b = data(&arguments[0], "Argument of 'bytes.to_hex'")?;
+    let hex = b.iter().map(|byte| format!("{:02x}", byte)).collect();
+    vm.new_string(hex)
+}
+
+/// Parses standard base64, `+` and `/` for 62 and 63. The `=` padding may
+/// be left out.
+pub(super) fn from_base64(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let s = string(&arguments[0], "Argument of 'bytes.from_base64'")?;
+    let invalid = || RuntimeError {
+        message: format!("'{}' is not base64.", s),
+    };
+    let unpadded = s.trim_end_matches('=');
+    // Padding fills the last group of four, no more.
+    if s.len() - unpadded.len() > 2 || (s.len() != unpadded.len() && s.len() % 4 != 0) {
+        return Err(invalid());
+    }
+    let mut bytes = Vec::with_capacity(unpadded.len() / 4 * 3 + 2);
+    for group in unpadded.as_bytes().chunks(4) {
+        // A single digit is only 6 bits, not a whole byte.
+        if group.len() == 1 {
+            return Err(invalid());
+        }
+        let mut bits = 0u32;
+        for (i, digit) in group.iter().enumerate() {
+            let value = BASE64.iter().position(|d| d == digit).ok_or_else(invalid)?;
+            bits |= (value as u32) << (18 - 6 * i);
+        }
+        bytes.extend(&bits.to_be_bytes()[1..group.len()]);
+    }
+    vm.new_bytes(bytes)
+}
+
+/// Standard base64 of the byte string, or of the UTF-8 of the string, with
+/// `=` padding.
+pub(super) fn to_base64(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let b = data(&arguments[0], "Argument of 'bytes.to_base64'")?;
+    let mut encoded = String::with_capacity(b.len().div_ceil(3) * 4);
+    for group in b.chunks(3) {
+        let mut bits = [0; 4];
+        bits[1..=group.len()].copy_from_slice(group);
+        let bits = u32::from_be_bytes(bits);
+        for i in 0..4 {
+            match i <= group.len() {
+                true => encoded.push(BASE64[(bits >> (18 - 6 * i) & 63) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    vm.new_string(encoded)
+}
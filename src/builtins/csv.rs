@@ -0,0 +1,88 @@
+use super::{list, string};
+use crate::vm::{runtime_error, Object, RuntimeError, VM};
+
+/// Splits CSV text into rows of string fields, as RFC 4180 has it: fields
+/// in double quotes may contain commas, line breaks and `""` for a quote.
+/// Lines end in `\n`, `\r\n` or `\r`, the last one may not.
+pub(super) fn parse(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let text = string(&arguments[0], "Argument of 'csv.parse'")?;
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    while chars.peek().is_some() {
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => runtime_error!(format!(
+                        "Quoted field in row {} of 'csv.parse' is never closed.",
+                        rows.len()
+                    )),
+                }
+            }
+            if !matches!(chars.peek(), None | Some(',' | '\r' | '\n')) {
+                runtime_error!(format!(
+                    "Quoted field in row {} of 'csv.parse' is followed by more than a comma or a line break.",
+                    rows.len()
+                ));
+            }
+        }
+        while let Some(c) = chars.next_if(|c| !matches!(c, ',' | '\r' | '\n')) {
+            field.push(c);
+        }
+        row.push(vm.new_string(std::mem::take(&mut field))?);
+        if chars.next_if_eq(&',').is_some() {
+            // A trailing comma ends the row with an empty field.
+            if matches!(chars.peek(), None | Some('\r' | '\n')) {
+                row.push(vm.new_string(String::new())?);
+            }
+        }
+        let cr = chars.next_if_eq(&'\r').is_some();
+        if chars.next_if_eq(&'\n').is_some() || cr || chars.peek().is_none() {
+            rows.push(vm.new_list(std::mem::take(&mut row))?);
+        }
+    }
+    vm.new_list(rows)
+}
+
+/// Writes rows of strings and numbers as CSV, a `\n` after each row.
+/// Fields with commas, quotes or line breaks are quoted.
+pub(super) fn stringify(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    // Copied, a row may be the list of rows itself.
+    let rows: Vec<_> = list(&arguments[0], "Argument of 'csv.stringify'")?
+        .items()
+        .iter()
+        .cloned()
+        .collect();
+    let mut text = String::new();
+    for (i, row) in rows.iter().enumerate() {
+        let row = list(row, &format!("Row {} of 'csv.stringify'", i))?.items();
+        for (j, field) in row.iter().enumerate() {
+            if j > 0 {
+                text.push(',');
+            }
+            let field = match field {
+                Object::String(s) => s.to_string(),
+                Object::Number(n) => n.to_string(),
+                _ => runtime_error!(format!(
+                    "Field {} of row {} of 'csv.stringify' must be a string or a number, found {}.",
+                    j,
+                    i,
+                    field.type_name()
+                )),
+            };
+            if field.contains([',', '"', '\r', '\n']) {
+                text.push('"');
+                text.push_str(&field.replace('"', "\"\""));
+                text.push('"');
+            } else {
+                text.push_str(&field);
+            }
+        }
+        text.push('\n');
+    }
+    vm.new_string(text)
+}
@@ -0,0 +1,35 @@
+use crate::vm::{Object, RuntimeError, VM};
+
+/// Names of the functions being called, innermost last, the script first.
+/// The stack depth is its length.
+pub(super) fn stack(vm: &mut VM, _arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let names: Vec<_> = vm
+        .frames()
+        .iter()
+        .map(|frame| frame.function.map_or("<script>", |f| &f.name).to_string())
+        .collect();
+    let names = names
+        .into_iter()
+        .map(|name| vm.new_string(name))
+        .collect::<Result<_, _>>()?;
+    vm.new_list(names)
+}
+
+/// `[name, value]` pairs of the arguments and locals of the calling
+/// function that have names at the call, by slot.
+pub(super) fn locals(vm: &mut VM, _arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let frames = vm.frames();
+    let frame = frames.last().expect("no frame");
+    let named: Vec<_> = frame
+        .names
+        .iter()
+        .zip(frame.values)
+        .filter_map(|(name, value)| Some((name.as_ref()?.to_string(), value.clone())))
+        .collect();
+    let mut locals = Vec::with_capacity(named.len());
+    for (name, value) in named {
+        let name = vm.new_string(name)?;
+        locals.push(vm.new_list(vec![name, value])?);
+    }
+    vm.new_list(locals)
+}
@@ -0,0 +1,21 @@
+use super::{byte_string, string};
+use crate::vm::{Object, RuntimeError, VM};
+
+/// The contents of the file at `path`, as a byte string.
+pub(super) fn read_bytes(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let path = string(&arguments[0], "Path of 'fs.read_bytes'")?;
+    let bytes = std::fs::read(&path).map_err(|e| RuntimeError {
+        message: format!("Can't read '{}': {}.", path, e),
+    })?;
+    vm.new_bytes(bytes)
+}
+
+/// Writes the byte string to the file at `path`, replacing what was there.
+pub(super) fn write_bytes(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let path = string(&arguments[0], "Path of 'fs.write_bytes'")?;
+    let bytes = byte_string(&arguments[1], "Bytes of 'fs.write_bytes'")?;
+    std::fs::write(&path, bytes).map_err(|e| RuntimeError {
+        message: format!("Can't write '{}': {}.", path, e),
+    })?;
+    Ok(Object::Null)
+}
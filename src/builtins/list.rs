@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use super::list;
+use crate::list::List;
+use crate::vm::{runtime_error, Object, RuntimeError, VM};
+
+/// The list `object` to change in place, for every value sharing it.
+fn shared(object: &Object, what: &str) -> Result<Arc<List>, RuntimeError> {
+    match object {
+        Object::List(l) => Ok(l.clone()),
+        _ => runtime_error!(format!("{} must be a list.", what)),
+    }
+}
+
+pub(super) fn new(vm: &mut VM, _arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    vm.new_list(vec![])
+}
+
+/// Appends the item to the list and returns the list.
+pub(super) fn push(vm: &mut VM, mut arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let item = arguments.pop().unwrap();
+    let list = shared(&arguments[0], "List of 'list.push'")?;
+    let mut items = list.items_mut()?;
+    vm.size_limits().check_list(items.len() + 1)?;
+    vm.heap_mut().grow(size_of::<Object>())?;
+    items.push_back(item);
+    Ok(arguments.pop().unwrap())
+}
+
+/// Puts the item in front of the list and returns the list.
+pub(super) fn unshift(vm: &mut VM, mut arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let item = arguments.pop().unwrap();
+    let list = shared(&arguments[0], "List of 'list.unshift'")?;
+    let mut items = list.items_mut()?;
+    vm.size_limits().check_list(items.len() + 1)?;
+    vm.heap_mut().grow(size_of::<Object>())?;
+    items.push_front(item);
+    Ok(arguments.pop().unwrap())
+}
+
+/// Takes the first item off the list and returns the list.
+pub(super) fn shift(_vm: &mut VM, mut arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let list = shared(&arguments[0], "List of 'list.shift'")?;
+    if list.items_mut()?.pop_front().is_none() {
+        runtime_error!("Can't shift an empty list.");
+    }
+    Ok(arguments.pop().unwrap())
+}
+
+pub(super) fn peek(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    match list(&arguments[0], "List of 'list.peek'")?.items().front() {
+        Some(item) => Ok(item.clone()),
+        None => runtime_error!("Can't peek at an empty list."),
+    }
+}
+
+/// Whether the script function `less` says `a` goes before `b`.
+fn less(
+    vm: &mut VM,
+    less: &Object,
+    a: &Object,
+    b: &Object,
+    what: &str,
+) -> Result<bool, RuntimeError> {
+    match vm.call(less, vec![a.clone(), b.clone()])? {
+        Object::Bool(b) => Ok(b),
+        result => runtime_error!(format!(
+            "Comparator of '{}' must return a bool, found {}.",
+            what,
+            result.type_name()
+        )),
+    }
+}
+
+/// The function `object` of a builtin taking a comparator.
+fn comparator<'a>(object: &'a Object, what: &str) -> Result<&'a Object, RuntimeError> {
+    match object {
+        Object::Function(_) => Ok(object),
+        _ => runtime_error!(format!("Comparator of '{}' must be a function.", what)),
+    }
+}
+
+/// Sorts `items` stably with a merge sort. Comparators are scripts and
+/// may fail or contradict themselves, which the standard library's sorts
+/// don't allow for.
+fn merge_sort(
+    vm: &mut VM,
+    comparator: &Object,
+    mut items: Vec<Object>,
+) -> Result<Vec<Object>, RuntimeError> {
+    if items.len() < 2 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(vm, comparator, items)?;
+    let right = merge_sort(vm, comparator, right)?;
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Equal items keep their order, the left one goes first.
+        if less(vm, comparator, b, a, "sort_by")? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// Sorts the list by the function `less(a, b)`, returning whether `a`
+/// goes before `b`, and returns it. Items it doesn't order stay in the
+/// order they were. The comparator sees the list as it was before.
+pub(super) fn sort_by(vm: &mut VM, mut arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let comparator = arguments.pop().unwrap();
+    let comparator = self::comparator(&comparator, "sort_by")?;
+    let list = shared(&arguments[0], "List of 'sort_by'")?;
+    if list.is_frozen() {
+        runtime_error!("Can't change a frozen list.");
+    }
+    let items = list.items().iter().cloned().collect();
+    let sorted = merge_sort(vm, comparator, items)?;
+    *list.items_mut()? = sorted.into();
+    Ok(arguments.pop().unwrap())
+}
+
+/// The first of the smallest items by `less`, or with `max` set the first
+/// of the largest.
+fn extreme(
+    vm: &mut VM,
+    arguments: Vec<Object>,
+    max: bool,
+    what: &str,
+) -> Result<Object, RuntimeError> {
+    let comparator = self::comparator(&arguments[1], what)?;
+    // Copied, the comparator may change the list.
+    let items: Vec<_> = list(&arguments[0], &format!("List of '{}'", what))?
+        .items()
+        .iter()
+        .cloned()
+        .collect();
+    let Some(mut best) = items.first() else {
+        runtime_error!(format!("List of '{}' must not be empty.", what));
+    };
+    for item in items.iter().skip(1) {
+        let better = match max {
+            true => less(vm, comparator, best, item, what)?,
+            false => less(vm, comparator, item, best, what)?,
+        };
+        if better {
+            best = item;
+        }
+    }
+    Ok(best.clone())
+}
+
+pub(super) fn min_by(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    extreme(vm, arguments, false, "min_by")
+}
+
+pub(super) fn max_by(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    extreme(vm, arguments, true, "max_by")
+}
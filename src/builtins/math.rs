@@ -0,0 +1,81 @@
+use super::{list, number};
+use crate::vm::{runtime_error, Object, RuntimeError, VM};
+
+pub(super) fn sqrt(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    Ok(number(&arguments[0], "Argument of 'math.sqrt'")?
+        .sqrt()
+        .into())
+}
+
+pub(super) fn floor(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    Ok(number(&arguments[0], "Argument of 'math.floor'")?
+        .floor()
+        .into())
+}
+
+pub(super) fn abs(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    Ok(number(&arguments[0], "Argument of 'math.abs'")?
+        .abs()
+        .into())
+}
+
+/// Folds the numbers of the list argument of `what` into `init` with `f`,
+/// the result and how many there were.
+fn fold(
+    arguments: &[Object],
+    what: &str,
+    init: f64,
+    f: impl Fn(f64, f64) -> f64,
+) -> Result<(f64, usize), RuntimeError> {
+    let items = list(&arguments[0], &format!("Argument of '{}'", what))?.items();
+    let mut result = init;
+    for (i, item) in items.iter().enumerate() {
+        let Object::Number(n) = item else {
+            runtime_error!(format!(
+                "Item {} of the list of '{}' must be a number, found {}.",
+                i,
+                what,
+                item.type_name()
+            ));
+        };
+        result = f(result, *n);
+    }
+    Ok((result, items.len()))
+}
+
+/// Like [`fold`], for aggregates that an empty list has none of.
+fn fold_nonempty(
+    arguments: &[Object],
+    what: &str,
+    init: f64,
+    f: impl Fn(f64, f64) -> f64,
+) -> Result<(f64, usize), RuntimeError> {
+    let (result, len) = fold(arguments, what, init, f)?;
+    if len == 0 {
+        runtime_error!(format!("List of '{}' must not be empty.", what));
+    }
+    Ok((result, len))
+}
+
+/// The smallest number of the list, `NaN`s are skipped unless there is
+/// nothing else.
+pub(super) fn min(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let (min, _) = fold_nonempty(&arguments, "math.min", f64::NAN, f64::min)?;
+    Ok(min.into())
+}
+
+pub(super) fn max(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let (max, _) = fold_nonempty(&arguments, "math.max", f64::NAN, f64::max)?;
+    Ok(max.into())
+}
+
+/// The sum of the numbers of the list, 0 for an empty one.
+pub(super) fn sum(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let (sum, _) = fold(&arguments, "math.sum", 0.0, |a, b| a + b)?;
+    Ok(sum.into())
+}
+
+pub(super) fn avg(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let (sum, len) = fold_nonempty(&arguments, "math.avg", 0.0, |a, b| a + b)?;
+    Ok((sum / len as f64).into())
+}
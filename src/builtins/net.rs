@@ -0,0 +1,101 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use super::string;
+use crate::vm::{runtime_error, Object, RuntimeError, VM};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fetches `url`, returns `[status, body]`.
+pub(super) fn http_get(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let url = string(&arguments[0], "URL of 'net.get'")?;
+    request(vm, "GET", &url, None)
+}
+
+/// Posts `body` to `url`, returns `[status, body]`.
+pub(super) fn http_post(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let url = string(&arguments[0], "URL of 'net.post'")?;
+    let body = string(&arguments[1], "Body of 'net.post'")?;
+    request(vm, "POST", &url, Some(&body))
+}
+
+/// A plain HTTP/1.1 request, no TLS.
+fn request(
+    vm: &mut VM,
+    method: &str,
+    url: &str,
+    body: Option<&str>,
+) -> Result<Object, RuntimeError> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        runtime_error!(format!("Unsupported URL '{}', only http:// is.", url));
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+        method, path, authority
+    );
+    if let Some(body) = body {
+        request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    request.push_str("\r\n");
+    request.push_str(body.unwrap_or(""));
+
+    let io_error = |e: std::io::Error| RuntimeError {
+        message: format!("HTTP request to '{}' failed: {}", url, e),
+    };
+    let mut stream = TcpStream::connect(&address).map_err(io_error)?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(io_error)?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(io_error)?;
+    stream.write_all(request.as_bytes()).map_err(io_error)?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).map_err(io_error)?;
+
+    let (status, body) = parse_response(&response).ok_or_else(|| RuntimeError {
+        message: format!("Malformed HTTP response from '{}'.", url),
+    })?;
+    let body = vm.new_string(String::from_utf8_lossy(&body).into_owned())?;
+    vm.new_list(vec![status.into(), body])
+}
+
+fn parse_response(response: &[u8]) -> Option<(f64, Vec<u8>)> {
+    let end = response.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&response[..end]).ok()?;
+    let body = &response[end + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines.next()?.split(' ').nth(1)?.parse().ok()?;
+    let chunked = lines.any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+    if chunked {
+        Some((status, unchunk(body)?))
+    } else {
+        Some((status, body.to_vec()))
+    }
+}
+
+fn unchunk(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut result = Vec::new();
+    loop {
+        let end = body.windows(2).position(|w| w == b"\r\n")?;
+        let size = std::str::from_utf8(&body[..end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        body = &body[end + 2..];
+        if size == 0 {
+            return Some(result);
+        }
+        result.extend_from_slice(body.get(..size)?);
+        body = body.get(size + 2..)?;
+    }
+}
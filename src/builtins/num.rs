@@ -0,0 +1,70 @@
+use super::{number, string};
+use crate::vm::{runtime_error, Object, RuntimeError, VM};
+
+/// The number `object` as a whole number from `min` to `max`.
+fn whole(object: &Object, what: &str, min: u32, max: u32) -> Result<u32, RuntimeError> {
+    let n = number(object, what)?;
+    if n.fract() != 0.0 || n < min as f64 || n > max as f64 {
+        runtime_error!(format!(
+            "{} must be a whole number from {} to {}.",
+            what, min, max
+        ));
+    }
+    Ok(n as u32)
+}
+
+/// Parses a whole number written in base `radix`, 2 to 36, with an
+/// optional sign: `num.parse_int("ff", 16)`. Letters are digits from 10
+/// on in either case, whitespace around the number is ignored.
+pub(super) fn parse_int(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let s = string(&arguments[0], "String of 'num.parse_int'")?;
+    let radix = whole(&arguments[1], "Radix of 'num.parse_int'", 2, 36)?;
+    let invalid = || RuntimeError {
+        message: format!("'{}' is not a whole number in base {}.", s, radix),
+    };
+    let trimmed = s.trim();
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+    // Exact up to `u128`, rounded once to the nearest number. Only longer
+    // numbers carry on in floats, rounding at each digit.
+    let mut exact: Option<u128> = Some(0);
+    let mut n = 0.0;
+    for c in digits.chars() {
+        let digit = c.to_digit(radix).ok_or_else(invalid)?;
+        exact = exact.and_then(|e| e.checked_mul(radix as u128)?.checked_add(digit as u128));
+        n = match exact {
+            Some(e) => e as f64,
+            None => n * radix as f64 + digit as f64,
+        };
+    }
+    Ok(if negative { -n } else { n }.into())
+}
+
+/// Parses a decimal number, `1.5`, `-2` or `3e8`, whitespace around it is
+/// ignored. Unlike Rust, `inf` and `NaN` aren't numbers here.
+pub(super) fn parse_float(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let s = string(&arguments[0], "String of 'num.parse_float'")?;
+    let trimmed = s.trim();
+    let is_decimal = trimmed
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+    match trimmed.parse::<f64>() {
+        Ok(n) if is_decimal => Ok(n.into()),
+        _ => runtime_error!(format!("'{}' is not a number.", s)),
+    }
+}
+
+/// The number with `digits` digits after the point, 0 to 100:
+/// `num.to_fixed(2 / 3, 2)` is `"0.67"`. It's the number's exact binary
+/// value that is rounded, to the nearest and ties to even, `0.125` to
+/// `"0.12"`, but `2.675` is a little less and gives `"2.67"`.
+pub(super) fn to_fixed(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let n = number(&arguments[0], "Number of 'num.to_fixed'")?;
+    let digits = whole(&arguments[1], "Digits of 'num.to_fixed'", 0, 100)?;
+    vm.new_string(format!("{:.*}", digits as usize, n))
+}
@@ -0,0 +1,30 @@
+use super::string;
+use crate::vm::{Object, RuntimeError, VM};
+
+pub(super) fn re_match(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let s = string(&arguments[1], "Subject of 're.match'")?;
+    let regex = vm.regex(&string(&arguments[0], "Pattern of 're.match'")?)?;
+    Ok(regex.is_match(&s).into())
+}
+
+pub(super) fn re_find_all(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let s = string(&arguments[1], "Subject of 're.find_all'")?;
+    let regex = vm.regex(&string(&arguments[0], "Pattern of 're.find_all'")?)?;
+    let matches: Vec<_> = regex
+        .find_iter(&s)
+        .map(|m| m.as_str().to_string())
+        .collect();
+    let matches = matches
+        .into_iter()
+        .map(|m| vm.new_string(m))
+        .collect::<Result<_, _>>()?;
+    vm.new_list(matches)
+}
+
+pub(super) fn re_replace(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let s = string(&arguments[1], "Subject of 're.replace'")?;
+    let replacement = string(&arguments[2], "Replacement of 're.replace'")?;
+    let regex = vm.regex(&string(&arguments[0], "Pattern of 're.replace'")?)?;
+    let replaced = regex.replace_all(&s, replacement).into_owned();
+    vm.new_string(replaced)
+}
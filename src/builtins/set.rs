@@ -0,0 +1,64 @@
+use super::list;
+use crate::key::Key;
+use crate::set::Set;
+use crate::vm::{runtime_error, Object, RuntimeError, VM};
+
+fn set<'a>(object: &'a Object, what: &str) -> Result<&'a Set, RuntimeError> {
+    match object {
+        Object::Set(s) => Ok(s),
+        _ => runtime_error!(format!("{} must be a set.", what)),
+    }
+}
+
+pub(super) fn new(vm: &mut VM, _arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    vm.heap_mut().set(Set::new())
+}
+
+pub(super) fn of(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let set = Set::new();
+    // Copied, making a key locks a list in it, which may be this one.
+    let items: Vec<_> = list(&arguments[0], "Argument of 'set.of'")?
+        .items()
+        .iter()
+        .cloned()
+        .collect();
+    for item in &items {
+        set.insert(item)?;
+    }
+    vm.heap_mut().set(set)
+}
+
+/// Inserts the value into the set and returns the set.
+pub(super) fn insert(vm: &mut VM, mut arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let item = arguments.pop().unwrap();
+    if set(&arguments[0], "Set of 'set.insert'")?.insert(&item)? {
+        vm.heap_mut().grow(size_of::<Key>())?;
+    }
+    Ok(arguments.pop().unwrap())
+}
+
+/// Removes the value from the set and returns the set.
+pub(super) fn remove(_vm: &mut VM, mut arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let item = arguments.pop().unwrap();
+    set(&arguments[0], "Set of 'set.remove'")?.remove(&item)?;
+    Ok(arguments.pop().unwrap())
+}
+
+pub(super) fn contains(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let set = set(&arguments[0], "Set of 'set.contains'")?;
+    Ok(set.contains(&arguments[1])?.into())
+}
+
+pub(super) fn union(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let a = set(&arguments[0], "Argument of 'set.union'")?;
+    let b = set(&arguments[1], "Argument of 'set.union'")?;
+    let union = a.union(b);
+    vm.heap_mut().set(union)
+}
+
+pub(super) fn intersect(vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let a = set(&arguments[0], "Argument of 'set.intersect'")?;
+    let b = set(&arguments[1], "Argument of 'set.intersect'")?;
+    let intersection = a.intersection(b);
+    vm.heap_mut().set(intersection)
+}
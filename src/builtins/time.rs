@@ -0,0 +1,154 @@
+use std::sync::OnceLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use super::string;
+use crate::vm::{runtime_error, Object, RuntimeError, VM};
+
+/// Seconds since the Unix epoch, always 0 on a deterministic VM.
+pub(super) fn now(vm: &mut VM, _arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    if vm.is_deterministic() {
+        return Ok(0.0.into());
+    }
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(elapsed.as_secs_f64().into())
+}
+
+/// Milliseconds on a monotonic clock, counted from its first reading in
+/// the process, for measuring durations. Always 0 on a deterministic VM.
+pub(super) fn clock(vm: &mut VM, _arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    static START: OnceLock<Instant> = OnceLock::new();
+    if vm.is_deterministic() {
+        return Ok(0.0.into());
+    }
+    let elapsed = START.get_or_init(Instant::now).elapsed();
+    Ok((elapsed.as_secs_f64() * 1000.0).into())
+}
+
+/// Formats a timestamp in UTC. Supports `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`
+/// and `%%`.
+pub(super) fn format_time(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let Object::Number(ts) = arguments[0] else {
+        runtime_error!("Timestamp of 'time.format' must be a number.");
+    };
+    let format = string(&arguments[1], "Format of 'time.format'")?;
+
+    let seconds = ts.floor() as i64;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let time = seconds.rem_euclid(86400);
+    let (hour, minute, second) = (time / 3600, time / 60 % 60, time % 60);
+
+    let mut result = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => result.push_str(&format!("{:04}", year)),
+            Some('m') => result.push_str(&format!("{:02}", month)),
+            Some('d') => result.push_str(&format!("{:02}", day)),
+            Some('H') => result.push_str(&format!("{:02}", hour)),
+            Some('M') => result.push_str(&format!("{:02}", minute)),
+            Some('S') => result.push_str(&format!("{:02}", second)),
+            Some('%') => result.push('%'),
+            Some(c) => runtime_error!(format!("Unknown time format '%{}'.", c)),
+            None => runtime_error!("Time format ends in '%'."),
+        }
+    }
+    Ok(result.into())
+}
+
+/// Parses a UTC time written in the `format_time` format into a timestamp.
+pub(super) fn parse_time(_vm: &mut VM, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let s = string(&arguments[0], "Time of 'time.parse'")?;
+    let format = string(&arguments[1], "Format of 'time.parse'")?;
+    let mismatch = || RuntimeError {
+        message: format!("'{}' does not match the time format '{}'.", s, format),
+    };
+
+    let (mut year, mut month, mut day) = (1970, 1, 1);
+    let (mut hour, mut minute, mut second) = (0, 0, 0);
+    let mut input = s.as_str();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        let (field, digits) = match c {
+            '%' => match chars.next() {
+                Some('Y') => (&mut year, 4),
+                Some('m') => (&mut month, 2),
+                Some('d') => (&mut day, 2),
+                Some('H') => (&mut hour, 2),
+                Some('M') => (&mut minute, 2),
+                Some('S') => (&mut second, 2),
+                Some('%') => {
+                    input = input.strip_prefix('%').ok_or_else(mismatch)?;
+                    continue;
+                }
+                Some(c) => runtime_error!(format!("Unknown time format '%{}'.", c)),
+                None => runtime_error!("Time format ends in '%'."),
+            },
+            c => {
+                input = input.strip_prefix(c).ok_or_else(mismatch)?;
+                continue;
+            }
+        };
+        let len = input
+            .bytes()
+            .take(digits)
+            .take_while(u8::is_ascii_digit)
+            .count();
+        *field = input[..len].parse().map_err(|_| mismatch())?;
+        input = &input[len..];
+    }
+    if !input.is_empty()
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(mismatch());
+    }
+    let days = days_from_civil(year, month, day);
+    Ok(((days * 86400 + hour * 3600 + minute * 60 + second) as f64).into())
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Proleptic Gregorian date of a number of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,409 @@
+/// A kind of diagnostic. Codes are stable, messages may change: `E` codes
+/// are syntax and name errors, `R` codes are type errors and `W` codes are
+/// warnings of the [linter](crate::lint).
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub title: &'static str,
+    /// Longer description with examples, for `reaper explain`.
+    pub explanation: &'static str,
+}
+
+pub static CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: "E001",
+        title: "unexpected token",
+        explanation: "\
+The parser found a token that can't continue the statement it is reading.
+Most often a ';' is missing at the end of the previous statement:
+
+    print 1
+    print 2;
+
+Every statement except blocks, 'if', 'while', 'for' and 'fn' ends with a
+';':
+
+    print 1;
+    print 2;
+",
+    },
+    ErrorCode {
+        code: "E002",
+        title: "unexpected end of file",
+        explanation: "\
+The file ended in the middle of a statement, usually because a block or a
+string isn't closed:
+
+    fn f() {
+        return 1;
+
+Close the block with '}' or the string with '\"'.
+",
+    },
+    ErrorCode {
+        code: "E003",
+        title: "nested too deeply",
+        explanation: "\
+Statements and expressions are nested in each other deeper than the
+compiler allows, 200 levels unless the embedder changed the limit. Each
+block, condition, call, operator and index counts:
+
+    x = f(f(f(f(f(f(f(f(1))))))));
+
+Long chains of operators count as well, every operator is one more level.
+Split the work into variables or functions:
+
+    a = f(f(f(f(1))));
+    x = f(f(f(f(a))));
+",
+    },
+    ErrorCode {
+        code: "E004",
+        title: "chained comparison",
+        explanation: "\
+Comparisons are made one pair at a time. A chain compares the bool result
+of the first comparison with the next operand, which is an error:
+
+    if (0 < i < 10) {
+        print i;
+    }
+
+Compare each pair on its own, nesting the conditions:
+
+    if (0 < i) {
+        if (i < 10) {
+            print i;
+        }
+    }
+",
+    },
+    ErrorCode {
+        code: "E010",
+        title: "undefined variable",
+        explanation: "\
+A variable is read before anything was assigned to it:
+
+    print total;
+
+Variables are declared by the first assignment, so assign it first:
+
+    total = 0;
+    print total;
+
+Functions only see their parameters and the variables assigned inside of
+them, not the variables of the script.
+",
+    },
+    ErrorCode {
+        code: "E011",
+        title: "undefined function",
+        explanation: "\
+A call names a function that is neither declared in the script nor a
+builtin:
+
+    print square(3);
+
+Declare it with 'fn', anywhere in the script:
+
+    fn square(x) {
+        return x * x;
+    }
+    print square(3);
+",
+    },
+    ErrorCode {
+        code: "E012",
+        title: "invalid assignment target",
+        explanation: "\
+Only variables and indexed values can be assigned to:
+
+    1 = 2;
+    f() = 2;
+
+Assign to a variable or an element instead:
+
+    x = 1;
+    s[0] = \"c\";
+",
+    },
+    ErrorCode {
+        code: "E013",
+        title: "'break' outside of a loop",
+        explanation: "\
+'break' leaves the innermost 'while', 'for' or 'do' loop, so it can only
+be used inside of one:
+
+    if (done) {
+        break;
+    }
+
+Use 'return' to leave a function early.
+",
+    },
+    ErrorCode {
+        code: "E014",
+        title: "undefined loop label",
+        explanation: "\
+'break' names a label that no enclosing loop has:
+
+    outer: while (true) {
+        while (true) {
+            break inner;
+        }
+    }
+
+Label the loop to leave, or use one of the enclosing labels:
+
+    outer: while (true) {
+        while (true) {
+            break outer;
+        }
+    }
+",
+    },
+    ErrorCode {
+        code: "E015",
+        title: "'defer' not directly in a function body",
+        explanation: "\
+Deferred expressions run when the function returns, so 'defer' has to be
+directly in a function body, not in the script or a nested block:
+
+    fn f() {
+        if (true) {
+            defer cleanup();
+        }
+    }
+
+Move it to the top level of the function:
+
+    fn f() {
+        defer cleanup();
+    }
+",
+    },
+    ErrorCode {
+        code: "E016",
+        title: "statement in a sandboxed expression",
+        explanation: "\
+Expressions evaluated in a sandbox, rules or formulas from untrusted
+sources, are a single expression: no statements, loops, functions or
+assignments.
+
+    total = price * 2
+
+Leave the assignment to the host, which gets the value of the expression:
+
+    price * 2
+",
+    },
+    ErrorCode {
+        code: "E017",
+        title: "not allowed in the sandbox",
+        explanation: "\
+A sandboxed expression uses a variable or calls a function that the
+embedder didn't allow:
+
+    time.now() - start
+
+Only the names whitelisted in the `Sandbox` the expression is evaluated
+with can be used.
+",
+    },
+    ErrorCode {
+        code: "E018",
+        title: "'continue' outside of a loop",
+        explanation: "\
+'continue' goes on with the next iteration of the innermost 'while', 'for'
+or 'do' loop, so it can only be used inside of one:
+
+    fn f(x) {
+        if (x < 0) {
+            continue;
+        }
+    }
+
+Use 'return' to leave a function early.
+",
+    },
+    ErrorCode {
+        code: "E019",
+        title: "'return' outside of a function",
+        explanation: "\
+'return' leaves the function it is in with a value, the script itself
+can't return:
+
+    if (done) {
+        return 1;
+    }
+
+Put the code in a function, or let the script run to its end:
+
+    fn check(done) {
+        if (done) {
+            return 1;
+        }
+        return 0;
+    }
+",
+    },
+    ErrorCode {
+        code: "E020",
+        title: "program too large",
+        explanation: "\
+The program has more functions, constants or instructions than the
+compiler allows, or a call, function or the script has more arguments,
+parameters or locals. The limits are far above what scripts written by
+hand need, and guard embedders against generated or hostile input.
+
+Embedders that do run such programs raise the limits in the
+`CompileOptions` they compile with.
+",
+    },
+    ErrorCode {
+        code: "R001",
+        title: "type mismatch",
+        explanation: "\
+A value's type doesn't match what an annotation or operator expects:
+
+    fn add(a: num, b: num): num {
+        return a + b;
+    }
+    add(1, \"2\");
+
+Pass a value of the expected type, or drop the annotation to have the
+value checked at runtime only:
+
+    add(1, 2);
+",
+    },
+    ErrorCode {
+        code: "R002",
+        title: "unknown type",
+        explanation: "\
+An annotation or 'is' test names a type that doesn't exist:
+
+    fn f(x: int) {}
+
+The types are any, num, bool, str, bytes, list, set and null:
+
+    fn f(x: num) {}
+",
+    },
+    ErrorCode {
+        code: "R003",
+        title: "wrong number of arguments",
+        explanation: "\
+A function is called with more or fewer arguments than it has
+parameters:
+
+    fn add(a, b) {
+        return a + b;
+    }
+    add(1);
+
+Pass one argument per parameter:
+
+    add(1, 2);
+",
+    },
+    ErrorCode {
+        code: "W001",
+        title: "variable shadows a function",
+        explanation: "\
+A parameter or variable has the name of a function or builtin. Calls by
+that name call the variable instead:
+
+    fn total(len) {
+        return len(1);
+    }
+
+Rename the variable:
+
+    fn total(count) {
+        return count;
+    }
+
+Allow it with 'lint.allow = [\"shadowing\"]' in reaper.toml.
+",
+    },
+    ErrorCode {
+        code: "W002",
+        title: "unused result",
+        explanation: "\
+An expression statement computes a value and throws it away. Only calls
+and assignments do something as statements:
+
+    total + 1;
+
+Assign or print the value:
+
+    total = total + 1;
+
+Allow it with 'lint.allow = [\"unused-result\"]' in reaper.toml.
+",
+    },
+    ErrorCode {
+        code: "W003",
+        title: "constant condition",
+        explanation: "\
+The condition of an 'if' or a loop is made of literals only, its branch
+always or never runs:
+
+    if (1 == 2) {
+        print \"never\";
+    }
+
+Remove the branch or test a variable. 'while (true)' is allowed for loops
+left with 'break' or 'return'.
+
+Allow it with 'lint.allow = [\"constant-condition\"]' in reaper.toml.
+",
+    },
+    ErrorCode {
+        code: "W004",
+        title: "deep nesting",
+        explanation: "\
+Functions, 'if' statements and loops are nested deeper than the limit,
+4 levels unless 'lint.max-nesting' in reaper.toml sets another:
+
+    fn f(a, b, c) {
+        if (a) {
+            while (b) {
+                if (c) {
+                    if (a == b) {
+                        print a;
+                    }
+                }
+            }
+        }
+    }
+
+Move inner parts into functions or return early.
+
+Allow it with 'lint.allow = [\"deep-nesting\"]' in reaper.toml.
+",
+    },
+    ErrorCode {
+        code: "W005",
+        title: "assignment in a condition",
+        explanation: "\
+The condition of an 'if' or a loop assigns instead of comparing:
+
+    if (count = 0) {
+        print \"empty\";
+    }
+
+Compare with '==', which 'reaper fix' does:
+
+    if (count == 0) {
+        print \"empty\";
+    }
+
+Allow it with 'lint.allow = [\"assignment-in-condition\"]' in reaper.toml.
+",
+    },
+];
+
+/// The error code `code`, in any case.
+pub fn lookup(code: &str) -> Option<&'static ErrorCode> {
+    CODES.iter().find(|c| c.code.eq_ignore_ascii_case(code))
+}
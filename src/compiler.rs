@@ -1,18 +1,168 @@
-use std::rc::Rc;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
+use crate::builtins;
+use crate::consteval;
+use crate::driver::Diagnostic;
 use crate::parser::{
-    AssignExpression, BinaryExpression, BinaryExpressionKind, BlockStatement, CallExpression,
-    Expression, ExpressionStatement, FnStatement, IfStatement, Literal, LiteralExpression,
-    PrintStatement, ReturnStatement, Statement, UnaryExpression, VariableExpression,
-    WhileStatement,
+    self, AssignExpression, BinaryExpression, BinaryExpressionKind, BlockStatement, BreakStatement,
+    CallExpression, ContinueStatement, DeferStatement, DoWhileStatement, Expression,
+    ExpressionStatement, FnStatement, ForStatement, IfStatement, IndexExpression, Literal,
+    LiteralExpression, PrintStatement, ReturnStatement, Statement, TypeTestExpression,
+    UnaryExpression, UnaryExpressionKind, VariableExpression, WhileStatement,
 };
+use crate::prelude;
+use crate::program::{Function, LocalName, Program};
+use crate::resolver::Resolver;
+use crate::sandbox::Sandbox;
+use crate::tokenizer::Span;
+use crate::typecheck::{Type, TypeChecker};
+use crate::vm::Object;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum OptLevel {
+    O0,
+    O1,
+    /// Also evaluates calls of pure functions with literal arguments at
+    /// compile time.
+    O2,
+}
+
+/// How a [`Compiler`] builds programs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompileOptions {
+    /// Records the source of instructions that can fail, so runtime
+    /// errors point at the code. Programs without it are smaller.
+    pub debug_info: bool,
+    pub optimize: OptLevel,
+    pub limits: Limits,
+}
+
+/// Bounds on the programs that compile, so hostile or generated input
+/// fails with a diagnostic instead of exhausting the stack or memory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// How deep statements and expressions may be nested in each other,
+    /// checked by the parser.
+    pub nesting: usize,
+    /// Functions, including the prelude's.
+    pub functions: usize,
+    /// Arguments of a call.
+    pub arguments: usize,
+    /// Parameters of a function.
+    pub parameters: usize,
+    /// Slots for the parameters and variables of a function, or the
+    /// variables of the script.
+    pub locals: usize,
+    /// Entries in the constant pool.
+    pub constants: usize,
+    /// Instructions.
+    pub code: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            nesting: parser::MAX_NESTING,
+            functions: 1 << 16,
+            arguments: u16::MAX as usize,
+            parameters: u16::MAX as usize,
+            locals: 1 << 16,
+            constants: 1 << 24,
+            code: 1 << 24,
+        }
+    }
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        OptLevel::O0.into()
+    }
+}
+
+impl From<OptLevel> for CompileOptions {
+    fn from(optimize: OptLevel) -> Self {
+        CompileOptions {
+            debug_info: true,
+            optimize,
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// A jump target, bound to an address once the code it points to is
+/// emitted. Jumps to it can be emitted before that.
+#[derive(Debug, Clone, Copy)]
+struct Label(usize);
+
+/// A jump at `at` that still needs the address of `label`.
+struct Fixup {
+    at: usize,
+    label: Label,
+    jump: fn(i32) -> Opcode,
+}
+
+/// What makes constants the same, for sharing one pool entry. Numbers by
+/// their bits, `0` and `-0` are different constants.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Number(u64),
+    Bool(bool),
+    String(String),
+    Bytes(Vec<u8>),
+    Function(usize),
+    Null,
+}
+
+impl ConstantKey {
+    /// `None` for lists and sets, which get an entry each, and host values.
+    fn of(object: &Object) -> Option<ConstantKey> {
+        match object {
+            Object::Number(n) => Some(ConstantKey::Number(n.to_bits())),
+            Object::Bool(b) => Some(ConstantKey::Bool(*b)),
+            Object::String(s) => Some(ConstantKey::String(s.to_string())),
+            Object::Bytes(b) => Some(ConstantKey::Bytes(b.to_vec())),
+            Object::Function(f) => Some(ConstantKey::Function(f.addr)),
+            Object::Null => Some(ConstantKey::Null),
+            Object::List(_) | Object::Set(_) | Object::Native(_) => None,
+        }
+    }
+}
 
 pub struct Compiler {
     bytecode: Vec<Opcode>,
-    functions: std::collections::HashMap<String, usize>,
-    locals: Vec<String>,
-    depth: usize,
-    pops: [usize; 1024],
+    constants: Vec<Object>,
+    /// Index of each constant in `constants`, all uses of a constant in
+    /// the program share its entry.
+    constant_indices: HashMap<ConstantKey, usize>,
+    /// Addresses of all labels, by label, `None` until bound.
+    labels: Vec<Option<usize>>,
+    fixups: Vec<Fixup>,
+    functions: HashMap<String, Arc<Function>>,
+    /// Functions that are completely emitted and known to be pure.
+    pure_functions: HashSet<String>,
+    opt_level: OptLevel,
+    debug_info: bool,
+    limits: Limits,
+    globals: Vec<String>,
+    sandbox: Option<Sandbox>,
+    /// Entries into the deferred expressions of the function being emitted,
+    /// one per `defer` emitted so far.
+    deferred: Vec<Label>,
+    /// Labels of the loops being emitted, innermost last, with the end of
+    /// each loop for `break` to jump to and where the next iteration starts
+    /// for `continue`.
+    loops: Vec<(Option<String>, Label, Label)>,
+    /// See [`Program::with_spans`].
+    spans: Vec<(usize, Span)>,
+    /// The function being emitted, `None` for the script.
+    function: Option<String>,
+    /// Variables of the blocks being emitted in the current function,
+    /// innermost last, with their slot and the address of their first
+    /// store.
+    scopes: Vec<Vec<(String, usize, usize)>>,
+    /// See [`Program::with_locals`].
+    locals: Vec<LocalName>,
 }
 
 impl Default for Compiler {
@@ -25,43 +175,292 @@ impl Compiler {
     pub fn new() -> Compiler {
         Compiler {
             bytecode: Vec::new(),
-            functions: std::collections::HashMap::new(),
+            constants: Vec::new(),
+            constant_indices: HashMap::new(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+            functions: HashMap::new(),
+            pure_functions: HashSet::new(),
+            opt_level: OptLevel::O0,
+            debug_info: true,
+            limits: Limits::default(),
+            globals: Vec::new(),
+            sandbox: None,
+            deferred: Vec::new(),
+            loops: Vec::new(),
+            spans: Vec::new(),
+            function: None,
+            scopes: Vec::new(),
             locals: Vec::new(),
-            depth: 0,
-            pops: [0; 1024],
         }
     }
 
-    pub fn compile(&mut self, ast: Vec<Statement>) -> Vec<Opcode> {
+    pub fn with_opt_level(opt_level: OptLevel) -> Compiler {
+        Compiler::with_options(opt_level.into())
+    }
+
+    pub fn with_options(options: CompileOptions) -> Compiler {
+        Compiler {
+            opt_level: options.optimize,
+            debug_info: options.debug_info,
+            limits: options.limits,
+            ..Compiler::new()
+        }
+    }
+
+    /// Declares a variable the embedder sets with [`VM::set_global`] before
+    /// running the program.
+    ///
+    /// [`VM::set_global`]: crate::vm::VM::set_global
+    pub fn declare_global(&mut self, name: &str) {
+        self.globals.push(name.to_string());
+    }
+
+    /// Restricts the variables and functions the program may use, see
+    /// [`crate::sandbox`].
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.sandbox = Some(sandbox);
+    }
+
+    /// Compiles `ast`, or returns the resolver and type errors without
+    /// generating any code.
+    pub fn compile(&mut self, ast: Vec<Statement>) -> Result<Program, Vec<Diagnostic>> {
+        let mut ast: Vec<_> = prelude::statements().into_iter().chain(ast).collect();
+        let mut resolver = Resolver::new();
+        for name in &self.globals {
+            resolver.declare_global(name);
+        }
+        if let Some(sandbox) = &self.sandbox {
+            resolver.set_sandbox(sandbox.clone());
+        }
+        let resolution = resolver.resolve(&mut ast);
+        let type_errors = TypeChecker::new().check(&ast);
+        if !resolution.errors.is_empty() || !type_errors.is_empty() {
+            let diagnostics = resolution.errors.iter().map(Diagnostic::from);
+            return Err(diagnostics
+                .chain(type_errors.iter().map(Diagnostic::from))
+                .collect());
+        }
+        let globals = resolution.globals();
+        self.generate(ast, resolution.slots, &globals)
+            .map_err(|diagnostic| vec![diagnostic])?;
+        // The script's own locals live in the bottom frame, reserved by the
+        // VM so they can be set before and read after a run.
+        Ok(Program::new(
+            self.bytecode.clone(),
+            self.constants.clone(),
+            self.functions.clone(),
+        )
+        .expect("the compiler emitted invalid code")
+        .with_globals(resolution.slots, globals)
+        .with_spans(self.spans.clone())
+        .with_locals(self.locals.clone()))
+    }
+
+    /// Emits the code of the resolved `ast`, the script using `slots`
+    /// locals.
+    fn generate(
+        &mut self,
+        ast: Vec<Statement>,
+        slots: usize,
+        globals: &HashMap<String, usize>,
+    ) -> Result<(), Diagnostic> {
+        let limits = self.limits;
+        check_limit("script", slots, "locals", limits.locals, Span::default())?;
+        self.begin_scope();
+        for name in self.globals.clone() {
+            self.declare_local(&name, globals[&name]);
+        }
         for statement in ast {
-            statement.codegen(self);
+            statement.codegen(self)?;
+        }
+        // Through the `EndOfProgram` appended by `Program::new`.
+        self.end_scope_at(self.bytecode.len() + 1);
+        self.patch_jumps();
+        assert!(self.fixups.is_empty(), "jump to an unbound label");
+        let sizes = [
+            (self.functions.len(), "functions", limits.functions),
+            (self.bytecode.len(), "instructions", limits.code),
+        ];
+        for (n, what, limit) in sizes {
+            check_limit("program", n, what, limit, Span::default())?;
         }
-        self.bytecode.clone()
+        Ok(())
     }
 
     fn emit_bytes(&mut self, opcodes: &[Opcode]) -> usize {
-        for opcode in opcodes {
-            self.bytecode.push(opcode.clone());
-        }
+        self.bytecode.extend_from_slice(opcodes);
         self.bytecode.len() - opcodes.len()
     }
 
-    fn emit_stack_cleanup(&mut self) {
-        let popcount = self.pops[self.depth];
-        for _ in 0..popcount {
-            self.bytecode.push(Opcode::Pop);
+    /// Emits `opcode` as the code for the source at `span`.
+    fn emit_at(&mut self, opcode: Opcode, span: Span) -> usize {
+        let at = self.emit_bytes(&[opcode]);
+        if self.debug_info {
+            self.spans.push((at, span));
+        }
+        at
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    /// Ends the variables of the innermost block at the next instruction.
+    fn end_scope(&mut self) {
+        self.end_scope_at(self.bytecode.len());
+    }
+
+    fn end_scope_at(&mut self, end: usize) {
+        for (name, slot, start) in self.scopes.pop().unwrap() {
+            if self.debug_info {
+                self.locals.push(LocalName {
+                    function: self.function.clone(),
+                    slot,
+                    live: start..end,
+                    name,
+                });
+            }
+        }
+    }
+
+    /// Names `slot` from the next instruction on, unless `name` is known in
+    /// the current function already.
+    fn declare_local(&mut self, name: &str, slot: usize) {
+        if self
+            .scopes
+            .iter()
+            .flatten()
+            .any(|(known, _, _)| known == name)
+        {
+            return;
+        }
+        let start = self.bytecode.len();
+        let scope = self.scopes.last_mut().unwrap();
+        scope.push((name.to_string(), slot, start));
+    }
+
+    /// The end and the start of the next iteration of the innermost loop,
+    /// or the one with `label`. Locals live in slots rather than on the
+    /// stack, jumping out of blocks leaves nothing to pop.
+    fn find_loop(&self, label: &Option<String>) -> (Label, Label) {
+        let &(_, end, next) = self
+            .loops
+            .iter()
+            .rev()
+            .find(|(name, _, _)| label.is_none() || name == label)
+            .expect("break or continue outside of a loop");
+        (end, next)
+    }
+
+    fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Binds `label` to the address of the next instruction emitted.
+    fn bind_label(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.bytecode.len());
+    }
+
+    /// Emits `jump` to `label`, e.g. `emit_jump(Opcode::Jz, label)`.
+    fn emit_jump(&mut self, jump: fn(i32) -> Opcode, label: Label) -> usize {
+        let at = self.emit_bytes(&[jump(0)]);
+        self.fixups.push(Fixup { at, label, jump });
+        at
+    }
+
+    /// Fills in the offsets of all jumps to bound labels.
+    fn patch_jumps(&mut self) {
+        let labels = &self.labels;
+        let bytecode = &mut self.bytecode;
+        self.fixups.retain(|fixup| match labels[fixup.label.0] {
+            Some(addr) => {
+                bytecode[fixup.at] = (fixup.jump)(jump_offset(fixup.at, addr));
+                false
+            }
+            None => true,
+        });
+    }
+
+    /// Returns the value on top of the stack, after running the deferred
+    /// expressions reached so far.
+    fn emit_return(&mut self) {
+        match self.deferred.last() {
+            Some(&entry) => {
+                self.emit_jump(Opcode::Jmp, entry);
+            }
+            None => {
+                self.emit_bytes(&[Opcode::Ret]);
+            }
         }
     }
 
-    fn resolve_local(&self, name: String) -> Option<usize> {
-        self.locals.iter().position(|local| *local == name)
+    fn emit_constant(&mut self, object: Object) -> Result<usize, Diagnostic> {
+        let key = ConstantKey::of(&object);
+        let idx = match key.as_ref().and_then(|key| self.constant_indices.get(key)) {
+            Some(&idx) => idx,
+            None => {
+                let n = self.constants.len() + 1;
+                let limit = self.limits.constants;
+                check_limit("program", n, "constants", limit, Span::default())?;
+                self.constants.push(object);
+                if let Some(key) = key {
+                    self.constant_indices.insert(key, n - 1);
+                }
+                n - 1
+            }
+        };
+        Ok(self.emit_bytes(&[Opcode::Const(operand(idx)?)]))
     }
 }
 
-#[derive(Debug, Clone)]
+/// Narrows an index or count to the width of an instruction operand. The
+/// default [`Limits`] keep them in range, those raised by embedders may
+/// not.
+fn operand<T: TryFrom<usize>>(n: usize) -> Result<T, Diagnostic> {
+    T::try_from(n).map_err(|_| {
+        let message = format!("bytecode operand {} out of range", n);
+        Diagnostic::new("E020", &message, Span::default())
+    })
+}
+
+/// Fails when `owner` has more of `what` than `limit` allows, one of the
+/// [`Limits`].
+fn check_limit(
+    owner: &str,
+    n: usize,
+    what: &str,
+    limit: usize,
+    span: Span,
+) -> Result<(), Diagnostic> {
+    if n > limit {
+        let message = format!("{} has {} {}, the limit is {}", owner, n, what, limit);
+        return Err(Diagnostic::new("E020", &message, span));
+    }
+    Ok(())
+}
+
+/// Offset of a jump at `from` to `to`.
+pub(crate) fn jump_offset(from: usize, to: usize) -> i32 {
+    match i32::try_from(to as isize - from as isize) {
+        Ok(offset) => offset,
+        Err(_) => panic!("jump from {} to {} out of range", from, to),
+    }
+}
+
+// Every instruction is the opcode plus up to 6 bytes of operands, anything
+// bigger lives in the constant pool.
+const _: () = assert!(std::mem::size_of::<Opcode>() == 8);
+
+/// New instructions are added as the language grows, embedders looking at
+/// bytecode need a catch-all arm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
 pub enum Opcode {
     Print,
-    Const(f64),
+    /// Pushes the constant with that index in the constant pool.
+    Const(u32),
     Add,
     Sub,
     Mul,
@@ -70,25 +469,52 @@ pub enum Opcode {
     Not,
     False,
     Eq,
-    Jmp(usize),
-    Jz(usize),
+    Neq,
+    /// Jumps by that many instructions, relative to the jump itself, so
+    /// code can be moved around without patching.
+    Jmp(i32),
+    Jz(i32),
     Ret,
+    Neg,
     Less,
-    Deepget(usize),
-    Deepset(usize),
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    /// Pushes a copy of the local in that slot of the current frame.
+    GetLocal(u32),
+    /// Pops a value and stores it in the local in that slot, in place.
+    SetLocal(u32),
+    /// Pushes the local in that slot and leaves `null` in its place, for a
+    /// value about to be stored back, so it isn't shared while it changes.
+    Take(u32),
     Pop,
-    Invoke(usize),
-    Str(Rc<str>),
+    /// Calls with that many arguments on the stack, reserving slots for
+    /// that many arguments and locals in total.
+    Invoke(u16, u32),
+    /// Calls the function object below that many arguments.
+    Call(u16),
+    /// Calls the builtin with that index with that many arguments.
+    Native(u16, u16),
     Strcat,
+    /// Pops the index and the string, pushes the character at the index.
+    Index,
+    /// Pops the end, the start and the string, pushes the substring.
+    Slice,
+    /// Pops the value and that many indices, stores the value at those
+    /// indices into the local in the slot, `a[i][j] = v`, and pushes the
+    /// value back.
+    SetIndex(u32, u16),
+    /// Pops a value, pushes whether it has the type.
+    TypeTest(Type),
     EndOfProgram,
 }
 
 trait Codegen {
-    fn codegen(&self, _compiler: &mut Compiler) {}
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic>;
 }
 
 impl Codegen for Statement {
-    fn codegen(&self, compiler: &mut Compiler) {
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
         match self {
             Statement::Print(print_statement) => print_statement.codegen(compiler),
             Statement::Fn(fn_statement) => fn_statement.codegen(compiler),
@@ -97,107 +523,248 @@ impl Codegen for Statement {
             Statement::If(if_statement) => if_statement.codegen(compiler),
             Statement::Block(block_statement) => block_statement.codegen(compiler),
             Statement::While(while_statement) => while_statement.codegen(compiler),
-            _ => {}
+            Statement::For(for_statement) => for_statement.codegen(compiler),
+            Statement::DoWhile(do_while_statement) => do_while_statement.codegen(compiler),
+            Statement::Break(break_statement) => break_statement.codegen(compiler),
+            Statement::Continue(continue_statement) => continue_statement.codegen(compiler),
+            Statement::Defer(defer_statement) => defer_statement.codegen(compiler),
+            _ => Ok(()),
         }
     }
 }
 
 impl Codegen for PrintStatement {
-    fn codegen(&self, compiler: &mut Compiler) {
-        self.expression.codegen(compiler);
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        self.expression.codegen(compiler)?;
         compiler.emit_bytes(&[Opcode::Print]);
+        Ok(())
     }
 }
 
 impl Codegen for FnStatement {
-    fn codegen(&self, compiler: &mut Compiler) {
-        let jmp_idx = compiler.emit_bytes(&[Opcode::Jmp(0xFFFF)]);
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        let owner = format!("function '{}'", self.name);
+        let limits = compiler.limits;
+        check_limit(
+            &owner,
+            self.arguments.len(),
+            "parameters",
+            limits.parameters,
+            self.span,
+        )?;
+        check_limit(&owner, self.slots, "locals", limits.locals, self.span)?;
 
-        compiler.functions.insert(self.name.clone(), jmp_idx);
+        let end = compiler.new_label();
+        compiler.emit_jump(Opcode::Jmp, end);
 
-        for argument in &self.arguments {
-            compiler.locals.push(argument.clone());
-        }
+        let function = Function {
+            name: self.name.clone(),
+            arity: self.arguments.len(),
+            addr: compiler.bytecode.len(),
+            slots: self.slots,
+        };
+        // A function of the same name before may have been pure, this one
+        // isn't known to be until its code is complete.
+        compiler.pure_functions.remove(&self.name);
+        compiler
+            .functions
+            .insert(self.name.clone(), Arc::new(function));
 
-        compiler.pops[1] = compiler.locals.len();
+        let outer_function = compiler.function.replace(self.name.clone());
+        let outer_scopes = std::mem::take(&mut compiler.scopes);
+        compiler.begin_scope();
+        for (slot, argument) in self.arguments.iter().enumerate() {
+            compiler.declare_local(&argument.name, slot);
+        }
 
+        let outer_deferred = std::mem::take(&mut compiler.deferred);
+        let mut deferred = vec![];
+        // The locals of the body stay in scope for the deferred
+        // expressions.
         if let Statement::Block(block) = &*self.body {
-            block.codegen(compiler);
+            for statement in &block.body {
+                statement.codegen(compiler)?;
+            }
+            deferred.extend(block.body.iter().filter_map(|s| match s {
+                Statement::Defer(defer) => Some(defer),
+                _ => None,
+            }));
         }
 
-        compiler.emit_stack_cleanup();
+        // The deferred expressions in reverse, each returning into the one
+        // before it. Returns jump to the last one reached.
+        compiler.emit_bytes(&[Opcode::Null]);
+        let entries = std::mem::replace(&mut compiler.deferred, outer_deferred);
+        for (defer, entry) in deferred.iter().zip(entries).rev() {
+            compiler.bind_label(entry);
+            defer.expression.codegen(compiler)?;
+            compiler.emit_bytes(&[Opcode::Pop]);
+        }
+        compiler.emit_bytes(&[Opcode::Ret]);
+        compiler.end_scope();
+        compiler.function = outer_function;
+        compiler.scopes = outer_scopes;
+        compiler.bind_label(end);
+        // Calls may be evaluated at compile time from here on.
+        compiler.patch_jumps();
 
-        compiler.emit_bytes(&[Opcode::Null, Opcode::Ret]);
+        if consteval::is_pure(self, &compiler.pure_functions) {
+            compiler.pure_functions.insert(self.name.clone());
+        }
+        Ok(())
+    }
+}
 
-        compiler.bytecode[jmp_idx] = Opcode::Jmp(compiler.bytecode.len() - 1);
+impl Codegen for WhileStatement {
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        let start = compiler.new_label();
+        let end = compiler.new_label();
+        compiler.bind_label(start);
+        self.condition.codegen(compiler)?;
+        compiler.emit_jump(Opcode::Jz, end);
+        compiler.loops.push((self.label.clone(), end, start));
+        self.body.codegen(compiler)?;
+        compiler.loops.pop();
+        compiler.emit_jump(Opcode::Jmp, start);
+        compiler.bind_label(end);
+        Ok(())
+    }
+}
 
-        compiler.locals.clear();
+impl Codegen for ForStatement {
+    /// A `while` with the initializer before it and the increment at the
+    /// end of its body, where `continue` jumps to.
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        compiler.begin_scope();
+        if let Some(initializer) = &self.initializer {
+            discard(initializer, compiler)?;
+        }
+        let start = compiler.new_label();
+        let next = compiler.new_label();
+        let end = compiler.new_label();
+        compiler.bind_label(start);
+        if let Some(condition) = &self.condition {
+            condition.codegen(compiler)?;
+            compiler.emit_jump(Opcode::Jz, end);
+        }
+        compiler.loops.push((self.label.clone(), end, next));
+        self.body.codegen(compiler)?;
+        compiler.loops.pop();
+        compiler.bind_label(next);
+        if let Some(increment) = &self.increment {
+            discard(increment, compiler)?;
+        }
+        compiler.emit_jump(Opcode::Jmp, start);
+        compiler.bind_label(end);
+        compiler.end_scope();
+        Ok(())
     }
 }
 
-impl Codegen for WhileStatement {
-    fn codegen(&self, compiler: &mut Compiler) {
-        let loop_start = compiler.bytecode.len() - 1;
-        self.condition.codegen(compiler);
-        let jz_idx = compiler.emit_bytes(&[Opcode::Jz(0xFFFF)]);
-        self.body.codegen(compiler);
-        compiler.emit_bytes(&[Opcode::Jmp(loop_start)]);
-        compiler.bytecode[jz_idx] = Opcode::Jz(compiler.bytecode.len() - 1);
+impl Codegen for DoWhileStatement {
+    /// The condition comes last and jumps back unless it is false, the
+    /// same test as in `while`.
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        let start = compiler.new_label();
+        let next = compiler.new_label();
+        let end = compiler.new_label();
+        compiler.bind_label(start);
+        compiler.loops.push((self.label.clone(), end, next));
+        self.body.codegen(compiler)?;
+        compiler.loops.pop();
+        compiler.bind_label(next);
+        self.condition.codegen(compiler)?;
+        compiler.emit_jump(Opcode::Jz, end);
+        compiler.emit_jump(Opcode::Jmp, start);
+        compiler.bind_label(end);
+        Ok(())
+    }
+}
+
+impl Codegen for BreakStatement {
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        let (end, _) = compiler.find_loop(&self.label);
+        compiler.emit_jump(Opcode::Jmp, end);
+        Ok(())
+    }
+}
+
+impl Codegen for ContinueStatement {
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        let (_, next) = compiler.find_loop(&self.label);
+        compiler.emit_jump(Opcode::Jmp, next);
+        Ok(())
     }
 }
 
 impl Codegen for ExpressionStatement {
-    fn codegen(&self, compiler: &mut Compiler) {
-        match &self.expression {
-            Expression::Call(call_expr) => {
-                call_expr.codegen(compiler);
-                compiler.emit_bytes(&[Opcode::Pop]);
-            }
-            Expression::Assign(assign_expr) => assign_expr.codegen(compiler),
-            _ => unimplemented!(),
-        }
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        discard(&self.expression, compiler)
     }
 }
 
-impl Codegen for ReturnStatement {
-    fn codegen(&self, compiler: &mut Compiler) {
-        self.expression.codegen(compiler);
-        let mut deepset_no = compiler.locals.len() - 1;
-        for _ in 0..compiler.locals.len() {
-            compiler.emit_bytes(&[Opcode::Deepset(deepset_no)]);
-            deepset_no = deepset_no.saturating_sub(1);
+/// Evaluates `expression` for its effect only, leaving nothing on the stack.
+fn discard(expression: &Expression, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+    match expression {
+        Expression::Assign(assign_expr) => assign_expr.store(compiler, false),
+        expression => {
+            expression.codegen(compiler)?;
+            compiler.emit_bytes(&[Opcode::Pop]);
+            Ok(())
         }
-        compiler.emit_bytes(&[Opcode::Ret]);
     }
 }
 
-impl Codegen for IfStatement {
-    fn codegen(&self, compiler: &mut Compiler) {
-        self.condition.codegen(compiler);
+impl Codegen for ReturnStatement {
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        self.expression.codegen(compiler)?;
+        compiler.emit_return();
+        Ok(())
+    }
+}
 
-        let jz_idx = compiler.emit_bytes(&[Opcode::Jz(0xFFFF)]);
-        self.if_branch.codegen(compiler);
-        compiler.bytecode[jz_idx] = Opcode::Jz(compiler.bytecode.len() - 1);
+impl Codegen for DeferStatement {
+    /// Only marks the point from which returns run the expression, which is
+    /// emitted at the end of the function.
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        let entry = compiler.new_label();
+        compiler.deferred.push(entry);
+        Ok(())
+    }
+}
 
-        let else_idx = compiler.emit_bytes(&[Opcode::Jmp(0xFFFF)]);
-        self.else_branch.codegen(compiler);
-        compiler.bytecode[else_idx] = Opcode::Jmp(compiler.bytecode.len() - 1);
+impl Codegen for IfStatement {
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        let end = compiler.new_label();
+        for (condition, body) in &self.branches {
+            let next = compiler.new_label();
+            condition.codegen(compiler)?;
+            compiler.emit_jump(Opcode::Jz, next);
+            body.codegen(compiler)?;
+            compiler.emit_jump(Opcode::Jmp, end);
+            compiler.bind_label(next);
+        }
+        if let Some(else_body) = &self.else_body {
+            else_body.codegen(compiler)?;
+        }
+        compiler.bind_label(end);
+        Ok(())
     }
 }
 
 impl Codegen for BlockStatement {
-    fn codegen(&self, compiler: &mut Compiler) {
-        compiler.depth += 1;
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        compiler.begin_scope();
         for statement in &self.body {
-            statement.codegen(compiler);
+            statement.codegen(compiler)?;
         }
-        compiler.emit_stack_cleanup();
-        compiler.depth -= 1;
+        compiler.end_scope();
+        Ok(())
     }
 }
 
 impl Codegen for Expression {
-    fn codegen(&self, compiler: &mut Compiler) {
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
         match self {
             Expression::Binary(binexp) => binexp.codegen(compiler),
             Expression::Literal(literal) => literal.codegen(compiler),
@@ -205,87 +772,301 @@ impl Codegen for Expression {
             Expression::Call(call) => call.codegen(compiler),
             Expression::Assign(assignment) => assignment.codegen(compiler),
             Expression::Unary(unary) => unary.codegen(compiler),
+            Expression::Index(index) => index.codegen(compiler),
+            Expression::TypeTest(type_test) => type_test.codegen(compiler),
         }
     }
 }
 
+impl Codegen for IndexExpression {
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        self.expr.codegen(compiler)?;
+        self.index.codegen(compiler)?;
+        match &self.end {
+            Some(end) => {
+                end.codegen(compiler)?;
+                compiler.emit_bytes(&[Opcode::Slice]);
+            }
+            None => {
+                compiler.emit_bytes(&[Opcode::Index]);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Codegen for TypeTestExpression {
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        self.expr.codegen(compiler)?;
+        // Unknown names were reported by the type checker.
+        let ty = Type::from_name(&self.type_name.name).unwrap_or(Type::Any);
+        compiler.emit_bytes(&[Opcode::TypeTest(ty)]);
+        Ok(())
+    }
+}
+
 impl Codegen for UnaryExpression {
-    fn codegen(&self, compiler: &mut Compiler) {
-        self.expr.codegen(compiler);
-        compiler.emit_bytes(&[Opcode::Not]);
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        match self.kind {
+            UnaryExpressionKind::Not => {
+                self.expr.codegen(compiler)?;
+                compiler.emit_bytes(&[Opcode::Not]);
+            }
+            // Negative numbers are constants of their own, as they were
+            // when the sign was part of the number.
+            UnaryExpressionKind::Neg => match &*self.expr {
+                Expression::Literal(LiteralExpression {
+                    value: Literal::Num(n),
+                    ..
+                }) => {
+                    compiler.emit_constant((-n).into())?;
+                }
+                expr => {
+                    expr.codegen(compiler)?;
+                    compiler.emit_at(Opcode::Neg, expr.span());
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+impl AssignExpression {
+    /// Stores the value, leaving it on the stack when `keep` is set.
+    fn store(&self, compiler: &mut Compiler, keep: bool) -> Result<(), Diagnostic> {
+        // `a[i][j] = v` evaluates `i`, `j` and `v` and then updates `a` in
+        // place.
+        let mut indices = vec![];
+        let mut target = &*self.lhs;
+        while let Expression::Index(index) = target {
+            indices.push(&index.index);
+            target = &index.expr;
+        }
+        let variable = match target {
+            Expression::Variable(variable) => variable,
+            _ => unreachable!("invalid assignment target"),
+        };
+        let slot = variable.slot.expect("unresolved variable");
+        for index in indices.iter().rev() {
+            index.codegen(compiler)?;
+        }
+        match &*self.rhs {
+            Expression::Call(call) if indices.is_empty() && call.moves(compiler, slot) => {
+                call.codegen_moving(compiler)?
+            }
+            rhs => rhs.codegen(compiler)?,
+        }
+        match (indices.len(), keep) {
+            (0, false) => compiler.emit_bytes(&[Opcode::SetLocal(operand(slot)?)]),
+            (0, true) => compiler.emit_bytes(&[
+                Opcode::SetLocal(operand(slot)?),
+                Opcode::GetLocal(operand(slot)?),
+            ]),
+            (n, false) => {
+                compiler.emit_bytes(&[Opcode::SetIndex(operand(slot)?, operand(n)?), Opcode::Pop])
+            }
+            (n, true) => compiler.emit_bytes(&[Opcode::SetIndex(operand(slot)?, operand(n)?)]),
+        };
+        if indices.is_empty() {
+            compiler.declare_local(&variable.value, slot);
+        }
+        Ok(())
     }
 }
 
 impl Codegen for AssignExpression {
-    fn codegen(&self, compiler: &mut Compiler) {
-        let variable_name = match &*self.lhs {
-            Expression::Variable(variable) => &variable.value,
-            _ => unimplemented!(),
+    /// An assignment evaluates to the assigned value.
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        self.store(compiler, true)
+    }
+}
+
+impl CallExpression {
+    /// Whether the call is of a builtin with the variable in `slot` as
+    /// its first argument and not in the others, `q = list.push(q, x)`. The
+    /// variable is overwritten with the result, so it can be moved into the
+    /// call, and a builtin changes what it was the only one holding in
+    /// place instead of copying it.
+    fn moves(&self, compiler: &Compiler, slot: usize) -> bool {
+        let builtin = match &*self.callee {
+            Expression::Variable(callee) => {
+                callee.slot.is_none() && !compiler.functions.contains_key(&callee.value)
+            }
+            _ => false,
         };
-        self.rhs.codegen(compiler);
+        match self.arguments.split_first() {
+            Some((Expression::Variable(first), rest)) => {
+                builtin && first.slot == Some(slot) && !rest.iter().any(|a| reads(a, slot))
+            }
+            _ => false,
+        }
+    }
 
-        let local = compiler.resolve_local(variable_name.clone());
+    /// Compiles a call [`moves`](Self::moves) allows, moving the first
+    /// argument.
+    fn codegen_moving(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        let Expression::Variable(callee) = &*self.callee else {
+            unreachable!("not a builtin call");
+        };
+        let builtin = builtins::lookup(&callee.value).expect("unresolved function");
+        let (Expression::Variable(first), rest) = self.arguments.split_first().unwrap() else {
+            unreachable!("first argument not a variable");
+        };
+        self.check_arguments(compiler)?;
+        compiler.emit_bytes(&[Opcode::Take(operand(first.slot.unwrap())?)]);
+        for argument in rest {
+            argument.codegen(compiler)?;
+        }
+        compiler.emit_bytes(&[Opcode::Native(
+            operand(builtin)?,
+            operand(self.arguments.len())?,
+        )]);
+        Ok(())
+    }
 
-        if let Some(idx) = local {
-            compiler.emit_bytes(&[Opcode::Deepset(idx)]);
-        } else {
-            compiler.locals.push(variable_name.clone());
-            compiler.pops[compiler.depth] += 1;
+    fn check_arguments(&self, compiler: &Compiler) -> Result<(), Diagnostic> {
+        let limit = compiler.limits.arguments;
+        check_limit("call", self.arguments.len(), "arguments", limit, self.span)
+    }
+}
+
+/// Whether `expression` may read the local in `slot`.
+fn reads(expression: &Expression, slot: usize) -> bool {
+    match expression {
+        Expression::Literal(_) => false,
+        Expression::Variable(e) => e.slot == Some(slot),
+        Expression::Call(e) => reads(&e.callee, slot) || e.arguments.iter().any(|a| reads(a, slot)),
+        Expression::Assign(e) => reads(&e.lhs, slot) || reads(&e.rhs, slot),
+        Expression::Binary(e) => reads(&e.lhs, slot) || reads(&e.rhs, slot),
+        Expression::Unary(e) => reads(&e.expr, slot),
+        Expression::Index(e) => {
+            reads(&e.expr, slot)
+                || reads(&e.index, slot)
+                || e.end.as_ref().is_some_and(|end| reads(end, slot))
         }
+        Expression::TypeTest(e) => reads(&e.expr, slot),
     }
 }
 
 impl Codegen for CallExpression {
-    fn codegen(&self, compiler: &mut Compiler) {
-        for argument in &self.arguments {
-            argument.codegen(compiler);
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        self.check_arguments(compiler)?;
+        // Calls of functions by name are resolved at compile time, anything
+        // else is a function object on the stack.
+        let name = match &*self.callee {
+            Expression::Variable(variable) if variable.slot.is_none() => &variable.value,
+            callee => {
+                callee.codegen(compiler)?;
+                for argument in &self.arguments {
+                    argument.codegen(compiler)?;
+                }
+                compiler.emit_bytes(&[Opcode::Call(operand(self.arguments.len())?)]);
+                return Ok(());
+            }
+        };
+
+        let Some(function) = compiler.functions.get(name).cloned() else {
+            let builtin = builtins::lookup(name).expect("unresolved function");
+            for argument in &self.arguments {
+                argument.codegen(compiler)?;
+            }
+            compiler.emit_bytes(&[Opcode::Native(
+                operand(builtin)?,
+                operand(self.arguments.len())?,
+            )]);
+            return Ok(());
+        };
+
+        if compiler.opt_level >= OptLevel::O2 && compiler.pure_functions.contains(name) {
+            let arguments: Option<Vec<_>> = self
+                .arguments
+                .iter()
+                .map(|argument| match argument {
+                    Expression::Literal(literal) => Some(consteval::literal(&literal.value)),
+                    _ => None,
+                })
+                .collect();
+            let result = arguments.and_then(|arguments| {
+                consteval::evaluate(
+                    &compiler.bytecode,
+                    &compiler.constants,
+                    function.addr,
+                    function.slots,
+                    arguments,
+                )
+            });
+            if let Some(result) = result {
+                compiler.emit_constant(result)?;
+                return Ok(());
+            }
         }
 
-        let jmp_addr = compiler.functions.get(&self.variable).unwrap();
+        for argument in &self.arguments {
+            argument.codegen(compiler)?;
+        }
 
-        compiler.emit_bytes(&[Opcode::Invoke(self.arguments.len()), Opcode::Jmp(*jmp_addr)]);
+        let call = compiler.bytecode.len() + 1;
+        compiler.emit_bytes(&[
+            Opcode::Invoke(operand(self.arguments.len())?, operand(function.slots)?),
+            Opcode::Jmp(jump_offset(call, function.addr)),
+        ]);
+        Ok(())
     }
 }
 
 impl Codegen for BinaryExpression {
-    fn codegen(&self, compiler: &mut Compiler) {
-        self.lhs.codegen(compiler);
-        self.rhs.codegen(compiler);
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        self.lhs.codegen(compiler)?;
+        self.rhs.codegen(compiler)?;
 
+        let span = Span {
+            start: self.lhs.span().start,
+            end: self.rhs.span().end,
+        };
         match self.kind {
             BinaryExpressionKind::Add => {
-                compiler.emit_bytes(&[Opcode::Add]);
+                compiler.emit_at(Opcode::Add, span);
             }
             BinaryExpressionKind::Sub => {
-                compiler.emit_bytes(&[Opcode::Sub]);
+                compiler.emit_at(Opcode::Sub, span);
             }
             BinaryExpressionKind::Mul => {
-                compiler.emit_bytes(&[Opcode::Mul]);
+                compiler.emit_at(Opcode::Mul, span);
             }
             BinaryExpressionKind::Div => {
-                compiler.emit_bytes(&[Opcode::Div]);
+                compiler.emit_at(Opcode::Div, span);
             }
             BinaryExpressionKind::Less => {
-                compiler.emit_bytes(&[Opcode::Less]);
+                compiler.emit_at(Opcode::Less, span);
+            }
+            BinaryExpressionKind::LessEqual => {
+                compiler.emit_at(Opcode::LessEqual, span);
             }
-            BinaryExpressionKind::Equality(negation) => {
+            BinaryExpressionKind::Greater => {
+                compiler.emit_at(Opcode::Greater, span);
+            }
+            BinaryExpressionKind::GreaterEqual => {
+                compiler.emit_at(Opcode::GreaterEqual, span);
+            }
+            BinaryExpressionKind::Eq => {
                 compiler.emit_bytes(&[Opcode::Eq]);
-                if negation {
-                    compiler.emit_bytes(&[Opcode::Not]);
-                }
+            }
+            BinaryExpressionKind::NotEq => {
+                compiler.emit_bytes(&[Opcode::Neq]);
             }
             BinaryExpressionKind::Strcat => {
-                compiler.emit_bytes(&[Opcode::Strcat]);
+                compiler.emit_at(Opcode::Strcat, span);
             }
         }
+        Ok(())
     }
 }
 
 impl Codegen for LiteralExpression {
-    fn codegen(&self, compiler: &mut Compiler) {
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
         match &self.value {
             Literal::Num(n) => {
-                compiler.emit_bytes(&[Opcode::Const(*n)]);
+                compiler.emit_constant((*n).into())?;
             }
             Literal::Bool(b) => match b {
                 true => {
@@ -296,25 +1077,30 @@ impl Codegen for LiteralExpression {
                 }
             },
             Literal::String(s) => {
-                compiler.emit_bytes(&[Opcode::Str(s.clone().into())]);
+                compiler.emit_constant(s.clone().into())?;
+            }
+            Literal::Bytes(b) => {
+                compiler.emit_constant(Object::Bytes(b.clone().into()))?;
             }
             Literal::Null => {
                 compiler.emit_bytes(&[Opcode::Null]);
             }
         }
+        Ok(())
     }
 }
 
 impl Codegen for VariableExpression {
-    fn codegen(&self, compiler: &mut Compiler) {
-        let local = compiler.resolve_local(self.value.clone());
-
-        if let Some(idx) = local {
-            compiler.emit_bytes(&[Opcode::Deepget(idx)]);
-        } else {
-            compiler.locals.push(self.value.clone());
-            let idx = compiler.resolve_local(self.value.clone()).unwrap();
-            compiler.emit_bytes(&[Opcode::Deepget(idx)]);
+    fn codegen(&self, compiler: &mut Compiler) -> Result<(), Diagnostic> {
+        match self.slot {
+            Some(slot) => {
+                compiler.emit_bytes(&[Opcode::GetLocal(operand(slot)?)]);
+            }
+            None => {
+                let function = compiler.functions[&self.value].clone();
+                compiler.emit_constant(Object::Function(function))?;
+            }
         }
+        Ok(())
     }
 }
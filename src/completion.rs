@@ -0,0 +1,203 @@
+//! Where the cursor is in a program being typed, for an editor to rank
+//! completions. The program is usually incomplete around the cursor, so
+//! instead of parsing it the tokens in front of the cursor are scanned for
+//! the brackets still open.
+//!
+//! ```
+//! use reaper::completion::{context, Context};
+//!
+//! let src = "print max_of_list(xs, len(";
+//! let completion = context(src, src.len());
+//! assert_eq!(
+//!     completion.context,
+//!     Context::Argument {
+//!         function: "len".to_string(),
+//!         index: 0
+//!     }
+//! );
+//! ```
+
+use crate::tokenizer::{Token, TokenKind, Tokenizer, TriviaKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Context {
+    /// Where a statement starts: keywords and names.
+    Statement,
+    /// Inside an expression: names.
+    Expression,
+    /// Inside the arguments of a call of `function`, at the argument
+    /// `index`: names, those of the parameter's type first.
+    Argument { function: String, index: usize },
+    /// After the `.` of `object.`, `math.`: the builtins of the namespace.
+    Member { object: String },
+    /// After the `:` of an annotation or after `is`: type names.
+    Type,
+    /// Naming a new function or parameter, nothing to complete.
+    Declaration,
+    /// In a string or a comment.
+    None,
+}
+
+/// The context of a cursor and the name being typed there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub context: Context,
+    /// The part of the name in front of the cursor.
+    pub prefix: String,
+    /// Offset of the prefix, the start of what a completion replaces.
+    pub start: usize,
+}
+
+/// A bracket open in front of the cursor.
+enum Open {
+    Call {
+        function: String,
+        arguments: usize,
+    },
+    Parameters,
+    /// Other parentheses, around conditions.
+    Paren,
+    Bracket,
+    Brace,
+}
+
+/// The completion context at byte `offset` of `src`. Only the text in
+/// front of the cursor is looked at.
+pub fn context(src: &str, offset: usize) -> Completion {
+    let src = &src[..offset];
+    let mut tokens: Vec<Token> = Tokenizer::with_trivia(src).collect();
+    let eof = tokens.pop().expect("the tokenizer ends with Eof");
+    let in_comment = eof
+        .leading_trivia
+        .last()
+        .is_some_and(|t| t.kind == TriviaKind::Comment && t.span.end == offset);
+    if in_comment || in_string(src) {
+        return Completion {
+            context: Context::None,
+            prefix: String::new(),
+            start: offset,
+        };
+    }
+
+    let mut start = offset;
+    let mut prefix = String::new();
+    if let Some(last) = tokens.last() {
+        if last.span.end == offset && eof.leading_trivia.is_empty() && is_word(&last.value) {
+            start = last.span.start;
+            prefix = last.value.clone();
+            tokens.pop();
+        }
+    }
+
+    let mut open = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::LeftParen => {
+                let kinds = |back: usize| i.checked_sub(back).map(|j| tokens[j].kind);
+                open.push(match (kinds(2), kinds(1)) {
+                    (Some(TokenKind::Fn), Some(TokenKind::Identifier)) => Open::Parameters,
+                    (_, Some(TokenKind::Identifier)) => Open::Call {
+                        function: dotted_name(&tokens[..i]),
+                        arguments: 0,
+                    },
+                    _ => Open::Paren,
+                });
+            }
+            TokenKind::LeftBracket => open.push(Open::Bracket),
+            TokenKind::LeftBrace => open.push(Open::Brace),
+            // Unbalanced closing brackets are ignored, not the ones in
+            // front of them.
+            TokenKind::RightParen | TokenKind::RightBracket | TokenKind::RightBrace => {
+                open.pop();
+            }
+            TokenKind::Comma => {
+                if let Some(Open::Call { arguments, .. }) = open.last_mut() {
+                    *arguments += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let last = tokens.last().map(|t| t.kind);
+    let context = match (open.last(), last) {
+        (_, Some(TokenKind::Dot)) => Context::Member {
+            object: dotted_name(&tokens[..tokens.len() - 1]),
+        },
+        (_, Some(TokenKind::Colon | TokenKind::Is)) => Context::Type,
+        (_, Some(TokenKind::Fn)) => Context::Declaration,
+        (Some(Open::Parameters), Some(TokenKind::LeftParen | TokenKind::Comma)) => {
+            Context::Declaration
+        }
+        (
+            Some(Open::Call {
+                function,
+                arguments,
+            }),
+            _,
+        ) => Context::Argument {
+            function: function.clone(),
+            index: *arguments,
+        },
+        (
+            None | Some(Open::Brace),
+            None
+            | Some(
+                TokenKind::Semicolon
+                | TokenKind::LeftBrace
+                | TokenKind::RightBrace
+                | TokenKind::Else
+                | TokenKind::Do,
+            ),
+        ) => Context::Statement,
+        _ => Context::Expression,
+    };
+    Completion {
+        context,
+        prefix,
+        start,
+    }
+}
+
+/// Whether `value` is a name or keyword, rather than a number or symbol.
+fn is_word(value: &str) -> bool {
+    value
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+}
+
+/// The name `a.b.c` that `tokens` end with, empty if they don't end with
+/// one.
+fn dotted_name(tokens: &[Token]) -> String {
+    let mut parts = vec![];
+    let mut rest = tokens;
+    while let [before @ .., last] = rest {
+        if last.kind != TokenKind::Identifier {
+            break;
+        }
+        parts.push(last.value.as_str());
+        match before {
+            [before @ .., dot] if dot.kind == TokenKind::Dot => rest = before,
+            _ => break,
+        }
+    }
+    parts.reverse();
+    parts.join(".")
+}
+
+/// Whether the last line of `src` ends in a string, which can't span
+/// lines.
+fn in_string(src: &str) -> bool {
+    let line = src.rsplit('\n').next().unwrap_or_default();
+    let mut inside = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => inside = !inside,
+            '/' if !inside && chars.peek() == Some(&'/') => return false,
+            _ => {}
+        }
+    }
+    inside
+}
@@ -0,0 +1,128 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::compiler::{self, Opcode};
+use crate::parser::{Expression, FnStatement, Literal, Statement};
+use crate::program::Program;
+use crate::vm::{Object, VM};
+
+/// Instruction budget for evaluating one call at compile time.
+pub const FOLD_FUEL: usize = 1_000_000;
+
+/// A function is pure when its result depends only on its arguments: it
+/// neither prints nor calls functions that aren't pure themselves.
+pub fn is_pure(function: &FnStatement, pure: &HashSet<String>) -> bool {
+    statement_is_pure(&function.body, &function.name, pure)
+}
+
+fn statement_is_pure(statement: &Statement, name: &str, pure: &HashSet<String>) -> bool {
+    match statement {
+        Statement::Print(_) => false,
+        // Nested declarations are only jumped over.
+        Statement::Fn(_) | Statement::Dummy => true,
+        Statement::Break(_) | Statement::Continue(_) => true,
+        Statement::Expression(s) => expression_is_pure(&s.expression, name, pure),
+        Statement::Return(s) => expression_is_pure(&s.expression, name, pure),
+        Statement::Defer(s) => expression_is_pure(&s.expression, name, pure),
+        Statement::If(s) => {
+            s.branches.iter().all(|(condition, body)| {
+                expression_is_pure(condition, name, pure) && statement_is_pure(body, name, pure)
+            }) && s
+                .else_body
+                .as_ref()
+                .is_none_or(|body| statement_is_pure(body, name, pure))
+        }
+        Statement::Block(s) => s.body.iter().all(|s| statement_is_pure(s, name, pure)),
+        Statement::While(s) => {
+            expression_is_pure(&s.condition, name, pure) && statement_is_pure(&s.body, name, pure)
+        }
+        Statement::For(s) => {
+            [&s.initializer, &s.condition, &s.increment]
+                .into_iter()
+                .flatten()
+                .all(|e| expression_is_pure(e, name, pure))
+                && statement_is_pure(&s.body, name, pure)
+        }
+        Statement::DoWhile(s) => {
+            statement_is_pure(&s.body, name, pure) && expression_is_pure(&s.condition, name, pure)
+        }
+    }
+}
+
+fn expression_is_pure(expression: &Expression, name: &str, pure: &HashSet<String>) -> bool {
+    match expression {
+        Expression::Literal(_) | Expression::Variable(_) => true,
+        Expression::Binary(e) => {
+            expression_is_pure(&e.lhs, name, pure) && expression_is_pure(&e.rhs, name, pure)
+        }
+        Expression::Call(e) => {
+            let callee = match &*e.callee {
+                Expression::Variable(v) if v.slot.is_none() => &v.value,
+                // Function objects could be anything.
+                _ => return false,
+            };
+            (callee == name || pure.contains(callee))
+                && e.arguments
+                    .iter()
+                    .all(|a| expression_is_pure(a, name, pure))
+        }
+        Expression::Assign(e) => {
+            expression_is_pure(&e.lhs, name, pure) && expression_is_pure(&e.rhs, name, pure)
+        }
+        Expression::Unary(e) => expression_is_pure(&e.expr, name, pure),
+        Expression::TypeTest(e) => expression_is_pure(&e.expr, name, pure),
+        Expression::Index(e) => {
+            expression_is_pure(&e.expr, name, pure)
+                && expression_is_pure(&e.index, name, pure)
+                && e.end
+                    .as_ref()
+                    .is_none_or(|end| expression_is_pure(end, name, pure))
+        }
+    }
+}
+
+/// Calls the function at `addr` with `slots` stack slots in `bytecode` with
+/// constant `arguments` on a scratch VM. `None` when the call fails or runs
+/// out of fuel, in which case the call is left for runtime.
+pub fn evaluate(
+    bytecode: &[Opcode],
+    constants: &[Object],
+    addr: usize,
+    slots: usize,
+    arguments: Vec<Object>,
+) -> Option<Object> {
+    let mut code = bytecode.to_vec();
+    let mut constants = constants.to_vec();
+    let entry = code.len();
+    let argc = arguments.len();
+    for argument in arguments {
+        code.push(Opcode::Const(constants.len().try_into().ok()?));
+        constants.push(argument);
+    }
+    code.extend([
+        Opcode::Invoke(argc.try_into().ok()?, slots.try_into().ok()?),
+        Opcode::Jmp(compiler::jump_offset(code.len() + 1, addr)),
+    ]);
+    // The script around the call is still being compiled, with jumps yet
+    // to be patched, skip it for the checks of `Program::new`. Its first
+    // instruction is never the start of a function.
+    if entry > 0 {
+        code[0] = Opcode::Jmp(compiler::jump_offset(0, entry));
+    }
+
+    let mut vm = VM::new();
+    vm.load(Arc::new(
+        Program::new(code, constants, HashMap::new()).ok()?,
+    ));
+    vm.run_with_fuel(entry, FOLD_FUEL).ok()
+}
+
+pub fn literal(literal: &Literal) -> Object {
+    match literal {
+        Literal::Num(n) => Object::Number(*n),
+        Literal::Bool(b) => Object::Bool(*b),
+        Literal::String(s) => s.clone().into(),
+        Literal::Bytes(b) => Object::Bytes(b.clone().into()),
+        Literal::Null => Object::Null,
+    }
+}
@@ -0,0 +1,228 @@
+//! Conversions between [`Object`]s and Rust values, for host code passing
+//! values to and reading results from scripts.
+//!
+//! ```
+//! use reaper::{FromObject, Object};
+//!
+//! let point: Object = vec![1.0, 2.0].into();
+//! let (x, y) = <(f64, f64)>::from_object(point).unwrap();
+//! assert_eq!((x, y), (1.0, 2.0));
+//! ```
+
+use std::sync::Arc;
+
+use crate::native::Native;
+use crate::vm::{Object, RuntimeError};
+
+fn mismatch(expected: &str, found: &Object) -> RuntimeError {
+    RuntimeError {
+        message: format!("Expected {}, found {}.", expected, found.type_name()),
+    }
+}
+
+impl From<i64> for Object {
+    fn from(value: i64) -> Self {
+        Self::Number(value as f64)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(value: &str) -> Self {
+        value.to_string().into()
+    }
+}
+
+impl<T: Into<Object>> From<Vec<T>> for Object {
+    fn from(value: Vec<T>) -> Self {
+        Self::List(Arc::new(value.into_iter().map(Into::into).collect()))
+    }
+}
+
+impl From<Native> for Object {
+    fn from(value: Native) -> Self {
+        Self::Native(value)
+    }
+}
+
+/// `null` for `None`.
+impl<T: Into<Object>> From<Option<T>> for Object {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Object::Null, Into::into)
+    }
+}
+
+impl TryFrom<Object> for f64 {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<Self, Self::Error> {
+        match object {
+            Object::Number(n) => Ok(n),
+            _ => Err(mismatch("num", &object)),
+        }
+    }
+}
+
+/// Numbers without a fractional part that fit.
+impl TryFrom<Object> for i64 {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<Self, Self::Error> {
+        match object {
+            // The upper bound itself doesn't fit, it is 2^63.
+            Object::Number(n)
+                if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 =>
+            {
+                Ok(n as i64)
+            }
+            Object::Number(n) => Err(RuntimeError {
+                message: format!("Expected an integer, found {}.", n),
+            }),
+            _ => Err(mismatch("num", &object)),
+        }
+    }
+}
+
+impl TryFrom<Object> for bool {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<Self, Self::Error> {
+        match object {
+            Object::Bool(b) => Ok(b),
+            _ => Err(mismatch("bool", &object)),
+        }
+    }
+}
+
+impl TryFrom<Object> for String {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<Self, Self::Error> {
+        match object {
+            Object::String(s) => Ok(*s),
+            _ => Err(mismatch("str", &object)),
+        }
+    }
+}
+
+impl TryFrom<Object> for Vec<u8> {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<Self, Self::Error> {
+        match object {
+            Object::Bytes(b) => Ok(*b),
+            _ => Err(mismatch("bytes", &object)),
+        }
+    }
+}
+
+/// Takes the items without copying, copies them if the list is shared.
+impl TryFrom<Object> for Vec<Object> {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<Self, Self::Error> {
+        match object {
+            Object::List(l) => Ok(match Arc::try_unwrap(l) {
+                Ok(l) => l.into_items().into(),
+                Err(l) => l.items().iter().cloned().collect(),
+            }),
+            _ => Err(mismatch("list", &object)),
+        }
+    }
+}
+
+impl TryFrom<Object> for Native {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<Self, Self::Error> {
+        match object {
+            Object::Native(n) => Ok(n),
+            _ => Err(mismatch("a native object", &object)),
+        }
+    }
+}
+
+/// Rust values a script value converts to, also where the conversion
+/// nests: lists of them, `null` for `None` and tuples from lists of the
+/// same length. Host structs implement it on top of a tuple:
+///
+/// ```
+/// use reaper::{FromObject, Object, RuntimeError};
+///
+/// struct User {
+///     name: String,
+///     age: i64,
+/// }
+///
+/// impl FromObject for User {
+///     fn from_object(object: Object) -> Result<Self, RuntimeError> {
+///         let (name, age) = FromObject::from_object(object)?;
+///         Ok(User { name, age })
+///     }
+/// }
+///
+/// let user = User::from_object(vec![Object::from("ann"), 37.into()].into()).unwrap();
+/// assert_eq!((user.name.as_str(), user.age), ("ann", 37));
+/// ```
+pub trait FromObject: Sized {
+    fn from_object(object: Object) -> Result<Self, RuntimeError>;
+}
+
+impl FromObject for Object {
+    fn from_object(object: Object) -> Result<Self, RuntimeError> {
+        Ok(object)
+    }
+}
+
+macro_rules! from_object_by_try_from {
+    ($($ty:ty),*) => {
+        $(
+            impl FromObject for $ty {
+                fn from_object(object: Object) -> Result<Self, RuntimeError> {
+                    object.try_into()
+                }
+            }
+        )*
+    };
+}
+
+from_object_by_try_from!(f64, i64, bool, String, Native);
+
+impl<T: FromObject> FromObject for Vec<T> {
+    fn from_object(object: Object) -> Result<Self, RuntimeError> {
+        Vec::<Object>::try_from(object)?
+            .into_iter()
+            .map(T::from_object)
+            .collect()
+    }
+}
+
+impl<T: FromObject> FromObject for Option<T> {
+    fn from_object(object: Object) -> Result<Self, RuntimeError> {
+        match object {
+            Object::Null => Ok(None),
+            object => T::from_object(object).map(Some),
+        }
+    }
+}
+
+macro_rules! from_object_for_tuple {
+    ($n:literal: $($name:ident),*) => {
+        impl<$($name: FromObject),*> FromObject for ($($name,)*) {
+            fn from_object(object: Object) -> Result<Self, RuntimeError> {
+                let items = Vec::<Object>::try_from(object)?;
+                if items.len() != $n {
+                    return Err(RuntimeError {
+                        message: format!("Expected a list of {} items, found {}.", $n, items.len()),
+                    });
+                }
+                let mut items = items.into_iter();
+                Ok(($($name::from_object(items.next().unwrap())?,)*))
+            }
+        }
+    };
+}
+
+from_object_for_tuple!(1: A);
+from_object_for_tuple!(2: A, B);
+from_object_for_tuple!(3: A, B, C);
+from_object_for_tuple!(4: A, B, C, D);
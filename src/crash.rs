@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+
+use crate::util::json_string;
+use crate::vm::{RuntimeError, VM};
+
+/// Instructions shown on each side of the failing one.
+const CODE_WINDOW: usize = 5;
+/// Values shown from the top of the stack.
+const STACK_VALUES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+/// What the VM looked like when a runtime error escaped the script, for
+/// users to attach when they report a failing script.
+#[derive(Debug, PartialEq)]
+pub struct CrashReport {
+    pub error: String,
+    /// Function names and instruction addresses, innermost first.
+    pub trace: Vec<(String, usize)>,
+    pub ip: usize,
+    /// Disassembled instructions around `ip`, by address.
+    pub code: Vec<(usize, String)>,
+    /// Values at the top of the stack, topmost first.
+    pub stack: Vec<String>,
+    /// Named arguments and locals of the failing function, by slot.
+    pub locals: Vec<(String, String)>,
+}
+
+impl CrashReport {
+    /// Takes the report from `vm`, which trapped with `error`.
+    pub fn new(vm: &VM, error: &RuntimeError) -> CrashReport {
+        let frames = vm.frames();
+        let trace = frames
+            .iter()
+            .rev()
+            .map(|frame| {
+                let name = frame.function.map_or("<script>", |f| &f.name);
+                (name.to_string(), frame.ip)
+            })
+            .collect();
+        let code = vm.program().map_or(&[][..], |p| p.code());
+        let start = vm.ip().saturating_sub(CODE_WINDOW);
+        let end = (vm.ip() + CODE_WINDOW + 1).min(code.len());
+        CrashReport {
+            error: error.message.clone(),
+            trace,
+            ip: vm.ip(),
+            code: (start..end)
+                .map(|at| (at, format!("{:?}", code[at])))
+                .collect(),
+            stack: vm
+                .stack()
+                .iter()
+                .rev()
+                .take(STACK_VALUES)
+                .map(|value| format!("{:?}", value))
+                .collect(),
+            locals: frames.last().map_or(vec![], |frame| {
+                frame
+                    .names
+                    .iter()
+                    .zip(frame.values)
+                    .filter_map(|(name, value)| {
+                        Some(((*name)?.to_string(), format!("{:?}", value)))
+                    })
+                    .collect()
+            }),
+        }
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.text(),
+            ReportFormat::Json => self.json(),
+        }
+    }
+
+    fn text(&self) -> String {
+        let mut out = format!("error: {}\n\ntrace:\n", self.error);
+        for (name, ip) in &self.trace {
+            out += &format!("  {} at {}\n", name, ip);
+        }
+        out += "\ncode:\n";
+        for (at, op) in &self.code {
+            let marker = if *at == self.ip { ">" } else { " " };
+            out += &format!("{} {:>5} {}\n", marker, at, op);
+        }
+        out += "\nstack:\n";
+        for value in &self.stack {
+            out += &format!("  {}\n", value);
+        }
+        out += "\nlocals:\n";
+        for (name, value) in &self.locals {
+            out += &format!("  {} = {}\n", name, value);
+        }
+        out
+    }
+
+    fn json(&self) -> String {
+        let trace: Vec<_> = self
+            .trace
+            .iter()
+            .map(|(name, ip)| format!("{{\"function\":{},\"ip\":{}}}", json_string(name), ip))
+            .collect();
+        let code: Vec<_> = self
+            .code
+            .iter()
+            .map(|(at, op)| format!("{{\"ip\":{},\"op\":{}}}", at, json_string(op)))
+            .collect();
+        let stack: Vec<_> = self.stack.iter().map(|v| json_string(v)).collect();
+        let locals: Vec<_> = self
+            .locals
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "{{\"name\":{},\"value\":{}}}",
+                    json_string(name),
+                    json_string(value)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"error\":{},\"trace\":[{}],\"ip\":{},\"code\":[{}],\"stack\":[{}],\"locals\":[{}]}}\n",
+            json_string(&self.error),
+            trace.join(","),
+            self.ip,
+            code.join(","),
+            stack.join(","),
+            locals.join(",")
+        )
+    }
+}
+
+/// Writes crash reports in `format` to the file at `path`, or to stderr.
+#[derive(Debug, Clone)]
+pub struct CrashReporter {
+    pub format: ReportFormat,
+    pub path: Option<PathBuf>,
+}
+
+impl CrashReporter {
+    pub fn report(&self, vm: &VM, error: &RuntimeError) -> Result<(), std::io::Error> {
+        let report = CrashReport::new(vm, error).render(self.format);
+        match &self.path {
+            Some(path) => std::fs::write(path, report),
+            None => {
+                eprint!("{}", report);
+                Ok(())
+            }
+        }
+    }
+}
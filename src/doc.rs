@@ -0,0 +1,117 @@
+//! Reference documentation of script libraries, from the `///` comments
+//! above their functions.
+
+use std::path::Path;
+
+use crate::driver::{Diagnostic, Error};
+use crate::parser::{FnStatement, Parser, Statement, TypeAnnotation};
+use crate::tokenizer::Tokenizer;
+use crate::util::SourceManager;
+
+/// A documented function.
+#[derive(Debug, PartialEq)]
+pub struct FunctionDoc {
+    pub name: String,
+    /// The declaration up to the body, `fn add(a: num, b: num): num`.
+    pub signature: String,
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocFormat {
+    Markdown,
+    Html,
+}
+
+/// The functions declared at the top level of `src`, in source order.
+pub fn extract(src: &str) -> Result<Vec<FunctionDoc>, Vec<Diagnostic>> {
+    let mut parser = Parser::new();
+    let statements = parser.parse(Tokenizer::with_trivia(src).collect());
+    if !parser.errors().is_empty() {
+        return Err(parser.errors().iter().map(Diagnostic::from).collect());
+    }
+    Ok(statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Fn(function) => Some(FunctionDoc {
+                name: function.name.clone(),
+                signature: signature(function),
+                doc: function.doc.clone(),
+            }),
+            _ => None,
+        })
+        .collect())
+}
+
+fn signature(function: &FnStatement) -> String {
+    let annotation = |annotation: &Option<TypeAnnotation>| match annotation {
+        Some(annotation) => format!(": {}", annotation.name),
+        None => String::new(),
+    };
+    let parameters: Vec<_> = function
+        .arguments
+        .iter()
+        .map(|parameter| format!("{}{}", parameter.name, annotation(&parameter.annotation)))
+        .collect();
+    format!(
+        "fn {}({}){}",
+        function.name,
+        parameters.join(", "),
+        annotation(&function.return_type)
+    )
+}
+
+/// Renders `functions` as a page titled `title`.
+pub fn render(title: &str, functions: &[FunctionDoc], format: DocFormat) -> String {
+    match format {
+        DocFormat::Markdown => {
+            let mut out = format!("# {}\n", title);
+            for function in functions {
+                out += &format!("\n## {}\n\n`{}`\n", function.name, function.signature);
+                if let Some(doc) = &function.doc {
+                    out += &format!("\n{}\n", doc);
+                }
+            }
+            out
+        }
+        DocFormat::Html => {
+            let mut out = format!("<h1>{}</h1>\n", html(title));
+            for function in functions {
+                out += &format!(
+                    "<h2 id=\"{0}\">{0}</h2>\n<pre><code>{1}</code></pre>\n",
+                    html(&function.name),
+                    html(&function.signature)
+                );
+                // Paragraphs are separated by empty lines, as in Markdown.
+                for paragraph in function.doc.iter().flat_map(|doc| doc.split("\n\n")) {
+                    out += &format!("<p>{}</p>\n", html(paragraph.trim()));
+                }
+            }
+            out
+        }
+    }
+}
+
+fn html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Documentation of the script at `path`, titled with its file name.
+pub fn document_file(path: &str, format: DocFormat) -> Result<String, Error> {
+    let mut sources = SourceManager::new();
+    let file = sources.load(path)?;
+    let source = sources.get(file);
+    let functions = extract(source.text()).map_err(|mut diagnostics| {
+        for diagnostic in diagnostics.iter_mut() {
+            diagnostic.locate(source, 0);
+        }
+        Error::Diagnostics(diagnostics)
+    })?;
+    let title = Path::new(path)
+        .file_stem()
+        .map_or(path.into(), |stem| stem.to_string_lossy());
+    Ok(render(&title, &functions, format))
+}
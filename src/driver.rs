@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::artifact;
+use crate::compiler::{CompileOptions, Compiler};
+use crate::crash::CrashReporter;
+use crate::fix::Suggestion;
+use crate::parser::{
+    AssignExpression, Expression, ExpressionStatement, ParseError, Parser, Statement,
+    VariableExpression,
+};
+use crate::program::Program;
+use crate::resolver::ResolveError;
+use crate::sandbox::Sandbox;
+use crate::tokenizer::{Span, Tokenizer};
+use crate::trace::Recorder;
+use crate::typecheck::TypeError;
+use crate::util::{json_string, FileId, Location, Source, SourceManager};
+use crate::vm::{Object, RuntimeError, VM};
+
+/// A problem with the source found before the program runs.
+#[derive(Debug)]
+pub struct Diagnostic {
+    /// See [`crate::codes`].
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    /// File name and position of the start of the span, once looked up in
+    /// its [`Source`].
+    pub location: Option<(String, Location)>,
+    /// Fixes `reaper fix` can apply.
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(code: &'static str, message: &str, span: Span) -> Diagnostic {
+        Diagnostic {
+            code,
+            message: message.to_string(),
+            span,
+            location: None,
+            suggestions: vec![],
+        }
+    }
+
+    /// "warning" for the linter's `W` codes, "error" for everything else.
+    pub fn severity(&self) -> &'static str {
+        if self.code.starts_with('W') {
+            "warning"
+        } else {
+            "error"
+        }
+    }
+
+    /// Looks up the location in `source`, which starts at offset `base` of
+    /// the spans. The span is relative to `source` afterwards.
+    pub fn locate(&mut self, source: &Source, base: usize) {
+        self.span.start -= base;
+        self.span.end -= base;
+        for suggestion in &mut self.suggestions {
+            suggestion.span.start -= base;
+            suggestion.span.end -= base;
+        }
+        let location = source.location(self.span.start);
+        self.location = Some((source.name().to_string(), location));
+    }
+
+    /// The diagnostic as one line of JSON, for tools.
+    pub fn to_json(&self) -> String {
+        let (file, line, column) = match &self.location {
+            Some((file, location)) => (
+                json_string(file),
+                location.line.to_string(),
+                location.column.to_string(),
+            ),
+            None => ("null".to_string(), "null".to_string(), "null".to_string()),
+        };
+        let suggestions: Vec<_> = self.suggestions.iter().map(Suggestion::to_json).collect();
+        format!(
+            "{{\"severity\":\"{}\",\"code\":\"{}\",\"message\":{},\"file\":{},\"line\":{},\"column\":{},\"span\":{{\"start\":{},\"end\":{}}},\"suggestions\":[{}]}}",
+            self.severity(),
+            self.code,
+            json_string(&self.message),
+            file,
+            line,
+            column,
+            self.span.start,
+            self.span.end,
+            suggestions.join(",")
+        )
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some((file, location)) => write!(
+                f,
+                "{}[{}]: {} at {}:{}",
+                self.severity(),
+                self.code,
+                self.message,
+                file,
+                location
+            ),
+            None => write!(
+                f,
+                "{}[{}]: {} at byte {}",
+                self.severity(),
+                self.code,
+                self.message,
+                self.span.start
+            ),
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(error.code, &error.message, error.span);
+        diagnostic.suggestions.extend(error.suggestion.clone());
+        diagnostic
+    }
+}
+
+impl From<&ResolveError> for Diagnostic {
+    fn from(error: &ResolveError) -> Diagnostic {
+        Diagnostic::new(error.code, &error.message, error.span)
+    }
+}
+
+impl From<&TypeError> for Diagnostic {
+    fn from(error: &TypeError) -> Diagnostic {
+        Diagnostic::new(error.code, &error.message, error.span)
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    Io(std::io::Error),
+    /// The program was not run.
+    Diagnostics(Vec<Diagnostic>),
+    /// A `reaper.toml` that can't be used.
+    Manifest(String),
+    /// A `.reapc` file that can't be loaded.
+    Artifact(String),
+    /// With the file name and position of the failing code, when known.
+    Runtime(RuntimeError, Option<(String, Location)>),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Diagnostics(diagnostics) => {
+                let lines: Vec<_> = diagnostics.iter().map(|d| d.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            Error::Manifest(e) => write!(f, "reaper.toml: {}", e),
+            Error::Artifact(e) => write!(f, "invalid artifact: {}", e),
+            Error::Runtime(e, Some((file, location))) => {
+                write!(f, "{} at {}:{}", e, file, location)
+            }
+            Error::Runtime(e, None) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error {
+    /// The problems found in the source, none for other errors.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            Error::Diagnostics(diagnostics) => diagnostics,
+            _ => &[],
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(error: RuntimeError) -> Error {
+        Error::Runtime(error, None)
+    }
+}
+
+/// Parses and compiles `src`, stopping after the first phase that finds
+/// errors.
+pub fn compile_source(
+    src: &str,
+    compile_options: impl Into<CompileOptions>,
+) -> Result<Program, Error> {
+    let compile_options = compile_options.into();
+    let mut parser = Parser::with_max_nesting(compile_options.limits.nesting);
+    let ast = parser.parse(Tokenizer::new(src).collect());
+    if !parser.errors().is_empty() {
+        let diagnostics = parser.errors().iter().map(Diagnostic::from).collect();
+        return Err(Error::Diagnostics(diagnostics));
+    }
+    Compiler::with_options(compile_options)
+        .compile(ast)
+        .map_err(Error::Diagnostics)
+}
+
+/// Evaluates the expression `src`, `a + b * 2`, with the variables in
+/// `bindings`, for using scripts as formulas.
+pub fn eval_expr(src: &str, bindings: &HashMap<String, Object>) -> Result<Object, Error> {
+    eval(src, bindings, None)
+}
+
+/// Like [`eval_expr`], for expressions from untrusted sources: `src` may
+/// not assign and only use what `sandbox` allows.
+pub fn eval_expr_with(
+    src: &str,
+    bindings: &HashMap<String, Object>,
+    sandbox: &Sandbox,
+) -> Result<Object, Error> {
+    eval(src, bindings, Some(sandbox))
+}
+
+fn eval(
+    src: &str,
+    bindings: &HashMap<String, Object>,
+    sandbox: Option<&Sandbox>,
+) -> Result<Object, Error> {
+    // A name no script variable can have.
+    const RESULT: &str = "<result>";
+
+    let mut parser = Parser::new();
+    parser.set_expressions_only(sandbox.is_some());
+    let expression = parser.parse_expr(Tokenizer::new(src).collect());
+    if !parser.errors().is_empty() {
+        let diagnostics = parser.errors().iter().map(Diagnostic::from).collect();
+        return Err(Error::Diagnostics(diagnostics));
+    }
+    let span = expression.span();
+    let result = Expression::Variable(VariableExpression {
+        value: RESULT.to_string(),
+        span,
+        slot: None,
+    });
+    let ast = vec![Statement::Expression(ExpressionStatement {
+        expression: Expression::Assign(AssignExpression {
+            lhs: Box::new(result),
+            rhs: Box::new(expression),
+            span,
+        }),
+    })];
+
+    let mut compiler = Compiler::new();
+    compiler.declare_global(RESULT);
+    for name in bindings.keys() {
+        compiler.declare_global(name);
+    }
+    if let Some(sandbox) = sandbox {
+        // The result is assigned outside of it.
+        compiler.set_sandbox(sandbox.clone().variable(RESULT));
+    }
+    let program = compiler.compile(ast).map_err(Error::Diagnostics)?;
+    let mut vm = VM::new();
+    vm.load(Arc::new(program));
+    for (name, value) in bindings {
+        vm.set_global(name, value.clone())?;
+    }
+    vm.run()?;
+    Ok(vm.global(RESULT).cloned().unwrap_or(Object::Null))
+}
+
+/// Compiles `files` into one program, as if they were one file, in that
+/// order. Each file is parsed on its own, a statement can't continue into
+/// the next file.
+pub fn compile_files(
+    sources: &SourceManager,
+    files: &[FileId],
+    compile_options: impl Into<CompileOptions>,
+) -> Result<Program, Error> {
+    let compile_options = compile_options.into();
+    let mut ast = vec![];
+    let mut diagnostics = vec![];
+    // Spans of all files are kept apart by starting each file behind the
+    // end of the previous one.
+    let mut bases = vec![];
+    let mut base = 0;
+    for &file in files {
+        let source = sources.get(file);
+        let tokens = Tokenizer::new(source.text()).map(|mut token| {
+            token.span.start += base;
+            token.span.end += base;
+            token
+        });
+        let mut parser = Parser::with_max_nesting(compile_options.limits.nesting);
+        ast.extend(parser.parse(tokens.collect()));
+        diagnostics.extend(parser.errors().iter().map(Diagnostic::from));
+        bases.push((base, source));
+        base += source.text().len() + 1;
+    }
+
+    let result = if diagnostics.is_empty() {
+        Compiler::with_options(compile_options).compile(ast)
+    } else {
+        Err(diagnostics)
+    };
+    result.map_err(|mut diagnostics| {
+        for diagnostic in diagnostics.iter_mut() {
+            let (base, source) = bases
+                .iter()
+                .rfind(|(base, _)| *base <= diagnostic.span.start)
+                .unwrap();
+            diagnostic.locate(source, *base);
+        }
+        Error::Diagnostics(diagnostics)
+    })
+}
+
+/// How [`run_file_with`] and [`run_artifact`] run programs.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Writes a crash report when the program fails at runtime.
+    pub crash_reporter: Option<CrashReporter>,
+    /// See [`VM::set_deterministic`].
+    pub deterministic: bool,
+    /// Records that many of the last instructions and prints them to
+    /// stderr when the program fails at runtime, see [`Recorder`].
+    pub flight_recorder: Option<usize>,
+}
+
+/// Compiles the script at `path`.
+pub fn compile_file(
+    path: &str,
+    compile_options: impl Into<CompileOptions>,
+) -> Result<Program, Error> {
+    let mut sources = SourceManager::new();
+    let file = sources.load(path)?;
+    compile_files(&sources, &[file], compile_options)
+}
+
+/// Compiles and runs the script at `path`. Nothing is run when the script
+/// doesn't compile.
+pub fn run_file(path: &str, compile_options: impl Into<CompileOptions>) -> Result<(), Error> {
+    run_file_with(path, compile_options, &RunOptions::default())
+}
+
+/// Like [`run_file`], with `options`.
+pub fn run_file_with(
+    path: &str,
+    compile_options: impl Into<CompileOptions>,
+    options: &RunOptions,
+) -> Result<(), Error> {
+    let mut sources = SourceManager::new();
+    let file = sources.load(path)?;
+    let program = compile_files(&sources, &[file], compile_options)?;
+    let source = sources.get(file);
+    run(program, options, |span| {
+        Some((source.name().to_string(), source.location(span.start)))
+    })
+}
+
+/// Reads the program in the `.reapc` file at `path`.
+pub fn load_artifact(path: &str) -> Result<Program, Error> {
+    let bytes = std::fs::read(path)?;
+    artifact::read(&bytes).map_err(Error::Artifact)
+}
+
+/// Runs the program in the `.reapc` file at `path`.
+pub fn run_artifact(path: &str, options: &RunOptions) -> Result<(), Error> {
+    let program = load_artifact(path)?;
+    // Artifacts carry no source.
+    run(program, options, |_| None)
+}
+
+/// Runs `program`, `locate` looks up the source of failing code.
+fn run(
+    program: Program,
+    options: &RunOptions,
+    locate: impl Fn(Span) -> Option<(String, Location)>,
+) -> Result<(), Error> {
+    let mut vm = VM::new();
+    vm.set_deterministic(options.deterministic);
+    vm.load(Arc::new(program));
+    let mut recorder = options.flight_recorder.map(Recorder::new);
+    let result = match &mut recorder {
+        Some(recorder) => recorder.run(&mut vm),
+        None => vm.run(),
+    };
+    if let Err(error) = result {
+        if let (Some(recorder), Some(program)) = (&recorder, vm.program()) {
+            eprint!("{}", recorder.dump(program));
+        }
+        if let Some(reporter) = &options.crash_reporter {
+            reporter.report(&vm, &error)?;
+        }
+        let location = vm.span().and_then(locate);
+        return Err(Error::Runtime(error, location));
+    }
+    Ok(())
+}
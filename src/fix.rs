@@ -0,0 +1,105 @@
+//! Fixes that diagnostics suggest and `reaper fix` applies: a missing `;`
+//! inserted, `=` in a condition replaced with `==`.
+
+use crate::driver::{Diagnostic, Error};
+use crate::lint::{self, LintConfig};
+use crate::parser::Parser;
+use crate::tokenizer::{Span, Tokenizer};
+use crate::util::{json_string, read_file};
+
+/// Replaces the source in `span` with `replacement`, an insertion when the
+/// span is empty. Applying it is safe, the program still means what was
+/// most likely meant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    /// What the fix does, "insert ';'".
+    pub message: String,
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl Suggestion {
+    pub fn insert(at: usize, text: &str) -> Suggestion {
+        Suggestion {
+            message: format!("insert '{}'", text),
+            span: Span { start: at, end: at },
+            replacement: text.to_string(),
+        }
+    }
+
+    pub fn replace(span: Span, old: &str, new: &str) -> Suggestion {
+        Suggestion {
+            message: format!("replace '{}' with '{}'", old, new),
+            span,
+            replacement: new.to_string(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"message\":{},\"span\":{{\"start\":{},\"end\":{}}},\"replacement\":{}}}",
+            json_string(&self.message),
+            self.span.start,
+            self.span.end,
+            json_string(&self.replacement)
+        )
+    }
+}
+
+/// `src` with `suggestions` applied. Of overlapping suggestions only the
+/// first is.
+pub fn apply(src: &str, suggestions: &[&Suggestion]) -> String {
+    let mut suggestions = suggestions.to_vec();
+    suggestions.sort_by_key(|s| (s.span.start, s.span.end));
+    let mut out = String::new();
+    let mut end = 0;
+    for suggestion in suggestions {
+        if suggestion.span.start < end {
+            continue;
+        }
+        out += &src[end..suggestion.span.start];
+        out += &suggestion.replacement;
+        end = suggestion.span.end;
+    }
+    out += &src[end..];
+    out
+}
+
+/// Applies the suggestions of the syntax errors of `src`, or of its lint
+/// warnings once it parses, until there are none left. Returns the fixed
+/// source and the number of fixes.
+pub fn fix(src: &str, config: &LintConfig) -> (String, usize) {
+    let mut src = src.to_string();
+    let mut count = 0;
+    // Each round parses further, but give up on fixes that don't help.
+    for _ in 0..16 {
+        let mut parser = Parser::new();
+        parser.parse(Tokenizer::new(&src).collect());
+        let diagnostics: Vec<_> = if parser.errors().is_empty() {
+            lint::lint(&src, config).unwrap_or_default()
+        } else {
+            parser.errors().iter().map(Diagnostic::from).collect()
+        };
+        let suggestions: Vec<_> = diagnostics.iter().flat_map(|d| &d.suggestions).collect();
+        if suggestions.is_empty() {
+            break;
+        }
+        let fixed = apply(&src, &suggestions);
+        if fixed == src {
+            break;
+        }
+        count += suggestions.len();
+        src = fixed;
+    }
+    (src, count)
+}
+
+/// Fixes the script at `path` in place, returns the number of fixes.
+pub fn fix_file(path: &str, config: &LintConfig) -> Result<usize, Error> {
+    let src = read_file(path)?;
+    let (fixed, count) = fix(&src, config);
+    if count > 0 {
+        std::fs::write(path, fixed)?;
+    }
+    Ok(count)
+}
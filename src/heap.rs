@@ -0,0 +1,140 @@
+//! Where the VM gets the strings, byte strings, lists and sets scripts create at
+//! runtime.
+//! Constants are allocated once with the program and not counted.
+
+use std::sync::Arc;
+
+use crate::key::Key;
+use crate::set::Set;
+use crate::vm::{Object, RuntimeError};
+
+/// Allocates the values of a running script. Embedders plug in their own
+/// to account for or cap the memory untrusted scripts use.
+pub trait Heap: Send {
+    fn string(&mut self, s: String) -> Result<Object, RuntimeError>;
+
+    fn bytes(&mut self, bytes: Vec<u8>) -> Result<Object, RuntimeError>;
+
+    fn list(&mut self, items: Vec<Object>) -> Result<Object, RuntimeError>;
+
+    fn set(&mut self, set: Set) -> Result<Object, RuntimeError>;
+
+    /// Counts `size` more bytes of a list or set a builtin grew in place.
+    fn grow(&mut self, size: usize) -> Result<(), RuntimeError>;
+
+    /// Bytes handed out since the heap was created.
+    fn allocated(&self) -> usize;
+}
+
+/// Approximate size of a string's, list's or set's contents, what heaps
+/// count.
+pub fn string_size(s: &str) -> usize {
+    s.len()
+}
+
+pub fn bytes_size(bytes: &[u8]) -> usize {
+    bytes.len()
+}
+
+pub fn list_size(items: &[Object]) -> usize {
+    std::mem::size_of_val(items)
+}
+
+pub fn set_size(set: &Set) -> usize {
+    set.len() * std::mem::size_of::<Key>()
+}
+
+/// The global allocator, the default.
+#[derive(Debug, Default)]
+pub struct SystemHeap {
+    allocated: usize,
+}
+
+impl Heap for SystemHeap {
+    fn string(&mut self, s: String) -> Result<Object, RuntimeError> {
+        self.allocated += string_size(&s);
+        Ok(Object::String(s.into()))
+    }
+
+    fn bytes(&mut self, bytes: Vec<u8>) -> Result<Object, RuntimeError> {
+        self.allocated += bytes_size(&bytes);
+        Ok(Object::Bytes(bytes.into()))
+    }
+
+    fn list(&mut self, items: Vec<Object>) -> Result<Object, RuntimeError> {
+        self.allocated += list_size(&items);
+        Ok(Object::List(Arc::new(items.into())))
+    }
+
+    fn set(&mut self, set: Set) -> Result<Object, RuntimeError> {
+        self.allocated += set_size(&set);
+        Ok(Object::Set(Arc::new(set)))
+    }
+
+    fn grow(&mut self, size: usize) -> Result<(), RuntimeError> {
+        self.allocated += size;
+        Ok(())
+    }
+
+    fn allocated(&self) -> usize {
+        self.allocated
+    }
+}
+
+/// Fails allocations once `limit` bytes have been handed out. Values are
+/// reference counted and nothing is given back when they are dropped, so
+/// the limit bounds all allocations of a run, not what is live.
+#[derive(Debug)]
+pub struct LimitedHeap {
+    limit: usize,
+    heap: SystemHeap,
+}
+
+impl LimitedHeap {
+    pub fn new(limit: usize) -> LimitedHeap {
+        LimitedHeap {
+            limit,
+            heap: SystemHeap::default(),
+        }
+    }
+
+    fn reserve(&self, size: usize) -> Result<(), RuntimeError> {
+        if self.heap.allocated + size > self.limit {
+            return Err(RuntimeError {
+                message: format!("Memory limit of {} bytes exceeded.", self.limit),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Heap for LimitedHeap {
+    fn string(&mut self, s: String) -> Result<Object, RuntimeError> {
+        self.reserve(string_size(&s))?;
+        self.heap.string(s)
+    }
+
+    fn bytes(&mut self, bytes: Vec<u8>) -> Result<Object, RuntimeError> {
+        self.reserve(bytes_size(&bytes))?;
+        self.heap.bytes(bytes)
+    }
+
+    fn list(&mut self, items: Vec<Object>) -> Result<Object, RuntimeError> {
+        self.reserve(list_size(&items))?;
+        self.heap.list(items)
+    }
+
+    fn set(&mut self, set: Set) -> Result<Object, RuntimeError> {
+        self.reserve(set_size(&set))?;
+        self.heap.set(set)
+    }
+
+    fn grow(&mut self, size: usize) -> Result<(), RuntimeError> {
+        self.reserve(size)?;
+        self.heap.grow(size)
+    }
+
+    fn allocated(&self) -> usize {
+        self.heap.allocated()
+    }
+}
@@ -0,0 +1,298 @@
+use crate::parser::{Expression, Parser, Statement};
+use crate::tokenizer::{Span, Token, Tokenizer};
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// Replacement of the source text in `range` (byte offsets into the text
+/// before the edit) with `text`.
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub range: Span,
+    pub text: String,
+}
+
+/// How much work an edit caused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EditStats {
+    pub relexed_tokens: usize,
+    pub reparsed_statements: usize,
+}
+
+struct ParsedStatement {
+    statement: Statement,
+    tokens: Range<usize>,
+}
+
+/// Source text together with its tokens and top level statements, kept up
+/// to date across edits by re-tokenizing and re-parsing only the region
+/// around each edit.
+pub struct Document {
+    src: String,
+    tokens: Vec<Token>,
+    statements: Vec<ParsedStatement>,
+    parser: Parser,
+}
+
+impl Document {
+    pub fn new(src: &str) -> Document {
+        let mut document = Document {
+            src: src.to_string(),
+            tokens: Tokenizer::with_trivia(src).collect(),
+            statements: Vec::new(),
+            parser: Parser::default(),
+        };
+        document.reparse_from(0, 0, 0, 0);
+        document
+    }
+
+    pub fn source(&self) -> &str {
+        &self.src
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn statements(&self) -> impl Iterator<Item = &Statement> {
+        self.statements.iter().map(|s| &s.statement)
+    }
+
+    pub fn edit(&mut self, edit: TextEdit) -> EditStats {
+        let Span { start, end } = edit.range;
+        self.src.replace_range(start..end, &edit.text);
+        let delta = edit.text.len() as isize - (end - start) as isize;
+        let shift = |offset: usize| (offset as isize + delta) as usize;
+
+        // Back up to the line of the edit, where a quote may now open or
+        // close a string, and one token more so edits that join or split
+        // the token in front are picked up. No token spans lines.
+        let line = self.src[..start].rfind('\n').map_or(0, |i| i + 1);
+        let first = self
+            .tokens
+            .iter()
+            .position(|t| t.span.end >= line)
+            .unwrap_or(self.tokens.len())
+            .saturating_sub(1);
+        let offset = match first {
+            0 => 0,
+            n => self.tokens[n - 1].span.end,
+        };
+
+        // Re-tokenize until a token lines up with an unchanged old token
+        // behind the edit, everything from there on is reused.
+        let edit_end = start + edit.text.len();
+        let mut relexed = Vec::new();
+        let mut old = first;
+        let mut resync = None;
+        for token in Tokenizer::with_trivia(&self.src).starting_at(offset) {
+            if token.span.start >= edit_end {
+                while old < self.tokens.len()
+                    && (self.tokens[old].span.start < end
+                        || shift(self.tokens[old].span.start) < token.span.start)
+                {
+                    old += 1;
+                }
+                if let Some(candidate) = self.tokens.get(old) {
+                    if shift(candidate.span.start) == token.span.start
+                        && candidate.kind == token.kind
+                        && candidate.value == token.value
+                    {
+                        relexed.push(token);
+                        resync = Some(old + 1);
+                        break;
+                    }
+                }
+            }
+            relexed.push(token);
+        }
+
+        let old_end = resync.unwrap_or(self.tokens.len());
+        let relexed_tokens = relexed.len();
+        let mut tail = self.tokens.split_off(old_end);
+        for token in tail.iter_mut() {
+            token.span.start = shift(token.span.start);
+            token.span.end = shift(token.span.end);
+            for trivia in token.leading_trivia.iter_mut() {
+                trivia.span.start = shift(trivia.span.start);
+                trivia.span.end = shift(trivia.span.end);
+            }
+        }
+        self.tokens.truncate(first);
+        self.tokens.extend(relexed);
+        self.tokens.extend(tail);
+
+        let new_end = first + relexed_tokens;
+        let reparsed_statements = self.reparse_from(first, old_end, new_end, delta);
+
+        EditStats {
+            relexed_tokens,
+            reparsed_statements,
+        }
+    }
+
+    /// Re-parses statements starting with the one in front of token
+    /// `first`, old tokens `first..old_end` having been replaced by new
+    /// tokens `first..new_end` and the text behind them having moved by
+    /// `delta` bytes. Returns the number of statements parsed.
+    fn reparse_from(
+        &mut self,
+        first: usize,
+        old_end: usize,
+        new_end: usize,
+        delta: isize,
+    ) -> usize {
+        // A following token can extend the statement in front of the
+        // edit (`else` after an `if`), so it is parsed again as well.
+        let damaged = self
+            .statements
+            .iter()
+            .position(|s| s.tokens.end > first)
+            .unwrap_or(self.statements.len())
+            .saturating_sub(1);
+        let mut reusable: VecDeque<ParsedStatement> =
+            self.statements.split_off(damaged).into_iter().collect();
+        let base = self.statements.last().map_or(0, |s| s.tokens.end);
+        let token_delta = new_end as isize - old_end as isize;
+        let shift = |index: usize| (index as isize + token_delta) as usize;
+
+        self.parser
+            .reset(self.tokens[base..].iter().cloned().collect());
+        let mut count = 0;
+        loop {
+            let position = base + self.parser.position();
+            while reusable
+                .front()
+                .is_some_and(|s| s.tokens.start < old_end || shift(s.tokens.start) < position)
+            {
+                reusable.pop_front();
+            }
+            if position >= new_end
+                && reusable
+                    .front()
+                    .is_some_and(|s| shift(s.tokens.start) == position)
+            {
+                self.statements.extend(reusable.into_iter().map(|mut s| {
+                    shift_statement(&mut s.statement, delta);
+                    ParsedStatement {
+                        tokens: shift(s.tokens.start)..shift(s.tokens.end),
+                        statement: s.statement,
+                    }
+                }));
+                break;
+            }
+            match self.parser.next_statement() {
+                Some(statement) => {
+                    count += 1;
+                    self.statements.push(ParsedStatement {
+                        statement,
+                        tokens: position..base + self.parser.position(),
+                    });
+                }
+                None => break,
+            }
+        }
+        count
+    }
+}
+
+fn shift_span(span: &mut Span, delta: isize) {
+    span.start = (span.start as isize + delta) as usize;
+    span.end = (span.end as isize + delta) as usize;
+}
+
+fn shift_statement(statement: &mut Statement, delta: isize) {
+    match statement {
+        Statement::Print(s) => shift_expression(&mut s.expression, delta),
+        Statement::Fn(s) => {
+            shift_span(&mut s.span, delta);
+            for argument in s.arguments.iter_mut() {
+                shift_span(&mut argument.span, delta);
+                if let Some(annotation) = &mut argument.annotation {
+                    shift_span(&mut annotation.span, delta);
+                }
+            }
+            if let Some(annotation) = &mut s.return_type {
+                shift_span(&mut annotation.span, delta);
+            }
+            shift_statement(&mut s.body, delta);
+        }
+        Statement::Expression(s) => shift_expression(&mut s.expression, delta),
+        Statement::Return(s) => {
+            shift_span(&mut s.span, delta);
+            shift_expression(&mut s.expression, delta);
+        }
+        Statement::Defer(s) => {
+            shift_span(&mut s.span, delta);
+            shift_expression(&mut s.expression, delta);
+        }
+        Statement::If(s) => {
+            for (condition, body) in s.branches.iter_mut() {
+                shift_expression(condition, delta);
+                shift_statement(body, delta);
+            }
+            if let Some(else_body) = &mut s.else_body {
+                shift_statement(else_body, delta);
+            }
+        }
+        Statement::Block(s) => {
+            for statement in s.body.iter_mut() {
+                shift_statement(statement, delta);
+            }
+        }
+        Statement::While(s) => {
+            shift_expression(&mut s.condition, delta);
+            shift_statement(&mut s.body, delta);
+        }
+        Statement::For(s) => {
+            shift_span(&mut s.span, delta);
+            let clauses = [&mut s.initializer, &mut s.condition, &mut s.increment];
+            for expression in clauses.into_iter().flatten() {
+                shift_expression(expression, delta);
+            }
+            shift_statement(&mut s.body, delta);
+        }
+        Statement::Break(s) => shift_span(&mut s.span, delta),
+        Statement::Continue(s) => shift_span(&mut s.span, delta),
+        Statement::DoWhile(s) => {
+            shift_statement(&mut s.body, delta);
+            shift_expression(&mut s.condition, delta);
+        }
+        Statement::Dummy => {}
+    }
+}
+
+fn shift_expression(expression: &mut Expression, delta: isize) {
+    match expression {
+        Expression::Literal(e) => shift_span(&mut e.span, delta),
+        Expression::Variable(e) => shift_span(&mut e.span, delta),
+        Expression::Binary(e) => {
+            shift_expression(&mut e.lhs, delta);
+            shift_expression(&mut e.rhs, delta);
+        }
+        Expression::Call(e) => {
+            shift_span(&mut e.span, delta);
+            shift_expression(&mut e.callee, delta);
+            for argument in e.arguments.iter_mut() {
+                shift_expression(argument, delta);
+            }
+        }
+        Expression::Assign(e) => {
+            shift_span(&mut e.span, delta);
+            shift_expression(&mut e.lhs, delta);
+            shift_expression(&mut e.rhs, delta);
+        }
+        Expression::Unary(e) => shift_expression(&mut e.expr, delta),
+        Expression::TypeTest(e) => {
+            shift_expression(&mut e.expr, delta);
+            shift_span(&mut e.type_name.span, delta);
+        }
+        Expression::Index(e) => {
+            shift_span(&mut e.span, delta);
+            shift_expression(&mut e.expr, delta);
+            shift_expression(&mut e.index, delta);
+            if let Some(end) = &mut e.end {
+                shift_expression(end, delta);
+            }
+        }
+    }
+}
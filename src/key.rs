@@ -0,0 +1,109 @@
+//! Objects as hash keys, for `memoize` and maps.
+//!
+//! Numbers, strings, byte strings, bools, `null` and lists of them are
+//! hashable. Keys compare like the objects do with `==`: `-0` and `0` are
+//! the same key. `NaN` equals nothing, not even itself, a `NaN` key could
+//! never be found again and is an error, like functions and native objects.
+//! So is a list holding itself, its key would never end.
+//!
+//! ```
+//! use reaper::key::{hash, Key};
+//! use reaper::Object;
+//!
+//! assert_eq!(hash(&Object::Number(-0.0)), hash(&Object::Number(0.0)));
+//! let key = Key::new(&vec![1.0, 2.0].into()).unwrap();
+//! assert_eq!(key.to_object(), vec![1.0, 2.0].into());
+//! assert!(Key::new(&Object::Number(f64::NAN)).is_err());
+//! ```
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::list::List;
+use crate::vm::{Object, RuntimeError};
+
+/// A hashable [`Object`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Key(Repr);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Repr {
+    /// The bits of a number other than `NaN` and `-0`.
+    Number(u64),
+    Bool(bool),
+    String(String),
+    Bytes(Vec<u8>),
+    List(Vec<Repr>),
+    Null,
+}
+
+impl Key {
+    pub fn new(object: &Object) -> Result<Key, RuntimeError> {
+        Repr::new(object, &mut Vec::new()).map(Key)
+    }
+
+    /// The object the key was made from, or one equal to it.
+    pub fn to_object(&self) -> Object {
+        self.0.to_object()
+    }
+}
+
+impl Repr {
+    /// `keying` holds the lists the key is being made of, innermost last.
+    fn new(object: &Object, keying: &mut Vec<*const List>) -> Result<Repr, RuntimeError> {
+        Ok(match object {
+            Object::Number(n) if n.is_nan() => {
+                return Err(RuntimeError {
+                    message: "Can't use NaN as a key, it equals nothing.".to_string(),
+                })
+            }
+            // Adding 0 turns -0 into 0 and leaves everything else.
+            Object::Number(n) => Repr::Number((n + 0.0).to_bits()),
+            Object::Bool(b) => Repr::Bool(*b),
+            Object::String(s) => Repr::String(s.to_string()),
+            Object::Bytes(b) => Repr::Bytes(b.to_vec()),
+            Object::List(l) => {
+                let address = std::sync::Arc::as_ptr(l);
+                if keying.contains(&address) {
+                    return Err(RuntimeError {
+                        message: "Can't use a list holding itself as a key.".to_string(),
+                    });
+                }
+                // Copied, a list further in may be this one and locking it
+                // again could wait on a writer.
+                let items: Vec<Object> = l.items().iter().cloned().collect();
+                keying.push(address);
+                let repr: Result<_, _> = items.iter().map(|item| Repr::new(item, keying)).collect();
+                keying.pop();
+                Repr::List(repr?)
+            }
+            Object::Null => Repr::Null,
+            Object::Set(_) | Object::Function(_) | Object::Native(_) => {
+                return Err(RuntimeError {
+                    message: format!(
+                    "Can't use {} as a key, only numbers, strings, byte strings, bools, null and lists of them.",
+                    object.type_name()
+                ),
+                })
+            }
+        })
+    }
+
+    fn to_object(&self) -> Object {
+        match self {
+            Repr::Number(bits) => Object::Number(f64::from_bits(*bits)),
+            Repr::Bool(b) => Object::Bool(*b),
+            Repr::String(s) => s.as_str().into(),
+            Repr::Bytes(b) => Object::Bytes(b.clone().into()),
+            Repr::List(l) => l.iter().map(Repr::to_object).collect::<Vec<_>>().into(),
+            Repr::Null => Object::Null,
+        }
+    }
+}
+
+/// Hash of `object`, the same in every run of a program. It may change
+/// with the Rust version reaper is built with.
+pub fn hash(object: &Object) -> Result<u64, RuntimeError> {
+    let mut hasher = DefaultHasher::new();
+    Key::new(object)?.hash(&mut hasher);
+    Ok(hasher.finish())
+}
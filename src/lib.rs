@@ -1,7 +1,59 @@
+//! An embeddable scripting language compiled to bytecode for a stack VM.
+//!
+//! The items re-exported here are the API embedders should build on, they
+//! only change with a new minor version before 1.0. The modules expose the
+//! compiler's internals for tooling and follow the implementation.
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! let program = reaper::compile_source("x = 6 * 7;", reaper::OptLevel::O1).unwrap();
+//! let mut vm = reaper::VM::new();
+//! vm.load(Arc::new(program));
+//! vm.run().unwrap();
+//! assert_eq!(vm.global("x"), Some(&reaper::Object::Number(42.0)));
+//! ```
+
+pub mod analysis;
+pub mod artifact;
+pub mod batch;
+pub mod bench;
+pub mod builtins;
+pub mod codes;
 pub mod compiler;
+pub mod completion;
+pub mod consteval;
+pub mod convert;
+pub mod crash;
+pub mod doc;
+pub mod driver;
+pub mod fix;
+pub mod heap;
+pub mod incremental;
+pub mod key;
+pub mod lint;
+pub mod list;
+pub mod native;
 pub mod parser;
+pub mod prelude;
+pub mod program;
+pub mod project;
+pub mod resolver;
+pub mod sandbox;
+pub mod set;
 pub mod tokenizer;
+pub mod trace;
+pub mod typecheck;
 pub mod util;
 pub mod vm;
 
-pub use vm::Object;
+pub use builtins::{Capabilities, Capability};
+pub use compiler::{CompileOptions, Limits, OptLevel};
+pub use convert::FromObject;
+pub use driver::{
+    compile_files, compile_source, eval_expr, eval_expr_with, run_file, Diagnostic, Error,
+    RunOptions,
+};
+pub use program::Program;
+pub use util::{FileId, Location, Source, SourceManager};
+pub use vm::{Indexing, Object, RuntimeError, SizeLimits, State, StepResult, VM};
@@ -0,0 +1,349 @@
+//! `reaper lint`: warnings about code that runs but probably doesn't do
+//! what was meant. Each rule has a `W` code, see [`crate::codes`], and can
+//! be turned off in the `lint` keys of a [`Manifest`].
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::analysis::collect_functions;
+use crate::builtins::BUILTINS;
+use crate::driver::{Diagnostic, Error};
+use crate::fix::Suggestion;
+use crate::parser::{Expression, Literal, LiteralExpression, Parser, Statement};
+use crate::prelude;
+use crate::project::Manifest;
+use crate::tokenizer::{Span, Tokenizer};
+use crate::util::{FileId, SourceManager};
+
+pub struct Rule {
+    /// What `lint.allow` names the rule by, its code works as well.
+    pub name: &'static str,
+    pub code: &'static str,
+}
+
+pub static RULES: &[Rule] = &[
+    Rule {
+        name: "shadowing",
+        code: "W001",
+    },
+    Rule {
+        name: "unused-result",
+        code: "W002",
+    },
+    Rule {
+        name: "constant-condition",
+        code: "W003",
+    },
+    Rule {
+        name: "deep-nesting",
+        code: "W004",
+    },
+    Rule {
+        name: "assignment-in-condition",
+        code: "W005",
+    },
+];
+
+/// The rule named or coded `name`.
+pub fn rule(name: &str) -> Option<&'static Rule> {
+    RULES.iter().find(|r| r.name == name || r.code == name)
+}
+
+/// The `lint` keys of a `reaper.toml`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintConfig {
+    /// Names of the rules that are off.
+    pub allow: Vec<&'static str>,
+    /// Functions, `if` statements and loops nested deeper are warned
+    /// about.
+    pub max_nesting: usize,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            allow: vec![],
+            max_nesting: 4,
+        }
+    }
+}
+
+impl LintConfig {
+    fn allows(&self, code: &str) -> bool {
+        self.allow
+            .iter()
+            .any(|name| rule(name).is_some_and(|r| r.code == code))
+    }
+}
+
+/// The warnings for `src`, in source order, or its syntax errors.
+pub fn lint(src: &str, config: &LintConfig) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    let mut parser = Parser::new();
+    let ast = parser.parse(Tokenizer::new(src).collect());
+    if !parser.errors().is_empty() {
+        return Err(parser.errors().iter().map(Diagnostic::from).collect());
+    }
+    let mut linter = Linter::new(config, &[&ast]);
+    linter.lint_statements(&ast);
+    Ok(linter.warnings)
+}
+
+/// The warnings for `files`, linted as one program: a variable in one
+/// file shadows functions of the others.
+pub fn lint_files(
+    sources: &SourceManager,
+    files: &[FileId],
+    config: &LintConfig,
+) -> Result<Vec<Diagnostic>, Error> {
+    let mut asts = vec![];
+    let mut errors = vec![];
+    for &file in files {
+        let source = sources.get(file);
+        let mut parser = Parser::new();
+        asts.push(parser.parse(Tokenizer::new(source.text()).collect()));
+        errors.extend(parser.errors().iter().map(|error| {
+            let mut diagnostic = Diagnostic::from(error);
+            diagnostic.locate(source, 0);
+            diagnostic
+        }));
+    }
+    if !errors.is_empty() {
+        return Err(Error::Diagnostics(errors));
+    }
+
+    let all: Vec<_> = asts.iter().collect();
+    let mut linter = Linter::new(config, &all);
+    let mut warnings = vec![];
+    for (&file, ast) in files.iter().zip(&asts) {
+        linter.lint_statements(ast);
+        for mut warning in linter.warnings.drain(..) {
+            warning.locate(sources.get(file), 0);
+            warnings.push(warning);
+        }
+    }
+    Ok(warnings)
+}
+
+/// The warnings for the script at `path`.
+pub fn lint_file(path: &str, config: &LintConfig) -> Result<Vec<Diagnostic>, Error> {
+    let mut sources = SourceManager::new();
+    let file = sources.load(path)?;
+    lint_files(&sources, &[file], config)
+}
+
+/// The warnings for the project of the manifest at `path`, with the rules
+/// it configures.
+pub fn lint_project(path: &Path) -> Result<Vec<Diagnostic>, Error> {
+    let manifest = Manifest::load(path)?;
+    let mut sources = SourceManager::new();
+    let mut files = vec![];
+    for file in manifest.files()? {
+        files.push(sources.load(&file.to_string_lossy())?);
+    }
+    lint_files(&sources, &files, &manifest.lint)
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    /// Functions of the program, the prelude and the builtins.
+    functions: HashSet<String>,
+    /// Variables of the current function already warned about.
+    shadowing: HashSet<String>,
+    /// Functions, `if` statements and loops around the current statement.
+    depth: usize,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(config: &'a LintConfig, asts: &[&Vec<Statement>]) -> Linter<'a> {
+        let mut functions: HashSet<String> = BUILTINS.iter().map(|b| b.name.to_string()).collect();
+        for statement in prelude::statements()
+            .iter()
+            .chain(asts.iter().copied().flatten())
+        {
+            collect_functions(statement, &mut functions);
+        }
+        Linter {
+            config,
+            functions,
+            shadowing: HashSet::new(),
+            depth: 0,
+            warnings: vec![],
+        }
+    }
+
+    fn warn(&mut self, code: &'static str, message: &str, span: Span) {
+        self.warn_with(code, message, span, None);
+    }
+
+    /// Warns with a fix for `reaper fix`.
+    fn warn_with(
+        &mut self,
+        code: &'static str,
+        message: &str,
+        span: Span,
+        suggestion: Option<Suggestion>,
+    ) {
+        if !self.config.allows(code) {
+            let mut warning = Diagnostic::new(code, message, span);
+            warning.suggestions.extend(suggestion);
+            self.warnings.push(warning);
+        }
+    }
+
+    fn lint_statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.lint_statement(statement);
+        }
+    }
+
+    fn lint_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Dummy | Statement::Break(_) | Statement::Continue(_) => {}
+            Statement::Print(s) => self.lint_expression(&s.expression),
+            Statement::Return(s) => self.lint_expression(&s.expression),
+            Statement::Defer(s) => self.lint_expression(&s.expression),
+            Statement::Expression(s) => {
+                if !matches!(s.expression, Expression::Assign(_) | Expression::Call(_)) {
+                    self.warn(
+                        "W002",
+                        "the result of the expression is unused",
+                        s.expression.span(),
+                    );
+                }
+                self.lint_expression(&s.expression);
+            }
+            Statement::Block(s) => self.lint_statements(&s.body),
+            Statement::Fn(s) => {
+                self.nested(s.span, |linter| {
+                    let outer = std::mem::take(&mut linter.shadowing);
+                    for parameter in &s.arguments {
+                        linter.shadows(&parameter.name, parameter.span);
+                    }
+                    linter.lint_statement(&s.body);
+                    linter.shadowing = outer;
+                });
+            }
+            Statement::If(s) => {
+                self.nested(s.branches[0].0.span(), |linter| {
+                    for (condition, body) in &s.branches {
+                        linter.condition(condition, false);
+                        linter.lint_statement(body);
+                    }
+                    if let Some(body) = &s.else_body {
+                        linter.lint_statement(body);
+                    }
+                });
+            }
+            Statement::While(s) => {
+                self.nested(s.condition.span(), |linter| {
+                    linter.condition(&s.condition, true);
+                    linter.lint_statement(&s.body);
+                });
+            }
+            Statement::For(s) => {
+                self.nested(s.span, |linter| {
+                    if let Some(initializer) = &s.initializer {
+                        linter.lint_expression(initializer);
+                    }
+                    if let Some(condition) = &s.condition {
+                        linter.condition(condition, true);
+                    }
+                    if let Some(increment) = &s.increment {
+                        linter.lint_expression(increment);
+                    }
+                    linter.lint_statement(&s.body);
+                });
+            }
+            Statement::DoWhile(s) => {
+                self.nested(s.condition.span(), |linter| {
+                    linter.lint_statement(&s.body);
+                    linter.condition(&s.condition, true);
+                });
+            }
+        }
+    }
+
+    /// Lints a statement one level deeper. Only the outermost statement
+    /// too deep is warned about.
+    fn nested(&mut self, span: Span, lint: impl FnOnce(&mut Self)) {
+        self.depth += 1;
+        if self.depth == self.config.max_nesting + 1 {
+            let message = format!("nested deeper than {} levels", self.config.max_nesting);
+            self.warn("W004", &message, span);
+        }
+        lint(self);
+        self.depth -= 1;
+    }
+
+    fn condition(&mut self, condition: &Expression, is_loop: bool) {
+        let endless = matches!(
+            condition,
+            Expression::Literal(LiteralExpression {
+                value: Literal::Bool(true),
+                ..
+            })
+        );
+        if is_constant(condition) && !(is_loop && endless) {
+            self.warn("W003", "the condition is constant", condition.span());
+        }
+        if let Expression::Assign(assign) = condition {
+            let fix = Suggestion::replace(assign.span, "=", "==");
+            self.warn_with("W005", "assignment in a condition", assign.span, Some(fix));
+        }
+        self.lint_expression(condition);
+    }
+
+    fn shadows(&mut self, name: &str, span: Span) {
+        if self.functions.contains(name) && self.shadowing.insert(name.to_string()) {
+            let message = format!("'{}' shadows the function of the same name", name);
+            self.warn("W001", &message, span);
+        }
+    }
+
+    fn lint_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Literal(_) | Expression::Variable(_) => {}
+            Expression::Binary(e) => {
+                self.lint_expression(&e.lhs);
+                self.lint_expression(&e.rhs);
+            }
+            Expression::Call(e) => {
+                self.lint_expression(&e.callee);
+                for argument in &e.arguments {
+                    self.lint_expression(argument);
+                }
+            }
+            Expression::Assign(e) => {
+                if let Expression::Variable(variable) = &*e.lhs {
+                    self.shadows(&variable.value, variable.span);
+                }
+                self.lint_expression(&e.lhs);
+                self.lint_expression(&e.rhs);
+            }
+            Expression::Unary(e) => self.lint_expression(&e.expr),
+            Expression::Index(e) => {
+                self.lint_expression(&e.expr);
+                self.lint_expression(&e.index);
+                if let Some(end) = &e.end {
+                    self.lint_expression(end);
+                }
+            }
+            Expression::TypeTest(e) => self.lint_expression(&e.expr),
+        }
+    }
+}
+
+/// Whether `expression` is made of literals only.
+fn is_constant(expression: &Expression) -> bool {
+    match expression {
+        Expression::Literal(_) => true,
+        Expression::Binary(e) => is_constant(&e.lhs) && is_constant(&e.rhs),
+        Expression::Unary(e) => is_constant(&e.expr),
+        Expression::TypeTest(e) => is_constant(&e.expr),
+        Expression::Variable(_)
+        | Expression::Call(_)
+        | Expression::Assign(_)
+        | Expression::Index(_) => false,
+    }
+}
@@ -0,0 +1,158 @@
+//! Lists, the `list` type of scripts.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::vm::{runtime_error, Object, RuntimeError};
+
+/// Items in order, in a deque so that taking items off the front is as
+/// cheap as off the back. Lists are shared, every value holding one sees
+/// it change, from other threads too, so the items are behind a lock. A
+/// list may hold itself. Frozen lists, and the lists in them, can't be
+/// changed.
+#[derive(Default)]
+pub struct List {
+    items: RwLock<VecDeque<Object>>,
+    frozen: AtomicBool,
+}
+
+impl List {
+    pub fn new() -> List {
+        List::default()
+    }
+
+    /// The items, locked for reading until the guard is dropped.
+    pub fn items(&self) -> RwLockReadGuard<'_, VecDeque<Object>> {
+        self.items.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The items to change, an error if the list is frozen.
+    pub fn items_mut(&self) -> Result<RwLockWriteGuard<'_, VecDeque<Object>>, RuntimeError> {
+        if self.is_frozen() {
+            runtime_error!("Can't change a frozen list.");
+        }
+        Ok(self.items.write().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn len(&self) -> usize {
+        self.items().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items().is_empty()
+    }
+
+    /// The items, also of a frozen list, for the host.
+    pub fn into_items(self) -> VecDeque<Object> {
+        self.items
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::Relaxed)
+    }
+
+    /// Freezes the list and the lists and sets in it. Frozen lists are
+    /// skipped, their items are frozen already, which ends the walk
+    /// through a list holding itself.
+    pub fn freeze(&self) {
+        if self.frozen.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        for item in self.items().iter() {
+            item.freeze();
+        }
+    }
+}
+
+impl From<VecDeque<Object>> for List {
+    fn from(items: VecDeque<Object>) -> Self {
+        List {
+            items: RwLock::new(items),
+            frozen: AtomicBool::new(false),
+        }
+    }
+}
+
+impl From<Vec<Object>> for List {
+    fn from(items: Vec<Object>) -> Self {
+        VecDeque::from(items).into()
+    }
+}
+
+impl<const N: usize> From<[Object; N]> for List {
+    fn from(items: [Object; N]) -> Self {
+        VecDeque::from(items).into()
+    }
+}
+
+impl FromIterator<Object> for List {
+    fn from_iter<I: IntoIterator<Item = Object>>(iter: I) -> Self {
+        VecDeque::from_iter(iter).into()
+    }
+}
+
+thread_local! {
+    /// Pairs of lists being compared on this thread, innermost last.
+    static COMPARING: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+    /// Lists being printed on this thread, innermost last.
+    static PRINTING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Frozen or not, lists with equal items are equal, and a list equals
+/// itself. Lists holding themselves are equal unless a difference shows:
+/// a pair compared again further in is taken to be equal.
+impl PartialEq for List {
+    fn eq(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let pair = (self as *const List as usize, other as *const List as usize);
+        if COMPARING.with_borrow(|pairs| pairs.contains(&pair)) {
+            return true;
+        }
+        // Lists nested in both are compared with the locks released, a
+        // list further in may be one of these two.
+        let mut nested = Vec::new();
+        {
+            let (a, b) = (self.items(), other.items());
+            if a.len() != b.len() {
+                return false;
+            }
+            for (a, b) in a.iter().zip(b.iter()) {
+                match (a, b) {
+                    (Object::List(a), Object::List(b)) => nested.push((a.clone(), b.clone())),
+                    _ if a != b => return false,
+                    _ => {}
+                }
+            }
+        }
+        COMPARING.with_borrow_mut(|pairs| pairs.push(pair));
+        let equal = nested.iter().all(|(a, b)| a == b);
+        COMPARING.with_borrow_mut(|pairs| pairs.pop());
+        equal
+    }
+}
+
+impl<const N: usize> PartialEq<[Object; N]> for List {
+    fn eq(&self, other: &[Object; N]) -> bool {
+        *self.items() == *other
+    }
+}
+
+/// Shown as the items are, in brackets, a list inside itself as `[...]`.
+impl std::fmt::Debug for List {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let address = self as *const List as usize;
+        if PRINTING.with_borrow(|lists| lists.contains(&address)) {
+            return write!(f, "[...]");
+        }
+        PRINTING.with_borrow_mut(|lists| lists.push(address));
+        let result = self.items().fmt(f);
+        PRINTING.with_borrow_mut(|lists| lists.pop());
+        result
+    }
+}
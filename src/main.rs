@@ -1,26 +1,151 @@
-use reaper::compiler::Compiler;
-use reaper::parser::Parser;
-use reaper::tokenizer::Tokenizer;
-use reaper::util::read_file;
-use reaper::vm::VM;
 use std::env;
+use std::path::Path;
 
-fn main() -> Result<(), std::io::Error> {
+use reaper::crash::{CrashReporter, ReportFormat};
+use reaper::{CompileOptions, OptLevel, RunOptions};
+
+fn main() {
     let args: Vec<String> = env::args().collect();
-    match args.get(1) {
-        Some(path) => {
-            let src = read_file(path)?;
-            let tokenizer = Tokenizer::new(&src);
-            let mut parser = Parser::default();
-            let mut compiler = Compiler::default();
-            let mut vm = VM::default();
-            let ast = parser.parse(tokenizer.into_iter().collect());
-            let mut bytecode = compiler.compile(ast);
-            vm.load(&mut bytecode);
-            vm.run();
-        }
-        None => eprintln!("You must pass in a path."),
+    let mut compile_options = CompileOptions::default();
+    let mut command = None;
+    let mut path = None;
+    let mut format = None;
+    let mut report_path = None;
+    let mut deterministic = false;
+    let mut json_errors = false;
+    let mut dump_bytecode = false;
+    let mut flight_recorder = None;
+    let mut doc_format = reaper::doc::DocFormat::Markdown;
+    for arg in &args[1..] {
+        match arg.as_str() {
+            "-O0" => compile_options.optimize = OptLevel::O0,
+            "-O1" => compile_options.optimize = OptLevel::O1,
+            "-O2" => compile_options.optimize = OptLevel::O2,
+            "-g" => compile_options.debug_info = true,
+            "-g0" => compile_options.debug_info = false,
+            "--crash-report" | "--crash-report=text" => format = Some(ReportFormat::Text),
+            "--crash-report=json" => format = Some(ReportFormat::Json),
+            _ if arg.starts_with("--crash-report-file=") => {
+                report_path = Some(arg["--crash-report-file=".len()..].into());
+                format.get_or_insert(ReportFormat::Text);
+            }
+            "--deterministic" => deterministic = true,
+            "--error-format=text" => json_errors = false,
+            "--error-format=json" => json_errors = true,
+            "--dump-bytecode" => dump_bytecode = true,
+            _ if arg.starts_with("--flight-recorder=") => {
+                match arg["--flight-recorder=".len()..].parse() {
+                    Ok(steps) => flight_recorder = Some(steps),
+                    Err(_) => {
+                        eprintln!("--flight-recorder needs a number of steps.");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--doc-format=markdown" => doc_format = reaper::doc::DocFormat::Markdown,
+            "--doc-format=html" => doc_format = reaper::doc::DocFormat::Html,
+            "build" | "bench" | "doc" | "explain" | "fix" | "lint"
+                if command.is_none() && path.is_none() =>
+            {
+                command = Some(arg.as_str())
+            }
+            _ => path = Some(arg.as_str()),
+        }
+    }
+    let options = RunOptions {
+        crash_reporter: format.map(|format| CrashReporter {
+            format,
+            path: report_path,
+        }),
+        deterministic,
+        flight_recorder,
+    };
+    let result = match (command, path) {
+        (Some("build"), path) => {
+            let manifest = Path::new(path.unwrap_or("reaper.toml"));
+            reaper::project::build(manifest, compile_options).map(|output| {
+                println!("wrote {}", output.display());
+            })
+        }
+        (Some("bench"), path) => {
+            let dir = Path::new(path.unwrap_or("src/benchmarks/cases"));
+            reaper::bench::run(dir, compile_options).map(|results| {
+                print!("{}", reaper::bench::table(&results));
+            })
+        }
+        (Some("doc"), Some(path)) => {
+            reaper::doc::document_file(path, doc_format).map(|doc| print!("{}", doc))
+        }
+        (Some("doc"), None) => {
+            eprintln!("You must pass in a path.");
+            std::process::exit(1);
+        }
+        (Some("fix"), Some(path)) => {
+            reaper::fix::fix_file(path, &reaper::lint::LintConfig::default())
+                .map(|count| println!("applied {} fixes to {}", count, path))
+        }
+        (Some("fix"), None) => {
+            eprintln!("You must pass in a path.");
+            std::process::exit(1);
+        }
+        (Some("lint"), path) => {
+            let path = path.unwrap_or("reaper.toml");
+            let warnings = if path.ends_with(".toml") {
+                reaper::lint::lint_project(Path::new(path))
+            } else {
+                reaper::lint::lint_file(path, &reaper::lint::LintConfig::default())
+            };
+            warnings.map(|warnings| {
+                for warning in &warnings {
+                    if json_errors {
+                        println!("{}", warning.to_json());
+                    } else {
+                        println!("{}", warning);
+                    }
+                }
+                if !warnings.is_empty() {
+                    std::process::exit(1);
+                }
+            })
+        }
+        (Some(_), code) => match code.and_then(reaper::codes::lookup) {
+            Some(code) => {
+                print!("{}: {}\n\n{}", code.code, code.title, code.explanation);
+                Ok(())
+            }
+            None => {
+                eprintln!("Unknown error code, try 'reaper explain E001'.");
+                std::process::exit(1);
+            }
+        },
+        (None, Some(path)) if dump_bytecode => {
+            let program = if path.ends_with(".reapc") {
+                reaper::driver::load_artifact(path)
+            } else {
+                reaper::driver::compile_file(path, compile_options)
+            };
+            program.map(|program| {
+                print!("{}", program.disassemble(0..program.code().len()));
+                println!("{}", program.pool_stats());
+            })
+        }
+        (None, Some(path)) if path.ends_with(".reapc") => {
+            reaper::driver::run_artifact(path, &options)
+        }
+        (None, Some(path)) => reaper::driver::run_file_with(path, compile_options, &options),
+        (None, None) => {
+            eprintln!("You must pass in a path.");
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = result {
+        if json_errors && !e.diagnostics().is_empty() {
+            for diagnostic in e.diagnostics() {
+                eprintln!("{}", diagnostic.to_json());
+            }
+        } else {
+            eprintln!("{}", e);
+        }
+        std::process::exit(1);
     }
-
-    Ok(())
 }
@@ -0,0 +1,64 @@
+//! Opaque host values, database connections or game entities, that scripts
+//! pass around but can't look into.
+
+use std::any::Any;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+
+/// A host value tagged with a type name. Clones share the value, and two
+/// natives are equal when they are the same value.
+///
+/// ```
+/// use reaper::native::Native;
+///
+/// struct Connection(u32);
+///
+/// let db = Native::new("db", Connection(7));
+/// assert_eq!(db.name(), "db");
+/// assert_eq!(db.downcast_ref::<Connection>().map(|c| c.0), Some(7));
+/// assert!(db.downcast_ref::<String>().is_none());
+/// ```
+#[derive(Clone)]
+pub struct Native {
+    name: &'static str,
+    value: Arc<dyn Any + Send + Sync + RefUnwindSafe>,
+}
+
+impl Native {
+    /// `name` is what scripts see as the type, in errors and printed
+    /// values. It should not be one of the script types. Values shared
+    /// with the host and mutated are behind a `Mutex` or `RwLock`.
+    pub fn new<T: Any + Send + Sync + RefUnwindSafe>(name: &'static str, value: T) -> Native {
+        Native {
+            name,
+            value: Arc::new(value),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        let value: &(dyn Any + Send + Sync) = &*self.value;
+        value.downcast_ref()
+    }
+
+    /// The shared value, `None` if it isn't a `T`.
+    pub fn downcast<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        let value: Arc<dyn Any + Send + Sync> = self.value.clone();
+        value.downcast().ok()
+    }
+}
+
+impl PartialEq for Native {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.value, &other.value)
+    }
+}
+
+impl std::fmt::Debug for Native {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{}>", self.name)
+    }
+}
@@ -1,6 +1,11 @@
-use crate::tokenizer::{Token, TokenKind};
+use crate::fix::Suggestion;
+use crate::tokenizer::{Span, Token, TokenKind, Trivia, TriviaKind};
 use std::{collections::VecDeque, str::FromStr};
 
+/// Default for how deep statements and expressions may be nested in each
+/// other, the parser and the passes after it recurse that deep.
+pub const MAX_NESTING: usize = 200;
+
 #[derive(Debug)]
 pub enum Expression {
     Literal(LiteralExpression),
@@ -9,11 +14,39 @@ pub enum Expression {
     Call(CallExpression),
     Assign(AssignExpression),
     Unary(UnaryExpression),
+    Index(IndexExpression),
+    TypeTest(TypeTestExpression),
+}
+
+impl Expression {
+    /// Source range of the expression, as far as the AST records it.
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Literal(e) => e.span,
+            Expression::Variable(e) => e.span,
+            Expression::Binary(e) => Span {
+                start: e.lhs.span().start,
+                end: e.rhs.span().end,
+            },
+            Expression::Call(e) => e.span,
+            Expression::Assign(e) => Span {
+                start: e.lhs.span().start,
+                end: e.rhs.span().end,
+            },
+            Expression::Unary(e) => e.expr.span(),
+            Expression::Index(e) => e.span,
+            Expression::TypeTest(e) => Span {
+                start: e.expr.span().start,
+                end: e.type_name.span.end,
+            },
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct LiteralExpression {
     pub value: Literal,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -21,6 +54,7 @@ pub enum Literal {
     Num(f64),
     Bool(bool),
     String(String),
+    Bytes(Vec<u8>),
     Null,
 }
 
@@ -39,6 +73,9 @@ impl FromStr for Literal {
 #[derive(Debug)]
 pub struct VariableExpression {
     pub value: String,
+    pub span: Span,
+    /// Stack slot relative to the frame, filled in by the resolver.
+    pub slot: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -55,25 +92,67 @@ pub enum BinaryExpressionKind {
     Mul,
     Div,
     Less,
-    Equality(bool),
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Eq,
+    NotEq,
     Strcat,
 }
 
 #[derive(Debug)]
 pub struct CallExpression {
-    pub variable: String,
+    pub callee: Box<Expression>,
+    pub span: Span,
     pub arguments: Vec<Expression>,
 }
 
+impl CallExpression {
+    /// Name of the called function when the callee is a plain identifier.
+    pub fn name(&self) -> Option<&str> {
+        match &*self.callee {
+            Expression::Variable(v) => Some(&v.value),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AssignExpression {
     pub lhs: Box<Expression>,
     pub rhs: Box<Expression>,
+    /// Of the `=`.
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum UnaryExpressionKind {
+    /// `!`
+    Not,
+    /// `-`
+    Neg,
 }
 
 #[derive(Debug)]
 pub struct UnaryExpression {
+    pub kind: UnaryExpressionKind,
+    pub expr: Box<Expression>,
+}
+
+/// `expr[index]`, or `expr[index..end]` when `end` is set.
+#[derive(Debug)]
+pub struct IndexExpression {
+    pub expr: Box<Expression>,
+    pub index: Box<Expression>,
+    pub end: Option<Box<Expression>>,
+    pub span: Span,
+}
+
+/// `expr is type`, whether the value has the named type.
+#[derive(Debug)]
+pub struct TypeTestExpression {
     pub expr: Box<Expression>,
+    pub type_name: TypeAnnotation,
 }
 
 #[derive(Debug)]
@@ -86,6 +165,11 @@ pub enum Statement {
     If(IfStatement),
     Block(BlockStatement),
     While(WhileStatement),
+    For(ForStatement),
+    Defer(DeferStatement),
+    DoWhile(DoWhileStatement),
+    Break(BreakStatement),
+    Continue(ContinueStatement),
 }
 
 #[derive(Debug)]
@@ -96,8 +180,29 @@ pub struct PrintStatement {
 #[derive(Debug)]
 pub struct FnStatement {
     pub name: String,
-    pub arguments: Vec<String>,
+    pub span: Span,
+    pub arguments: Vec<Parameter>,
+    pub return_type: Option<TypeAnnotation>,
     pub body: Box<Statement>,
+    /// Stack slots for arguments and locals, filled in by the resolver.
+    pub slots: usize,
+    /// The `///` comment lines right above the declaration, without the
+    /// slashes. Only found in tokens with trivia.
+    pub doc: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub span: Span,
+    pub annotation: Option<TypeAnnotation>,
+}
+
+/// A type name written after a `:`, checked by the `typecheck` pass.
+#[derive(Debug)]
+pub struct TypeAnnotation {
+    pub name: String,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -108,19 +213,71 @@ pub struct ExpressionStatement {
 #[derive(Debug)]
 pub struct ReturnStatement {
     pub expression: Expression,
+    pub span: Span,
 }
 
+/// `defer expression;`, evaluated when the enclosing function returns,
+/// the last deferred first. Only allowed directly in a function body.
+#[derive(Debug)]
+pub struct DeferStatement {
+    pub expression: Expression,
+    pub span: Span,
+}
+
+/// `if (c) s elif (c) s ... else s`, runs the statement of the first
+/// condition that holds, or the `else` one when none does.
 #[derive(Debug)]
 pub struct IfStatement {
-    pub condition: Expression,
-    pub if_branch: Box<Statement>,
-    pub else_branch: Box<Statement>,
+    /// The `if` and any `elif` conditions with their statements.
+    pub branches: Vec<(Expression, Statement)>,
+    pub else_body: Option<Box<Statement>>,
 }
 
 #[derive(Debug)]
 pub struct WhileStatement {
     pub condition: Expression,
     pub body: Box<Statement>,
+    /// Name given with `label: while ...` for `break label`.
+    pub label: Option<String>,
+}
+
+/// `for (initializer; condition; increment) body`. The initializer runs
+/// once, in a scope of the loop, and the increment after each run of the
+/// body. Any of the three may be left out, without a condition the loop
+/// runs until a `break`.
+#[derive(Debug)]
+pub struct ForStatement {
+    pub initializer: Option<Expression>,
+    pub condition: Option<Expression>,
+    pub increment: Option<Expression>,
+    pub body: Box<Statement>,
+    pub label: Option<String>,
+    /// The `for` keyword.
+    pub span: Span,
+}
+
+/// `do body while (condition);`, runs the body at least once.
+#[derive(Debug)]
+pub struct DoWhileStatement {
+    pub body: Box<Statement>,
+    pub condition: Expression,
+    pub label: Option<String>,
+}
+
+/// `break;` leaves the innermost loop, `break label;` the loop with that
+/// label.
+#[derive(Debug)]
+pub struct BreakStatement {
+    pub label: Option<String>,
+    pub span: Span,
+}
+
+/// `continue;` goes on with the next iteration of the innermost loop, past
+/// the rest of its body, `continue label;` with that of the labeled loop.
+#[derive(Debug)]
+pub struct ContinueStatement {
+    pub label: Option<String>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -128,10 +285,35 @@ pub struct BlockStatement {
     pub body: Vec<Statement>,
 }
 
+#[derive(Debug)]
+pub struct ParseError {
+    /// See [`crate::codes`].
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub suggestion: Option<Suggestion>,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.span.start)
+    }
+}
+
 pub struct Parser {
     current: Option<Token>,
     previous: Option<Token>,
     tokens: VecDeque<Token>,
+    position: usize,
+    errors: Vec<ParseError>,
+    /// Set after an error until the next statement boundary, errors in
+    /// between are most likely caused by the first one.
+    panicking: bool,
+    /// Statements and expressions being parsed, innermost included.
+    depth: usize,
+    max_nesting: usize,
+    /// Rejects statements and assignments, see [`crate::sandbox`].
+    expressions_only: bool,
 }
 
 impl Default for Parser {
@@ -146,19 +328,162 @@ impl Parser {
             current: None,
             previous: None,
             tokens: VecDeque::new(),
+            position: 0,
+            errors: Vec::new(),
+            panicking: false,
+            depth: 0,
+            max_nesting: MAX_NESTING,
+            expressions_only: false,
+        }
+    }
+
+    /// A parser that gives up on input nested deeper than `max_nesting`.
+    pub fn with_max_nesting(max_nesting: usize) -> Parser {
+        Parser {
+            max_nesting,
+            ..Parser::new()
         }
     }
 
     pub fn parse(&mut self, tokens: VecDeque<Token>) -> Vec<Statement> {
-        self.tokens = tokens;
-        self.advance();
+        self.reset(tokens);
         let mut statements = vec![];
-        while self.current.is_some() {
-            statements.push(self.parse_statement());
+        while let Some(statement) = self.next_statement() {
+            statements.push(statement);
         }
         statements
     }
 
+    /// Only accepts expression statements without assignments, for
+    /// sandboxed expressions.
+    pub fn set_expressions_only(&mut self, expressions_only: bool) {
+        self.expressions_only = expressions_only;
+    }
+
+    /// Parses `tokens` as one expression without a semicolon, a formula
+    /// rather than a script.
+    pub fn parse_expr(&mut self, tokens: VecDeque<Token>) -> Expression {
+        self.reset(tokens);
+        let expression = self.parse_expression();
+        self.consume(TokenKind::Eof);
+        expression
+    }
+
+    /// Loads `tokens` for statement-wise parsing with
+    /// [`Parser::next_statement`].
+    pub fn reset(&mut self, tokens: VecDeque<Token>) {
+        self.tokens = tokens;
+        self.current = None;
+        self.previous = None;
+        self.position = 0;
+        self.errors.clear();
+        self.panicking = false;
+        self.depth = 0;
+        self.advance();
+    }
+
+    /// Parses the next top level statement, `None` at the end of input.
+    /// Tokens skipped to recover from a syntax error belong to the
+    /// statement with the error, the next one starts where a statement
+    /// parsed from scratch would.
+    pub fn next_statement(&mut self) -> Option<Statement> {
+        if self.current.as_ref()?.kind == TokenKind::Eof {
+            return None;
+        }
+        let statement = self.parse_statement();
+        if self.panicking {
+            self.synchronize();
+        }
+        Some(statement)
+    }
+
+    /// Index of the current token in the loaded token list.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Syntax errors found since the tokens were loaded.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    fn error(&mut self, code: &'static str, message: String) {
+        if self.panicking {
+            return;
+        }
+        self.panicking = true;
+        let span = self.current.as_ref().unwrap().span;
+        self.errors.push(ParseError {
+            code,
+            message,
+            span,
+            suggestion: None,
+        });
+    }
+
+    /// Reports valid syntax that is not allowed for expressions only. The
+    /// parser carries on as usual, unlike after [`Parser::error`].
+    fn disallowed(&mut self, message: String) {
+        if self.panicking {
+            return;
+        }
+        let span = self.current.as_ref().unwrap().span;
+        self.errors.push(ParseError {
+            code: "E016",
+            message,
+            span,
+            suggestion: None,
+        });
+    }
+
+    /// Reports `a < b < c`, which would compare the bool `a < b` with `c`.
+    /// The parser carries on, the rest of the expression is well formed.
+    fn chained_comparison(&mut self) {
+        if self.panicking {
+            return;
+        }
+        let span = self.previous.as_ref().unwrap().span;
+        self.errors.push(ParseError {
+            code: "E004",
+            message: "comparisons can't be chained, compare one pair at a time".to_string(),
+            span,
+            suggestion: None,
+        });
+    }
+
+    /// Skips to the start of the next statement after an error. At the
+    /// end of input there is nothing left to report.
+    fn synchronize(&mut self) {
+        while let Some(token) = &self.current {
+            if token.kind == TokenKind::Eof {
+                return;
+            }
+            if self
+                .previous
+                .as_ref()
+                .is_some_and(|t| t.kind == TokenKind::Semicolon)
+            {
+                break;
+            }
+            match token.kind {
+                TokenKind::Print
+                | TokenKind::Fn
+                | TokenKind::If
+                | TokenKind::While
+                | TokenKind::For
+                | TokenKind::Defer
+                | TokenKind::Do
+                | TokenKind::Break
+                | TokenKind::Continue
+                | TokenKind::Return
+                | TokenKind::LeftBrace
+                | TokenKind::RightBrace => break,
+                _ => self.advance(),
+            }
+        }
+        self.panicking = false;
+    }
+
     fn is_next(&mut self, tokens: &[TokenKind]) -> bool {
         for token in tokens {
             if self.check(*token) {
@@ -170,16 +495,81 @@ impl Parser {
     }
 
     fn check(&self, kind: TokenKind) -> bool {
-        self.current.clone().unwrap().kind == kind
+        self.current.as_ref().is_some_and(|t| t.kind == kind)
     }
 
     fn advance(&mut self) {
+        // Running into the end of input again and again is left to the
+        // callers.
+        if self.check(TokenKind::Eof) {
+            return;
+        }
+        if self.current.is_some() {
+            self.position += 1;
+        }
         self.previous = self.current.clone();
         self.current = self.tokens.pop_front();
     }
 
+    /// Enters a nested statement or expression. Deeper than allowed, the
+    /// rest of the input is skipped as nothing in it could be parsed.
+    fn nest(&mut self) -> bool {
+        if self.depth == self.max_nesting {
+            let message = format!("nested deeper than {} levels", self.max_nesting);
+            self.error("E003", message);
+            while !self.check(TokenKind::Eof) {
+                self.advance();
+            }
+            return false;
+        }
+        self.depth += 1;
+        true
+    }
+
     fn parse_statement(&mut self) -> Statement {
-        if self.is_next(&[TokenKind::Print]) {
+        if !self.nest() {
+            return Statement::Dummy;
+        }
+        let statement = self.parse_nested_statement();
+        self.depth -= 1;
+        statement
+    }
+
+    fn parse_nested_statement(&mut self) -> Statement {
+        if self.panicking {
+            self.synchronize();
+        }
+        let labeled = self.check(TokenKind::Identifier)
+            && self
+                .tokens
+                .front()
+                .is_some_and(|t| t.kind == TokenKind::Colon);
+        // Statements nested in one already reported are left alone.
+        if self.expressions_only && self.depth == 1 {
+            let current = self.current.as_ref().unwrap();
+            let statement = match current.kind {
+                _ if labeled => Some("a loop label".to_string()),
+                TokenKind::Print
+                | TokenKind::Fn
+                | TokenKind::If
+                | TokenKind::While
+                | TokenKind::For
+                | TokenKind::Do
+                | TokenKind::Return
+                | TokenKind::Defer
+                | TokenKind::Break
+                | TokenKind::Continue => Some(format!("'{}'", current.value)),
+                TokenKind::LeftBrace => Some("a block".to_string()),
+                _ => None,
+            };
+            if let Some(statement) = statement {
+                let message = format!("only expressions are allowed, found {}", statement);
+                self.disallowed(message);
+            }
+        }
+        if labeled {
+            self.parse_labeled_statement()
+        } else if self.is_next(&[TokenKind::Print]) {
             self.parse_print_statement()
         } else if self.is_next(&[TokenKind::Fn]) {
             self.parse_fn_statement()
@@ -187,10 +577,20 @@ impl Parser {
             self.parse_if_statement()
         } else if self.is_next(&[TokenKind::While]) {
             self.parse_while_statement()
+        } else if self.is_next(&[TokenKind::For]) {
+            self.parse_for_statement()
+        } else if self.is_next(&[TokenKind::Do]) {
+            self.parse_do_while_statement()
         } else if self.is_next(&[TokenKind::LeftBrace]) {
             self.parse_block_statement()
         } else if self.is_next(&[TokenKind::Return]) {
             self.parse_return_statement()
+        } else if self.is_next(&[TokenKind::Defer]) {
+            self.parse_defer_statement()
+        } else if self.is_next(&[TokenKind::Break]) {
+            self.parse_break_statement()
+        } else if self.is_next(&[TokenKind::Continue]) {
+            self.parse_continue_statement()
         } else {
             self.parse_expression_statement()
         }
@@ -204,37 +604,149 @@ impl Parser {
         Statement::While(WhileStatement {
             condition,
             body: body.into(),
+            label: None,
+        })
+    }
+
+    fn parse_for_statement(&mut self) -> Statement {
+        let span = self.previous.as_ref().unwrap().span;
+        self.consume(TokenKind::LeftParen);
+        let initializer = self.parse_clause(TokenKind::Semicolon);
+        let condition = self.parse_clause(TokenKind::Semicolon);
+        let increment = self.parse_clause(TokenKind::RightParen);
+        let body = self.parse_statement();
+        Statement::For(ForStatement {
+            initializer,
+            condition,
+            increment,
+            body: body.into(),
+            label: None,
+            span,
+        })
+    }
+
+    /// Parses a clause of a `for` up to `end`, `None` if it is left out.
+    fn parse_clause(&mut self, end: TokenKind) -> Option<Expression> {
+        if self.is_next(&[end]) {
+            return None;
+        }
+        let expression = self.parse_expression();
+        self.consume(end);
+        Some(expression)
+    }
+
+    /// Parses `label: loop`, only loops can be labeled.
+    fn parse_labeled_statement(&mut self) -> Statement {
+        let label = self.current.as_ref().unwrap().value.clone();
+        self.advance();
+        self.advance();
+        let mut statement = if self.is_next(&[TokenKind::While]) {
+            self.parse_while_statement()
+        } else if self.is_next(&[TokenKind::For]) {
+            self.parse_for_statement()
+        } else if self.is_next(&[TokenKind::Do]) {
+            self.parse_do_while_statement()
+        } else {
+            let expected = format!(
+                "{}, {} or {}",
+                TokenKind::While,
+                TokenKind::For,
+                TokenKind::Do
+            );
+            self.unexpected(expected);
+            return Statement::Dummy;
+        };
+        match &mut statement {
+            Statement::While(s) => s.label = Some(label),
+            Statement::For(s) => s.label = Some(label),
+            Statement::DoWhile(s) => s.label = Some(label),
+            _ => {}
+        }
+        statement
+    }
+
+    fn parse_break_statement(&mut self) -> Statement {
+        let span = self.previous.as_ref().unwrap().span;
+        let label = self.parse_loop_label();
+        Statement::Break(BreakStatement { label, span })
+    }
+
+    fn parse_continue_statement(&mut self) -> Statement {
+        let span = self.previous.as_ref().unwrap().span;
+        let label = self.parse_loop_label();
+        Statement::Continue(ContinueStatement { label, span })
+    }
+
+    /// Parses the optional label after `break` or `continue` and the `;`.
+    fn parse_loop_label(&mut self) -> Option<String> {
+        let label = if self.check(TokenKind::Identifier) {
+            self.consume(TokenKind::Identifier).map(|t| t.value)
+        } else {
+            None
+        };
+        self.consume(TokenKind::Semicolon);
+        label
+    }
+
+    fn parse_do_while_statement(&mut self) -> Statement {
+        let body = self.parse_statement();
+        self.consume(TokenKind::While);
+        self.consume(TokenKind::LeftParen);
+        let condition = self.parse_expression();
+        self.consume(TokenKind::RightParen);
+        self.consume(TokenKind::Semicolon);
+        Statement::DoWhile(DoWhileStatement {
+            body: body.into(),
+            condition,
+            label: None,
         })
     }
 
     fn parse_return_statement(&mut self) -> Statement {
+        let span = self.previous.as_ref().unwrap().span;
         let expression = self.parse_expression();
         self.consume(TokenKind::Semicolon);
-        Statement::Return(ReturnStatement { expression })
+        Statement::Return(ReturnStatement { expression, span })
+    }
+
+    fn parse_defer_statement(&mut self) -> Statement {
+        let span = self.previous.as_ref().unwrap().span;
+        let expression = self.parse_expression();
+        self.consume(TokenKind::Semicolon);
+        Statement::Defer(DeferStatement { expression, span })
     }
 
     fn parse_block_statement(&mut self) -> Statement {
         let mut body = vec![];
         while !self.is_next(&[TokenKind::RightBrace]) {
+            if self.check(TokenKind::Eof) {
+                self.unexpected(TokenKind::RightBrace.to_string());
+                break;
+            }
             body.push(self.parse_statement());
         }
         Statement::Block(BlockStatement { body })
     }
 
     fn parse_if_statement(&mut self) -> Statement {
-        self.consume(TokenKind::LeftParen);
-        let condition = self.parse_expression();
-        self.consume(TokenKind::RightParen);
-        let if_branch = self.parse_statement();
-        let else_branch: Statement = if self.is_next(&[TokenKind::Else]) {
-            self.parse_statement()
+        let mut branches = vec![];
+        loop {
+            self.consume(TokenKind::LeftParen);
+            let condition = self.parse_expression();
+            self.consume(TokenKind::RightParen);
+            branches.push((condition, self.parse_statement()));
+            if !self.is_next(&[TokenKind::Elif]) {
+                break;
+            }
+        }
+        let else_body = if self.is_next(&[TokenKind::Else]) {
+            Some(Box::new(self.parse_statement()))
         } else {
-            Statement::Dummy
+            None
         };
         Statement::If(IfStatement {
-            condition,
-            if_branch: Box::new(if_branch),
-            else_branch: Box::new(else_branch),
+            branches,
+            else_body,
         })
     }
 
@@ -245,94 +757,244 @@ impl Parser {
     }
 
     fn parse_print_statement(&mut self) -> Statement {
-        self.consume(TokenKind::Print);
         let expression = self.parse_expression();
         self.consume(TokenKind::Semicolon);
         Statement::Print(PrintStatement { expression })
     }
 
     fn parse_fn_statement(&mut self) -> Statement {
-        self.consume(TokenKind::Fn);
-        let name = self.consume(TokenKind::Identifier).unwrap();
+        let doc = doc_comment(&self.previous.as_ref().unwrap().leading_trivia);
+        let Some(name) = self.consume(TokenKind::Identifier) else {
+            return Statement::Dummy;
+        };
         self.consume(TokenKind::LeftParen);
-        let mut arguments = vec![];
-        while !self.is_next(&[TokenKind::RightParen]) {
-            let arg = self.consume(TokenKind::Identifier).unwrap();
-            self.consume(TokenKind::Comma);
-            arguments.push(arg.value);
+        let (arguments, close) = self.separated(TokenKind::RightParen, |parser| {
+            let arg = parser.consume(TokenKind::Identifier)?;
+            Some(Parameter {
+                name: arg.value,
+                span: arg.span,
+                annotation: parser.parse_type_annotation(),
+            })
+        });
+        if close.is_none() {
+            return Statement::Dummy;
         }
+        let return_type = self.parse_type_annotation();
         self.consume(TokenKind::LeftBrace);
         let body = self.parse_block_statement();
         Statement::Fn(FnStatement {
             name: name.value,
+            span: name.span,
             arguments,
+            return_type,
             body: body.into(),
+            slots: 0,
+            doc,
         })
     }
 
+    /// Parses comma separated items up to and including `close`, a comma
+    /// after the last item is allowed. The closing token is `None` when an
+    /// item or the closing token is missing.
+    fn separated<T>(
+        &mut self,
+        close: TokenKind,
+        mut item: impl FnMut(&mut Parser) -> Option<T>,
+    ) -> (Vec<T>, Option<Token>) {
+        let mut items = vec![];
+        while !self.check(close) {
+            match item(self) {
+                Some(parsed) => items.push(parsed),
+                None => return (items, None),
+            }
+            if !self.is_next(&[TokenKind::Comma]) {
+                break;
+            }
+        }
+        let close = self.consume(close);
+        (items, close)
+    }
+
+    fn parse_type_annotation(&mut self) -> Option<TypeAnnotation> {
+        if !self.is_next(&[TokenKind::Colon]) {
+            return None;
+        }
+        let token = if self.is_next(&[TokenKind::Null]) {
+            self.previous.clone().unwrap()
+        } else {
+            self.consume(TokenKind::Identifier)?
+        };
+        Some(TypeAnnotation {
+            name: token.value,
+            span: token.span,
+        })
+    }
+
+    /// Takes the current token when it is a `kind`, otherwise reports it
+    /// and leaves it for error recovery.
     fn consume(&mut self, kind: TokenKind) -> Option<Token> {
         if self.check(kind) {
             let token = self.current.clone();
             self.advance();
             return token;
         }
+        let reported = self.errors.len();
+        self.unexpected(kind.to_string());
+        // Most likely forgotten at the end of the previous line.
+        if kind == TokenKind::Semicolon && self.errors.len() > reported {
+            if let Some(previous) = &self.previous {
+                let insert = Suggestion::insert(previous.span.end, ";");
+                self.errors.last_mut().unwrap().suggestion = Some(insert);
+            }
+        }
         None
     }
 
+    /// Reports the current token where `expected` should have been.
+    fn unexpected(&mut self, expected: String) {
+        let current = self.current.as_ref().unwrap();
+        let (code, message) = match current.kind {
+            TokenKind::Eof => (
+                "E002",
+                format!("unexpected end of file, expected {}", expected),
+            ),
+            _ => (
+                "E001",
+                format!("expected {}, found '{}'", expected, current.value),
+            ),
+        };
+        self.error(code, message);
+    }
+
     fn parse_expression(&mut self) -> Expression {
-        self.assignment()
+        if !self.nest() {
+            return self.missing_expression();
+        }
+        let expr = self.assignment();
+        self.depth -= 1;
+        expr
+    }
+
+    /// Stands in for an expression that couldn't be parsed.
+    fn missing_expression(&self) -> Expression {
+        Expression::Literal(LiteralExpression {
+            value: Literal::Null,
+            span: self.current.as_ref().unwrap().span,
+        })
     }
 
     fn assignment(&mut self) -> Expression {
-        let mut result = self.equality();
-        while self.is_next(&[TokenKind::Equal]) {
-            result = Expression::Assign(AssignExpression {
+        let result = self.equality();
+        if self.expressions_only && self.check(TokenKind::Equal) {
+            let message = "only expressions are allowed, found an assignment".to_string();
+            self.disallowed(message);
+        }
+        if self.is_next(&[TokenKind::Equal]) {
+            let span = self.previous.as_ref().unwrap().span;
+            if !self.nest() {
+                return result;
+            }
+            // Right associative, `a = b = 1` assigns `b` first.
+            let rhs = self.assignment();
+            self.depth -= 1;
+            return Expression::Assign(AssignExpression {
                 lhs: result.into(),
-                rhs: self.equality().into(),
-            })
+                rhs: rhs.into(),
+                span,
+            });
         }
         result
     }
 
     fn equality(&mut self) -> Expression {
+        let depth = self.depth;
         let mut result = self.relational();
         while self.is_next(&[TokenKind::DoubleEqual, TokenKind::BangEqual]) {
-            let negation = match self.previous.clone().unwrap().kind {
-                TokenKind::BangEqual => true,
-                TokenKind::DoubleEqual => false,
+            if !self.nest() {
+                break;
+            }
+            let kind = match self.previous.clone().unwrap().kind {
+                TokenKind::BangEqual => BinaryExpressionKind::NotEq,
+                TokenKind::DoubleEqual => BinaryExpressionKind::Eq,
                 _ => unreachable!(),
             };
             result = Expression::Binary(BinaryExpression {
-                kind: BinaryExpressionKind::Equality(negation),
+                kind,
                 lhs: Box::new(result),
                 rhs: Box::new(self.relational()),
             });
         }
+        self.depth = depth;
         result
     }
 
     fn relational(&mut self) -> Expression {
+        let depth = self.depth;
         let mut result = self.term();
-        while self.is_next(&[TokenKind::Less]) {
+        while self.is_next(&[
+            TokenKind::Less,
+            TokenKind::LessEqual,
+            TokenKind::Greater,
+            TokenKind::GreaterEqual,
+            TokenKind::Is,
+        ]) {
+            if !self.nest() {
+                break;
+            }
+            if self.previous.as_ref().unwrap().kind == TokenKind::Is {
+                result = self.parse_type_test(result);
+                continue;
+            }
             let kind = match self.previous.clone() {
                 Some(token) => match token.kind {
                     TokenKind::Less => BinaryExpressionKind::Less,
+                    TokenKind::LessEqual => BinaryExpressionKind::LessEqual,
+                    TokenKind::Greater => BinaryExpressionKind::Greater,
+                    TokenKind::GreaterEqual => BinaryExpressionKind::GreaterEqual,
                     _ => unreachable!(),
                 },
                 None => unreachable!(),
             };
+            if is_comparison(&result) {
+                self.chained_comparison();
+            }
             result = Expression::Binary(BinaryExpression {
                 kind,
                 lhs: Box::new(result),
                 rhs: Box::new(self.term()),
             });
         }
+        self.depth = depth;
         result
     }
 
+    /// Parses the type after `expr is`. `null` is a type name as well.
+    fn parse_type_test(&mut self, expr: Expression) -> Expression {
+        let type_name = if self.is_next(&[TokenKind::Null]) {
+            self.previous.clone()
+        } else {
+            self.consume(TokenKind::Identifier)
+        };
+        let type_name = match type_name {
+            Some(token) => TypeAnnotation {
+                name: token.value,
+                span: token.span,
+            },
+            None => return expr,
+        };
+        Expression::TypeTest(TypeTestExpression {
+            expr: expr.into(),
+            type_name,
+        })
+    }
+
     fn term(&mut self) -> Expression {
+        let depth = self.depth;
         let mut result = self.factor();
         while self.is_next(&[TokenKind::Plus, TokenKind::Minus, TokenKind::PlusPlus]) {
+            if !self.nest() {
+                break;
+            }
             let kind = match self.previous.clone() {
                 Some(token) => match token.kind {
                     TokenKind::Plus => BinaryExpressionKind::Add,
@@ -348,12 +1010,17 @@ impl Parser {
                 rhs: Box::new(self.factor()),
             });
         }
+        self.depth = depth;
         result
     }
 
     fn factor(&mut self) -> Expression {
+        let depth = self.depth;
         let mut result = self.unary();
         while self.is_next(&[TokenKind::Star, TokenKind::Slash]) {
+            if !self.nest() {
+                break;
+            }
             let kind = match self.previous.clone() {
                 Some(token) => match token.kind {
                     TokenKind::Star => BinaryExpressionKind::Mul,
@@ -368,68 +1035,175 @@ impl Parser {
                 rhs: Box::new(self.unary()),
             });
         }
+        self.depth = depth;
         result
     }
 
     fn unary(&mut self) -> Expression {
-        if self.is_next(&[TokenKind::Bang]) {
+        if self.is_next(&[TokenKind::Bang, TokenKind::Minus]) {
+            let kind = match self.previous.as_ref().unwrap().kind {
+                TokenKind::Bang => UnaryExpressionKind::Not,
+                _ => UnaryExpressionKind::Neg,
+            };
+            if !self.nest() {
+                return self.missing_expression();
+            }
             let right = self.unary();
-            let result = Expression::Unary(UnaryExpression { expr: right.into() });
+            self.depth -= 1;
+            let result = Expression::Unary(UnaryExpression {
+                kind,
+                expr: right.into(),
+            });
             return result;
         }
         self.call()
     }
 
+    /// A primary expression followed by any number of calls and indexing.
     fn call(&mut self) -> Expression {
+        let depth = self.depth;
         let mut expr = self.primary();
-        if self.is_next(&[TokenKind::LeftParen]) {
-            let mut arguments = vec![];
-            if !self.check(TokenKind::RightParen) {
-                loop {
-                    arguments.push(self.parse_expression());
-                    if !self.is_next(&[TokenKind::Comma]) {
-                        break;
-                    }
-                }
+        while self.check(TokenKind::LeftParen) || self.check(TokenKind::LeftBracket) {
+            if !self.nest() {
+                break;
+            }
+            if self.is_next(&[TokenKind::LeftParen]) {
+                expr = self.finish_call(expr);
+            } else if self.is_next(&[TokenKind::LeftBracket]) {
+                expr = self.finish_index(expr);
             }
-            self.consume(TokenKind::RightParen);
-            let name = match expr {
-                Expression::Variable(v) => v.value,
-                _ => unimplemented!(),
-            };
-            expr = Expression::Call(CallExpression {
-                variable: name,
-                arguments,
-            });
         }
+        self.depth = depth;
         expr
     }
 
+    fn finish_call(&mut self, callee: Expression) -> Expression {
+        let (arguments, close) = self.separated(TokenKind::RightParen, |parser| {
+            Some(parser.parse_expression())
+        });
+        let start = callee.span().start;
+        let end = close.map_or(callee.span().end, |t| t.span.end);
+        Expression::Call(CallExpression {
+            callee: callee.into(),
+            span: Span { start, end },
+            arguments,
+        })
+    }
+
+    fn finish_index(&mut self, expr: Expression) -> Expression {
+        let index = self.parse_expression();
+        let end = if self.is_next(&[TokenKind::DotDot]) {
+            Some(self.parse_expression().into())
+        } else {
+            None
+        };
+        let start = expr.span().start;
+        let close = self.consume(TokenKind::RightBracket);
+        let end_offset = close.map_or(index.span().end, |t| t.span.end);
+        Expression::Index(IndexExpression {
+            expr: expr.into(),
+            index: index.into(),
+            end,
+            span: Span {
+                start,
+                end: end_offset,
+            },
+        })
+    }
+
     fn primary(&mut self) -> Expression {
         if self.is_next(&[TokenKind::Number]) {
-            let n = self.previous.clone().unwrap().value.parse().unwrap();
+            let token = self.previous.clone().unwrap();
             Expression::Literal(LiteralExpression {
-                value: Literal::Num(n),
+                value: Literal::Num(token.value.parse().unwrap()),
+                span: token.span,
             })
         } else if self.is_next(&[TokenKind::Identifier]) {
-            let var = self.previous.clone().unwrap().value;
-            Expression::Variable(VariableExpression { value: var })
+            let token = self.previous.clone().unwrap();
+            let mut value = token.value;
+            let mut span = token.span;
+            // A builtin in a namespace, `math.sqrt`.
+            while self.is_next(&[TokenKind::Dot]) {
+                let Some(name) = self.consume(TokenKind::Identifier) else {
+                    break;
+                };
+                value = format!("{}.{}", value, name.value);
+                span.end = name.span.end;
+            }
+            Expression::Variable(VariableExpression {
+                value,
+                span,
+                slot: None,
+            })
         } else if self.is_next(&[TokenKind::True, TokenKind::False, TokenKind::Null]) {
-            let literal: Literal = self
-                .previous
-                .clone()
-                .unwrap()
-                .value
-                .parse()
-                .expect("Failed to parse a literal.");
-            Expression::Literal(LiteralExpression { value: literal })
+            let token = self.previous.clone().unwrap();
+            // By kind, keyword tables may spell them differently.
+            let literal = match token.kind {
+                TokenKind::True => Literal::Bool(true),
+                TokenKind::False => Literal::Bool(false),
+                _ => Literal::Null,
+            };
+            Expression::Literal(LiteralExpression {
+                value: literal,
+                span: token.span,
+            })
         } else if self.is_next(&[TokenKind::String]) {
-            let string = self.previous.clone().unwrap().value;
+            let token = self.previous.clone().unwrap();
             Expression::Literal(LiteralExpression {
-                value: Literal::String(string),
+                value: Literal::String(token.value),
+                span: token.span,
+            })
+        } else if self.is_next(&[TokenKind::Bytes]) {
+            let token = self.previous.clone().unwrap();
+            Expression::Literal(LiteralExpression {
+                value: Literal::Bytes(token.value.into_bytes()),
+                span: token.span,
             })
         } else {
-            todo!();
+            // Skip the offending token so that parsing makes progress.
+            self.unexpected("expression".to_string());
+            let span = self.current.as_ref().unwrap().span;
+            self.advance();
+            Expression::Literal(LiteralExpression {
+                value: Literal::Null,
+                span,
+            })
+        }
+    }
+}
+
+fn is_comparison(expr: &Expression) -> bool {
+    matches!(
+        expr,
+        Expression::Binary(BinaryExpression {
+            kind: BinaryExpressionKind::Less
+                | BinaryExpressionKind::LessEqual
+                | BinaryExpressionKind::Greater
+                | BinaryExpressionKind::GreaterEqual,
+            ..
+        })
+    )
+}
+
+/// The `///` lines at the end of `trivia`, up to a blank line or any other
+/// comment.
+fn doc_comment(trivia: &[Trivia]) -> Option<String> {
+    let mut lines = vec![];
+    for trivia in trivia.iter().rev() {
+        match trivia.kind {
+            TriviaKind::Whitespace if trivia.text.matches('\n').count() > 1 => break,
+            TriviaKind::Whitespace => {}
+            TriviaKind::Comment => match trivia.text.strip_prefix("///") {
+                Some(line) if !line.starts_with('/') => {
+                    lines.push(line.strip_prefix(' ').unwrap_or(line))
+                }
+                _ => break,
+            },
         }
     }
+    if lines.is_empty() {
+        return None;
+    }
+    lines.reverse();
+    Some(lines.join("\n"))
 }
@@ -0,0 +1,14 @@
+use crate::parser::{Parser, Statement};
+use crate::tokenizer::Tokenizer;
+
+/// The part of the standard library written in reaper itself.
+pub const SOURCE: &str = include_str!("prelude.reap");
+
+/// The function declarations of the prelude, to be compiled in front of a
+/// program.
+pub fn statements() -> Vec<Statement> {
+    let mut parser = Parser::default();
+    let statements = parser.parse(Tokenizer::new(SOURCE).collect());
+    assert!(parser.errors().is_empty(), "broken prelude");
+    statements
+}
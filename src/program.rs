@@ -0,0 +1,420 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::builtins::BUILTINS;
+use crate::compiler::Opcode;
+use crate::tokenizer::Span;
+use crate::vm::Object;
+
+#[derive(Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    /// Index of the first instruction of the body.
+    pub addr: usize,
+    /// Stack slots for arguments and locals, reserved by `Invoke`.
+    pub slots: usize,
+}
+
+/// Shown as `<fn name/arity>`, where the function lives is no business of
+/// a script printing it.
+impl std::fmt::Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}/{}>", self.name, self.arity)
+    }
+}
+
+/// Name of an argument or local, for debuggers and crash reports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalName {
+    /// `None` for the script's own locals.
+    pub function: Option<String>,
+    /// Stack slot relative to the frame.
+    pub slot: usize,
+    /// Addresses of the instructions the slot holds the variable at.
+    pub live: Range<usize>,
+    pub name: String,
+}
+
+/// Entries of the constant pool of a program by kind.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PoolStats {
+    pub numbers: usize,
+    pub strings: usize,
+    pub functions: usize,
+    /// Bools, nulls and lists.
+    pub other: usize,
+    /// Length of all strings together.
+    pub string_bytes: usize,
+}
+
+impl std::fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} constants: {} numbers, {} strings ({} bytes), {} functions, {} other",
+            self.numbers + self.strings + self.functions + self.other,
+            self.numbers,
+            self.strings,
+            self.string_bytes,
+            self.functions,
+            self.other
+        )
+    }
+}
+
+/// Bytecode ending in `EndOfProgram` with every jump landing in it, every
+/// `Invoke` followed by its `Jmp` and every `Native` calling a builtin with
+/// as many arguments as it takes. Executing it, `ip` can't leave the code:
+/// instructions but `EndOfProgram` step to the next one, which exists,
+/// jumps and calls to program functions are checked here, and calls of
+/// function values where they happen. The operands that depend on the
+/// rest of the program are checked by [`Program::new`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Code(Vec<Opcode>);
+
+impl Code {
+    /// Checks `code` and terminates it with `EndOfProgram`.
+    pub fn new(mut code: Vec<Opcode>) -> Result<Code, String> {
+        code.push(Opcode::EndOfProgram);
+        for (at, op) in code.iter().enumerate() {
+            match *op {
+                Opcode::Jmp(offset) | Opcode::Jz(offset) => {
+                    let target = at as i64 + offset as i64;
+                    if target < 0 || target >= code.len() as i64 {
+                        return Err(format!("jump out of bounds at {}", at));
+                    }
+                }
+                Opcode::Invoke(..) if !matches!(code[at + 1], Opcode::Jmp(_)) => {
+                    return Err(format!("call without a jump at {}", at));
+                }
+                Opcode::Native(builtin, n) => {
+                    let Some(builtin) = BUILTINS.get(builtin as usize) else {
+                        return Err(format!("builtin {} out of bounds at {}", builtin, at));
+                    };
+                    if builtin.parameters.len() != n as usize {
+                        return Err(format!(
+                            "'{}' called with {} arguments at {}",
+                            builtin.name, n, at
+                        ));
+                    }
+                }
+                Opcode::SetIndex(_, 0) => {
+                    return Err(format!("element assignment without an index at {}", at));
+                }
+                _ => {}
+            }
+        }
+        Ok(Code(code))
+    }
+
+    /// The instruction at `ip`. It can only be out of the code through a
+    /// bug in the VM, the check is there for soundness and predicted to
+    /// pass.
+    #[inline(always)]
+    pub(crate) fn fetch(&self, ip: usize) -> Opcode {
+        match self.0.get(ip) {
+            Some(op) => *op,
+            None => out_of_bounds(ip),
+        }
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn out_of_bounds(ip: usize) -> ! {
+    panic!("ip {} out of bounds", ip)
+}
+
+impl std::ops::Deref for Code {
+    type Target = [Opcode];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Where an instruction runs: the script's frame, with as many slots as
+/// its code uses, or that of a function call with that many slots.
+type Frame = Option<usize>;
+
+/// Follows every path through `code` from the start of the script and the
+/// `entries` of functions, tracking the values each instruction finds on
+/// the stack above the frame's slots. Those have to be the same however
+/// an instruction is reached, enough for its operands, and locals have to
+/// be in the frame. Calls are assumed to return, with one value in place
+/// of their arguments. Returns the slots the script uses.
+fn verify(
+    code: &Code,
+    constants: &[Object],
+    entries: &HashSet<(usize, usize)>,
+) -> Result<usize, String> {
+    let mut depths: HashMap<(usize, Frame), usize> = HashMap::new();
+    let mut pending: Vec<(usize, Frame, usize)> = vec![(0, None, 0)];
+    pending.extend(entries.iter().map(|&(addr, slots)| (addr, Some(slots), 0)));
+    let mut script_slots = 0;
+    while let Some((at, frame, depth)) = pending.pop() {
+        // The program ends here with whatever is on the stack.
+        if let Opcode::EndOfProgram = code[at] {
+            continue;
+        }
+        match depths.insert((at, frame), depth) {
+            Some(known) if known == depth => continue,
+            Some(_) => return Err(format!("inconsistent stack depth at {}", at)),
+            None => {}
+        }
+        let op = code[at];
+        let (pops, pushes) = match op {
+            Opcode::Const(idx) if idx as usize >= constants.len() => {
+                return Err(format!("constant {} out of bounds at {}", idx, at));
+            }
+            Opcode::Const(_)
+            | Opcode::Null
+            | Opcode::False
+            | Opcode::GetLocal(_)
+            | Opcode::Take(_) => (0, 1),
+            Opcode::Not | Opcode::Neg | Opcode::TypeTest(_) => (1, 1),
+            Opcode::Add
+            | Opcode::Sub
+            | Opcode::Mul
+            | Opcode::Div
+            | Opcode::Eq
+            | Opcode::Neq
+            | Opcode::Less
+            | Opcode::LessEqual
+            | Opcode::Greater
+            | Opcode::GreaterEqual
+            | Opcode::Strcat
+            | Opcode::Index => (2, 1),
+            Opcode::Slice => (3, 1),
+            Opcode::Print | Opcode::Pop | Opcode::SetLocal(_) | Opcode::Jz(_) => (1, 0),
+            Opcode::Jmp(_) | Opcode::EndOfProgram => (0, 0),
+            Opcode::Ret => (1, 0),
+            Opcode::Invoke(n, _) => (n as usize, 1),
+            Opcode::Call(n) => (n as usize + 1, 1),
+            Opcode::Native(_, n) => (n as usize, 1),
+            Opcode::SetIndex(_, n) => (n as usize + 1, 1),
+        };
+        if depth < pops {
+            return Err(format!("stack underflow at {}", at));
+        }
+        if let Opcode::GetLocal(slot)
+        | Opcode::Take(slot)
+        | Opcode::SetLocal(slot)
+        | Opcode::SetIndex(slot, _) = op
+        {
+            match frame {
+                Some(slots) if slot as usize >= slots => {
+                    return Err(format!("local {} out of bounds at {}", slot, at));
+                }
+                Some(_) => {}
+                None => script_slots = script_slots.max(slot as usize + 1),
+            }
+        }
+        let next = depth - pops + pushes;
+        match op {
+            Opcode::Jmp(offset) => {
+                pending.push((at.wrapping_add_signed(offset as isize), frame, next));
+            }
+            Opcode::Jz(offset) => {
+                pending.push((at.wrapping_add_signed(offset as isize), frame, next));
+                pending.push((at + 1, frame, next));
+            }
+            // The `Jmp` behind runs in the callee's frame, the call
+            // returns behind it.
+            Opcode::Invoke(n, slots) => {
+                let slots = (slots as usize).max(n as usize);
+                pending.push((at + 1, Some(slots), 0));
+                pending.push((at + 2, frame, next));
+            }
+            Opcode::Ret => {}
+            _ => pending.push((at + 1, frame, next)),
+        }
+    }
+    Ok(script_slots)
+}
+
+/// Compiled bytecode and its function table. A program is immutable once
+/// built, wrap it in an `Arc` to run it on any number of VMs at once.
+#[derive(Debug)]
+pub struct Program {
+    code: Code,
+    /// Operands of `Const`, by index.
+    constants: Vec<Object>,
+    functions: HashMap<String, Arc<Function>>,
+    /// Stack slots of the script's own locals, reserved by the VM on load.
+    slots: usize,
+    /// Slots of the variables declared at the top level of the script.
+    globals: HashMap<String, usize>,
+    /// Source of the instructions that fail on bad operands, by address.
+    spans: Vec<(usize, Span)>,
+    /// Names of the slots in each function.
+    locals: Vec<LocalName>,
+    /// Addresses of the functions of the program and its constants, with
+    /// the slots of their frames, the calls of function values that were
+    /// checked.
+    entries: HashSet<(usize, usize)>,
+}
+
+impl Program {
+    /// Terminates `code` with `EndOfProgram` and checks that it can't make
+    /// the VM go wrong, see [`Code::new`]. All paths from the start and
+    /// from the functions in `functions` and `constants` are followed, for
+    /// constants in the pool, locals in their frame and operands on the
+    /// stack.
+    pub fn new(
+        code: Vec<Opcode>,
+        constants: Vec<Object>,
+        functions: HashMap<String, Arc<Function>>,
+    ) -> Result<Program, String> {
+        let code = Code::new(code)?;
+        let values = constants.iter().filter_map(|c| match c {
+            Object::Function(f) => Some(f),
+            _ => None,
+        });
+        let mut entries = HashSet::new();
+        for function in functions.values().chain(values) {
+            if function.addr >= code.len() {
+                return Err(format!("function '{}' starts out of bounds", function.name));
+            }
+            entries.insert((function.addr, function.slots.max(function.arity)));
+        }
+        let slots = verify(&code, &constants, &entries)?;
+        Ok(Program {
+            code,
+            constants,
+            functions,
+            slots,
+            globals: HashMap::new(),
+            spans: Vec::new(),
+            locals: Vec::new(),
+            entries,
+        })
+    }
+
+    /// Sets the slots of the script's own locals, at least those its code
+    /// and `globals` use.
+    pub fn with_globals(mut self, slots: usize, globals: HashMap<String, usize>) -> Program {
+        let used = globals.values().map(|&slot| slot + 1).max().unwrap_or(0);
+        self.slots = self.slots.max(slots).max(used);
+        self.globals = globals;
+        self
+    }
+
+    /// Whether calling the function value starting at `addr` with a frame
+    /// of `slots` runs checked code.
+    pub fn is_entry(&self, addr: usize, slots: usize) -> bool {
+        self.entries.contains(&(addr, slots))
+    }
+
+    /// Records where in the source the instructions come from, `spans`
+    /// must be sorted by address.
+    pub fn with_spans(mut self, spans: Vec<(usize, Span)>) -> Program {
+        self.spans = spans;
+        self
+    }
+
+    pub fn spans(&self) -> &[(usize, Span)] {
+        &self.spans
+    }
+
+    /// Source of the instruction at `addr`, if it was recorded.
+    pub fn span(&self, addr: usize) -> Option<Span> {
+        let idx = self.spans.binary_search_by_key(&addr, |&(at, _)| at).ok()?;
+        Some(self.spans[idx].1)
+    }
+
+    pub fn with_locals(mut self, locals: Vec<LocalName>) -> Program {
+        self.locals = locals;
+        self
+    }
+
+    pub fn locals(&self) -> &[LocalName] {
+        &self.locals
+    }
+
+    /// Name of the variable in `slot` of a frame of `function` (`None` for
+    /// the script) that is executing `addr`, if it was recorded.
+    pub fn local_name(&self, function: Option<&str>, addr: usize, slot: usize) -> Option<&str> {
+        self.locals
+            .iter()
+            .find(|l| l.slot == slot && l.live.contains(&addr) && l.function.as_deref() == function)
+            .map(|l| l.name.as_str())
+    }
+
+    pub fn code(&self) -> &Code {
+        &self.code
+    }
+
+    pub fn constants(&self) -> &[Object] {
+        &self.constants
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        let mut stats = PoolStats::default();
+        for constant in &self.constants {
+            match constant {
+                Object::Number(_) => stats.numbers += 1,
+                Object::String(s) => {
+                    stats.strings += 1;
+                    stats.string_bytes += s.len();
+                }
+                Object::Function(_) => stats.functions += 1,
+                _ => stats.other += 1,
+            }
+        }
+        stats
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name).map(|f| &**f)
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.functions.values().map(|f| &**f)
+    }
+
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    pub fn global(&self, name: &str) -> Option<usize> {
+        self.globals.get(name).copied()
+    }
+
+    pub fn globals(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.globals
+            .iter()
+            .map(|(name, &slot)| (name.as_str(), slot))
+    }
+
+    /// Lists the instructions in `addrs`, one per line with its address.
+    /// Constants are shown with their value, jumps with their target and
+    /// each function starts with a `fn name/arity:` header.
+    pub fn disassemble(&self, addrs: Range<usize>) -> String {
+        let mut functions: Vec<_> = self.functions().collect();
+        functions.sort_by(|a, b| (a.addr, &a.name).cmp(&(b.addr, &b.name)));
+        let mut out = String::new();
+        for at in addrs {
+            for function in functions.iter().filter(|f| f.addr == at) {
+                out += &format!("fn {}/{}:\n", function.name, function.arity);
+            }
+            let op = self.code[at];
+            out += &format!("{:>5} {:?}", at, op);
+            match op {
+                Opcode::Const(idx) => out += &format!("  ; {:?}", self.constants[idx as usize]),
+                Opcode::Jmp(offset) | Opcode::Jz(offset) => {
+                    let target = at.wrapping_add_signed(offset as isize);
+                    match functions.iter().find(|f| f.addr == target) {
+                        Some(function) => out += &format!("  ; -> {}", function.name),
+                        None => out += &format!("  ; -> {}", target),
+                    }
+                }
+                _ => {}
+            }
+            out += "\n";
+        }
+        out
+    }
+}
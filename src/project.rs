@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+
+use crate::artifact;
+use crate::compiler::CompileOptions;
+use crate::driver::{compile_files, Error};
+use crate::lint::{self, LintConfig};
+use crate::util::SourceManager;
+
+/// The contents of a `reaper.toml`:
+///
+/// ```toml
+/// # Runs last, after the top level code of all modules.
+/// entry = "main.reap"
+/// # Directories searched for `.reap` modules, optional.
+/// sources = ["lib"]
+/// # Optional, defaults to the name of the entry point.
+/// output = "build/app.reapc"
+/// # Rules `reaper lint` skips, by name or code, and the nesting it
+/// # allows, both optional.
+/// lint.allow = ["unused-result", "W003"]
+/// lint.max-nesting = 6
+/// ```
+///
+/// Only string, string array and number values are supported. Paths are
+/// relative to the directory of the manifest.
+#[derive(Debug, PartialEq)]
+pub struct Manifest {
+    pub entry: PathBuf,
+    pub sources: Vec<PathBuf>,
+    pub output: PathBuf,
+    pub lint: LintConfig,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Manifest, Error> {
+        let text = std::fs::read_to_string(path)?;
+        let root = path.parent().unwrap_or(Path::new(""));
+        Manifest::parse(&text, root).map_err(Error::Manifest)
+    }
+
+    pub fn parse(text: &str, root: &Path) -> Result<Manifest, String> {
+        let mut entry = None;
+        let mut sources = vec![];
+        let mut output = None;
+        let mut lint = LintConfig::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("{} on line {}", message, number + 1);
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected 'key = value'"))?;
+            let value = value.trim();
+            match key.trim() {
+                "entry" => {
+                    entry =
+                        Some(root.join(string(value).ok_or_else(|| error("expected a string"))?))
+                }
+                "output" => {
+                    output =
+                        Some(root.join(string(value).ok_or_else(|| error("expected a string"))?))
+                }
+                "sources" => {
+                    let strings =
+                        strings(value).ok_or_else(|| error("expected an array of strings"))?;
+                    sources = strings.into_iter().map(|s| root.join(s)).collect();
+                }
+                "lint.allow" => {
+                    let names =
+                        strings(value).ok_or_else(|| error("expected an array of strings"))?;
+                    lint.allow = names
+                        .into_iter()
+                        .map(|name| {
+                            lint::rule(name)
+                                .map(|rule| rule.name)
+                                .ok_or_else(|| error(&format!("unknown lint '{}'", name)))
+                        })
+                        .collect::<Result<_, _>>()?;
+                }
+                "lint.max-nesting" => {
+                    lint.max_nesting = value.parse().map_err(|_| error("expected a number"))?
+                }
+                key => return Err(error(&format!("unknown key '{}'", key))),
+            }
+        }
+        let entry: PathBuf = entry.ok_or("missing 'entry'")?;
+        let output = output.unwrap_or_else(|| entry.with_extension("reapc"));
+        Ok(Manifest {
+            entry,
+            sources,
+            output,
+            lint,
+        })
+    }
+
+    /// The `.reap` files of the source directories, including nested
+    /// directories, sorted by path, and the entry point last.
+    pub fn files(&self) -> Result<Vec<PathBuf>, std::io::Error> {
+        let mut files = vec![];
+        for directory in &self.sources {
+            collect_modules(directory, &mut files)?;
+        }
+        files.sort();
+        files.dedup();
+        let entry = std::fs::canonicalize(&self.entry)?;
+        let mut modules = vec![];
+        for file in files {
+            if std::fs::canonicalize(&file)? != entry {
+                modules.push(file);
+            }
+        }
+        modules.push(self.entry.clone());
+        Ok(modules)
+    }
+}
+
+fn collect_modules(directory: &Path, files: &mut Vec<PathBuf>) -> Result<(), std::io::Error> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_modules(&path, files)?;
+        } else if path.extension().is_some_and(|e| e == "reap") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// A double quoted string without escapes.
+fn string(value: &str) -> Option<&str> {
+    value
+        .strip_prefix('"')?
+        .strip_suffix('"')
+        .filter(|s| !s.contains('"'))
+}
+
+fn strings(value: &str) -> Option<Vec<&str>> {
+    let items = value.strip_prefix('[')?.strip_suffix(']')?.trim();
+    items
+        .split(',')
+        .map(str::trim)
+        // A trailing comma leaves an empty item.
+        .filter(|item| !item.is_empty())
+        .map(string)
+        .collect()
+}
+
+/// Compiles the project of the manifest at `path` into its output
+/// artifact, returns the path of the artifact.
+pub fn build(path: &Path, compile_options: impl Into<CompileOptions>) -> Result<PathBuf, Error> {
+    let manifest = Manifest::load(path)?;
+    let mut sources = SourceManager::new();
+    let mut files = vec![];
+    for file in manifest.files()? {
+        files.push(sources.load(&file.to_string_lossy())?);
+    }
+    let program = compile_files(&sources, &files, compile_options)?;
+    if let Some(directory) = manifest.output.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    std::fs::write(&manifest.output, artifact::write(&program))?;
+    Ok(manifest.output)
+}
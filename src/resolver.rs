@@ -0,0 +1,541 @@
+use std::collections::HashMap;
+
+use crate::builtins;
+use crate::parser::{
+    AssignExpression, BinaryExpression, BlockStatement, BreakStatement, CallExpression,
+    ContinueStatement, DeferStatement, DoWhileStatement, Expression, ExpressionStatement,
+    FnStatement, ForStatement, IfStatement, IndexExpression, PrintStatement, ReturnStatement,
+    Statement, UnaryExpression, VariableExpression, WhileStatement,
+};
+use crate::sandbox::Sandbox;
+use crate::tokenizer::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScopeKind {
+    Script,
+    Function,
+    Block,
+}
+
+#[derive(Debug)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub parent: Option<usize>,
+    pub declarations: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeclarationKind {
+    Function,
+    /// Declared by the embedder, see [`Resolver::declare_global`].
+    Global,
+    Parameter,
+    Local,
+}
+
+#[derive(Debug)]
+pub struct Declaration {
+    pub name: String,
+    pub kind: DeclarationKind,
+    pub scope: usize,
+    /// Stack slot relative to the frame, `None` for functions.
+    pub slot: Option<usize>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+    pub declaration: usize,
+}
+
+#[derive(Debug)]
+pub struct ResolveError {
+    /// See [`crate::codes`].
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+}
+
+impl std::fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.span.start)
+    }
+}
+
+/// Declarations, references and scopes of a program. Scopes, declarations
+/// and references refer to each other by index.
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub scopes: Vec<Scope>,
+    pub declarations: Vec<Declaration>,
+    pub references: Vec<Reference>,
+    pub errors: Vec<ResolveError>,
+    /// Stack slots needed by the locals of the script itself.
+    pub slots: usize,
+}
+
+impl Resolution {
+    /// Slots of the variables declared at the top level of the script.
+    pub fn globals(&self) -> HashMap<String, usize> {
+        self.scopes[0]
+            .declarations
+            .iter()
+            .map(|&d| &self.declarations[d])
+            .filter_map(|d| Some((d.name.clone(), d.slot?)))
+            .collect()
+    }
+
+    /// All references to the declaration with index `declaration`.
+    pub fn references_to(&self, declaration: usize) -> impl Iterator<Item = &Reference> {
+        self.references
+            .iter()
+            .filter(move |r| r.declaration == declaration)
+    }
+}
+
+/// Binds every variable to a declaration and assigns stack slots, writing
+/// them into [`VariableExpression::slot`] and the number of slots per
+/// function into [`FnStatement::slots`] for the compiler.
+///
+/// Variables are declared by their first assignment and live until the end
+/// of the enclosing block. Functions are global and visible from their
+/// declaration on; function bodies can't see the locals around them.
+#[derive(Default)]
+pub struct Resolver {
+    resolution: Resolution,
+    scopes: Vec<usize>,
+    functions: HashMap<String, usize>,
+    globals: Vec<String>,
+    next_slot: usize,
+    max_slots: usize,
+    /// Set for each statement directly in a function body, the only place
+    /// `defer` is allowed.
+    defer_allowed: bool,
+    /// Labels of the loops around the current statement, innermost last.
+    loops: Vec<Option<String>>,
+    /// Whitelists for the script outside of functions, the prelude's
+    /// functions are not restricted.
+    sandbox: Option<Sandbox>,
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver::default()
+    }
+
+    /// Declares a variable that is set from outside before the script runs.
+    pub fn declare_global(&mut self, name: &str) {
+        self.globals.push(name.to_string());
+    }
+
+    /// Only lets the script use the variables and functions `sandbox`
+    /// allows.
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.sandbox = Some(sandbox);
+    }
+
+    pub fn resolve(mut self, ast: &mut [Statement]) -> Resolution {
+        self.begin_scope(ScopeKind::Script);
+        for name in std::mem::take(&mut self.globals) {
+            self.declare(&name, DeclarationKind::Global, Span::default());
+        }
+        for statement in ast {
+            statement.resolve(&mut self);
+        }
+        self.end_scope();
+        self.resolution.slots = self.max_slots;
+        self.resolution
+    }
+
+    fn begin_scope(&mut self, kind: ScopeKind) {
+        self.resolution.scopes.push(Scope {
+            kind,
+            parent: self.scopes.last().copied(),
+            declarations: Vec::new(),
+        });
+        self.scopes.push(self.resolution.scopes.len() - 1);
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &str, kind: DeclarationKind, span: Span) -> usize {
+        let scope = *self.scopes.last().unwrap();
+        let slot = match kind {
+            DeclarationKind::Function => None,
+            _ => {
+                self.next_slot += 1;
+                self.max_slots = self.max_slots.max(self.next_slot);
+                Some(self.next_slot - 1)
+            }
+        };
+        self.resolution.declarations.push(Declaration {
+            name: name.to_string(),
+            kind,
+            scope,
+            slot,
+            span,
+        });
+        let idx = self.resolution.declarations.len() - 1;
+        self.resolution.scopes[scope].declarations.push(idx);
+        idx
+    }
+
+    /// Looks `name` up in the scopes of the current function.
+    fn lookup(&self, name: &str) -> Option<usize> {
+        for &scope in self.scopes.iter().rev() {
+            let found = self.resolution.scopes[scope]
+                .declarations
+                .iter()
+                .rev()
+                .find(|&&d| {
+                    let declaration = &self.resolution.declarations[d];
+                    declaration.kind != DeclarationKind::Function && declaration.name == name
+                });
+            if found.is_some() {
+                return found.copied();
+            }
+            if self.resolution.scopes[scope].kind != ScopeKind::Block {
+                break;
+            }
+        }
+        None
+    }
+
+    fn in_function(&self) -> bool {
+        self.scopes
+            .iter()
+            .any(|&scope| self.resolution.scopes[scope].kind == ScopeKind::Function)
+    }
+
+    /// The sandbox restricting the current code, none inside functions.
+    fn sandbox(&self) -> Option<&Sandbox> {
+        self.sandbox.as_ref().filter(|_| !self.in_function())
+    }
+
+    fn reference(&mut self, name: &str, span: Span, declaration: usize) {
+        self.resolution.references.push(Reference {
+            name: name.to_string(),
+            span,
+            declaration,
+        });
+    }
+
+    fn error(&mut self, code: &'static str, message: String, span: Span) {
+        self.resolution.errors.push(ResolveError {
+            code,
+            message,
+            span,
+        });
+    }
+}
+
+trait Resolve {
+    fn resolve(&mut self, _resolver: &mut Resolver) {}
+}
+
+impl Resolve for Statement {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        let defer_allowed = std::mem::take(&mut resolver.defer_allowed);
+        match self {
+            Statement::Print(print_statement) => print_statement.resolve(resolver),
+            Statement::Fn(fn_statement) => fn_statement.resolve(resolver),
+            Statement::Expression(expr_statement) => expr_statement.resolve(resolver),
+            Statement::Return(return_statement) => return_statement.resolve(resolver),
+            Statement::If(if_statement) => if_statement.resolve(resolver),
+            Statement::Block(block_statement) => block_statement.resolve(resolver),
+            Statement::While(while_statement) => while_statement.resolve(resolver),
+            Statement::For(for_statement) => for_statement.resolve(resolver),
+            Statement::DoWhile(do_while_statement) => do_while_statement.resolve(resolver),
+            Statement::Break(break_statement) => break_statement.resolve(resolver),
+            Statement::Continue(continue_statement) => continue_statement.resolve(resolver),
+            Statement::Defer(defer_statement) => {
+                if !defer_allowed {
+                    let message = "'defer' not directly in a function body".to_string();
+                    resolver.error("E015", message, defer_statement.span);
+                }
+                defer_statement.resolve(resolver);
+            }
+            Statement::Dummy => {}
+        }
+    }
+}
+
+impl Resolve for PrintStatement {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        self.expression.resolve(resolver);
+    }
+}
+
+impl Resolve for FnStatement {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        let declaration = resolver.declare(&self.name, DeclarationKind::Function, self.span);
+        resolver.functions.insert(self.name.clone(), declaration);
+
+        let outer_slot = std::mem::replace(&mut resolver.next_slot, 0);
+        let outer_max = std::mem::replace(&mut resolver.max_slots, 0);
+        let outer_scopes = std::mem::take(&mut resolver.scopes);
+        let outer_loops = std::mem::take(&mut resolver.loops);
+        resolver.scopes.push(*outer_scopes.last().unwrap());
+        resolver.begin_scope(ScopeKind::Function);
+        for argument in &self.arguments {
+            resolver.declare(&argument.name, DeclarationKind::Parameter, argument.span);
+        }
+        match &mut *self.body {
+            Statement::Block(block) => block.resolve_statements(resolver, true),
+            body => body.resolve(resolver),
+        }
+        self.slots = resolver.max_slots;
+        resolver.scopes = outer_scopes;
+        resolver.loops = outer_loops;
+        resolver.next_slot = outer_slot;
+        resolver.max_slots = outer_max;
+    }
+}
+
+impl Resolve for ExpressionStatement {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        self.expression.resolve(resolver);
+    }
+}
+
+impl Resolve for ReturnStatement {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        if !resolver.in_function() {
+            let message = "'return' outside of a function".to_string();
+            resolver.error("E019", message, self.span);
+        }
+        self.expression.resolve(resolver);
+    }
+}
+
+impl Resolve for DeferStatement {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        self.expression.resolve(resolver);
+    }
+}
+
+impl Resolve for IfStatement {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        for (condition, body) in &mut self.branches {
+            condition.resolve(resolver);
+            body.resolve(resolver);
+        }
+        if let Some(else_body) = &mut self.else_body {
+            else_body.resolve(resolver);
+        }
+    }
+}
+
+impl Resolve for WhileStatement {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        self.condition.resolve(resolver);
+        resolver.loops.push(self.label.clone());
+        self.body.resolve(resolver);
+        resolver.loops.pop();
+    }
+}
+
+impl Resolve for ForStatement {
+    /// The initializer declares its variables in a scope of the loop.
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        let outer_slot = resolver.next_slot;
+        resolver.begin_scope(ScopeKind::Block);
+        if let Some(initializer) = &mut self.initializer {
+            initializer.resolve(resolver);
+        }
+        if let Some(condition) = &mut self.condition {
+            condition.resolve(resolver);
+        }
+        resolver.loops.push(self.label.clone());
+        self.body.resolve(resolver);
+        resolver.loops.pop();
+        if let Some(increment) = &mut self.increment {
+            increment.resolve(resolver);
+        }
+        resolver.end_scope();
+        resolver.next_slot = outer_slot;
+    }
+}
+
+impl Resolve for DoWhileStatement {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        resolver.loops.push(self.label.clone());
+        self.body.resolve(resolver);
+        resolver.loops.pop();
+        self.condition.resolve(resolver);
+    }
+}
+
+impl Resolve for BreakStatement {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        if resolver.loops.is_empty() {
+            resolver.error("E013", "'break' outside of a loop".to_string(), self.span);
+        } else {
+            resolve_loop_label(resolver, &self.label, self.span);
+        }
+    }
+}
+
+impl Resolve for ContinueStatement {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        if resolver.loops.is_empty() {
+            let message = "'continue' outside of a loop".to_string();
+            resolver.error("E018", message, self.span);
+        } else {
+            resolve_loop_label(resolver, &self.label, self.span);
+        }
+    }
+}
+
+fn resolve_loop_label(resolver: &mut Resolver, label: &Option<String>, span: Span) {
+    if let Some(label) = label {
+        if !resolver.loops.contains(&Some(label.clone())) {
+            let message = format!("undefined loop label '{}'", label);
+            resolver.error("E014", message, span);
+        }
+    }
+}
+
+impl Resolve for BlockStatement {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        self.resolve_statements(resolver, false);
+    }
+}
+
+impl BlockStatement {
+    fn resolve_statements(&mut self, resolver: &mut Resolver, function_body: bool) {
+        let outer_slot = resolver.next_slot;
+        resolver.begin_scope(ScopeKind::Block);
+        for statement in &mut self.body {
+            resolver.defer_allowed = function_body;
+            statement.resolve(resolver);
+        }
+        resolver.end_scope();
+        resolver.next_slot = outer_slot;
+    }
+}
+
+impl Resolve for Expression {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        match self {
+            Expression::Binary(binexp) => binexp.resolve(resolver),
+            Expression::Literal(_) => {}
+            Expression::Variable(variable) => variable.resolve(resolver),
+            Expression::Call(call) => call.resolve(resolver),
+            Expression::Assign(assignment) => assignment.resolve(resolver),
+            Expression::Unary(unary) => unary.resolve(resolver),
+            Expression::Index(index) => index.resolve(resolver),
+            Expression::TypeTest(type_test) => type_test.expr.resolve(resolver),
+        }
+    }
+}
+
+impl Resolve for IndexExpression {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        self.expr.resolve(resolver);
+        self.index.resolve(resolver);
+        if let Some(end) = &mut self.end {
+            end.resolve(resolver);
+        }
+    }
+}
+
+impl Resolve for UnaryExpression {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        self.expr.resolve(resolver);
+    }
+}
+
+impl Resolve for AssignExpression {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        self.rhs.resolve(resolver);
+        if let Expression::Variable(variable) = &mut *self.lhs {
+            if resolver.lookup(&variable.value).is_none() {
+                resolver.declare(&variable.value, DeclarationKind::Local, variable.span);
+            }
+        }
+        self.lhs.resolve(resolver);
+
+        // Only locals and elements of them can be assigned to.
+        let mut target = &*self.lhs;
+        while let Expression::Index(index) = target {
+            target = &index.expr;
+        }
+        match target {
+            Expression::Variable(variable)
+                if variable.slot.is_some() || !resolver.functions.contains_key(&variable.value) => {
+            }
+            target => resolver.error(
+                "E012",
+                "invalid assignment target".to_string(),
+                target.span(),
+            ),
+        }
+    }
+}
+
+impl Resolve for CallExpression {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        match &mut *self.callee {
+            Expression::Variable(variable)
+                if resolver.lookup(&variable.value).is_none()
+                    && !resolver.functions.contains_key(&variable.value) =>
+            {
+                if builtins::lookup(&variable.value).is_none() {
+                    let message = format!("undefined function '{}'", variable.value);
+                    resolver.error("E011", message, variable.span);
+                } else if resolver
+                    .sandbox()
+                    .is_some_and(|sandbox| !sandbox.allows_function(&variable.value))
+                {
+                    let message = format!("function '{}' is not allowed", variable.value);
+                    resolver.error("E017", message, variable.span);
+                }
+            }
+            callee => callee.resolve(resolver),
+        }
+        for argument in &mut self.arguments {
+            argument.resolve(resolver);
+        }
+    }
+}
+
+impl Resolve for BinaryExpression {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        self.lhs.resolve(resolver);
+        self.rhs.resolve(resolver);
+    }
+}
+
+impl Resolve for VariableExpression {
+    fn resolve(&mut self, resolver: &mut Resolver) {
+        let declaration = resolver
+            .lookup(&self.value)
+            .or_else(|| resolver.functions.get(&self.value).copied());
+        match declaration {
+            Some(declaration) => {
+                self.slot = resolver.resolution.declarations[declaration].slot;
+                resolver.reference(&self.value, self.span, declaration);
+                if let Some(sandbox) = resolver.sandbox() {
+                    let (what, allowed) = match self.slot {
+                        Some(_) => ("variable", sandbox.allows_variable(&self.value)),
+                        None => ("function", sandbox.allows_function(&self.value)),
+                    };
+                    if !allowed {
+                        let message = format!("{} '{}' is not allowed", what, self.value);
+                        resolver.error("E017", message, self.span);
+                    }
+                }
+            }
+            None => resolver.error(
+                "E010",
+                format!("undefined variable '{}'", self.value),
+                self.span,
+            ),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Restrictions for expressions from untrusted sources, rules or formulas
+//! evaluated with [`eval_expr_with`](crate::driver::eval_expr_with).
+//!
+//! The parser, set to [expressions only](crate::parser::Parser::set_expressions_only),
+//! rejects statements and assignments with E016. The resolver, given a
+//! [`Sandbox`], rejects variables and functions that aren't whitelisted
+//! with E017.
+
+use std::collections::HashSet;
+
+/// The variables and functions, builtins and the prelude's alike, that
+/// sandboxed expressions may use.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sandbox {
+    variables: HashSet<String>,
+    functions: HashSet<String>,
+}
+
+impl Sandbox {
+    /// A sandbox allowing nothing but literals and operators.
+    pub fn new() -> Sandbox {
+        Sandbox::default()
+    }
+
+    pub fn variable(mut self, name: &str) -> Sandbox {
+        self.variables.insert(name.to_string());
+        self
+    }
+
+    /// Allows calling the function, or builtin like `math.sqrt`, `name`.
+    pub fn function(mut self, name: &str) -> Sandbox {
+        self.functions.insert(name.to_string());
+        self
+    }
+
+    pub fn allows_variable(&self, name: &str) -> bool {
+        self.variables.contains(name)
+    }
+
+    pub fn allows_function(&self, name: &str) -> bool {
+        self.functions.contains(name)
+    }
+}
@@ -0,0 +1,119 @@
+//! Sets of hashable values, the `set` type of scripts.
+
+use std::collections::HashSet;
+use std::hash::{BuildHasherDefault, DefaultHasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::key::Key;
+use crate::vm::{runtime_error, Object, RuntimeError};
+
+// Not randomly seeded, sets would print differently on every run.
+type Keys = HashSet<Key, BuildHasherDefault<DefaultHasher>>;
+
+/// Values without duplicates, see [`crate::key`] for which values can be
+/// in a set. The order sets are printed and listed in is the same in
+/// every run, but not the one the values were inserted in. Sets are
+/// shared like lists, see [`crate::list::List`], but hold keys made from
+/// the values rather than the values. Frozen sets can't be changed.
+#[derive(Default)]
+pub struct Set {
+    keys: RwLock<Keys>,
+    frozen: AtomicBool,
+}
+
+impl Set {
+    pub fn new() -> Set {
+        Set::default()
+    }
+
+    /// Inserts `object`, `false` if it already was in the set.
+    pub fn insert(&self, object: &Object) -> Result<bool, RuntimeError> {
+        let key = Key::new(object)?;
+        Ok(self.keys_mut()?.insert(key))
+    }
+
+    /// Removes `object`, `false` if it wasn't in the set.
+    pub fn remove(&self, object: &Object) -> Result<bool, RuntimeError> {
+        let key = Key::new(object)?;
+        Ok(self.keys_mut()?.remove(&key))
+    }
+
+    fn keys(&self) -> RwLockReadGuard<'_, Keys> {
+        self.keys.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn keys_mut(&self) -> Result<RwLockWriteGuard<'_, Keys>, RuntimeError> {
+        if self.is_frozen() {
+            runtime_error!("Can't change a frozen set.");
+        }
+        Ok(self.keys.write().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn contains(&self, object: &Object) -> Result<bool, RuntimeError> {
+        Ok(self.keys().contains(&Key::new(object)?))
+    }
+
+    pub fn union(&self, other: &Set) -> Set {
+        if std::ptr::eq(self, other) {
+            return self.thawed();
+        }
+        let keys = self.keys().union(&other.keys()).cloned().collect();
+        Set::from_keys(keys)
+    }
+
+    pub fn intersection(&self, other: &Set) -> Set {
+        if std::ptr::eq(self, other) {
+            return self.thawed();
+        }
+        let keys = self.keys().intersection(&other.keys()).cloned().collect();
+        Set::from_keys(keys)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys().is_empty()
+    }
+
+    /// The values, made from the keys.
+    pub fn values(&self) -> Vec<Object> {
+        self.keys().iter().map(Key::to_object).collect()
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::Relaxed)
+    }
+
+    pub fn freeze(&self) {
+        self.frozen.store(true, Ordering::Relaxed);
+    }
+
+    /// A copy that can be changed, also of a frozen set.
+    pub fn thawed(&self) -> Set {
+        Set::from_keys(self.keys().clone())
+    }
+
+    fn from_keys(keys: Keys) -> Set {
+        Set {
+            keys: RwLock::new(keys),
+            frozen: AtomicBool::new(false),
+        }
+    }
+}
+
+/// Frozen or not, sets with equal values are equal.
+impl PartialEq for Set {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other) || *self.keys() == *other.keys()
+    }
+}
+
+/// Shown like lists are, in braces.
+impl std::fmt::Debug for Set {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.values()).finish()
+    }
+}
@@ -1,4 +1,5 @@
 use regex::Regex;
+use std::collections::{HashMap, VecDeque};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenKind {
@@ -6,11 +7,14 @@ pub enum TokenKind {
     Fn,
     If,
     Else,
+    Elif,
     Identifier,
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Number,
     Plus,
     PlusPlus,
@@ -18,8 +22,14 @@ pub enum TokenKind {
     Star,
     Slash,
     Comma,
+    Colon,
+    Dot,
+    DotDot,
     Semicolon,
     Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
     Return,
     Equal,
     Bang,
@@ -29,125 +39,423 @@ pub enum TokenKind {
     False,
     Null,
     While,
+    For,
+    Defer,
+    Do,
+    Break,
+    Continue,
+    Is,
     String,
+    /// A byte string, `b"..."`.
+    Bytes,
+    /// A character no token starts with, a stray `@` or the quote of an
+    /// unterminated string. The parser reports it.
+    Unknown,
+    /// End of input, carries the trivia behind the last token.
+    Eof,
+}
+
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TokenKind::Print => "'print'",
+            TokenKind::Fn => "'fn'",
+            TokenKind::If => "'if'",
+            TokenKind::Else => "'else'",
+            TokenKind::Elif => "'elif'",
+            TokenKind::Identifier => "identifier",
+            TokenKind::LeftParen => "'('",
+            TokenKind::RightParen => "')'",
+            TokenKind::LeftBrace => "'{'",
+            TokenKind::RightBrace => "'}'",
+            TokenKind::LeftBracket => "'['",
+            TokenKind::RightBracket => "']'",
+            TokenKind::Number => "number",
+            TokenKind::Plus => "'+'",
+            TokenKind::PlusPlus => "'++'",
+            TokenKind::Minus => "'-'",
+            TokenKind::Star => "'*'",
+            TokenKind::Slash => "'/'",
+            TokenKind::Comma => "','",
+            TokenKind::Colon => "':'",
+            TokenKind::Dot => "'.'",
+            TokenKind::DotDot => "'..'",
+            TokenKind::Semicolon => "';'",
+            TokenKind::Less => "'<'",
+            TokenKind::LessEqual => "'<='",
+            TokenKind::Greater => "'>'",
+            TokenKind::GreaterEqual => "'>='",
+            TokenKind::Return => "'return'",
+            TokenKind::Equal => "'='",
+            TokenKind::Bang => "'!'",
+            TokenKind::BangEqual => "'!='",
+            TokenKind::DoubleEqual => "'=='",
+            TokenKind::True => "'true'",
+            TokenKind::False => "'false'",
+            TokenKind::Null => "'null'",
+            TokenKind::While => "'while'",
+            TokenKind::For => "'for'",
+            TokenKind::Defer => "'defer'",
+            TokenKind::Do => "'do'",
+            TokenKind::Break => "'break'",
+            TokenKind::Continue => "'continue'",
+            TokenKind::Is => "'is'",
+            TokenKind::String => "string",
+            TokenKind::Bytes => "byte string",
+            TokenKind::Unknown => "unknown character",
+            TokenKind::Eof => "end of file",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Byte range of a token or trivia in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+}
+
+/// Source text that carries no meaning for the parser.
+#[derive(Debug, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Token {
     pub kind: TokenKind,
     pub value: String,
+    pub span: Span,
+    /// Whitespace and comments preceding the token, only filled in when
+    /// the tokenizer was created with [`Tokenizer::with_trivia`].
+    pub leading_trivia: Vec<Trivia>,
 }
 
 impl Token {
-    fn new(kind: TokenKind, value: &str) -> Token {
+    fn new(kind: TokenKind, value: &str, span: Span) -> Token {
         Token {
             kind,
             value: value.to_string(),
+            span,
+            leading_trivia: Vec::new(),
+        }
+    }
+}
+
+/// Spellings of the keywords and literals, `fn` and `true`, which are
+/// otherwise identifiers. Embedders running a small language of their own
+/// rename them or add aliases:
+///
+/// ```
+/// use reaper::tokenizer::{Keywords, TokenKind, Tokenizer};
+///
+/// let keywords = Keywords::default().rename("fn", "def").alias("print", "show");
+/// let kinds: Vec<_> = Tokenizer::new("def fn show print")
+///     .with_keywords(keywords)
+///     .map(|token| token.kind)
+///     .collect();
+/// assert_eq!(
+///     kinds,
+///     [TokenKind::Fn, TokenKind::Identifier, TokenKind::Print, TokenKind::Print, TokenKind::Eof]
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keywords {
+    words: HashMap<String, TokenKind>,
+}
+
+impl Default for Keywords {
+    fn default() -> Self {
+        let words = [
+            ("print", TokenKind::Print),
+            ("fn", TokenKind::Fn),
+            ("if", TokenKind::If),
+            ("else", TokenKind::Else),
+            ("elif", TokenKind::Elif),
+            ("return", TokenKind::Return),
+            ("while", TokenKind::While),
+            ("for", TokenKind::For),
+            ("defer", TokenKind::Defer),
+            ("do", TokenKind::Do),
+            ("break", TokenKind::Break),
+            ("continue", TokenKind::Continue),
+            ("is", TokenKind::Is),
+            ("true", TokenKind::True),
+            ("false", TokenKind::False),
+            ("null", TokenKind::Null),
+        ];
+        Keywords {
+            words: words
+                .into_iter()
+                .map(|(word, kind)| (word.to_string(), kind))
+                .collect(),
         }
     }
 }
 
+impl Keywords {
+    /// Spells `keyword` as `word` instead, `keyword` becomes an identifier.
+    ///
+    /// Panics if `keyword` isn't a keyword or `word` can't be one.
+    pub fn rename(mut self, keyword: &str, word: &str) -> Keywords {
+        let kind = self
+            .words
+            .remove(keyword)
+            .unwrap_or_else(|| panic!("'{}' is not a keyword", keyword));
+        self.insert(word, kind);
+        self
+    }
+
+    /// Also spells `keyword` as `word`.
+    ///
+    /// Panics if `keyword` isn't a keyword or `word` can't be one.
+    pub fn alias(mut self, keyword: &str, word: &str) -> Keywords {
+        let kind = *self
+            .words
+            .get(keyword)
+            .unwrap_or_else(|| panic!("'{}' is not a keyword", keyword));
+        self.insert(word, kind);
+        self
+    }
+
+    fn insert(&mut self, word: &str, kind: TokenKind) {
+        let mut chars = word.chars();
+        assert!(
+            chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            "'{}' is not an identifier",
+            word
+        );
+        assert!(
+            !self.words.contains_key(word),
+            "'{}' is already a keyword",
+            word
+        );
+        self.words.insert(word.to_string(), kind);
+    }
+
+    /// The keyword or literal spelled `word`.
+    pub fn kind(&self, word: &str) -> Option<TokenKind> {
+        self.words.get(word).copied()
+    }
+}
+
 pub struct Tokenizer<'a> {
     src: &'a str,
     start: usize,
+    regex: Regex,
+    keywords: Keywords,
+    keep_trivia: bool,
+    trivia: Vec<Trivia>,
+    done: bool,
+    /// Tokens scanned ahead by [`Tokenizer::peek_n`], returned first.
+    lookahead: VecDeque<Token>,
 }
 
 impl Iterator for Tokenizer<'_> {
     type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let re_keyword = r"?P<keyword>print|fn|if|else|return|while";
-        let re_literal = r"?P<literal>true|false|null";
+        self.lookahead.pop_front().or_else(|| self.scan())
+    }
+}
+
+impl<'a> Tokenizer<'a> {
+    /// Creates a tokenizer that discards whitespace and comments.
+    pub fn new(src: &'a str) -> Tokenizer<'a> {
+        let re_whitespace = r"?P<whitespace>\s+";
+        let re_comment = r"?P<comment>//[^\n]*";
+        // Keywords and literals are identifiers found in the keyword table.
         let re_identifier = r"?P<identifier>[a-zA-Z_][a-zA-Z0-9_]*";
-        let re_individual = r"?P<individual>[-+*/(){};,<=!]";
-        let re_double = r"?P<double>==|!=|\+\+";
-        let re_number = r"?P<number>[-+]?\d+(\.\d+)?";
+        let re_individual = r"?P<individual>[-+*/(){}\[\];:,<>=!.]";
+        let re_double = r"?P<double>==|!=|<=|>=|\+\+|\.\.";
+        let re_number = r"?P<number>\d+(\.\d+)?";
         let re_string = r#""(?P<string>[^\n"]*)""#;
+        // Ahead of identifiers, which `b` would otherwise start.
+        let re_bytes = r#"b"(?P<bytes>[^\n"]*)""#;
+        // Last, so every character starts a match and none are skipped.
+        let re_unknown = r"?P<unknown>.";
 
-        let r = Regex::new(
+        let regex = Regex::new(
             format!(
-                "({})|({})|({})|({})|({})|({})|{}",
-                re_keyword,
-                re_literal,
+                "({})|({})|{}|({})|({})|({})|({})|{}|({})",
+                re_whitespace,
+                re_comment,
+                re_bytes,
                 re_identifier,
                 re_double,
                 re_individual,
                 re_number,
                 re_string,
+                re_unknown,
             )
             .as_str(),
         )
         .unwrap();
 
-        let token = match r.captures_at(self.src, self.start) {
-            Some(captures) => {
-                if let Some(m) = captures.name("keyword") {
-                    self.start = m.end();
-                    match m.as_str() {
-                        "print" => Token::new(TokenKind::Print, "print"),
-                        "fn" => Token::new(TokenKind::Fn, "fn"),
-                        "if" => Token::new(TokenKind::If, "if"),
-                        "else" => Token::new(TokenKind::Else, "else"),
-                        "return" => Token::new(TokenKind::Return, "return"),
-                        "while" => Token::new(TokenKind::While, "return"),
-                        _ => unreachable!(),
-                    }
-                } else if let Some(m) = captures.name("literal") {
-                    self.start = m.end();
-                    match m.as_str() {
-                        "true" => Token::new(TokenKind::True, "true"),
-                        "false" => Token::new(TokenKind::False, "false"),
-                        "null" => Token::new(TokenKind::Null, "null"),
-                        _ => unreachable!(),
-                    }
-                } else if let Some(m) = captures.name("identifier") {
-                    self.start = m.end();
-                    Token::new(TokenKind::Identifier, m.as_str())
-                } else if let Some(m) = captures.name("double") {
-                    self.start = m.end();
-                    match m.as_str() {
-                        "==" => Token::new(TokenKind::DoubleEqual, "=="),
-                        "!=" => Token::new(TokenKind::BangEqual, "!="),
-                        "++" => Token::new(TokenKind::PlusPlus, "++"),
-                        _ => unreachable!(),
-                    }
-                } else if let Some(m) = captures.name("individual") {
-                    self.start = m.end();
-                    match m.as_str() {
-                        "(" => Token::new(TokenKind::LeftParen, "("),
-                        ")" => Token::new(TokenKind::RightParen, ")"),
-                        "{" => Token::new(TokenKind::LeftBrace, "{"),
-                        "}" => Token::new(TokenKind::RightBrace, "}"),
-                        "+" => Token::new(TokenKind::Plus, "+"),
-                        "-" => Token::new(TokenKind::Minus, "-"),
-                        "*" => Token::new(TokenKind::Star, "*"),
-                        "/" => Token::new(TokenKind::Slash, "/"),
-                        ";" => Token::new(TokenKind::Semicolon, ";"),
-                        "," => Token::new(TokenKind::Comma, ","),
-                        "<" => Token::new(TokenKind::Less, ","),
-                        "=" => Token::new(TokenKind::Equal, ","),
-                        "!" => Token::new(TokenKind::Bang, ","),
-                        _ => unreachable!(),
-                    }
-                } else if let Some(m) = captures.name("number") {
-                    self.start = m.end();
-                    Token::new(TokenKind::Number, m.as_str())
-                } else if let Some(m) = captures.name("string") {
-                    self.start = m.end();
-                    Token::new(TokenKind::String, m.as_str())
-                } else {
+        Tokenizer {
+            src,
+            start: 0,
+            regex,
+            keywords: Keywords::default(),
+            keep_trivia: false,
+            trivia: Vec::new(),
+            done: false,
+            lookahead: VecDeque::new(),
+        }
+    }
+
+    /// Creates a tokenizer that attaches whitespace and comments to the
+    /// following token as [`Token::leading_trivia`], for tools that need
+    /// to reproduce the source text.
+    pub fn with_trivia(src: &'a str) -> Tokenizer<'a> {
+        Tokenizer {
+            keep_trivia: true,
+            ..Tokenizer::new(src)
+        }
+    }
+
+    /// Spells keywords and literals as in `keywords`.
+    pub fn with_keywords(mut self, keywords: Keywords) -> Tokenizer<'a> {
+        self.keywords = keywords;
+        self
+    }
+
+    /// Continues tokenizing at byte `offset`, which must lie on a token
+    /// or trivia boundary.
+    pub fn starting_at(mut self, offset: usize) -> Tokenizer<'a> {
+        self.start = offset;
+        self.lookahead.clear();
+        self
+    }
+
+    /// The token [`Iterator::next`] returns next, without consuming it.
+    pub fn peek(&mut self) -> Option<&Token> {
+        self.peek_n(0)
+    }
+
+    /// The token `k` tokens after the next one, `peek_n(0)` being the
+    /// next. Tokens up to it are scanned once and kept until consumed.
+    pub fn peek_n(&mut self, k: usize) -> Option<&Token> {
+        while self.lookahead.len() <= k {
+            let token = self.scan()?;
+            self.lookahead.push_back(token);
+        }
+        self.lookahead.get(k)
+    }
+
+    /// Scans the token at `start`, collecting the trivia in front of it.
+    fn scan(&mut self) -> Option<Token> {
+        loop {
+            let Some(captures) = self.regex.captures_at(self.src, self.start) else {
+                if self.done {
                     return None;
                 }
+                self.done = true;
+                let end = self.src.len();
+                let mut token = Token::new(TokenKind::Eof, "", Span { start: end, end });
+                token.leading_trivia = std::mem::take(&mut self.trivia);
+                return Some(token);
+            };
+
+            let trivia_kind = if captures.name("whitespace").is_some() {
+                Some(TriviaKind::Whitespace)
+            } else if captures.name("comment").is_some() {
+                Some(TriviaKind::Comment)
+            } else {
+                None
+            };
+
+            if let Some(kind) = trivia_kind {
+                let m = captures.get(0).unwrap();
+                self.start = m.end();
+                if self.keep_trivia {
+                    self.trivia.push(Trivia {
+                        kind,
+                        text: m.as_str().to_string(),
+                        span: Span {
+                            start: m.start(),
+                            end: m.end(),
+                        },
+                    });
+                }
+                continue;
             }
-            None => return None,
-        };
 
-        Some(token)
-    }
-}
+            let (kind, m) = if let Some(m) = captures.name("identifier") {
+                let kind = self.keywords.kind(m.as_str());
+                (kind.unwrap_or(TokenKind::Identifier), m)
+            } else if let Some(m) = captures.name("double") {
+                let kind = match m.as_str() {
+                    "==" => TokenKind::DoubleEqual,
+                    "!=" => TokenKind::BangEqual,
+                    "<=" => TokenKind::LessEqual,
+                    ">=" => TokenKind::GreaterEqual,
+                    "++" => TokenKind::PlusPlus,
+                    ".." => TokenKind::DotDot,
+                    _ => unreachable!(),
+                };
+                (kind, m)
+            } else if let Some(m) = captures.name("individual") {
+                let kind = match m.as_str() {
+                    "(" => TokenKind::LeftParen,
+                    ")" => TokenKind::RightParen,
+                    "{" => TokenKind::LeftBrace,
+                    "}" => TokenKind::RightBrace,
+                    "[" => TokenKind::LeftBracket,
+                    "]" => TokenKind::RightBracket,
+                    "+" => TokenKind::Plus,
+                    "-" => TokenKind::Minus,
+                    "*" => TokenKind::Star,
+                    "/" => TokenKind::Slash,
+                    ";" => TokenKind::Semicolon,
+                    "," => TokenKind::Comma,
+                    ":" => TokenKind::Colon,
+                    "." => TokenKind::Dot,
+                    "<" => TokenKind::Less,
+                    ">" => TokenKind::Greater,
+                    "=" => TokenKind::Equal,
+                    "!" => TokenKind::Bang,
+                    _ => unreachable!(),
+                };
+                (kind, m)
+            } else if let Some(m) = captures.name("number") {
+                (TokenKind::Number, m)
+            } else if let Some(m) = captures.name("string") {
+                (TokenKind::String, m)
+            } else if let Some(m) = captures.name("bytes") {
+                (TokenKind::Bytes, m)
+            } else if let Some(m) = captures.name("unknown") {
+                (TokenKind::Unknown, m)
+            } else {
+                return None;
+            };
 
-impl<'a> Tokenizer<'a> {
-    pub fn new(src: &'a str) -> Tokenizer<'a> {
-        Tokenizer { src, start: 0 }
+            // The whole match may be wider than the named group (quotes
+            // around strings), the span and the resume position use it.
+            let whole = captures.get(0).unwrap();
+            self.start = whole.end();
+            let mut token = Token::new(
+                kind,
+                m.as_str(),
+                Span {
+                    start: whole.start(),
+                    end: whole.end(),
+                },
+            );
+            token.leading_trivia = std::mem::take(&mut self.trivia);
+            return Some(token);
+        }
     }
 }
@@ -0,0 +1,175 @@
+//! A flight recorder: what the last instructions did to the stack, to see
+//! how a program got to a runtime error and to step back through it.
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use reaper::trace::Recorder;
+//!
+//! let program = reaper::compile_source("s = \"abc\"; i = 1 + 2; c = s[i];", reaper::OptLevel::O0).unwrap();
+//! let mut vm = reaper::VM::new();
+//! vm.load(Arc::new(program));
+//! let mut recorder = Recorder::new(8);
+//! assert!(recorder.run(&mut vm).is_err());
+//!
+//! let mut replay = recorder.replay(&vm);
+//! let index = replay.back().unwrap();
+//! assert_eq!(format!("{:?}", index.opcode), "Index");
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::compiler::Opcode;
+use crate::program::Program;
+use crate::vm::{Object, RuntimeError, StepResult, VM};
+
+/// An executed instruction and the values it changed. The instruction
+/// left the stack below `kept` alone and replaced the values above it,
+/// `before`, with `after`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub ip: usize,
+    pub opcode: Opcode,
+    pub kept: usize,
+    pub before: Vec<Object>,
+    pub after: Vec<Object>,
+}
+
+/// Keeps the last `capacity` [`Step`]s of the programs it runs. Recording
+/// copies the innermost frame at every instruction, it is for debugging.
+#[derive(Debug)]
+pub struct Recorder {
+    steps: VecDeque<Step>,
+    capacity: usize,
+    /// Steps executed, also those no longer kept.
+    count: usize,
+}
+
+impl Recorder {
+    pub fn new(capacity: usize) -> Recorder {
+        Recorder {
+            steps: VecDeque::new(),
+            capacity,
+            count: 0,
+        }
+    }
+
+    /// Runs `vm` to the end of its program like [`VM::run`], recording
+    /// each instruction. The failing instruction is the last step.
+    pub fn run(&mut self, vm: &mut VM) -> Result<(), RuntimeError> {
+        loop {
+            let ip = vm.ip();
+            let opcode = vm.program().expect("no program loaded").code()[ip];
+            let base = vm.frame_base();
+            let before = vm.stack()[base..].to_vec();
+            let result = vm.step();
+            self.record(ip, opcode, base, before, vm.stack());
+            match result {
+                StepResult::Continue => {}
+                StepResult::Done => return Ok(()),
+                StepResult::Trapped(e) => return Err(e),
+            }
+        }
+    }
+
+    fn record(
+        &mut self,
+        ip: usize,
+        opcode: Opcode,
+        base: usize,
+        before: Vec<Object>,
+        stack: &[Object],
+    ) {
+        // Calls only push above the frame, returns only drop down to the
+        // callee's base, everything below `base` stays.
+        let same = before
+            .iter()
+            .zip(&stack[base.min(stack.len())..])
+            .take_while(|(a, b)| a == b)
+            .count();
+        let kept = base + same;
+        self.count += 1;
+        if self.capacity == 0 {
+            return;
+        }
+        if self.steps.len() == self.capacity {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(Step {
+            ip,
+            opcode,
+            kept,
+            before: before[same..].to_vec(),
+            after: stack[kept.min(stack.len())..].to_vec(),
+        });
+    }
+
+    /// The recorded steps, oldest first.
+    pub fn steps(&self) -> &VecDeque<Step> {
+        &self.steps
+    }
+
+    /// The recorded steps, one line each with what they took off and left
+    /// on the stack, for printing after a runtime error.
+    pub fn dump(&self, program: &Program) -> String {
+        let mut out = format!("last {} of {} steps:\n", self.steps.len(), self.count);
+        for step in &self.steps {
+            let instruction = program.disassemble(step.ip..step.ip + 1);
+            // Without the function header when `ip` starts one.
+            let instruction = instruction.lines().last().unwrap_or_default();
+            out += &format!("{}  {:?} -> {:?}\n", instruction, step.before, step.after);
+        }
+        out
+    }
+
+    /// Steps back through the recording from the state `vm` is in after
+    /// the recorded run.
+    pub fn replay(&self, vm: &VM) -> Replay<'_> {
+        Replay {
+            steps: &self.steps,
+            stack: vm.stack().to_vec(),
+            position: self.steps.len(),
+        }
+    }
+}
+
+/// The stack as it was at each recorded step, moving backwards and
+/// forwards through a [`Recorder`]'s steps.
+#[derive(Debug)]
+pub struct Replay<'a> {
+    steps: &'a VecDeque<Step>,
+    stack: Vec<Object>,
+    /// Steps applied to `stack`, all of them at the start.
+    position: usize,
+}
+
+impl<'a> Replay<'a> {
+    /// Undoes the last applied step and returns it, `None` at the first
+    /// recorded one.
+    pub fn back(&mut self) -> Option<&'a Step> {
+        self.position = self.position.checked_sub(1)?;
+        let step = &self.steps[self.position];
+        self.stack.truncate(step.kept);
+        self.stack.extend(step.before.iter().cloned());
+        Some(step)
+    }
+
+    /// Applies the next step again and returns it, `None` at the end.
+    pub fn forward(&mut self) -> Option<&'a Step> {
+        let step = self.steps.get(self.position)?;
+        self.position += 1;
+        self.stack.truncate(step.kept);
+        self.stack.extend(step.after.iter().cloned());
+        Some(step)
+    }
+
+    /// The stack before the next step.
+    pub fn stack(&self) -> &[Object] {
+        &self.stack
+    }
+
+    /// Address of the next step, `None` at the end.
+    pub fn ip(&self) -> Option<usize> {
+        self.steps.get(self.position).map(|step| step.ip)
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+
+use crate::builtins::BUILTINS;
+use crate::parser::{
+    BinaryExpressionKind, Expression, FnStatement, Literal, Statement, TypeAnnotation,
+    UnaryExpressionKind,
+};
+use crate::tokenizer::Span;
+use crate::vm::Object;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    /// Unannotated, checked at runtime only.
+    Any,
+    Num,
+    Bool,
+    Str,
+    Bytes,
+    List,
+    Set,
+    Null,
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "any" => Some(Type::Any),
+            "num" => Some(Type::Num),
+            "bool" => Some(Type::Bool),
+            "str" => Some(Type::Str),
+            "bytes" => Some(Type::Bytes),
+            "list" => Some(Type::List),
+            "set" => Some(Type::Set),
+            "null" => Some(Type::Null),
+            _ => None,
+        }
+    }
+
+    /// Whether `object` has this type. Function objects are only `any`.
+    pub fn matches(self, object: &Object) -> bool {
+        matches!(
+            (self, object),
+            (Type::Any, _)
+                | (Type::Num, Object::Number(_))
+                | (Type::Bool, Object::Bool(_))
+                | (Type::Str, Object::String(_))
+                | (Type::Bytes, Object::Bytes(_))
+                | (Type::List, Object::List(_))
+                | (Type::Set, Object::Set(_))
+                | (Type::Null, Object::Null)
+        )
+    }
+
+    fn accepts(self, other: Type) -> bool {
+        self == Type::Any || other == Type::Any || self == other
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Type::Any => "any",
+            Type::Num => "num",
+            Type::Bool => "bool",
+            Type::Str => "str",
+            Type::Bytes => "bytes",
+            Type::List => "list",
+            Type::Set => "set",
+            Type::Null => "null",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+pub struct TypeError {
+    /// See [`crate::codes`].
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+}
+
+impl std::fmt::Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.span.start)
+    }
+}
+
+struct Signature {
+    parameters: Vec<Type>,
+    result: Type,
+}
+
+/// Checks annotated functions (`fn add(a: num, b: num): num`) against the
+/// types that can be derived from literals, operators and other annotated
+/// functions. Everything unannotated is `any` and stays dynamic.
+#[derive(Default)]
+pub struct TypeChecker {
+    functions: HashMap<String, Signature>,
+    variables: HashMap<String, Type>,
+    return_type: Option<Type>,
+    errors: Vec<TypeError>,
+}
+
+impl TypeChecker {
+    pub fn new() -> TypeChecker {
+        let mut checker = TypeChecker::default();
+        for builtin in BUILTINS {
+            let signature = Signature {
+                parameters: builtin.parameters.to_vec(),
+                result: builtin.result,
+            };
+            checker
+                .functions
+                .insert(builtin.name.to_string(), signature);
+        }
+        checker
+    }
+
+    pub fn check(mut self, ast: &[Statement]) -> Vec<TypeError> {
+        for statement in ast {
+            self.statement(statement);
+        }
+        self.errors
+    }
+
+    fn error(&mut self, code: &'static str, message: String, span: Span) {
+        self.errors.push(TypeError {
+            code,
+            message,
+            span,
+        });
+    }
+
+    fn expect(&mut self, expected: Type, found: Type, what: &str, span: Span) {
+        if !expected.accepts(found) {
+            self.error(
+                "R001",
+                format!("{} expects {}, found {}", what, expected, found),
+                span,
+            );
+        }
+    }
+
+    fn optional_annotation(&mut self, annotation: &Option<TypeAnnotation>) -> Type {
+        match annotation {
+            Some(annotation) => self.annotation(annotation),
+            None => Type::Any,
+        }
+    }
+
+    fn annotation(&mut self, annotation: &TypeAnnotation) -> Type {
+        match Type::from_name(&annotation.name) {
+            Some(t) => t,
+            None => {
+                self.error(
+                    "R002",
+                    format!("unknown type '{}'", annotation.name),
+                    annotation.span,
+                );
+                Type::Any
+            }
+        }
+    }
+
+    fn function(&mut self, function: &FnStatement) {
+        let parameters: Vec<Type> = function
+            .arguments
+            .iter()
+            .map(|argument| self.optional_annotation(&argument.annotation))
+            .collect();
+        let result = self.optional_annotation(&function.return_type);
+
+        let variables = function
+            .arguments
+            .iter()
+            .zip(&parameters)
+            .map(|(argument, t)| (argument.name.clone(), *t))
+            .collect();
+        self.functions
+            .insert(function.name.clone(), Signature { parameters, result });
+
+        let outer_variables = std::mem::replace(&mut self.variables, variables);
+        let outer_return = self.return_type.replace(result);
+        self.statement(&function.body);
+        self.variables = outer_variables;
+        self.return_type = outer_return;
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Print(s) => {
+                self.expression(&s.expression);
+            }
+            Statement::Fn(s) => self.function(s),
+            Statement::Expression(s) => {
+                self.expression(&s.expression);
+            }
+            Statement::Defer(s) => {
+                self.expression(&s.expression);
+            }
+            Statement::Return(s) => {
+                let found = self.expression(&s.expression);
+                if let Some(expected) = self.return_type {
+                    self.expect(expected, found, "return", s.expression.span());
+                }
+            }
+            Statement::If(s) => {
+                for (condition, body) in &s.branches {
+                    let found = self.expression(condition);
+                    self.expect(Type::Bool, found, "condition", condition.span());
+                    self.statement(body);
+                }
+                if let Some(else_body) = &s.else_body {
+                    self.statement(else_body);
+                }
+            }
+            Statement::While(s) => {
+                let found = self.expression(&s.condition);
+                self.expect(Type::Bool, found, "condition", s.condition.span());
+                self.statement(&s.body);
+            }
+            Statement::For(s) => {
+                if let Some(initializer) = &s.initializer {
+                    self.expression(initializer);
+                }
+                if let Some(condition) = &s.condition {
+                    let found = self.expression(condition);
+                    self.expect(Type::Bool, found, "condition", condition.span());
+                }
+                self.statement(&s.body);
+                if let Some(increment) = &s.increment {
+                    self.expression(increment);
+                }
+            }
+            Statement::DoWhile(s) => {
+                self.statement(&s.body);
+                let found = self.expression(&s.condition);
+                self.expect(Type::Bool, found, "condition", s.condition.span());
+            }
+            Statement::Block(s) => {
+                for statement in &s.body {
+                    self.statement(statement);
+                }
+            }
+            Statement::Break(_) | Statement::Continue(_) | Statement::Dummy => {}
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Type {
+        match expression {
+            Expression::Literal(e) => match e.value {
+                Literal::Num(_) => Type::Num,
+                Literal::Bool(_) => Type::Bool,
+                Literal::String(_) => Type::Str,
+                Literal::Bytes(_) => Type::Bytes,
+                Literal::Null => Type::Null,
+            },
+            Expression::Variable(e) => *self.variables.get(&e.value).unwrap_or(&Type::Any),
+            Expression::Binary(e) => {
+                let lhs = self.expression(&e.lhs);
+                let rhs = self.expression(&e.rhs);
+                let (operator, operand, result) = match e.kind {
+                    BinaryExpressionKind::Add => ("'+'", Type::Num, Type::Num),
+                    BinaryExpressionKind::Sub => ("'-'", Type::Num, Type::Num),
+                    BinaryExpressionKind::Mul => ("'*'", Type::Num, Type::Num),
+                    BinaryExpressionKind::Div => ("'/'", Type::Num, Type::Num),
+                    BinaryExpressionKind::Less => ("'<'", Type::Num, Type::Bool),
+                    BinaryExpressionKind::LessEqual => ("'<='", Type::Num, Type::Bool),
+                    BinaryExpressionKind::Greater => ("'>'", Type::Num, Type::Bool),
+                    BinaryExpressionKind::GreaterEqual => ("'>='", Type::Num, Type::Bool),
+                    // Of byte strings if either side is one, of strings
+                    // otherwise.
+                    BinaryExpressionKind::Strcat if lhs == Type::Bytes || rhs == Type::Bytes => {
+                        ("'++'", Type::Bytes, Type::Bytes)
+                    }
+                    BinaryExpressionKind::Strcat => ("'++'", Type::Str, Type::Str),
+                    BinaryExpressionKind::Eq | BinaryExpressionKind::NotEq => return Type::Bool,
+                };
+                // Once per operator, at the first operand that's wrong.
+                let what = format!("operator {}", operator);
+                if operand.accepts(lhs) {
+                    self.expect(operand, rhs, &what, e.rhs.span());
+                } else {
+                    self.expect(operand, lhs, &what, e.lhs.span());
+                }
+                result
+            }
+            Expression::Call(e) => {
+                let name = match e.name() {
+                    Some(name) if !self.variables.contains_key(name) => name,
+                    _ => {
+                        self.expression(&e.callee);
+                        ""
+                    }
+                };
+                let arguments: Vec<Type> = e.arguments.iter().map(|a| self.expression(a)).collect();
+                let Some(signature) = self.functions.get(name) else {
+                    return Type::Any;
+                };
+                let (parameters, result) = (signature.parameters.clone(), signature.result);
+                if parameters.len() != arguments.len() {
+                    self.error(
+                        "R003",
+                        format!(
+                            "function '{}' takes {} arguments, found {}",
+                            name,
+                            parameters.len(),
+                            arguments.len()
+                        ),
+                        e.span,
+                    );
+                }
+                for ((expected, found), argument) in
+                    parameters.iter().zip(arguments).zip(&e.arguments)
+                {
+                    let what = format!("argument of '{}'", name);
+                    self.expect(*expected, found, &what, argument.span());
+                }
+                result
+            }
+            Expression::Assign(e) => {
+                let found = self.expression(&e.rhs);
+                match &*e.lhs {
+                    Expression::Variable(variable) => {
+                        if let Some(&expected) = self.variables.get(&variable.value) {
+                            let what = format!("'{}'", variable.value);
+                            self.expect(expected, found, &what, e.rhs.span());
+                        }
+                    }
+                    lhs => {
+                        self.expression(lhs);
+                    }
+                }
+                found
+            }
+            Expression::TypeTest(e) => {
+                self.expression(&e.expr);
+                self.annotation(&e.type_name);
+                Type::Bool
+            }
+            Expression::Unary(e) => {
+                let found = self.expression(&e.expr);
+                let (operator, operand) = match e.kind {
+                    UnaryExpressionKind::Not => ("operator '!'", Type::Bool),
+                    UnaryExpressionKind::Neg => ("operator '-'", Type::Num),
+                };
+                self.expect(operand, found, operator, e.expr.span());
+                operand
+            }
+            Expression::Index(e) => {
+                let found = self.expression(&e.expr);
+                for index in std::iter::once(&e.index).chain(&e.end) {
+                    let found = self.expression(index);
+                    self.expect(Type::Num, found, "index", index.span());
+                }
+                match found {
+                    Type::List if e.end.is_none() => Type::Any,
+                    Type::Bytes if e.end.is_none() => Type::Num,
+                    Type::Any | Type::List | Type::Bytes => found,
+                    _ => {
+                        self.expect(Type::Str, found, "indexing", e.expr.span());
+                        Type::Str
+                    }
+                }
+            }
+        }
+    }
+}
@@ -7,3 +7,120 @@ pub fn read_file(file: &str) -> Result<String, std::io::Error> {
     f.read_to_string(&mut buffer)?;
     Ok(buffer)
 }
+
+/// `s` as a JSON string literal, quotes included.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Identifies a source in a [`SourceManager`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(usize);
+
+/// Line and column of a byte offset, both counting from 1. Columns count
+/// characters, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Source text with the offsets at which its lines start.
+pub struct Source {
+    name: String,
+    text: String,
+    line_starts: Vec<usize>,
+}
+
+impl Source {
+    pub fn new(name: &str, text: String) -> Source {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Source {
+            name: name.to_string(),
+            text,
+            line_starts,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Location of byte `offset`, offsets past the end map to the end.
+    pub fn location(&self, offset: usize) -> Location {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        let column = self.text[start..]
+            .char_indices()
+            .take_while(|&(i, _)| start + i < offset)
+            .count();
+        Location {
+            line: line + 1,
+            column: column + 1,
+        }
+    }
+
+    /// The text of line `line`, counting from 1, without its line break.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.text.len(), |&end| end - 1);
+        Some(&self.text[start..end])
+    }
+}
+
+/// All sources of a program, so that positions are derived in one place.
+#[derive(Default)]
+pub struct SourceManager {
+    sources: Vec<Source>,
+}
+
+impl SourceManager {
+    pub fn new() -> SourceManager {
+        SourceManager::default()
+    }
+
+    /// Reads the file at `path`.
+    pub fn load(&mut self, path: &str) -> Result<FileId, std::io::Error> {
+        let text = read_file(path)?;
+        Ok(self.add(path, text))
+    }
+
+    /// Adds source text that didn't come from a file.
+    pub fn add(&mut self, name: &str, text: String) -> FileId {
+        self.sources.push(Source::new(name, text));
+        FileId(self.sources.len() - 1)
+    }
+
+    pub fn get(&self, id: FileId) -> &Source {
+        &self.sources[id.0]
+    }
+}
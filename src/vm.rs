@@ -1,16 +1,116 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use regex::Regex;
+
+use crate::builtins::{self, Capabilities, BUILTINS};
 use crate::compiler::Opcode;
+use crate::heap::{Heap, SystemHeap};
+use crate::key::Key;
+use crate::list::List;
+use crate::native::Native;
+use crate::program::{Function, Program};
+use crate::set::Set;
+use crate::tokenizer::Span;
+use crate::typecheck::Type;
 
-#[derive(Debug, PartialEq, Clone)]
+/// A value of a script. Lists, sets and native objects are shared by
+/// assignments, arguments and stores into lists: after `b = a` changing
+/// the list in `b` changes the one in `a`, it's the same list, and a list
+/// can hold itself. `clone(x)` copies a list or set deeply. Numbers,
+/// strings and byte strings are copied, changing a string in one variable
+/// leaves others as they were.
+#[derive(Debug, Clone)]
 pub enum Object {
     Number(f64),
     Bool(bool),
     String(Box<String>),
+    /// A byte string, binary data that needn't be text.
+    Bytes(Box<Vec<u8>>),
+    List(Arc<List>),
+    Set(Arc<Set>),
+    Function(Arc<Function>),
+    /// A host value, see [`Native`].
+    Native(Native),
     Null,
 }
 
-#[derive(Debug, Clone, Copy)]
-enum InternalObject {
-    BytecodePtr(usize, usize),
+#[derive(Debug, Clone)]
+struct CallFrame {
+    /// Address of the instruction after the `Invoke`, `Jmp` pair.
+    return_ip: usize,
+    /// Stack index of the first argument.
+    base: usize,
+    /// The function object of a `Call`, `Invoke` leaves the function to be
+    /// looked up from the `Jmp` in front of `return_ip`.
+    function: Option<Arc<Function>>,
+    /// Address of a memoized function and the arguments it was called
+    /// with, for caching the result on return.
+    memo: Option<(usize, Vec<Key>)>,
+}
+
+/// Read-only view of a call frame, for tooling.
+#[derive(Debug)]
+pub struct Frame<'a> {
+    /// `None` for the script itself.
+    pub function: Option<&'a Function>,
+    /// Stack index of the first argument or local.
+    pub base: usize,
+    /// Address of the instruction being executed, the call for all but the
+    /// innermost frame.
+    pub ip: usize,
+    /// Arguments and locals followed by temporaries, up to the next frame.
+    pub values: &'a [Object],
+    /// Names of the values where known: the script's own variables, and
+    /// the arguments and locals in the program's [`Program::locals`].
+    pub names: Vec<Option<&'a str>>,
+}
+
+impl Object {
+    /// Makes lists and sets, and those in them, unchangeable. Other values
+    /// can't be changed anyway, but for strings and byte strings.
+    pub fn freeze(&self) {
+        match self {
+            Object::List(l) => l.freeze(),
+            Object::Set(s) => s.freeze(),
+            _ => {}
+        }
+    }
+
+    /// Name of the type as written in annotations, `fn` for functions.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Number(_) => "num",
+            Object::Bool(_) => "bool",
+            Object::String(_) => "str",
+            Object::Bytes(_) => "bytes",
+            Object::List(_) => "list",
+            Object::Set(_) => "set",
+            Object::Function(_) => "fn",
+            Object::Native(n) => n.name(),
+            Object::Null => "null",
+        }
+    }
+}
+
+/// Values compare by value, functions by identity: a function equals
+/// itself, however it was passed around, and no other function, whatever
+/// its name.
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::Number(a), Object::Number(b)) => a == b,
+            (Object::Bool(a), Object::Bool(b)) => a == b,
+            (Object::String(a), Object::String(b)) => a == b,
+            (Object::Bytes(a), Object::Bytes(b)) => a == b,
+            (Object::List(a), Object::List(b)) => a == b,
+            (Object::Set(a), Object::Set(b)) => a == b,
+            (Object::Function(a), Object::Function(b)) => a.addr == b.addr,
+            (Object::Native(a), Object::Native(b)) => a == b,
+            (Object::Null, Object::Null) => true,
+            _ => false,
+        }
+    }
 }
 
 impl std::ops::Add for Object {
@@ -57,6 +157,17 @@ impl std::ops::Div for Object {
     }
 }
 
+impl std::ops::Neg for Object {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Object::Number(n) => (-n).into(),
+            _ => unimplemented!(),
+        }
+    }
+}
+
 impl std::ops::Not for Object {
     type Output = Object;
 
@@ -95,121 +206,815 @@ impl From<String> for Object {
     }
 }
 
-macro_rules! adjust_idx {
-    ($self:tt, $index:expr) => {{
-        let (fp, idx) = match $self.frame_ptrs.last() {
-            Some(&internal_obj) => {
-                let InternalObject::BytecodePtr(_, location) = internal_obj;
-                (location, $index)
-            }
-            None => (0, $index),
-        };
-        fp + idx
-    }};
-}
-
 macro_rules! binop {
     ($self:tt, $op:tt) => {
         {
             let b = $self.stack.pop().unwrap();
             let a = $self.stack.pop().unwrap();
+            if !matches!((&a, &b), (Object::Number(_), Object::Number(_))) {
+                runtime_error!(format!(
+                    concat!("Operands of '", stringify!($op), "' must be numbers, found {} and {}."),
+                    a.type_name(),
+                    b.type_name()
+                ));
+            }
             $self.stack.push((a $op b).into());
         }
     };
 }
 
-pub struct VM<'a> {
-    bytecode: Option<&'a [Opcode]>,
+/// A VM, like the [`Object`]s it works on, owns all of its state and can be
+/// moved to another thread. [`Program`]s are immutable and `Sync`, so VMs on
+/// several threads can execute the same `Arc<Program>`.
+pub struct VM {
+    program: Option<Arc<Program>>,
     stack: Vec<Object>,
-    frame_ptrs: Vec<InternalObject>,
+    frames: Vec<CallFrame>,
+    /// Base of the innermost frame, 0 for the script.
+    base: usize,
     ip: usize,
+    state: State,
+    /// Compiled patterns of the regex builtins.
+    regexes: HashMap<String, Regex>,
+    capabilities: Capabilities,
+    deterministic: bool,
+    indexing: Indexing,
+    size_limits: SizeLimits,
+    heap: Box<dyn Heap>,
+    /// Handlers attached with `on` by event name, for the events the host
+    /// registered.
+    events: HashMap<String, Vec<Arc<Function>>>,
+    /// Results of the functions passed to `memoize`, by function address
+    /// and arguments.
+    memos: HashMap<usize, HashMap<Vec<Key>, Object>>,
 }
 
-impl Default for VM<'_> {
+impl Default for VM {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Outcome of [`VM::step`] and [`VM::run_for`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepResult {
+    Continue,
+    Done,
+    Trapped(RuntimeError),
+}
+
+/// How indices that aren't natural numbers are taken, by strings and
+/// lists, in indexing, slicing and storing. By default `a[-1]` is the last
+/// item and `a[1.5]` is an error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Indexing {
+    /// Count negative indices from the end, `a[-1]` is `a[len(a) - 1]`.
+    /// Otherwise they are out of range.
+    pub from_end: bool,
+    /// Truncate fractional indices towards zero, `a[1.5]` is `a[1]`.
+    /// Otherwise they are errors.
+    pub truncate: bool,
+}
+
+impl Default for Indexing {
+    fn default() -> Self {
+        Indexing {
+            from_end: true,
+            truncate: false,
+        }
+    }
+}
+
+/// Largest strings and lists a script may create, so that a single value
+/// can't take all the memory a [`LimitedHeap`](crate::heap::LimitedHeap)
+/// allows. Strings in the program are not checked, only those built while
+/// it runs. Unlimited by default.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeLimits {
+    /// Bytes of a string, in UTF-8, or of a byte string.
+    pub string_bytes: usize,
+    /// Items of a list.
+    pub list_items: usize,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        SizeLimits {
+            string_bytes: usize::MAX,
+            list_items: usize::MAX,
+        }
+    }
+}
+
+impl SizeLimits {
+    /// An error if a string of `bytes` would be too long.
+    pub fn check_string(&self, bytes: usize) -> Result<(), RuntimeError> {
+        if bytes > self.string_bytes {
+            runtime_error!(format!(
+                "String of {} bytes exceeds the limit of {} bytes.",
+                bytes, self.string_bytes
+            ));
+        }
+        Ok(())
+    }
+
+    /// An error if a list of `items` would be too long.
+    pub fn check_list(&self, items: usize) -> Result<(), RuntimeError> {
+        if items > self.list_items {
+            runtime_error!(format!(
+                "List of {} items exceeds the limit of {} items.",
+                items, self.list_items
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How far a VM got with its program, see [`VM::state`].
+///
+/// [`load`](VM::load) and [`reset`](VM::reset) make a VM `Ready`.
+/// [`step`](VM::step) and [`run_for`](VM::run_for) leave it `Suspended`
+/// until the program ends or traps, [`resume`](VM::resume) runs it on to
+/// the end and [`run`](VM::run) does too, but starts over a program that
+/// is `Done` or `Trapped`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum State {
+    /// No program loaded.
+    Empty,
+    /// At the start of the program.
+    Ready,
+    /// Stopped between two instructions.
+    Suspended,
+    /// At the end of the program.
+    Done,
+    /// Stopped by the error, at the failing instruction.
+    Trapped(RuntimeError),
+}
+
 macro_rules! runtime_error {
     ($msg:expr) => {{
-        eprintln!("{}", $msg);
-        std::process::exit(1);
+        return Err(RuntimeError {
+            message: $msg.to_string(),
+        });
     }};
 }
 
-const STACK_MIN: usize = 1024;
+pub(crate) use runtime_error;
 
+const STACK_MIN: usize = 1024;
 
+/// Outcome of looking up a call in the cache of a memoized function.
+enum Memo {
+    Hit,
+    Miss(Vec<Key>),
+    /// The function isn't memoized.
+    Uncached,
+}
 
-impl<'a> VM<'a> {
+// Keep the thread-safety guarantees documented on `VM` from regressing,
+// and programs usable across `catch_unwind`.
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+    fn assert_unwind_safe<T: std::panic::UnwindSafe>() {}
+    assert_send::<VM>();
+    assert_sync::<Program>();
+    assert_unwind_safe::<Program>();
+    assert_send::<Object>();
+    assert_send::<RuntimeError>();
+};
 
-    pub fn new() -> VM<'a> {
+impl VM {
+    pub fn new() -> VM {
         VM {
-            bytecode: None,
+            program: None,
             stack: Vec::with_capacity(STACK_MIN),
-            frame_ptrs: Vec::with_capacity(STACK_MIN),
+            frames: Vec::with_capacity(STACK_MIN),
+            base: 0,
             ip: 0,
+            state: State::Empty,
+            regexes: HashMap::new(),
+            capabilities: Capabilities::all(),
+            deterministic: false,
+            indexing: Indexing::default(),
+            size_limits: SizeLimits::default(),
+            heap: Box::new(SystemHeap::default()),
+            events: HashMap::new(),
+            memos: HashMap::new(),
+        }
+    }
+
+    /// Restricts the builtins scripts may call, all are allowed by default.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    /// Makes runs reproducible across machines: `time.now` and `time.clock`
+    /// read clocks frozen at 0 and builtins that reach outside the VM fail,
+    /// whatever the capabilities.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    pub fn set_indexing(&mut self, indexing: Indexing) {
+        self.indexing = indexing;
+    }
+
+    pub fn set_size_limits(&mut self, limits: SizeLimits) {
+        self.size_limits = limits;
+    }
+
+    pub fn size_limits(&self) -> SizeLimits {
+        self.size_limits
+    }
+
+    /// Replaces the heap the strings and lists scripts create come from,
+    /// a [`SystemHeap`] by default. Values already created are kept.
+    pub fn set_heap(&mut self, heap: Box<dyn Heap>) {
+        self.heap = heap;
+    }
+
+    pub fn heap(&self) -> &dyn Heap {
+        &*self.heap
+    }
+
+    pub(crate) fn heap_mut(&mut self) -> &mut dyn Heap {
+        &mut *self.heap
+    }
+
+    /// A new string of the script, from the heap and within the limits.
+    pub(crate) fn new_string(&mut self, s: String) -> Result<Object, RuntimeError> {
+        self.size_limits.check_string(s.len())?;
+        self.heap.string(s)
+    }
+
+    /// A new byte string of the script, from the heap and within the
+    /// limits.
+    pub(crate) fn new_bytes(&mut self, bytes: Vec<u8>) -> Result<Object, RuntimeError> {
+        self.size_limits.check_string(bytes.len())?;
+        self.heap.bytes(bytes)
+    }
+
+    /// A new list of the script, from the heap and within the limits.
+    pub(crate) fn new_list(&mut self, items: Vec<Object>) -> Result<Object, RuntimeError> {
+        self.size_limits.check_list(items.len())?;
+        self.heap.list(items)
+    }
+
+    /// Resets the VM to the start of `program` with all globals `null`.
+    /// Registered events are kept, their handlers are not.
+    pub fn load(&mut self, program: Arc<Program>) {
+        self.stack.clear();
+        self.stack.resize(program.slots(), Object::Null);
+        self.frames.clear();
+        self.base = 0;
+        self.ip = 0;
+        self.state = State::Ready;
+        self.program = Some(program);
+        for handlers in self.events.values_mut() {
+            handlers.clear();
         }
+        self.memos.clear();
+    }
+
+    /// Back to the start of the loaded program, like loading it again. The
+    /// compiled regexes and the heap are kept, runs after the first start
+    /// warm.
+    pub fn reset(&mut self) {
+        let program = self.program.clone().expect("no program loaded");
+        self.load(program);
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Lets scripts attach handlers to `event` with `on(event, handler)`.
+    pub fn register_event(&mut self, event: &str) {
+        self.events.entry(event.to_string()).or_default();
     }
 
-    pub fn load(&mut self, bytecode: &'a mut Vec<Opcode>) {
-        bytecode.push(Opcode::EndOfProgram);
-        self.bytecode = Some(bytecode);
-    }
-
-    pub fn run(&mut self) {
-        let bytecode= self.bytecode.expect("no program loaded");
-        assert!(self.ip < bytecode.len(), "ip out of bounds");
-        loop {
-            match unsafe { bytecode.get_unchecked(self.ip)} {
-                Opcode::Const(n) => self.handle_op_const(*n),
-                Opcode::Str(ref s) => self.handle_op_str(s),
-                Opcode::Strcat => self.handle_op_strcat(),
-                Opcode::Print => self.handle_op_print(),
-                Opcode::Add => self.handle_op_add(),
-                Opcode::Sub => self.handle_op_sub(),
-                Opcode::Mul => self.handle_op_mul(),
-                Opcode::Div => self.handle_op_div(),
-                Opcode::Less => self.handle_op_less(),
-                Opcode::Eq => self.handle_op_eq(),
-                Opcode::False => self.handle_op_false(),
-                Opcode::Not => self.handle_op_not(),
-                Opcode::Null => self.handle_op_null(),
-                Opcode::Jmp(addr) => self.handle_op_jmp(*addr),
-                Opcode::Jz(addr) => self.handle_op_jz(*addr),
-                Opcode::Invoke(n) => self.handle_op_invoke(*n),
-                Opcode::Ret => self.handle_op_ret(),
-                Opcode::Deepget(idx) => self.handle_op_deepget(*idx),
-                Opcode::Deepset(idx) => self.handle_op_deepset(*idx),
-                Opcode::Pop => self.handle_op_pop(),
-                Opcode::EndOfProgram => break,
+    pub(crate) fn subscribe(
+        &mut self,
+        event: &str,
+        handler: Arc<Function>,
+    ) -> Result<(), RuntimeError> {
+        let Some(handlers) = self.events.get_mut(event) else {
+            runtime_error!(format!("Unknown event '{}'.", event));
+        };
+        handlers.push(handler);
+        Ok(())
+    }
+
+    /// Calls the handlers of `event` with `arguments` in the order they
+    /// were attached, and returns their results. Stops at the first
+    /// handler that fails.
+    pub fn emit(&mut self, event: &str, arguments: &[Object]) -> Result<Vec<Object>, RuntimeError> {
+        let Some(handlers) = self.events.get(event) else {
+            runtime_error!(format!("Unknown event '{}'.", event));
+        };
+        let handlers = handlers.clone();
+        handlers
+            .into_iter()
+            .map(|handler| self.call(&Object::Function(handler), arguments.to_vec()))
+            .collect()
+    }
+
+    /// Calls the script function `function` and runs it to its return,
+    /// also from within a running program, which then carries on where it
+    /// was. On errors the call is unwound and the VM left as before.
+    pub fn call(
+        &mut self,
+        function: &Object,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RuntimeError> {
+        let program = self.program.clone().expect("no program loaded");
+        let (ip, base, height, depth) = (self.ip, self.base, self.stack.len(), self.frames.len());
+        let n = arguments.len();
+        self.stack.push(function.clone());
+        self.stack.extend(arguments);
+        let result = self.handle_op_call(n).and_then(|()| {
+            while self.frames.len() > depth {
+                self.dispatch(&program)?;
             }
-            self.ip += 1;
+            Ok(self.stack.pop().unwrap())
+        });
+        self.frames.truncate(depth);
+        self.stack.truncate(height);
+        self.base = base;
+        self.ip = ip;
+        result
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Object) -> Result<(), RuntimeError> {
+        let program = self.program.as_ref().expect("no program loaded");
+        let Some(slot) = program.global(name) else {
+            runtime_error!(format!("Undefined global '{}'.", name));
+        };
+        self.stack[slot] = value;
+        Ok(())
+    }
+
+    pub fn global(&self, name: &str) -> Option<&Object> {
+        let program = self.program.as_ref()?;
+        program.global(name).map(|slot| &self.stack[slot])
+    }
+
+    /// All globals by name, meaningful once the program is done.
+    pub fn globals(&self) -> HashMap<String, Object> {
+        let program = self.program.as_ref().expect("no program loaded");
+        program
+            .globals()
+            .map(|(name, slot)| (name.to_string(), self.stack[slot].clone()))
+            .collect()
+    }
+
+    /// The whole value stack, the script's variables at the bottom.
+    pub fn stack(&self) -> &[Object] {
+        &self.stack
+    }
+
+    /// Stack index of the first value of the innermost call, 0 in the
+    /// script.
+    pub fn frame_base(&self) -> usize {
+        self.base
+    }
+
+    /// The script and all active calls, innermost last.
+    pub fn frames(&self) -> Vec<Frame<'_>> {
+        let program = self.program.as_ref().expect("no program loaded");
+        let mut names = vec![None; program.slots()];
+        for (name, slot) in program.globals() {
+            names[slot] = Some(name);
+        }
+        let mut frames = vec![Frame {
+            function: None,
+            base: 0,
+            ip: self.ip,
+            values: &[],
+            names,
+        }];
+        for frame in &self.frames {
+            // The caller is at the call, `Invoke` is followed by a `Jmp`.
+            let call = match frame.function {
+                Some(_) => frame.return_ip - 1,
+                None => frame.return_ip - 2,
+            };
+            frames.last_mut().unwrap().ip = call;
+            let function = match &frame.function {
+                Some(function) => Some(&**function),
+                None => match program.code()[frame.return_ip - 1] {
+                    Opcode::Jmp(offset) => {
+                        let addr = (frame.return_ip - 1).wrapping_add_signed(offset as isize);
+                        program.functions().find(|f| f.addr == addr)
+                    }
+                    _ => None,
+                },
+            };
+            frames.push(Frame {
+                function,
+                base: frame.base,
+                ip: self.ip,
+                values: &[],
+                names: Vec::new(),
+            });
+        }
+        let ends: Vec<_> = frames[1..]
+            .iter()
+            .map(|f| f.base)
+            .chain([self.stack.len()])
+            .collect();
+        for (frame, end) in frames.iter_mut().zip(ends) {
+            frame.values = &self.stack[frame.base..end];
+            frame.names.resize(frame.values.len(), None);
+            let function = frame.function.map(|f| f.name.as_str());
+            for (slot, name) in frame.names.iter_mut().enumerate() {
+                if name.is_none() {
+                    *name = program.local_name(function, frame.ip, slot);
+                }
+            }
+        }
+        frames
+    }
+
+    pub fn program(&self) -> Option<&Program> {
+        self.program.as_deref()
+    }
+
+    /// Address of the next instruction, or of the failing one after a trap.
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// Source of the instruction at `ip`, where the compiler recorded it.
+    pub fn span(&self) -> Option<Span> {
+        self.program.as_ref()?.span(self.ip)
+    }
+
+    /// Runs the loaded program to its end, from where it was suspended or
+    /// from the start. A program that ended or trapped is [reset] first.
+    ///
+    /// [reset]: VM::reset
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        if matches!(self.state, State::Done | State::Trapped(_)) {
+            self.reset();
         }
+        self.resume()
     }
 
-    fn handle_op_const(&mut self, n: f64) {
-        self.stack.push(n.into());
+    /// Runs the loaded program on to its end. Never starts over: a program
+    /// that ended stays so and one that trapped fails with the same error.
+    pub fn resume(&mut self) -> Result<(), RuntimeError> {
+        if let Some(result) = self.finished() {
+            return match result {
+                StepResult::Trapped(e) => Err(e),
+                _ => Ok(()),
+            };
+        }
+        self.execute()
     }
 
-    fn handle_op_str(&mut self, s: &str) {
-        self.stack.push(s.to_owned().into());
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> StepResult {
+        self.run_for(1)
     }
 
-    fn handle_op_strcat(&mut self) {
+    /// Executes up to `n` instructions, `Continue` when the program is
+    /// still running afterwards. A program that ended or trapped isn't run
+    /// again, the result is the same as the last time.
+    pub fn run_for(&mut self, n: usize) -> StepResult {
+        if let Some(result) = self.finished() {
+            return result;
+        }
+        let program = self.program.clone().unwrap();
+        let mut result = StepResult::Continue;
+        for _ in 0..n {
+            match self.dispatch(&program) {
+                Ok(false) => {}
+                Ok(true) => {
+                    result = StepResult::Done;
+                    break;
+                }
+                Err(e) => {
+                    result = StepResult::Trapped(e);
+                    break;
+                }
+            }
+        }
+        self.state = match &result {
+            StepResult::Continue => State::Suspended,
+            StepResult::Done => State::Done,
+            StepResult::Trapped(e) => State::Trapped(e.clone()),
+        };
+        result
+    }
+
+    /// How the last run of a program that ended or trapped went, `None`
+    /// if it can go on.
+    fn finished(&self) -> Option<StepResult> {
+        match &self.state {
+            State::Empty => panic!("no program loaded"),
+            State::Ready | State::Suspended => None,
+            State::Done => Some(StepResult::Done),
+            State::Trapped(e) => Some(StepResult::Trapped(e.clone())),
+        }
+    }
+
+    /// Runs from `entry` until the end of the program and returns the
+    /// value left on top of the stack, giving up after `fuel` instructions.
+    pub(crate) fn run_with_fuel(
+        &mut self,
+        entry: usize,
+        fuel: usize,
+    ) -> Result<Object, RuntimeError> {
+        assert!(entry < self.program.as_ref().unwrap().code().len());
+        self.ip = entry;
+        match self.run_for(fuel) {
+            StepResult::Done => Ok(self.stack.pop().unwrap_or(Object::Null)),
+            StepResult::Trapped(e) => Err(e),
+            StepResult::Continue => runtime_error!("Out of fuel."),
+        }
+    }
+
+    /// Runs the loaded program to its end like [`VM::run`], and returns
+    /// the number of instructions executed.
+    pub fn run_counted(&mut self) -> Result<u64, RuntimeError> {
+        if matches!(self.state, State::Done | State::Trapped(_)) {
+            self.reset();
+        }
+        let program = self.program.clone().expect("no program loaded");
+        // Counted in a loop of its own to keep `execute` lean.
+        let mut count = 1;
+        let result = loop {
+            match self.dispatch(&program) {
+                Ok(false) => count += 1,
+                Ok(true) => break Ok(count),
+                Err(e) => break Err(e),
+            }
+        };
+        self.finish(&result);
+        result
+    }
+
+    pub(crate) fn execute(&mut self) -> Result<(), RuntimeError> {
+        let program = self.program.clone().expect("no program loaded");
+        let result = loop {
+            match self.dispatch(&program) {
+                Ok(false) => {}
+                Ok(true) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.finish(&result);
+        result
+    }
+
+    /// Records how a run to the end went.
+    fn finish<T>(&mut self, result: &Result<T, RuntimeError>) {
+        self.state = match result {
+            Ok(_) => State::Done,
+            Err(e) => State::Trapped(e.clone()),
+        };
+    }
+
+    /// Executes the instruction at `ip`, `true` once the end of the program
+    /// is reached. `ip` stays on a failing instruction. Jumps go straight
+    /// to their target address, everything else falls through.
+    #[inline(always)]
+    fn dispatch(&mut self, program: &Program) -> Result<bool, RuntimeError> {
+        match program.code().fetch(self.ip) {
+            Opcode::Const(idx) => self.handle_op_const(program, idx)?,
+            Opcode::Strcat => self.handle_op_strcat()?,
+            Opcode::Index => self.handle_op_index()?,
+            Opcode::Slice => self.handle_op_slice()?,
+            Opcode::SetIndex(slot, n) => self.handle_op_set_index(slot as usize, n as usize)?,
+            Opcode::Print => self.handle_op_print(),
+            Opcode::Add => self.handle_op_add()?,
+            Opcode::Sub => self.handle_op_sub()?,
+            Opcode::Mul => self.handle_op_mul()?,
+            Opcode::Div => self.handle_op_div()?,
+            Opcode::Less => self.handle_op_less()?,
+            Opcode::LessEqual => self.handle_op_less_equal()?,
+            Opcode::Greater => self.handle_op_greater()?,
+            Opcode::GreaterEqual => self.handle_op_greater_equal()?,
+            Opcode::Eq => self.handle_op_eq(),
+            Opcode::Neq => self.handle_op_neq(),
+            Opcode::TypeTest(ty) => self.handle_op_type_test(ty),
+            Opcode::False => self.handle_op_false(),
+            Opcode::Not => self.handle_op_not()?,
+            Opcode::Neg => self.handle_op_neg()?,
+            Opcode::Null => self.handle_op_null(),
+            Opcode::Jmp(offset) => return Ok(self.handle_op_jmp(offset)),
+            Opcode::Jz(offset) => return Ok(self.handle_op_jz(offset)),
+            Opcode::Invoke(n, slots) => {
+                self.handle_op_invoke(program, n as usize, slots as usize)?
+            }
+            Opcode::Call(n) => return self.handle_op_call(n as usize).map(|_| false),
+            Opcode::Native(builtin, n) => self.handle_op_native(builtin as usize, n as usize)?,
+            Opcode::Ret => return self.handle_op_ret(),
+            Opcode::GetLocal(idx) => self.handle_op_get_local(idx as usize),
+            Opcode::Take(idx) => self.handle_op_take(idx as usize),
+            Opcode::SetLocal(idx) => self.handle_op_set_local(idx as usize),
+            Opcode::Pop => self.handle_op_pop(),
+            Opcode::EndOfProgram => return Ok(true),
+        }
+        self.ip += 1;
+        Ok(false)
+    }
+
+    fn handle_op_const(&mut self, program: &Program, idx: u32) -> Result<(), RuntimeError> {
+        let constant = &program.constants()[idx as usize];
+        // Lists are shared, each use of a constant one gets a list of its
+        // own rather than one changing the program.
+        let value = match constant {
+            Object::List(_) => builtins::deep_copy(self, constant, &mut HashMap::new())?,
+            _ => constant.clone(),
+        };
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn handle_op_strcat(&mut self) -> Result<(), RuntimeError> {
         let b = self.stack.pop().unwrap();
         let a = self.stack.pop().unwrap();
 
         match (a, b) {
             (Object::String(mut a), Object::String(b)) => {
+                // Checked before the string grows past the limit.
+                self.size_limits.check_string(a.len() + b.len())?;
                 a.push_str(&b);
-                self.stack.push((*a).into());
+                let s = self.heap.string(*a)?;
+                self.stack.push(s);
             }
-            _ => {
-                runtime_error!("Can only concatenate two strings.");
+            (Object::Bytes(mut a), Object::Bytes(b)) => {
+                self.size_limits.check_string(a.len() + b.len())?;
+                a.extend_from_slice(&b);
+                let bytes = self.heap.bytes(*a)?;
+                self.stack.push(bytes);
+            }
+            (a, b) => {
+                runtime_error!(format!(
+                    "Can only concatenate two strings or two byte strings, found {} and {}.",
+                    a.type_name(),
+                    b.type_name()
+                ));
             }
         }
+        Ok(())
+    }
+
+    fn handle_op_index(&mut self) -> Result<(), RuntimeError> {
+        let index = self.stack.pop().unwrap();
+        match self.stack.pop().unwrap() {
+            Object::String(s) => {
+                let len = s.chars().count();
+                let index = position(&index, len, "String", self.indexing)?;
+                match s.chars().nth(index) {
+                    Some(c) => {
+                        let s = self.new_string(c.to_string())?;
+                        self.stack.push(s);
+                    }
+                    None => runtime_error!(format!(
+                        "String index {} out of range for length {}.",
+                        index, len
+                    )),
+                }
+            }
+            Object::Bytes(b) => {
+                let index = byte_position(&index, b.len(), self.indexing)?;
+                self.stack.push((b[index] as f64).into());
+            }
+            Object::List(l) => {
+                let items = l.items();
+                let index = position(&index, items.len(), "List", self.indexing)?;
+                match items.get(index) {
+                    Some(item) => self.stack.push(item.clone()),
+                    None => runtime_error!(format!(
+                        "List index {} out of range for length {}.",
+                        index,
+                        items.len()
+                    )),
+                }
+            }
+            _ => runtime_error!("Can only index strings, byte strings and lists."),
+        }
+        Ok(())
+    }
+
+    fn handle_op_slice(&mut self) -> Result<(), RuntimeError> {
+        let end = self.stack.pop().unwrap();
+        let start = self.stack.pop().unwrap();
+        let (what, len) = match self.stack.last().unwrap() {
+            Object::String(s) => ("String", s.chars().count()),
+            Object::Bytes(b) => ("Byte string", b.len()),
+            Object::List(l) => ("List", l.len()),
+            _ => runtime_error!("Can only index strings, byte strings and lists."),
+        };
+        let start = position(&start, len, what, self.indexing)?;
+        let end = position(&end, len, what, self.indexing)?;
+        if start > end || end > len {
+            runtime_error!(format!(
+                "{} slice {}..{} out of range for length {}.",
+                what, start, end, len
+            ));
+        }
+        let slice = match self.stack.pop().unwrap() {
+            Object::String(s) => {
+                let s = s.chars().skip(start).take(end - start).collect();
+                self.new_string(s)?
+            }
+            Object::Bytes(b) => self.new_bytes(b[start..end].to_vec())?,
+            Object::List(l) => {
+                let items = l.items().range(start..end).cloned().collect();
+                self.new_list(items)?
+            }
+            _ => unreachable!(),
+        };
+        self.stack.push(slice);
+        Ok(())
+    }
+
+    fn handle_op_set_index(&mut self, slot: usize, n: usize) -> Result<(), RuntimeError> {
+        let value = self.stack.pop().unwrap();
+        let indices = self.stack.split_off(self.stack.len() - n);
+        let (last, path) = indices.split_last().unwrap();
+        let indexing = self.indexing;
+        let limits = self.size_limits;
+
+        // Lists on the way down are shared, only the one holding the target
+        // is locked, and only to store into it: a list it holds may be
+        // itself.
+        let mut holder: Option<(Arc<List>, &Object)> = None;
+        let mut list = as_list(&self.stack[self.base + slot]);
+        for index in path {
+            let Some(l) = list else {
+                runtime_error!("Can only index strings, byte strings and lists.");
+            };
+            list = {
+                let items = l.items();
+                as_list(&items[list_position(index, items.len(), indexing)?])
+            };
+            holder = Some((l, index));
+        }
+
+        if let Some(l) = list {
+            let mut items = l.items_mut()?;
+            let index = list_position(last, items.len(), indexing)?;
+            items[index] = value.clone();
+            drop(items);
+            self.stack.push(value);
+            return Ok(());
+        }
+
+        // Strings and byte strings are changed where they are held.
+        let mut items;
+        let target = match &holder {
+            None => &mut self.stack[self.base + slot],
+            Some((l, i)) => {
+                items = l.items_mut()?;
+                let index = list_position(i, items.len(), indexing)?;
+                &mut items[index]
+            }
+        };
+        match target {
+            Object::String(s) => {
+                let Object::String(value) = &value else {
+                    runtime_error!("Can only store strings into strings.");
+                };
+                let len = s.chars().count();
+                let index = position(last, len, "String", indexing)?;
+                let Some((at, c)) = s.char_indices().nth(index) else {
+                    runtime_error!(format!(
+                        "String index {} out of range for length {}.",
+                        index, len
+                    ));
+                };
+                limits.check_string(s.len() - c.len_utf8() + value.len())?;
+                s.replace_range(at..at + c.len_utf8(), value);
+            }
+            Object::Bytes(b) => {
+                let index = byte_position(last, b.len(), indexing)?;
+                b[index] = byte(&value)?;
+            }
+            _ => runtime_error!("Can only index strings, byte strings and lists."),
+        }
+        self.stack.push(value);
+        Ok(())
     }
 
     fn handle_op_print(&mut self) {
@@ -222,80 +1027,354 @@ impl<'a> VM<'a> {
         }
     }
 
-    fn handle_op_add(&mut self) {
+    fn handle_op_add(&mut self) -> Result<(), RuntimeError> {
         binop!(self, +);
+        Ok(())
     }
 
-    fn handle_op_sub(&mut self) {
+    fn handle_op_sub(&mut self) -> Result<(), RuntimeError> {
         binop!(self, -);
+        Ok(())
     }
 
-    fn handle_op_mul(&mut self) {
+    fn handle_op_mul(&mut self) -> Result<(), RuntimeError> {
         binop!(self, *);
+        Ok(())
     }
 
-    fn handle_op_div(&mut self) {
+    fn handle_op_div(&mut self) -> Result<(), RuntimeError> {
         binop!(self, /);
+        Ok(())
     }
 
     fn handle_op_eq(&mut self) {
-        binop!(self, ==);
+        let b = self.stack.pop().unwrap();
+        let a = self.stack.pop().unwrap();
+        self.stack.push((a == b).into());
+    }
+
+    fn handle_op_neq(&mut self) {
+        let b = self.stack.pop().unwrap();
+        let a = self.stack.pop().unwrap();
+        self.stack.push((a != b).into());
     }
 
-    fn handle_op_less(&mut self) {
+    fn handle_op_type_test(&mut self, ty: Type) {
+        let object = self.stack.pop().unwrap();
+        self.stack.push(ty.matches(&object).into());
+    }
+
+    fn handle_op_less(&mut self) -> Result<(), RuntimeError> {
         binop!(self, <);
+        Ok(())
+    }
+
+    fn handle_op_less_equal(&mut self) -> Result<(), RuntimeError> {
+        binop!(self, <=);
+        Ok(())
+    }
+
+    fn handle_op_greater(&mut self) -> Result<(), RuntimeError> {
+        binop!(self, >);
+        Ok(())
+    }
+
+    fn handle_op_greater_equal(&mut self) -> Result<(), RuntimeError> {
+        binop!(self, >=);
+        Ok(())
     }
 
     fn handle_op_false(&mut self) {
         self.stack.push(false.into());
     }
 
-    fn handle_op_not(&mut self) {
+    fn handle_op_not(&mut self) -> Result<(), RuntimeError> {
         let obj = self.stack.pop().unwrap();
+        if !matches!(obj, Object::Bool(_)) {
+            runtime_error!("Operand of '!' must be a bool.");
+        }
         self.stack.push(!obj);
+        Ok(())
+    }
+
+    fn handle_op_neg(&mut self) -> Result<(), RuntimeError> {
+        let obj = self.stack.pop().unwrap();
+        if !matches!(obj, Object::Number(_)) {
+            runtime_error!(format!(
+                "Operand of '-' must be a number, found {}.",
+                obj.type_name()
+            ));
+        }
+        self.stack.push(-obj);
+        Ok(())
     }
 
     fn handle_op_null(&mut self) {
         self.stack.push(Object::Null);
     }
 
-    fn handle_op_jmp(&mut self, addr: usize) {
-//        assert!(addr+1 < self.bytecode.unwrap().len(), "jmp out of bounds");
-        self.ip = addr;
+    fn handle_op_jmp(&mut self, offset: i32) -> bool {
+        self.ip = self.ip.wrapping_add_signed(offset as isize);
+        false
     }
 
-    fn handle_op_jz(&mut self, addr: usize) {
+    fn handle_op_jz(&mut self, offset: i32) -> bool {
         let item = self.stack.pop().unwrap();
         if let Object::Bool(_b @ false) = item {
-//            assert!(addr+1 < self.bytecode.unwrap().len(), "jz out of bounds");
-            self.ip = addr;
+            self.ip = self.ip.wrapping_add_signed(offset as isize);
+        } else {
+            self.ip += 1;
         }
+        false
     }
 
-    fn handle_op_invoke(&mut self, n: usize) {
-        self.frame_ptrs.push(InternalObject::BytecodePtr(
-            self.ip + 1,
-            self.stack.len() - n,
-        ));
+    fn handle_op_invoke(
+        &mut self,
+        program: &Program,
+        n: usize,
+        slots: usize,
+    ) -> Result<(), RuntimeError> {
+        let base = self.stack.len() - n;
+        let mut memo = None;
+        if !self.memos.is_empty() {
+            let Opcode::Jmp(offset) = program.code()[self.ip + 1] else {
+                unreachable!("Invoke without Jmp");
+            };
+            let addr = (self.ip + 1).wrapping_add_signed(offset as isize);
+            match self.memoized(addr, base)? {
+                Memo::Hit => {
+                    // Past the `Jmp` as well.
+                    self.ip += 1;
+                    return Ok(());
+                }
+                Memo::Miss(key) => memo = Some((addr, key)),
+                Memo::Uncached => {}
+            }
+        }
+        self.frames.push(CallFrame {
+            return_ip: self.ip + 2,
+            base,
+            function: None,
+            memo,
+        });
+        self.base = base;
+        self.stack.resize(base + slots.max(n), Object::Null);
+        Ok(())
+    }
+
+    fn handle_op_call(&mut self, n: usize) -> Result<(), RuntimeError> {
+        let callee = self.stack.len() - n - 1;
+        let Object::Function(function) = self.stack.remove(callee) else {
+            runtime_error!("Can only call functions.");
+        };
+        // Function values may come from the host or another program, only
+        // the program's own were checked.
+        let slots = function.slots.max(function.arity);
+        let known = self
+            .program
+            .as_ref()
+            .is_some_and(|p| p.is_entry(function.addr, slots));
+        if !known {
+            runtime_error!(format!(
+                "Function '{}' is not in the program.",
+                function.name
+            ));
+        }
+        if function.arity != n {
+            runtime_error!(format!(
+                "Function '{}' takes {} arguments, found {}.",
+                function.name, function.arity, n
+            ));
+        }
+        let mut memo = None;
+        match self.memoized(function.addr, callee)? {
+            Memo::Hit => {
+                self.ip += 1;
+                return Ok(());
+            }
+            Memo::Miss(key) => memo = Some((function.addr, key)),
+            Memo::Uncached => {}
+        }
+        self.base = callee;
+        self.stack
+            .resize(callee + function.slots.max(n), Object::Null);
+        let return_ip = self.ip + 1;
+        self.ip = function.addr;
+        self.frames.push(CallFrame {
+            return_ip,
+            base: callee,
+            function: Some(function),
+            memo,
+        });
+        Ok(())
+    }
+
+    /// Looks the call of the function at `addr` with the arguments from
+    /// `base` up in its cache, replacing the arguments with the result on
+    /// a hit.
+    fn memoized(&mut self, addr: usize, base: usize) -> Result<Memo, RuntimeError> {
+        let Some(cache) = self.memos.get(&addr) else {
+            return Ok(Memo::Uncached);
+        };
+        let key: Vec<Key> = self.stack[base..]
+            .iter()
+            .map(Key::new)
+            .collect::<Result<_, _>>()?;
+        Ok(match cache.get(&key) {
+            Some(result) => {
+                let result = result.clone();
+                self.stack.truncate(base);
+                self.stack.push(result);
+                Memo::Hit
+            }
+            None => Memo::Miss(key),
+        })
+    }
+
+    /// Caches the results of the function at `addr` from now on, see the
+    /// `memoize` builtin. Cached calls don't run, not even for their side
+    /// effects.
+    pub(crate) fn memoize(&mut self, addr: usize) {
+        self.memos.entry(addr).or_default();
+    }
+
+    fn handle_op_ret(&mut self) -> Result<bool, RuntimeError> {
+        let Some(frame) = self.frames.pop() else {
+            runtime_error!("Can't return outside of a function.");
+        };
+        let result = self.stack.pop().unwrap();
+        if let Some((addr, key)) = frame.memo {
+            let cache = self.memos.get_mut(&addr).unwrap();
+            cache.insert(key, result.clone());
+        }
+        self.stack.truncate(frame.base);
+        self.stack.push(result);
+        self.base = self.frames.last().map_or(0, |f| f.base);
+        self.ip = frame.return_ip;
+        Ok(false)
+    }
+
+    fn handle_op_native(&mut self, builtin: usize, n: usize) -> Result<(), RuntimeError> {
+        let builtin = &BUILTINS[builtin];
+        if let Some(capability) = builtin.capability {
+            if !self.capabilities.allows(capability) {
+                runtime_error!(format!(
+                    "'{}' needs the '{}' capability.",
+                    builtin.name,
+                    capability.name()
+                ));
+            }
+            if self.deterministic && !capability.is_deterministic() {
+                runtime_error!(format!(
+                    "'{}' is not available in deterministic mode.",
+                    builtin.name
+                ));
+            }
+        }
+        let arguments = self.stack.split_off(self.stack.len() - n);
+        let result = (builtin.function)(self, arguments)?;
+        self.stack.push(result);
+        Ok(())
+    }
+
+    /// The compiled `pattern`, cached for the lifetime of the VM.
+    pub(crate) fn regex(&mut self, pattern: &str) -> Result<&Regex, RuntimeError> {
+        if !self.regexes.contains_key(pattern) {
+            match Regex::new(pattern) {
+                Ok(regex) => self.regexes.insert(pattern.to_string(), regex),
+                Err(e) => runtime_error!(format!("Invalid regex: {}", e)),
+            };
+        }
+        Ok(&self.regexes[pattern])
     }
 
-    fn handle_op_ret(&mut self) {
-        let retaddr = self.frame_ptrs.pop().unwrap();
-        let InternalObject::BytecodePtr(ptr, _) = retaddr;
-//        debug_assert!(ptr+1 < self.bytecode.unwrap().len(), "ret out of bounds");
-        self.ip = ptr;
+    fn handle_op_get_local(&mut self, idx: usize) {
+        let item = self.stack[self.base + idx].clone();
+        self.stack.push(item);
     }
 
-    fn handle_op_deepget(&mut self, idx: usize) {
-        let item = self.stack[adjust_idx!(self, idx)].clone();
+    fn handle_op_take(&mut self, idx: usize) {
+        let item = std::mem::replace(&mut self.stack[self.base + idx], Object::Null);
         self.stack.push(item);
     }
 
-    fn handle_op_deepset(&mut self, idx: usize) {
-        self.stack.swap_remove(adjust_idx!(self, idx));
+    fn handle_op_set_local(&mut self, idx: usize) {
+        let value = self.stack.pop().unwrap();
+        self.stack[self.base + idx] = value;
     }
 
     fn handle_op_pop(&mut self) {
         self.stack.pop();
     }
 }
+
+/// Converts an index operand into a `what` of `len` to a position as
+/// `indexing` says, which is only known to be non-negative.
+fn position(
+    index: &Object,
+    len: usize,
+    what: &str,
+    indexing: Indexing,
+) -> Result<usize, RuntimeError> {
+    let Object::Number(n) = *index else {
+        runtime_error!(format!("{} index must be a number.", what));
+    };
+    let mut position = n;
+    if position.fract() != 0.0 {
+        // Infinities and NaN have no whole part to truncate to.
+        if !indexing.truncate || !position.is_finite() {
+            runtime_error!(format!("{} index {} is not a whole number.", what, n));
+        }
+        position = position.trunc();
+    }
+    if position < 0.0 && indexing.from_end {
+        position += len as f64;
+    }
+    if position < 0.0 {
+        runtime_error!(format!(
+            "{} index {} out of range for length {}.",
+            what, n, len
+        ));
+    }
+    Ok(position as usize)
+}
+
+/// Position of an existing byte in a byte string of `len`.
+fn byte_position(index: &Object, len: usize, indexing: Indexing) -> Result<usize, RuntimeError> {
+    let position = position(index, len, "Byte string", indexing)?;
+    if position >= len {
+        runtime_error!(format!(
+            "Byte string index {} out of range for length {}.",
+            position, len
+        ));
+    }
+    Ok(position)
+}
+
+/// The number `value` stored into a byte string as a byte.
+fn byte(value: &Object) -> Result<u8, RuntimeError> {
+    match value {
+        Object::Number(n) if n.fract() == 0.0 && (0.0..=255.0).contains(n) => Ok(*n as u8),
+        _ => runtime_error!("Can only store whole numbers from 0 to 255 into byte strings."),
+    }
+}
+
+/// The list `item` is, shared.
+fn as_list(item: &Object) -> Option<Arc<List>> {
+    match item {
+        Object::List(l) => Some(l.clone()),
+        _ => None,
+    }
+}
+
+/// Position of an existing element in a list of `len`.
+fn list_position(index: &Object, len: usize, indexing: Indexing) -> Result<usize, RuntimeError> {
+    let position = position(index, len, "List", indexing)?;
+    if position >= len {
+        runtime_error!(format!(
+            "List index {} out of range for length {}.",
+            position, len
+        ));
+    }
+    Ok(position)
+}
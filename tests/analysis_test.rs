@@ -0,0 +1,65 @@
+use std::collections::BTreeSet;
+
+use reaper::analysis::{analyze, CallGraph, SCRIPT};
+use reaper::parser::Parser;
+use reaper::tokenizer::Tokenizer;
+use reaper::Capability;
+
+fn graph(src: &str) -> CallGraph {
+    let mut parser = Parser::new();
+    let ast = parser.parse(Tokenizer::new(src).collect());
+    assert!(parser.errors().is_empty());
+    analyze(&ast)
+}
+
+fn set(names: &[&str]) -> BTreeSet<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+#[test]
+fn test_call_graph() {
+    let graph = graph(
+        "fn a(x) { return b(x) + len(x); }
+         fn b(x) { return re.match(x, \"y\"); }
+         fn c() { return a; }
+         fn d(f) { return f(1); }
+         print a(\"s\");",
+    );
+    assert_eq!(graph.functions[SCRIPT].calls, set(&["a"]));
+    assert_eq!(graph.functions["a"].calls, set(&["b"]));
+    assert_eq!(graph.functions["a"].builtins, set(&["len"]));
+    assert_eq!(graph.functions["b"].builtins, set(&["re.match"]));
+    // Passed as a value, may be called.
+    assert_eq!(graph.functions["c"].calls, set(&["a"]));
+    // A parameter, not a function.
+    assert!(graph.functions["d"].calls.is_empty());
+    assert!(graph.functions.contains_key("max_of_list"));
+
+    assert_eq!(graph.reachable(SCRIPT), set(&[SCRIPT, "a", "b"]));
+    assert_eq!(graph.reachable("c"), set(&["a", "b", "c"]));
+}
+
+#[test]
+fn test_globals() {
+    let graph = graph(
+        "total = 0;
+         total = total + limit;
+         if (total < 1) { t = total; print t; }
+         xs[0] = 1;
+         fn f(n) { total = n; return total; }",
+    );
+    let script = &graph.functions[SCRIPT];
+    // `limit` is declared by the embedder, `t` is local to the block.
+    assert_eq!(script.reads, set(&["limit", "total"]));
+    assert_eq!(script.writes, set(&["total", "xs"]));
+    assert!(graph.functions["f"].reads.is_empty());
+    assert!(graph.functions["f"].writes.is_empty());
+}
+
+#[test]
+fn test_capabilities() {
+    let graph = graph("fn f() { return measure(g); } fn g() {} print 1;");
+    assert!(!graph.capabilities(SCRIPT).allows(Capability::Time));
+    assert!(graph.capabilities("f").allows(Capability::Time));
+    assert!(!graph.capabilities("g").allows(Capability::Time));
+}
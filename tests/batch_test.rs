@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use reaper::batch::run_many;
+use reaper::compiler::Compiler;
+use reaper::parser::Parser;
+use reaper::program::Program;
+use reaper::tokenizer::Tokenizer;
+use reaper::Object;
+
+fn compile(src: &str, globals: &[&str]) -> Arc<Program> {
+    let ast = Parser::default().parse(Tokenizer::new(src).collect());
+    let mut compiler = Compiler::default();
+    for global in globals {
+        compiler.declare_global(global);
+    }
+    Arc::new(compiler.compile(ast).unwrap())
+}
+
+#[test]
+fn test_run_many_per_record() {
+    let program = compile(
+        r#"fn double(n) { return n * 2; } y = double(x); if (x < 0) { z = x ++ "!"; } else {}"#,
+        &["x"],
+    );
+    let inputs = (-1..100)
+        .map(|x| HashMap::from([("x".to_string(), Object::Number(x as f64))]))
+        .collect();
+    let results = run_many(&program, inputs, 4);
+
+    assert_eq!(results.len(), 101);
+    match &results[0] {
+        Err(e) => assert_eq!(
+            e.message,
+            "Can only concatenate two strings or two byte strings, found num and str."
+        ),
+        result => panic!("unexpected {:?}", result),
+    }
+    for (x, result) in (0..100).zip(&results[1..]) {
+        let globals = result.as_ref().unwrap();
+        assert_eq!(globals["x"], Object::Number(x as f64));
+        assert_eq!(globals["y"], Object::Number(2.0 * x as f64));
+    }
+}
+
+#[test]
+fn test_run_many_rejects_unknown_globals() {
+    let program = compile("y = 1;", &[]);
+    let inputs = vec![HashMap::from([("x".to_string(), Object::Null)])];
+    let results = run_many(&program, inputs, 2);
+    assert_eq!(
+        results[0].as_ref().unwrap_err().message,
+        "Undefined global 'x'."
+    );
+    assert!(run_many(&program, Vec::new(), 2).is_empty());
+}
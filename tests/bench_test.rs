@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use reaper::bench::{self, BenchResult};
+use reaper::compiler::OptLevel;
+use reaper::driver::Error;
+
+#[test]
+fn test_runs_scripts_in_order() {
+    let dir = std::env::temp_dir().join(format!("reaper-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("b.reap"), "i = 0; while (i < 10) { i = i + 1; }").unwrap();
+    std::fs::write(dir.join("a.reap"), "x = 1;").unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a script").unwrap();
+
+    let results = bench::run(&dir, OptLevel::O0).unwrap();
+    let names: Vec<_> = results.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["a", "b"]);
+    assert!(results[0].instructions < results[1].instructions);
+
+    std::fs::write(dir.join("c.reap"), "x = 1 ++ 2;").unwrap();
+    assert!(matches!(
+        bench::run(&dir, OptLevel::O0),
+        Err(Error::Diagnostics(_))
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_table() {
+    let results = [BenchResult {
+        name: "loops".to_string(),
+        instructions: 2_000_000,
+        elapsed: Duration::from_millis(500),
+    }];
+    assert_eq!(
+        bench::table(&results),
+        "script    instructions          ms    Minstr/s\n\
+         loops          2000000       500.0         4.0\n"
+    );
+}
@@ -0,0 +1,50 @@
+use reaper::codes::{lookup, CODES};
+use reaper::driver::compile_source;
+use reaper::OptLevel;
+use std::collections::HashSet;
+
+fn code(src: &str) -> &'static str {
+    let e = compile_source(src, OptLevel::O0).unwrap_err();
+    e.diagnostics()[0].code
+}
+
+#[test]
+fn test_diagnostics_have_codes() {
+    assert_eq!(code("print 1 print 2;"), "E001");
+    assert_eq!(code("fn f() {"), "E002");
+    assert_eq!(code(&"{".repeat(1000)), "E003");
+    assert_eq!(code("print 0 < 1 < 2;"), "E004");
+    assert_eq!(code("print x;"), "E010");
+    assert_eq!(code("print f();"), "E011");
+    assert_eq!(code("1 = 2;"), "E012");
+    assert_eq!(code("break;"), "E013");
+    assert_eq!(code("while (true) { break outer; }"), "E014");
+    assert_eq!(code("defer f();"), "E015");
+    assert_eq!(code("continue;"), "E018");
+    assert_eq!(code("return 1;"), "E019");
+    assert_eq!(code("fn f(a: num) {} f(\"a\");"), "R001");
+    assert_eq!(code("fn f(a: int) {}"), "R002");
+    assert_eq!(code("fn f(a) {} f();"), "R003");
+}
+
+#[test]
+fn test_codes_are_explained() {
+    let mut seen = HashSet::new();
+    for code in CODES {
+        assert!(seen.insert(code.code), "{} twice", code.code);
+        assert!(code.explanation.ends_with('\n'));
+    }
+    assert_eq!(lookup("r001").map(|c| c.title), Some("type mismatch"));
+    assert!(lookup("E999").is_none());
+}
+
+#[test]
+fn test_explain_command() {
+    let mut cmd = assert_cmd::Command::cargo_bin("reaper").unwrap();
+    let output = cmd.args(["explain", "E013"]).assert().success();
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    assert!(stdout.starts_with("E013: 'break' outside of a loop\n\n"));
+
+    let mut cmd = assert_cmd::Command::cargo_bin("reaper").unwrap();
+    cmd.args(["explain", "E999"]).assert().failure();
+}
@@ -0,0 +1,67 @@
+use reaper::completion::{context, Completion, Context};
+
+/// The completion at the `|` in `src`.
+fn at_cursor(src: &str) -> Completion {
+    let offset = src.find('|').unwrap();
+    context(&src.replacen('|', "", 1), offset)
+}
+
+fn argument(function: &str, index: usize) -> Context {
+    Context::Argument {
+        function: function.to_string(),
+        index,
+    }
+}
+
+#[test]
+fn test_contexts() {
+    for (src, expected) in [
+        ("|", Context::Statement),
+        ("x = 1;\nwh|", Context::Statement),
+        ("fn f() { |", Context::Statement),
+        ("if (a) { print 1; } else |", Context::Statement),
+        ("print |", Context::Expression),
+        ("x = a + |", Context::Expression),
+        ("if (|", Context::Expression),
+        ("print xs[|", Context::Expression),
+        ("print f(|", argument("f", 0)),
+        ("print f(1, g(2), |", argument("f", 2)),
+        ("print f(1, g(|", argument("g", 0)),
+        ("print math.pow(2, |", argument("math.pow", 1)),
+        ("print f(xs[1], |) + 1;", argument("f", 1)),
+        (
+            "print math.|",
+            Context::Member {
+                object: "math".to_string(),
+            },
+        ),
+        ("fn f(a: |", Context::Type),
+        ("fn f(a): |", Context::Type),
+        ("print x is |", Context::Type),
+        ("fn |", Context::Declaration),
+        ("fn f(a, |", Context::Declaration),
+        ("print \"a, |", Context::None),
+        ("print 1; // f(|", Context::None),
+    ] {
+        assert_eq!(at_cursor(src).context, expected, "{}", src);
+    }
+}
+
+#[test]
+fn test_prefix() {
+    let completion = at_cursor("print 1;\nprint max_of_list(le|n(xs));");
+    assert_eq!(completion.context, argument("max_of_list", 0));
+    assert_eq!((completion.prefix.as_str(), completion.start), ("le", 27));
+
+    let completion = at_cursor("print math.sq|");
+    assert_eq!(
+        completion.context,
+        Context::Member {
+            object: "math".to_string()
+        }
+    );
+    assert_eq!(completion.prefix, "sq");
+
+    assert_eq!(at_cursor("print 12|").prefix, "");
+    assert_eq!(at_cursor("print a |").prefix, "");
+}
@@ -0,0 +1,65 @@
+use reaper::compiler::{Compiler, Opcode, OptLevel};
+use reaper::parser::Parser;
+use reaper::program::Program;
+use reaper::tokenizer::Tokenizer;
+use reaper::Object;
+
+const SRC: &str = "
+fn fib(n) {
+    if (n < 2) return n;
+    return fib(n-1)+fib(n-2);
+}
+
+fn noisy(n) {
+    print n;
+    return n;
+}
+
+fn forever(n) {
+    return forever(n);
+}
+
+print fib(20);
+print noisy(1);
+print forever(1);
+";
+
+fn compile(opt_level: OptLevel) -> Program {
+    let ast = Parser::default().parse(Tokenizer::new(SRC).collect());
+    Compiler::with_opt_level(opt_level).compile(ast).unwrap()
+}
+
+fn invokes(program: &Program) -> usize {
+    program
+        .code()
+        .iter()
+        .filter(|op| matches!(op, Opcode::Invoke(..)))
+        .count()
+}
+
+#[test]
+fn test_pure_calls_are_folded_at_o2() {
+    let program = compile(OptLevel::O2);
+    assert!(program.code().iter().any(|op| matches!(op,
+        Opcode::Const(idx) if program.constants()[*idx as usize] == Object::Number(6765.0))));
+    // The recursive calls inside `fib`, `noisy(1)` because it prints and
+    // `forever(1)` because it runs out of fuel stay calls.
+    assert_eq!(invokes(&program), 5);
+}
+
+#[test]
+fn test_redefined_functions_are_not_folded_while_compiled() {
+    let src = "fn f(a, b) { return a; }
+        fn f(a, b) { for (a = 0; true; a = a) for (a = b; false; a = f(1, 2)) b; }
+        print f(1, 2);";
+    let ast = Parser::default().parse(Tokenizer::new(src).collect());
+    let program = Compiler::with_opt_level(OptLevel::O2).compile(ast).unwrap();
+    // Neither call is folded, the one in the second `f` would run it half
+    // compiled and the script's never returns.
+    assert_eq!(invokes(&program), 2);
+}
+
+#[test]
+fn test_nothing_is_folded_below_o2() {
+    assert_eq!(invokes(&compile(OptLevel::O0)), 6);
+}
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use reaper::{compile_source, FromObject, Object, OptLevel, VM};
+
+#[test]
+fn test_try_from() {
+    assert_eq!(f64::try_from(Object::Number(1.5)), Ok(1.5));
+    assert_eq!(i64::try_from(Object::Number(-3.0)), Ok(-3));
+    assert_eq!(bool::try_from(Object::Bool(true)), Ok(true));
+    assert_eq!(String::try_from(Object::from("hi")), Ok("hi".to_string()));
+    assert_eq!(
+        Vec::<Object>::try_from(Object::from(vec![1.0, 2.0])),
+        Ok(vec![Object::Number(1.0), Object::Number(2.0)])
+    );
+
+    assert_eq!(
+        f64::try_from(Object::Null).unwrap_err().message,
+        "Expected num, found null."
+    );
+    assert_eq!(
+        i64::try_from(Object::Number(1.5)).unwrap_err().message,
+        "Expected an integer, found 1.5."
+    );
+    assert!(i64::try_from(Object::Number(2f64.powi(63))).is_err());
+    assert_eq!(
+        String::try_from(Object::from(vec![true]))
+            .unwrap_err()
+            .message,
+        "Expected str, found list."
+    );
+}
+
+#[test]
+fn test_from() {
+    assert_eq!(Object::from(7i64), Object::Number(7.0));
+    assert_eq!(Object::from(None::<bool>), Object::Null);
+    assert_eq!(
+        Object::from(vec![Some("a"), None]),
+        Object::List(Arc::new(["a".to_string().into(), Object::Null].into()))
+    );
+}
+
+#[test]
+fn test_from_object() {
+    // What a script returns to the host.
+    let program = compile_source(
+        "s = \"ann\"; n = 37; fn f(x) { return null; } r = f(1);",
+        OptLevel::O1,
+    )
+    .unwrap();
+    let mut vm = VM::new();
+    vm.load(Arc::new(program));
+    vm.run().unwrap();
+    let global = |name| vm.global(name).cloned().unwrap();
+    assert_eq!(<Option<i64>>::from_object(global("r")), Ok(None));
+    let p = Object::from(vec![global("s"), global("n"), vec![true, false].into()]);
+
+    let (name, age, flags) = <(String, i64, Vec<bool>)>::from_object(p.clone()).unwrap();
+    assert_eq!((name.as_str(), age, flags), ("ann", 37, vec![true, false]));
+    assert_eq!(
+        <(String, i64)>::from_object(p.clone())
+            .err()
+            .unwrap()
+            .message,
+        "Expected a list of 2 items, found 3."
+    );
+    assert_eq!(
+        <(String, Option<i64>, Vec<f64>)>::from_object(p)
+            .err()
+            .unwrap()
+            .message,
+        "Expected num, found bool."
+    );
+}
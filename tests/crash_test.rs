@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use reaper::compiler::OptLevel;
+use reaper::crash::{CrashReport, CrashReporter, ReportFormat};
+use reaper::driver::{compile_source, run_file_with, Error, RunOptions};
+use reaper::vm::VM;
+
+fn crash(src: &str) -> CrashReport {
+    let program = compile_source(src, OptLevel::O0).unwrap();
+    let mut vm = VM::new();
+    vm.load(Arc::new(program));
+    let error = vm.run().unwrap_err();
+    CrashReport::new(&vm, &error)
+}
+
+#[test]
+fn test_report_contents() {
+    let report = crash(
+        "fn inner(a) { return a[5]; }
+        fn outer(n) { return inner(n); }
+        x = outer(\"abc\");",
+    );
+    let trace: Vec<_> = report.trace.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(trace, ["inner", "outer", "<script>"]);
+    assert_eq!(report.trace[0].1, report.ip);
+    assert_eq!(
+        report
+            .code
+            .iter()
+            .find(|(at, _)| *at == report.ip)
+            .unwrap()
+            .1,
+        "Index"
+    );
+    assert!(report.code.len() <= 11);
+    // The operands are gone, `a` is on top.
+    assert_eq!(report.stack[0], "String(\"abc\")");
+    assert_eq!(
+        report.locals,
+        [("a".to_string(), "String(\"abc\")".to_string())]
+    );
+}
+
+#[test]
+fn test_render() {
+    let report = crash("s = \"abc\"; x = s[3];");
+    let text = report.render(ReportFormat::Text);
+    assert!(text.starts_with(&format!("error: {}\n", report.error)));
+    assert!(text.contains(&format!(">{:>6} Index\n", report.ip)));
+    assert!(text.contains("  <script> at "));
+    assert!(text.contains("\nlocals:\n  s = String(\"abc\")\n"));
+
+    let json = report.render(ReportFormat::Json);
+    assert!(json.starts_with("{\"error\":\""));
+    assert!(json.contains("{\"function\":\"<script>\",\"ip\":"));
+    assert!(json.contains(",\"String(\\\"abc\\\")\""));
+    assert!(json.contains("\"locals\":[{\"name\":\"s\",\"value\":"));
+}
+
+#[test]
+fn test_report_to_file() {
+    let dir = std::env::temp_dir().join(format!("reaper-crash-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("fail.reap");
+    std::fs::write(&script, "s = \"abc\";\nx = s[3];\n").unwrap();
+    let options = RunOptions {
+        crash_reporter: Some(CrashReporter {
+            format: ReportFormat::Json,
+            path: Some(dir.join("crash.json")),
+        }),
+        ..RunOptions::default()
+    };
+
+    let result = run_file_with(script.to_str().unwrap(), OptLevel::O0, &options);
+    assert!(matches!(result, Err(Error::Runtime(..))));
+    let report = std::fs::read_to_string(dir.join("crash.json")).unwrap();
+    assert!(report.contains("\"trace\":[{\"function\":\"<script>\""));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
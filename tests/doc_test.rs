@@ -0,0 +1,84 @@
+use reaper::doc::{extract, render, DocFormat, FunctionDoc};
+
+const SRC: &str = "// A library, not documentation.
+
+/// The larger of `a` and `b`.
+///
+/// Returns `a` when they are equal.
+fn max(a: num, b: num): num {
+    /// Not a function.
+    if (a < b) return b;
+    return a;
+}
+
+/// Detached by the empty line.
+
+fn helper(x) { return x; }
+
+/// Wraps <s> in \"quotes\".
+fn quote(s: str) {
+    /// Nested functions are not listed.
+    fn inner() {}
+    return s;
+}
+";
+
+#[test]
+fn test_doc_comments_are_extracted() {
+    let functions = extract(SRC).unwrap();
+    assert_eq!(
+        functions,
+        [
+            FunctionDoc {
+                name: "max".to_string(),
+                signature: "fn max(a: num, b: num): num".to_string(),
+                doc: Some(
+                    "The larger of `a` and `b`.\n\nReturns `a` when they are equal.".to_string()
+                ),
+            },
+            FunctionDoc {
+                name: "helper".to_string(),
+                signature: "fn helper(x)".to_string(),
+                doc: None,
+            },
+            FunctionDoc {
+                name: "quote".to_string(),
+                signature: "fn quote(s: str)".to_string(),
+                doc: Some("Wraps <s> in \"quotes\".".to_string()),
+            },
+        ]
+    );
+
+    let e = extract("fn f( {}").unwrap_err();
+    assert_eq!(e[0].code, "E001");
+}
+
+#[test]
+fn test_render() {
+    let functions = extract(SRC).unwrap();
+    let markdown = render("lib", &functions, DocFormat::Markdown);
+    assert!(markdown.starts_with("# lib\n\n## max\n\n`fn max(a: num, b: num): num`\n\n"));
+    assert!(markdown.contains("\n## helper\n\n`fn helper(x)`\n\n## quote\n"));
+
+    let html = render("lib", &functions, DocFormat::Html);
+    assert!(html.starts_with("<h1>lib</h1>\n<h2 id=\"max\">max</h2>\n"));
+    assert!(html.contains("<p>The larger of `a` and `b`.</p>\n<p>Returns"));
+    assert!(html.contains("<p>Wraps &lt;s&gt; in &quot;quotes&quot;.</p>"));
+}
+
+#[test]
+fn test_doc_command() {
+    let path = std::env::temp_dir().join(format!("reaper-doc-{}.reap", std::process::id()));
+    std::fs::write(&path, SRC).unwrap();
+    let mut cmd = assert_cmd::Command::cargo_bin("reaper").unwrap();
+    let output = cmd
+        .args(["doc", "--doc-format=html", path.to_str().unwrap()])
+        .assert()
+        .success();
+    std::fs::remove_file(&path).unwrap();
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    assert!(stdout.starts_with(&format!("<h1>reaper-doc-{}</h1>\n", std::process::id())));
+
+    let mut cmd = assert_cmd::Command::cargo_bin("reaper").unwrap();
+    cmd.arg("doc").assert().failure();
+}
@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+
+use reaper::compiler::{CompileOptions, Limits, OptLevel};
+use reaper::driver::{compile_source, eval_expr, eval_expr_with, Error};
+use reaper::parser::Parser;
+use reaper::sandbox::Sandbox;
+use reaper::tokenizer::Tokenizer;
+use reaper::Object;
+
+fn messages(src: &str) -> Vec<String> {
+    match compile_source(src, OptLevel::O0) {
+        Err(Error::Diagnostics(diagnostics)) => diagnostics.iter().map(|d| d.to_string()).collect(),
+        Err(e) => panic!("unexpected {:?}", e),
+        Ok(_) => vec![],
+    }
+}
+
+#[test]
+fn test_parse_errors_stop_compilation() {
+    // The undefined function would be reported by the resolver.
+    assert_eq!(
+        messages("print 1 print g();"),
+        ["error[E001]: expected ';', found 'print' at byte 8"]
+    );
+}
+
+#[test]
+fn test_resolver_and_type_errors_are_collected() {
+    assert_eq!(
+        messages("fn f(a: num) {} print g(); f(\"x\");"),
+        [
+            "error[E011]: undefined function 'g' at byte 22",
+            "error[R001]: argument of 'f' expects num, found str at byte 29"
+        ]
+    );
+    assert!(messages("print 1;").is_empty());
+}
+
+#[test]
+fn test_run_file_errors() {
+    let path = std::env::temp_dir().join(format!("reaper-driver-{}.reap", std::process::id()));
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "x = \"a\"; y = x[3];").unwrap();
+    let result = reaper::run_file(path, OptLevel::O0);
+    std::fs::remove_file(path).unwrap();
+    match result {
+        Err(Error::Runtime(e, _)) => {
+            assert_eq!(e.message, "String index 3 out of range for length 1.")
+        }
+        result => panic!("unexpected {:?}", result),
+    }
+    assert!(matches!(
+        reaper::run_file(path, OptLevel::O0),
+        Err(Error::Io(_))
+    ));
+}
+
+#[test]
+fn test_runtime_errors_name_operand_types_and_location() {
+    let path = std::env::temp_dir().join(format!("reaper-add-{}.reap", std::process::id()));
+    std::fs::write(
+        &path,
+        "fn add(a, b) {\n    return a + b;\n}\nprint add(1, \"a\");\n",
+    )
+    .unwrap();
+    let result = reaper::run_file(path.to_str().unwrap(), OptLevel::O0);
+    std::fs::remove_file(&path).unwrap();
+    match result {
+        Err(e @ Error::Runtime(..)) => assert_eq!(
+            e.to_string(),
+            format!(
+                "Operands of '+' must be numbers, found num and str. at {}:2:12",
+                path.display()
+            )
+        ),
+        result => panic!("unexpected {:?}", result),
+    }
+}
+
+#[test]
+fn test_run_file_reports_lines_and_columns() {
+    let path = std::env::temp_dir().join(format!("reaper-lines-{}.reap", std::process::id()));
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "x = 1;\nprint \"ä\" y;\n").unwrap();
+    let result = reaper::run_file(path, OptLevel::O0);
+    std::fs::remove_file(path).unwrap();
+    match result {
+        Err(e @ Error::Diagnostics(_)) => {
+            let expected = format!("error[E001]: expected ';', found 'y' at {}:2:11", path);
+            assert_eq!(e.to_string(), expected)
+        }
+        result => panic!("unexpected {:?}", result),
+    }
+}
+
+#[test]
+fn test_diagnostics_as_json() {
+    let path = std::env::temp_dir().join(format!("reaper-json-{}.reap", std::process::id()));
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "x = 1;\nprint \"ä\" y;\n").unwrap();
+    let result = reaper::run_file(path, OptLevel::O0);
+    std::fs::remove_file(path).unwrap();
+    let e = result.unwrap_err();
+    let json: Vec<String> = e.diagnostics().iter().map(|d| d.to_json()).collect();
+    let expected = format!(
+        "{{\"severity\":\"error\",\"code\":\"E001\",\"message\":\"expected ';', found 'y'\",\"file\":\"{}\",\"line\":2,\"column\":11,\"span\":{{\"start\":18,\"end\":19}},\"suggestions\":[{{\"message\":\"insert ';'\",\"span\":{{\"start\":17,\"end\":17}},\"replacement\":\";\"}}]}}",
+        path
+    );
+    assert_eq!(json, [expected]);
+
+    let e = compile_source("print \"a\\b\" x;", OptLevel::O0).unwrap_err();
+    assert_eq!(
+        e.diagnostics()[0].to_json(),
+        "{\"severity\":\"error\",\"code\":\"E001\",\"message\":\"expected ';', found 'x'\",\"file\":null,\"line\":null,\"column\":null,\"span\":{\"start\":12,\"end\":13},\"suggestions\":[{\"message\":\"insert ';'\",\"span\":{\"start\":11,\"end\":11},\"replacement\":\";\"}]}"
+    );
+}
+
+#[test]
+fn test_limits() {
+    let compile = |src: &str, limits| {
+        let options = CompileOptions {
+            limits,
+            ..CompileOptions::default()
+        };
+        match compile_source(src, options) {
+            Err(Error::Diagnostics(diagnostics)) => Some(diagnostics[0].message.clone()),
+            Err(e) => panic!("unexpected {:?}", e),
+            Ok(_) => None,
+        }
+    };
+    let nesting = Limits {
+        nesting: 3,
+        ..Limits::default()
+    };
+    assert_eq!(compile("{ print 1; }", nesting), None);
+    assert_eq!(
+        compile("{ { print 1; } }", nesting).as_deref(),
+        Some("nested deeper than 3 levels")
+    );
+    assert_eq!(
+        compile("x = 1 + 2 + 3 + 4;", nesting).as_deref(),
+        Some("nested deeper than 3 levels")
+    );
+
+    let code = Limits {
+        code: 1000,
+        ..Limits::default()
+    };
+    assert_eq!(compile("print 1;", code), None);
+    let message = compile(&"print 1;".repeat(1000), code).unwrap();
+    assert!(
+        message.ends_with("instructions, the limit is 1000"),
+        "{}",
+        message
+    );
+
+    let functions = Limits {
+        functions: 20,
+        ..Limits::default()
+    };
+    let src: String = (0..20).map(|i| format!("fn f{}() {{}}", i)).collect();
+    let message = compile(&src, functions).unwrap();
+    assert!(
+        message.ends_with("functions, the limit is 20"),
+        "{}",
+        message
+    );
+
+    let calls = Limits {
+        arguments: 3,
+        parameters: 3,
+        locals: 8,
+        ..Limits::default()
+    };
+    assert_eq!(compile("fn f(a, b, c) {} f(1, 2, 3);", calls), None);
+    assert_eq!(
+        compile("fn f() {} g = f; g(1, 2, 3, 4);", calls).as_deref(),
+        Some("call has 4 arguments, the limit is 3")
+    );
+    assert_eq!(
+        compile("fn f(a, b, c, d) {}", calls).as_deref(),
+        Some("function 'f' has 4 parameters, the limit is 3")
+    );
+    let src = "fn f(a) { b = 1; c = 2; d = 3; e = 4; f = 5; g = 6; h = 7; i = 8; }";
+    assert_eq!(
+        compile(src, calls).as_deref(),
+        Some("function 'f' has 9 locals, the limit is 8")
+    );
+    let src: String = (0..9).map(|i| format!("x{} = {};", i, i)).collect();
+    assert_eq!(
+        compile(&src, calls).as_deref(),
+        Some("script has 9 locals, the limit is 8")
+    );
+
+    let constants = Limits {
+        constants: 10,
+        ..Limits::default()
+    };
+    let src: String = (0..10).map(|i| format!("print {};", i)).collect();
+    let message = compile(&src, constants).unwrap();
+    assert!(
+        message.ends_with("11 constants, the limit is 10"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn test_operands_over_the_limits() {
+    // Over the default limits, and over what the instructions can hold
+    // once an embedder raises them.
+    let src = format!("fn f() {{}} g = f; g({});", vec!["1"; 70_000].join(", "));
+    let e = compile_source(&src, OptLevel::O0).unwrap_err();
+    assert_eq!(e.diagnostics()[0].code, "E020");
+    assert_eq!(
+        e.diagnostics()[0].message,
+        "call has 70000 arguments, the limit is 65535"
+    );
+
+    let options = CompileOptions {
+        limits: Limits {
+            arguments: usize::MAX,
+            ..Limits::default()
+        },
+        ..CompileOptions::default()
+    };
+    let e = compile_source(&src, options).unwrap_err();
+    assert_eq!(
+        e.diagnostics()[0].message,
+        "bytecode operand 70000 out of range"
+    );
+}
+
+#[test]
+fn test_deep_nesting_is_an_error() {
+    for src in [
+        format!("x = {}1;", "f(".repeat(10_000)),
+        format!("x = 1{};", " + 1".repeat(10_000)),
+        "{".repeat(10_000),
+    ] {
+        let e = compile_source(&src, OptLevel::O0).unwrap_err();
+        assert_eq!(e.diagnostics()[0].code, "E003");
+    }
+}
+
+#[test]
+fn test_eval_expr() {
+    let bindings = HashMap::from([
+        ("a".to_string(), Object::Number(1.0)),
+        ("b".to_string(), Object::Number(4.0)),
+        ("name".to_string(), Object::from("ann")),
+    ]);
+    let eval = |src| eval_expr(src, &bindings);
+    assert_eq!(eval("a + b * 2").unwrap(), Object::Number(9.0));
+    assert_eq!(eval("a < b").unwrap(), Object::Bool(true));
+    assert_eq!(eval("name ++ \"!\"").unwrap(), Object::from("ann!"));
+    assert_eq!(eval("len(name[1..3])").unwrap(), Object::Number(2.0));
+    assert_eq!(eval("42").unwrap(), Object::Number(42.0));
+
+    let codes = |src| -> Vec<&str> {
+        match eval(src) {
+            Err(Error::Diagnostics(diagnostics)) => diagnostics.iter().map(|d| d.code).collect(),
+            other => panic!("unexpected {:?}", other),
+        }
+    };
+    assert_eq!(codes("a + b;"), ["E001"]);
+    assert_eq!(codes("x = 1; y"), ["E001"]);
+    assert_eq!(codes(""), ["E002"]);
+    assert_eq!(codes("a + c"), ["E010"]);
+    match eval("a - name") {
+        Err(Error::Runtime(e, None)) => {
+            assert_eq!(
+                e.message,
+                "Operands of '-' must be numbers, found num and str."
+            )
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_eval_expr_with_sandbox() {
+    let bindings = HashMap::from([
+        ("price".to_string(), Object::Number(3.0)),
+        ("prices".to_string(), Object::from(vec![1.0, 3.0, 2.0])),
+        ("secret".to_string(), Object::from("hunter2")),
+    ]);
+    let sandbox = Sandbox::new()
+        .variable("price")
+        .variable("prices")
+        .function("math.floor");
+    let eval = |src| eval_expr_with(src, &bindings, &sandbox);
+    assert_eq!(eval("math.floor(price / 2)").unwrap(), Object::Number(1.0));
+
+    let messages = |src| -> Vec<String> {
+        match eval(src) {
+            Err(Error::Diagnostics(diagnostics)) => {
+                diagnostics.iter().map(|d| d.to_string()).collect()
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    };
+    assert_eq!(
+        messages("price = 1"),
+        ["error[E016]: only expressions are allowed, found an assignment at byte 6"]
+    );
+    assert_eq!(
+        messages("len(secret) + math.sqrt(price)"),
+        [
+            "error[E017]: function 'len' is not allowed at byte 0",
+            "error[E017]: variable 'secret' is not allowed at byte 4",
+            "error[E017]: function 'math.sqrt' is not allowed at byte 14",
+        ]
+    );
+    // Prelude functions too, which aren't restricted themselves.
+    assert_eq!(
+        messages("max_of_list(prices)"),
+        ["error[E017]: function 'max_of_list' is not allowed at byte 0"]
+    );
+    let sandbox = sandbox.clone().function("max_of_list");
+    assert_eq!(
+        eval_expr_with("max_of_list(prices)", &bindings, &sandbox).unwrap(),
+        Object::Number(3.0)
+    );
+}
+
+#[test]
+fn test_expressions_only() {
+    let mut parser = Parser::new();
+    parser.set_expressions_only(true);
+    parser.parse(Tokenizer::new("1 + 2; f(3);").collect());
+    assert!(parser.errors().is_empty());
+    for (src, found) in [
+        ("while (true) {}", "'while'"),
+        ("fn f() {}", "'fn'"),
+        ("print 1;", "'print'"),
+        ("{ 1; }", "a block"),
+        ("outer: while (true) {}", "a loop label"),
+        ("x = 1;", "an assignment"),
+    ] {
+        parser.parse(Tokenizer::new(src).collect());
+        let errors: Vec<_> = parser
+            .errors()
+            .iter()
+            .map(|e| (e.code, e.message.clone()))
+            .collect();
+        assert_eq!(
+            errors,
+            [(
+                "E016",
+                format!("only expressions are allowed, found {}", found)
+            )],
+            "{}",
+            src
+        );
+    }
+}
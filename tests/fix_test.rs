@@ -0,0 +1,59 @@
+use reaper::driver::compile_source;
+use reaper::fix::{apply, fix, Suggestion};
+use reaper::lint::{lint, LintConfig};
+use reaper::tokenizer::Span;
+use reaper::OptLevel;
+
+#[test]
+fn test_apply() {
+    let insert = Suggestion::insert(7, ";");
+    let replace = Suggestion::replace(Span { start: 2, end: 3 }, "=", "==");
+    assert_eq!(insert.message, "insert ';'");
+    assert_eq!(
+        apply("a = 1\nb = 2;", &[&insert, &replace]),
+        "a == 1\nb; = 2;"
+    );
+    // Overlapping suggestions after the first are skipped.
+    let other = Suggestion::replace(Span { start: 2, end: 3 }, "=", "!=");
+    assert_eq!(apply("a = 1;", &[&replace, &other]), "a == 1;");
+}
+
+#[test]
+fn test_suggestions() {
+    let e = compile_source("print 1\nprint 2;", OptLevel::O0).unwrap_err();
+    let diagnostic = &e.diagnostics()[0];
+    assert_eq!(diagnostic.suggestions, [Suggestion::insert(7, ";")]);
+    assert!(diagnostic.to_json().ends_with(
+        "\"suggestions\":[{\"message\":\"insert ';'\",\"span\":{\"start\":7,\"end\":7},\"replacement\":\";\"}]}"
+    ));
+
+    let warnings = lint("a = 1; while (a = 2) {}", &LintConfig::default()).unwrap();
+    assert_eq!(warnings[0].code, "W005");
+    assert_eq!(warnings[0].suggestions[0].message, "replace '=' with '=='");
+}
+
+#[test]
+fn test_fix() {
+    let config = LintConfig::default();
+    let (fixed, count) = fix("a = 1\nif (a = 2) { print a }\nprint a", &config);
+    assert_eq!(fixed, "a = 1;\nif (a == 2) { print a; }\nprint a;");
+    assert_eq!(count, 4);
+    assert_eq!(fix("print 1;", &config), ("print 1;".to_string(), 0));
+    // Nothing to suggest for other errors.
+    assert_eq!(fix("print );", &config).1, 0);
+}
+
+#[test]
+fn test_fix_command() {
+    let path = std::env::temp_dir().join(format!("reaper-fix-{}.reap", std::process::id()));
+    std::fs::write(&path, "print 1\n").unwrap();
+    let mut cmd = assert_cmd::Command::cargo_bin("reaper").unwrap();
+    cmd.args(["fix", &path.to_string_lossy()])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "print 1;\n");
+    std::fs::remove_file(&path).unwrap();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("reaper").unwrap();
+    cmd.arg("fix").assert().failure();
+}
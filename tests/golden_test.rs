@@ -0,0 +1,76 @@
+use std::path::{Path, PathBuf};
+
+use reaper::compiler::OptLevel;
+use reaper::driver::compile_source;
+use reaper::Object;
+
+/// Disassembles what the script at `path` adds to the prelude.
+fn listing(path: &Path) -> String {
+    let prelude = compile_source("", OptLevel::O0).unwrap().code().len() - 1;
+    let src = std::fs::read_to_string(path).unwrap();
+    let program =
+        compile_source(&src, OptLevel::O0).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    program.disassemble(prelude..program.code().len())
+}
+
+/// Compares the bytecode of every `tests/golden/*.reap` with the `.bc` file
+/// next to it. Run with `REAPER_BLESS=1` to write the `.bc` files instead,
+/// then review the diff.
+#[test]
+fn test_golden_bytecode() {
+    let bless = std::env::var_os("REAPER_BLESS").is_some();
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(Path::new("tests/golden"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "reap"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    let mut failed = vec![];
+    for path in paths {
+        let actual = listing(&path);
+        let expected_path = path.with_extension("bc");
+        if bless {
+            std::fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+        let expected = std::fs::read_to_string(&expected_path).unwrap_or_default();
+        if actual != expected {
+            eprintln!(
+                "--- {}\n{}+++ actual\n{}",
+                expected_path.display(),
+                expected,
+                actual
+            );
+            failed.push(path);
+        }
+    }
+    assert!(failed.is_empty(), "bytecode changed for {:?}", failed);
+}
+
+#[test]
+fn test_constants_are_shared() {
+    let src = "fn f() { return \"abc\" ++ \"abc\"; } x = 1.5 + 1.5; y = \"abc\" ++ f(); z = 0 - 0;";
+    let program = compile_source(src, OptLevel::O0).unwrap();
+    let count = |object: &Object| program.constants().iter().filter(|c| *c == object).count();
+    assert_eq!(count(&Object::String("abc".to_string().into())), 1);
+    assert_eq!(count(&Object::Number(1.5)), 1);
+    assert_eq!(count(&Object::Number(0.0)), 1);
+    let stats = program.pool_stats();
+    assert_eq!(stats.numbers + stats.strings, program.constants().len());
+}
+
+#[test]
+fn test_dump_bytecode() {
+    let mut cmd = assert_cmd::Command::cargo_bin("reaper").unwrap();
+    let output = cmd
+        .args(["--dump-bytecode", "tests/golden/call.reap"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    assert!(stdout.contains("fn add/2:\n"));
+    assert!(stdout.ends_with(" 0 functions, 0 other\n"), "{}", stdout);
+    // Nothing is run.
+    assert!(!stdout.contains("dbg:"));
+}
@@ -0,0 +1,149 @@
+use reaper::fix;
+use reaper::incremental::{Document, TextEdit};
+use reaper::lint::{self, LintConfig};
+use reaper::parser::{Expression, Parser, Statement};
+use reaper::tokenizer::{Span, Tokenizer};
+
+const SRC: &str = "fn f(n) {
+    return n;
+}
+
+fn g(n) {
+    return n + 1;
+}
+
+print f(1);
+print g(2);
+";
+
+fn apply(src: &str, at: &str, replacement: &str) -> (Document, String) {
+    let start = src.find(at).unwrap();
+    let mut document = Document::new(src);
+    let edit = TextEdit {
+        range: Span {
+            start,
+            end: start + at.len(),
+        },
+        text: replacement.to_string(),
+    };
+    document.edit(edit);
+    let expected = src.replacen(at, replacement, 1);
+    (document, expected)
+}
+
+fn assert_matches_full_parse(document: &Document, src: &str) {
+    assert_eq!(document.source(), src);
+    let tokens: Vec<_> = Tokenizer::with_trivia(src).collect();
+    assert_eq!(format!("{:?}", document.tokens()), format!("{:?}", tokens),);
+    let statements = Parser::default().parse(tokens.into_iter().collect());
+    let reparsed: Vec<_> = document.statements().collect();
+    assert_eq!(format!("{:?}", reparsed), format!("{:?}", statements));
+}
+
+#[test]
+fn test_edit_inside_function_reuses_neighbours() {
+    let start = SRC.find("n + 1").unwrap();
+    let mut document = Document::new(SRC);
+    let stats = document.edit(TextEdit {
+        range: Span {
+            start,
+            end: start + 1,
+        },
+        text: "n * 2 + n".to_string(),
+    });
+    let expected = SRC.replacen("n + 1", "n * 2 + n + 1", 1);
+    assert_matches_full_parse(&document, &expected);
+    // From the start of the line to the `+` behind the edit.
+    assert_eq!(stats.relexed_tokens, 8);
+    // The function in front is parsed again, the prints are reused.
+    assert_eq!(stats.reparsed_statements, 2);
+}
+
+#[test]
+fn test_edits_match_full_parse() {
+    let edits = [
+        ("print f(1);", "print f(10);"),
+        ("fn g", "fn gg"),
+        ("print g(2);\n", ""),
+        ("\nprint f", "\n// call\nprint f"),
+        ("return n;", "return n; print n;"),
+    ];
+    for (at, replacement) in edits {
+        let (document, expected) = apply(SRC, at, replacement);
+        assert_matches_full_parse(&document, &expected);
+    }
+}
+
+#[test]
+fn test_edit_above_an_assignment_moves_it() {
+    let src = "x = 1;\nprint x;\nif (y = 2) print y;\n";
+    let (document, expected) = apply(src, "x = 1", "x = 1 + 1");
+    assert_matches_full_parse(&document, &expected);
+
+    let Some(Statement::If(s)) = document.statements().nth(2) else {
+        panic!("not an if statement");
+    };
+    let Expression::Assign(assign) = &s.branches[0].0 else {
+        panic!("not an assignment");
+    };
+    assert_eq!(&document.source()[assign.span.start..assign.span.end], "=");
+
+    // The fix of W005 replaces that `=`.
+    let warnings = lint::lint(document.source(), &LintConfig::default()).unwrap();
+    let warning = warnings.iter().find(|w| w.code == "W005").unwrap();
+    assert_eq!(warning.span, assign.span);
+    let fixed = fix::apply(document.source(), &[&warning.suggestions[0]]);
+    assert_eq!(fixed, "x = 1 + 1;\nprint x;\nif (y == 2) print y;\n");
+}
+
+/// xorshift64*, so failures reproduce from the seed in the message.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as usize % n
+    }
+}
+
+#[test]
+fn test_random_edits_match_a_new_document() {
+    // Pieces that join, split or swallow the tokens around them.
+    const PIECES: [&str; 16] = [
+        "\"", "b\"", "b", "@", "//", "\n", " ", "=", "==", ";", "{", "}", "fn", "1.", ".5", "x",
+    ];
+    let src = "x = 1; \" y @ 2;\nfn f() { return \"s\"; } // c\nprint b\"z\";\n";
+    for seed in 1..=100u64 {
+        let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1);
+        let mut document = Document::new(src);
+        for _ in 0..6 {
+            let text = document.source().to_string();
+            let start = rng.below(text.len() + 1);
+            let end = (start + rng.below(4)).min(text.len());
+            let piece = PIECES[rng.below(PIECES.len())];
+            document.edit(TextEdit {
+                range: Span { start, end },
+                text: piece.to_string(),
+            });
+            let expected = Document::new(document.source());
+            assert_eq!(
+                format!("{:?}", document.tokens()),
+                format!("{:?}", expected.tokens()),
+                "seed {}: {:?}",
+                seed,
+                document.source()
+            );
+            let statements: Vec<_> = document.statements().collect();
+            let expected: Vec<_> = expected.statements().collect();
+            assert_eq!(
+                format!("{:?}", statements),
+                format!("{:?}", expected),
+                "seed {}: {:?}",
+                seed,
+                document.source()
+            );
+        }
+    }
+}
@@ -1,14 +1,13 @@
-use assert_cmd;
-use reaper::vm::Object;
+use reaper::driver::compile_file;
+use reaper::vm::{Object, VM};
+use reaper::OptLevel;
 use std::collections::VecDeque;
+use std::sync::Arc;
 
 macro_rules! object_vec {
     ( $($obj:expr),* ) => {
         {
-            let mut v: Vec<Object> = vec![];
-            $(
-                v.push($obj.into());
-            )*
+            let v: Vec<Object> = vec![$($obj.into()),*];
             v
         }
     }
@@ -42,6 +41,16 @@ fn fetch_output(path: &str) -> (VecDeque<String>, VecDeque<String>) {
     (split, filtered)
 }
 
+/// What the script at `path` leaves on the stack above its variables,
+/// nothing unless the compiler lost track of a value.
+fn leftover_stack(path: &str) -> Vec<Object> {
+    let program = Arc::new(compile_file(path, OptLevel::O0).unwrap());
+    let mut vm = VM::new();
+    vm.load(program.clone());
+    vm.run().unwrap();
+    vm.stack()[program.slots()..].to_vec()
+}
+
 #[test]
 fn test_code_fragments() {
     let pairs = [
@@ -65,6 +74,46 @@ fn test_code_fragments() {
             "tests/cases/while01.reap",
             object_vec![0.0, 1.0, 2.0, 3.0, 4.0],
         ),
+        (
+            "tests/cases/dowhile01.reap",
+            object_vec![0.0, 1.0, 2.0, "once".to_string(), 5.0],
+        ),
+        (
+            "tests/cases/for01.reap",
+            object_vec![0.0, 1.0, 2.0, 12.0, 15.0, 10.0, "done".to_string()],
+        ),
+        (
+            "tests/cases/continue01.reap",
+            object_vec![1.0, 3.0, 4.0, 5.0, 0.0, 20.0, 30.0, 3.0, 0.0, 100.0],
+        ),
+        (
+            "tests/cases/break01.reap",
+            object_vec![0.0, 1.0, 10.0, 11.0, 2.0, 3.0],
+        ),
+        (
+            "tests/cases/is01.reap",
+            object_vec![
+                "num".to_string(),
+                "str".to_string(),
+                "null".to_string(),
+                "list".to_string(),
+                "other".to_string(),
+                true,
+                true
+            ],
+        ),
+        (
+            "tests/cases/debug01.reap",
+            object_vec![
+                3.0,
+                "<script>".to_string(),
+                "inner".to_string(),
+                3.0,
+                "b".to_string(),
+                "hi!".to_string(),
+                "x".to_string()
+            ],
+        ),
         (
             "tests/cases/str01.reap",
             object_vec!["Hello, world!".to_string()],
@@ -73,13 +122,108 @@ fn test_code_fragments() {
             "tests/cases/str02.reap",
             object_vec!["Hello, world!".to_string()],
         ),
+        (
+            "tests/cases/str03.reap",
+            object_vec![
+                "ü".to_string(),
+                "Grüße".to_string(),
+                "world!".to_string(),
+                "G".to_string(),
+                "r".to_string(),
+                "ü".to_string(),
+                "".to_string()
+            ],
+        ),
+        (
+            "tests/cases/regex01.reap",
+            object_vec![
+                true,
+                false,
+                2.0,
+                "2024-01-16".to_string(),
+                "2024-01-15".to_string(),
+                "15.01.2024".to_string()
+            ],
+        ),
+        (
+            "tests/cases/time01.reap",
+            object_vec![
+                1709211909.0,
+                "29.02.2024 13:05:09 %".to_string(),
+                "1970-01-01".to_string(),
+                "1969-12-31 23:59:59".to_string(),
+                true
+            ],
+        ),
+        ("tests/cases/if01.reap", object_vec![-1.0, 0.0, 1.0, 20.0]),
+        (
+            "tests/cases/elif01.reap",
+            object_vec![
+                "fail".to_string(),
+                "pass".to_string(),
+                "good".to_string(),
+                "excellent".to_string(),
+                "one".to_string()
+            ],
+        ),
+        ("tests/cases/exprstmt01.reap", object_vec![1.0, 3.0]),
+        (
+            "tests/cases/calls01.reap",
+            object_vec![12.0, 10.0, 4.0, "ab".to_string(), 4.0],
+        ),
+        (
+            "tests/cases/assign02.reap",
+            object_vec![
+                "one2three".to_string(),
+                "2".to_string(),
+                "Three".to_string(),
+                "Jellly".to_string()
+            ],
+        ),
+        (
+            "tests/cases/assign03.reap",
+            object_vec![6.0, 6.0, 6.0, "zerozerozero".to_string(), "x".to_string()],
+        ),
+        (
+            "tests/cases/prelude01.reap",
+            object_vec![
+                "pear, apple, fig".to_string(),
+                true,
+                false,
+                9.0,
+                2.0,
+                15.0,
+                "replaced".to_string()
+            ],
+        ),
+        ("tests/cases/math01.reap", object_vec![4.0, 5.0, true]),
+        (
+            "tests/cases/num01.reap",
+            object_vec![255.0, -5.0, 2500.0, "0.67".to_string(), "2".to_string()],
+        ),
+        ("tests/cases/comments01.reap", object_vec![1.0]),
+        ("tests/cases/scope01.reap", object_vec![0.0, 10.0, 7.0, 2.0]),
+        (
+            "tests/cases/defer01.reap",
+            object_vec![
+                "working".to_string(),
+                "second".to_string(),
+                "first".to_string(),
+                "done".to_string(),
+                "first".to_string(),
+                "failed".to_string(),
+                "cleanup".to_string(),
+                Object::Null,
+                1.0
+            ],
+        ),
     ];
     for (path, expected) in pairs {
-        let (stdout, mut filtered) = fetch_output(path);
+        let (_, mut filtered) = fetch_output(path);
         for e in expected {
             assert!(filtered.pop_front().unwrap() == format!("dbg: {:?}", e));
         }
-        assert!(stdout.back().unwrap() == "stack: []");
+        assert_eq!(leftover_stack(path), vec![]);
         println!("done: {}", path);
     }
 }
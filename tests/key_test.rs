@@ -0,0 +1,53 @@
+use std::collections::HashSet;
+
+use reaper::key::{hash, Key};
+use reaper::native::Native;
+use reaper::Object;
+
+#[test]
+fn test_keys_equal_like_objects() {
+    let objects: Vec<Object> = vec![
+        1.5.into(),
+        true.into(),
+        "a".into(),
+        Object::Null,
+        vec![Object::from(1.0), "b".into(), vec![Object::Null].into()].into(),
+        Vec::<Object>::new().into(),
+    ];
+    let mut keys = HashSet::new();
+    for object in &objects {
+        let key = Key::new(object).unwrap();
+        assert_eq!(&key.to_object(), object);
+        assert_eq!(Key::new(&object.clone()).unwrap(), key);
+        assert_eq!(hash(object).unwrap(), hash(&key.to_object()).unwrap());
+        keys.insert(key);
+    }
+    assert_eq!(keys.len(), objects.len());
+
+    // Like `-0 == 0`.
+    assert_eq!(
+        Key::new(&Object::Number(-0.0)).unwrap(),
+        Key::new(&Object::Number(0.0)).unwrap()
+    );
+    assert_eq!(
+        Key::new(&Object::Number(-0.0)).unwrap().to_object(),
+        Object::Number(0.0)
+    );
+    assert_ne!(
+        Key::new(&"1".into()).unwrap(),
+        Key::new(&1.0.into()).unwrap()
+    );
+}
+
+#[test]
+fn test_unhashable() {
+    let nan = Key::new(&f64::NAN.into()).unwrap_err();
+    assert_eq!(nan.message, "Can't use NaN as a key, it equals nothing.");
+    let nested = Key::new(&vec![Object::from(1.0), f64::NAN.into()].into());
+    assert_eq!(nested.unwrap_err(), nan);
+    let native = hash(&Native::new("db", 1).into()).unwrap_err();
+    assert_eq!(
+        native.message,
+        "Can't use db as a key, only numbers, strings, byte strings, bools, null and lists of them."
+    );
+}
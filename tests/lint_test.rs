@@ -0,0 +1,90 @@
+use reaper::lint::{lint, lint_project, LintConfig};
+
+fn codes(src: &str, config: &LintConfig) -> Vec<&'static str> {
+    lint(src, config).unwrap().iter().map(|d| d.code).collect()
+}
+
+#[test]
+fn test_rules() {
+    let config = LintConfig::default();
+    assert_eq!(codes("fn f(len) { return len; }", &config), ["W001"]);
+    assert_eq!(codes("join = 1; join = 2;", &config), ["W001"]);
+    assert_eq!(codes("fn g() {} fn f() { g = 1; }", &config), ["W001"]);
+    assert_eq!(codes("a = 1; a + 1; print a;", &config), ["W002"]);
+    assert_eq!(codes("a = 1; print a;", &config), [] as [&str; 0]);
+    assert_eq!(codes("if (1 < 2) { print 1; }", &config), ["W003"]);
+    assert_eq!(codes("do { print 1; } while (false);", &config), ["W003"]);
+    assert_eq!(
+        codes("a = 1; while (true) { if (a) { break; } }", &config),
+        [] as [&str; 0]
+    );
+    assert_eq!(
+        codes(
+            "fn f(a) { if (a) { while (a) { if (a) { if (a) { if (a) {} } } } } }",
+            &config
+        ),
+        ["W004"]
+    );
+}
+
+#[test]
+fn test_config() {
+    let src = "fn f(a) { a; if (a) { if (a) { print a; } } }";
+    assert_eq!(codes(src, &LintConfig::default()), ["W002"]);
+    let config = LintConfig {
+        allow: vec!["unused-result"],
+        max_nesting: 2,
+    };
+    assert_eq!(codes(src, &config), ["W004"]);
+
+    let warnings = lint(src, &LintConfig::default()).unwrap();
+    assert_eq!(
+        warnings[0].to_string(),
+        "warning[W002]: the result of the expression is unused at byte 10"
+    );
+    assert!(warnings[0]
+        .to_json()
+        .starts_with("{\"severity\":\"warning\","));
+    assert_eq!(
+        lint("print", &LintConfig::default()).unwrap_err()[0].code,
+        "E002"
+    );
+}
+
+#[test]
+fn test_lint_project() {
+    let dir = std::env::temp_dir().join(format!("reaper-lint-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(
+        dir.join("reaper.toml"),
+        "entry = \"main.reap\"\nsources = [\"lib\"]\nlint.allow = [\"W003\"]\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("lib/text.reap"), "fn shout(s) { return s; }\n").unwrap();
+    std::fs::write(dir.join("main.reap"), "if (true) {}\nshout = 1;\n").unwrap();
+
+    let warnings = lint_project(&dir.join("reaper.toml")).unwrap();
+    let lines: Vec<_> = warnings.iter().map(|w| w.to_string()).collect();
+    assert_eq!(
+        lines,
+        [format!(
+            "warning[W001]: 'shout' shadows the function of the same name at {}:2:1",
+            dir.join("main.reap").display()
+        )]
+    );
+
+    let mut cmd = assert_cmd::Command::cargo_bin("reaper").unwrap();
+    let output = cmd
+        .args(["lint", &dir.join("reaper.toml").to_string_lossy()])
+        .assert()
+        .failure();
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    assert_eq!(stdout.lines().count(), 1);
+
+    let mut cmd = assert_cmd::Command::cargo_bin("reaper").unwrap();
+    cmd.args(["lint", &dir.join("lib/text.reap").to_string_lossy()])
+        .assert()
+        .success();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "net")]
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+
+use reaper::builtins::{Capabilities, Capability};
+use reaper::compiler::Compiler;
+use reaper::parser::Parser;
+use reaper::tokenizer::Tokenizer;
+use reaper::vm::{StepResult, VM};
+use reaper::Object;
+
+fn serve(response: &'static str) -> (u16, std::thread::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        let n = stream.read(&mut request).unwrap();
+        stream.write_all(response.as_bytes()).unwrap();
+        String::from_utf8_lossy(&request[..n]).into_owned()
+    });
+    (port, server)
+}
+
+fn run(src: &str, capabilities: Capabilities) -> (VM, StepResult) {
+    let mut compiler = Compiler::default();
+    let ast = Parser::default().parse(Tokenizer::new(src).collect());
+    let mut vm = VM::new();
+    vm.set_capabilities(capabilities);
+    vm.load(Arc::new(compiler.compile(ast).unwrap()));
+    let result = vm.run_for(1000);
+    (vm, result)
+}
+
+#[test]
+fn test_http_post() {
+    let (port, server) = serve("HTTP/1.1 201 Created\r\nContent-Length: 2\r\n\r\nok");
+    let src = format!(r#"r = net.post("http://127.0.0.1:{}/items", "abc");"#, port);
+    let (vm, result) = run(&src, Capabilities::all());
+    assert_eq!(result, StepResult::Done);
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /items HTTP/1.1\r\n"));
+    assert!(request.ends_with("\r\n\r\nabc"));
+    let Some(Object::List(response)) = vm.global("r") else {
+        panic!("no response");
+    };
+    assert_eq!(**response, [Object::Number(201.0), "ok".to_string().into()]);
+}
+
+#[test]
+fn test_http_get_chunked_and_capability() {
+    let (port, server) = serve(
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n",
+    );
+    let src = format!(r#"r = net.get("http://127.0.0.1:{}")[1];"#, port);
+    let (vm, result) = run(&src, Capabilities::all());
+    assert_eq!(result, StepResult::Done);
+    assert!(server.join().unwrap().starts_with("GET / HTTP/1.1\r\n"));
+    assert_eq!(vm.global("r"), Some(&"abcde".to_string().into()));
+
+    let (_, result) = run(&src, Capabilities::all().without(Capability::Net));
+    assert_eq!(
+        result,
+        StepResult::Trapped(reaper::vm::RuntimeError {
+            message: "'net.get' needs the 'net' capability.".to_string()
+        })
+    );
+}
+
+#[test]
+fn test_net_is_not_deterministic() {
+    let mut vm = VM::new();
+    vm.set_deterministic(true);
+    let ast =
+        Parser::default().parse(Tokenizer::new(r#"r = net.get("http://127.0.0.1:1");"#).collect());
+    vm.load(Arc::new(Compiler::default().compile(ast).unwrap()));
+    assert_eq!(
+        vm.run_for(1000),
+        StepResult::Trapped(reaper::vm::RuntimeError {
+            message: "'net.get' is not available in deterministic mode.".to_string()
+        })
+    );
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use reaper::artifact;
+use reaper::compiler::Opcode;
+use reaper::program::{Function, Program};
+use reaper::vm::{RuntimeError, VM};
+use reaper::Object;
+
+/// Builds a program from hand-written `code` with `slots` script locals,
+/// round-tripped through an artifact.
+fn program(code: Vec<Opcode>, constants: Vec<Object>, slots: usize) -> Arc<Program> {
+    let program = Program::new(code, constants, HashMap::new())
+        .expect("invalid code")
+        .with_globals(slots, HashMap::new());
+    Arc::new(artifact::read(&artifact::write(&program)).expect("invalid code"))
+}
+
+/// Runs `code` and returns the stack it leaves behind.
+fn run(
+    code: Vec<Opcode>,
+    constants: Vec<Object>,
+    slots: usize,
+) -> Result<Vec<Object>, RuntimeError> {
+    let mut vm = VM::new();
+    vm.load(program(code, constants, slots));
+    vm.run()?;
+    Ok(vm.stack().to_vec())
+}
+
+fn numbers(ns: &[f64]) -> Vec<Object> {
+    ns.iter().map(|&n| Object::Number(n)).collect()
+}
+
+#[test]
+fn test_jumps_to_the_end() {
+    // Jumping onto the `EndOfProgram` behind the code ends the program.
+    let code = vec![Opcode::Jmp(3), Opcode::Null, Opcode::Null];
+    assert_eq!(run(code, vec![], 0).unwrap(), []);
+
+    let code = vec![Opcode::False, Opcode::Jz(3), Opcode::Null, Opcode::Null];
+    assert_eq!(run(code, vec![], 0).unwrap(), []);
+
+    // Anything but `false` falls through, `Jz` pops it either way.
+    let code = vec![Opcode::Null, Opcode::Jz(2), Opcode::Null];
+    assert_eq!(run(code, vec![], 0).unwrap(), [Object::Null]);
+
+    let code = vec![Opcode::Jmp(2)];
+    assert!(Program::new(code, vec![], HashMap::new()).is_err());
+}
+
+#[test]
+fn test_backward_jumps() {
+    // slot 0 = 0; while (slot 0 < 3) slot 0 = slot 0 + 1;
+    let code = vec![
+        Opcode::Const(0),
+        Opcode::SetLocal(0),
+        Opcode::GetLocal(0),
+        Opcode::Const(2),
+        Opcode::Less,
+        Opcode::Jz(6),
+        Opcode::GetLocal(0),
+        Opcode::Const(1),
+        Opcode::Add,
+        Opcode::SetLocal(0),
+        Opcode::Jmp(-8),
+    ];
+    let stack = run(code, numbers(&[0.0, 1.0, 3.0]), 1).unwrap();
+    assert_eq!(stack, numbers(&[3.0]));
+}
+
+#[test]
+fn test_invoke_and_ret() {
+    let code = vec![
+        Opcode::Const(0),
+        Opcode::Invoke(1, 2),
+        Opcode::Jmp(2),
+        // Returns here, behind the `Jmp`.
+        Opcode::Jmp(7),
+        // The argument is in slot 0, slot 1 is a local.
+        Opcode::GetLocal(0),
+        Opcode::GetLocal(0),
+        Opcode::Add,
+        Opcode::SetLocal(1),
+        Opcode::GetLocal(1),
+        Opcode::Ret,
+    ];
+    let stack = run(code, numbers(&[5.0]), 1).unwrap();
+    // The script's slot, then the result in place of the argument.
+    assert_eq!(stack, [Object::Null, Object::Number(10.0)]);
+}
+
+#[test]
+fn test_ret_drops_temporaries() {
+    let code = vec![
+        Opcode::Const(0),
+        Opcode::Invoke(0, 1),
+        Opcode::Jmp(2),
+        Opcode::Jmp(5),
+        Opcode::Null,
+        Opcode::Null,
+        Opcode::Const(1),
+        Opcode::Ret,
+    ];
+    let stack = run(code, numbers(&[1.0, 2.0]), 0).unwrap();
+    assert_eq!(stack, numbers(&[1.0, 2.0]));
+}
+
+#[test]
+fn test_ret_outside_of_a_function() {
+    let e = run(vec![Opcode::Null, Opcode::Ret], vec![], 0).unwrap_err();
+    assert_eq!(e.message, "Can't return outside of a function.");
+}
+
+#[test]
+fn test_locals_are_relative_to_the_frame() {
+    let code = vec![
+        Opcode::Const(0),
+        Opcode::SetLocal(0),
+        Opcode::Const(1),
+        Opcode::Const(2),
+        Opcode::Invoke(2, 3),
+        Opcode::Jmp(2),
+        Opcode::Jmp(9),
+        // The last slot is a local, still `null`.
+        Opcode::GetLocal(2),
+        Opcode::Pop,
+        // Overwrite the first argument under a temporary.
+        Opcode::GetLocal(1),
+        Opcode::Const(3),
+        Opcode::SetLocal(0),
+        Opcode::GetLocal(0),
+        Opcode::Add,
+        Opcode::Ret,
+    ];
+    let stack = run(code, numbers(&[100.0, 1.0, 2.0, 30.0]), 1).unwrap();
+    // The script's slot is untouched.
+    assert_eq!(stack, numbers(&[100.0, 32.0]));
+}
+
+#[test]
+fn test_call_checks_arity() {
+    let function = Function {
+        name: "f".to_string(),
+        arity: 1,
+        addr: 4,
+        slots: 1,
+    };
+    let constants = vec![Object::Function(Arc::new(function)), Object::Number(7.0)];
+    let code = vec![
+        Opcode::Const(0),
+        Opcode::Const(1),
+        Opcode::Call(1),
+        Opcode::Jmp(3),
+        Opcode::GetLocal(0),
+        Opcode::Ret,
+    ];
+    assert_eq!(run(code, constants.clone(), 0).unwrap(), numbers(&[7.0]));
+
+    let code = vec![
+        Opcode::Const(0),
+        Opcode::Call(0),
+        Opcode::Jmp(3),
+        Opcode::Null,
+        Opcode::GetLocal(0),
+        Opcode::Ret,
+    ];
+    let e = run(code, constants, 0).unwrap_err();
+    assert_eq!(e.message, "Function 'f' takes 1 arguments, found 0.");
+}
+
+#[test]
+fn test_take_leaves_null() {
+    let code = vec![
+        Opcode::Const(0),
+        Opcode::SetLocal(0),
+        Opcode::Take(0),
+        Opcode::GetLocal(0),
+    ];
+    let stack = run(code, numbers(&[7.0]), 1).unwrap();
+    assert_eq!(stack[1..], [Object::Number(7.0), Object::Null]);
+}
+
+#[test]
+fn test_code_is_checked() {
+    let code = vec![Opcode::Null, Opcode::Invoke(1, 1)];
+    let e = Program::new(code, vec![], HashMap::new()).unwrap_err();
+    assert_eq!(e, "call without a jump at 1");
+
+    let function = Function {
+        name: "f".to_string(),
+        arity: 0,
+        addr: 2,
+        slots: 0,
+    };
+    let functions = HashMap::from([("f".to_string(), Arc::new(function.clone()))]);
+    let e = Program::new(vec![Opcode::Null], vec![], functions).unwrap_err();
+    assert_eq!(e, "function 'f' starts out of bounds");
+
+    // So are function values in the constants.
+    let function = Function {
+        addr: 3,
+        ..function
+    };
+    let code = vec![Opcode::Const(0), Opcode::Call(0)];
+    let constants = vec![Object::Function(Arc::new(function.clone()))];
+    let e = Program::new(code, constants, HashMap::new()).unwrap_err();
+    assert_eq!(e, "function 'f' starts out of bounds");
+
+    // Function values from elsewhere are checked when called.
+    let code = vec![Opcode::GetLocal(0), Opcode::Call(0)];
+    let globals = HashMap::from([("g".to_string(), 0)]);
+    let mut vm = VM::new();
+    vm.load(Arc::new(
+        Program::new(code, vec![], HashMap::new())
+            .unwrap()
+            .with_globals(1, globals),
+    ));
+    vm.set_global("g", Object::Function(Arc::new(function)))
+        .unwrap();
+    let e = vm.run().unwrap_err();
+    assert_eq!(e.message, "Function 'f' is not in the program.");
+}
+
+#[test]
+fn test_operands_are_checked() {
+    let check = |code: Vec<Opcode>, constants: Vec<Object>| {
+        Program::new(code, constants, HashMap::new()).unwrap_err()
+    };
+
+    let e = check(vec![Opcode::Const(1)], numbers(&[1.0]));
+    assert_eq!(e, "constant 1 out of bounds at 0");
+
+    let e = check(vec![Opcode::Native(u16::MAX, 0)], vec![]);
+    assert_eq!(e, format!("builtin {} out of bounds at 0", u16::MAX));
+
+    // Builtins take exactly their parameters.
+    let e = check(vec![Opcode::Native(0, 5)], vec![]);
+    assert!(e.ends_with("called with 5 arguments at 0"), "{}", e);
+
+    let e = check(vec![Opcode::Null, Opcode::SetIndex(0, 0)], vec![]);
+    assert_eq!(e, "element assignment without an index at 1");
+}
+
+#[test]
+fn test_locals_are_checked() {
+    // A function with one slot reading its second.
+    let code = vec![
+        Opcode::Null,
+        Opcode::Invoke(1, 1),
+        Opcode::Jmp(2),
+        Opcode::Jmp(3),
+        Opcode::GetLocal(1),
+        Opcode::Ret,
+    ];
+    let e = Program::new(code, vec![], HashMap::new()).unwrap_err();
+    assert_eq!(e, "local 1 out of bounds at 4");
+
+    let function = Function {
+        name: "f".to_string(),
+        arity: 0,
+        addr: 1,
+        slots: 2,
+    };
+    let functions = HashMap::from([("f".to_string(), Arc::new(function))]);
+    let code = vec![Opcode::Jmp(3), Opcode::Null, Opcode::SetLocal(2)];
+    let e = Program::new(code, vec![], functions).unwrap_err();
+    assert_eq!(e, "local 2 out of bounds at 2");
+
+    // The script gets the slots its code uses.
+    let code = vec![Opcode::Null, Opcode::SetLocal(3)];
+    let program = Program::new(code, vec![], HashMap::new()).unwrap();
+    assert_eq!(program.slots(), 4);
+}
+
+#[test]
+fn test_stack_depth_is_checked() {
+    let check = |code: Vec<Opcode>| Program::new(code, vec![], HashMap::new()).unwrap_err();
+
+    assert_eq!(check(vec![Opcode::Pop]), "stack underflow at 0");
+    assert_eq!(
+        check(vec![Opcode::Null, Opcode::Add]),
+        "stack underflow at 1"
+    );
+    assert_eq!(
+        check(vec![Opcode::Null, Opcode::Call(1)]),
+        "stack underflow at 1"
+    );
+
+    // A function can't reach below its frame.
+    let code = vec![
+        Opcode::Null,
+        Opcode::Null,
+        Opcode::Invoke(0, 0),
+        Opcode::Jmp(2),
+        Opcode::Jmp(2),
+        Opcode::Ret,
+    ];
+    assert_eq!(check(code), "stack underflow at 5");
+
+    // A loop pushing a value each time around.
+    let code = vec![Opcode::Null, Opcode::Null, Opcode::Jmp(-1)];
+    assert_eq!(check(code), "inconsistent stack depth at 1");
+}
@@ -0,0 +1,163 @@
+use reaper::parser::{Expression, Parser, Statement};
+use reaper::tokenizer::Tokenizer;
+
+fn errors(src: &str) -> (Vec<Statement>, Vec<String>) {
+    let mut parser = Parser::default();
+    let statements = parser.parse(Tokenizer::new(src).collect());
+    let messages = parser.errors().iter().map(|e| e.to_string()).collect();
+    (statements, messages)
+}
+
+#[test]
+fn test_missing_tokens_are_reported() {
+    let (_, messages) = errors("x = 1 print x; while (x < 2 { x = x + 1; }");
+    assert_eq!(
+        messages,
+        [
+            "expected ';', found 'print' at byte 6",
+            "expected ')', found '{' at byte 28"
+        ]
+    );
+}
+
+#[test]
+fn test_recovery_at_statement_boundaries() {
+    let (statements, messages) = errors("x = * 2 3; print x; fn (a) { print a; } print 1;");
+    assert_eq!(
+        messages,
+        [
+            "expected expression, found '*' at byte 4",
+            "expected identifier, found '(' at byte 23"
+        ]
+    );
+    // The statements after each error are still parsed.
+    assert!(matches!(statements[1], Statement::Print(_)));
+    assert!(matches!(statements.last(), Some(Statement::Print(_))));
+}
+
+#[test]
+fn test_valid_programs_have_no_errors() {
+    let (_, messages) = errors("fn f(a, b) { return a[b]; } print f(\"ab\", 1);");
+    assert!(messages.is_empty());
+}
+
+#[test]
+fn test_only_loops_are_labeled() {
+    let (statements, messages) = errors("a: print 1; b: while (x) break b;");
+    assert_eq!(
+        messages,
+        ["expected 'while', 'for' or 'do', found 'print' at byte 3"]
+    );
+    let Some(Statement::While(labeled)) = statements.last() else {
+        panic!("no loop");
+    };
+    assert_eq!(labeled.label.as_deref(), Some("b"));
+}
+
+#[test]
+fn test_truncated_programs() {
+    for (src, message) in [
+        (
+            "fn f(a) { print a;",
+            "unexpected end of file, expected '}' at byte 18",
+        ),
+        (
+            "print 1 +",
+            "unexpected end of file, expected expression at byte 9",
+        ),
+        ("while (x", "unexpected end of file, expected ')' at byte 8"),
+        (
+            "fn",
+            "unexpected end of file, expected identifier at byte 2",
+        ),
+        (
+            "do { x = 1; } while (x)",
+            "unexpected end of file, expected ';' at byte 23",
+        ),
+        (
+            "do print 1;",
+            "unexpected end of file, expected 'while' at byte 11",
+        ),
+    ] {
+        let (_, messages) = errors(src);
+        assert_eq!(messages, [message]);
+    }
+}
+
+#[test]
+fn test_if_without_else_at_end_of_input() {
+    let (statements, messages) = errors("if (true) print 1;");
+    assert!(messages.is_empty());
+    assert!(matches!(statements[..], [Statement::If(_)]));
+}
+
+#[test]
+fn test_elif_chains_are_one_statement() {
+    let (statements, messages) =
+        errors("if (a) print 1; elif (b) print 2; elif (c) {} else print 3;");
+    assert!(messages.is_empty());
+    let [Statement::If(statement)] = &statements[..] else {
+        panic!("unexpected {:?}", statements);
+    };
+    assert_eq!(statement.branches.len(), 3);
+    assert!(statement.else_body.is_some());
+
+    let (_, messages) = errors("if (a) print 1; else print 2; elif (b) print 3;");
+    assert_eq!(messages, ["expected expression, found 'elif' at byte 30"]);
+}
+
+#[test]
+fn test_parameter_and_argument_lists() {
+    for src in [
+        "fn f() {} f();",
+        "fn f(a) {} f(1);",
+        "fn f(a, b) {} f(1, 2);",
+        "fn f(a, b,) {} f(1, 2,);",
+        "fn f(a: num, b: str,): num {} f(1, \"x\",);",
+    ] {
+        let (statements, messages) = errors(src);
+        assert!(messages.is_empty(), "{}: {:?}", src, messages);
+        let Statement::Fn(function) = &statements[0] else {
+            panic!("{}: not a function", src);
+        };
+        let Statement::Expression(call) = &statements[1] else {
+            panic!("{}: not a call", src);
+        };
+        let Expression::Call(call) = &call.expression else {
+            panic!("{}: not a call", src);
+        };
+        assert_eq!(function.arguments.len(), call.arguments.len());
+    }
+}
+
+#[test]
+fn test_missing_separators() {
+    let (_, messages) = errors("fn f(a b) {} f(1 2); f(,);");
+    assert_eq!(
+        messages,
+        [
+            "expected ')', found 'b' at byte 7",
+            "expected ')', found '2' at byte 17",
+            "expected expression, found ',' at byte 23"
+        ]
+    );
+}
+
+#[test]
+fn test_chained_comparisons() {
+    let (statements, messages) = errors("x = 0 < i < 10 < n; print x is bool;");
+    assert_eq!(
+        messages,
+        [
+            "comparisons can't be chained, compare one pair at a time at byte 10",
+            "comparisons can't be chained, compare one pair at a time at byte 15"
+        ]
+    );
+    assert!(matches!(statements.last(), Some(Statement::Print(_))));
+
+    let (_, messages) = errors("x = a >= b > c; y = a <= b == true;");
+    assert_eq!(
+        messages,
+        ["comparisons can't be chained, compare one pair at a time at byte 11"]
+    );
+}
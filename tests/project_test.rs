@@ -0,0 +1,186 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use reaper::artifact;
+use reaper::compiler::{CompileOptions, Compiler, OptLevel};
+use reaper::driver::Error;
+use reaper::lint::LintConfig;
+use reaper::parser::Parser;
+use reaper::project::{self, Manifest};
+use reaper::tokenizer::Tokenizer;
+use reaper::vm::VM;
+use reaper::Object;
+
+fn project_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("reaper-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("lib/text")).unwrap();
+    dir
+}
+
+#[test]
+fn test_manifest() {
+    let root = Path::new("app");
+    let manifest = Manifest::parse(
+        "# app\nentry = \"main.reap\"\nsources = [\"lib\", \"vendor\",]\n",
+        root,
+    )
+    .unwrap();
+    assert_eq!(
+        manifest,
+        Manifest {
+            entry: root.join("main.reap"),
+            sources: vec![root.join("lib"), root.join("vendor")],
+            output: root.join("main.reapc"),
+            lint: LintConfig::default(),
+        }
+    );
+
+    let manifest = Manifest::parse(
+        "entry = \"main.reap\"\nlint.allow = [\"W002\", \"shadowing\"]\nlint.max-nesting = 6\n",
+        root,
+    )
+    .unwrap();
+    assert_eq!(
+        manifest.lint,
+        LintConfig {
+            allow: vec!["unused-result", "shadowing"],
+            max_nesting: 6,
+        }
+    );
+
+    for (text, message) in [
+        ("sources = []", "missing 'entry'"),
+        ("entry = main.reap", "expected a string on line 1"),
+        ("\nname = \"x\"", "unknown key 'name' on line 2"),
+        ("entry", "expected 'key = value' on line 1"),
+        ("lint.allow = [\"tabs\"]", "unknown lint 'tabs' on line 1"),
+        ("lint.max-nesting = \"4\"", "expected a number on line 1"),
+    ] {
+        assert_eq!(Manifest::parse(text, root), Err(message.to_string()));
+    }
+}
+
+#[test]
+fn test_build_and_run_artifact() {
+    let dir = project_dir("build");
+    std::fs::write(
+        dir.join("reaper.toml"),
+        "entry = \"main.reap\"\nsources = [\"lib\", \".\"]\noutput = \"out/app.reapc\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib/math.reap"),
+        "fn double(n) { return n * 2; }\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib/text/shout.reap"),
+        "suffix = \"!\";\nfn shout(s, suffix) { return s ++ suffix; }\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("main.reap"),
+        "x = double(21);\ns = shout(re.find_all(\"\\w+\", \"hi there\")[1], suffix);\n",
+    )
+    .unwrap();
+
+    let output = project::build(&dir.join("reaper.toml"), OptLevel::O0).unwrap();
+    assert_eq!(output, dir.join("out/app.reapc"));
+
+    let program = artifact::read(&std::fs::read(&output).unwrap()).unwrap();
+    let mut vm = VM::new();
+    vm.load(Arc::new(program));
+    vm.run().unwrap();
+    assert_eq!(vm.global("x"), Some(&Object::Number(42.0)));
+    assert_eq!(vm.global("s"), Some(&Object::from("there!".to_string())));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_build_errors_name_the_file() {
+    let dir = project_dir("errors");
+    std::fs::write(
+        dir.join("reaper.toml"),
+        "entry = \"main.reap\"\nsources = [\"lib\"]\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("lib/a.reap"), "fn f() { return 1 }\n").unwrap();
+    std::fs::write(dir.join("main.reap"), "print f();\nprint g();\n").unwrap();
+
+    let result = project::build(&dir.join("reaper.toml"), OptLevel::O0);
+    let message = match result {
+        Err(e @ Error::Diagnostics(_)) => e.to_string(),
+        result => panic!("unexpected {:?}", result),
+    };
+    let file = dir.join("lib/a.reap");
+    assert_eq!(
+        message,
+        format!(
+            "error[E001]: expected ';', found '}}' at {}:1:19",
+            file.display()
+        )
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_artifact_round_trip() {
+    let src = "fn f(a) { return a ++ \"!\"; } g = f; x = len(g(\"a\")); l = re.find_all(\".\", \"ab\"); l[0] = 1; h = b\"hi\";";
+    let ast = Parser::default().parse(Tokenizer::new(src).collect());
+    let program = Compiler::with_opt_level(OptLevel::O2).compile(ast).unwrap();
+    let bytes = artifact::write(&program);
+    let read = artifact::read(&bytes).unwrap();
+    assert_eq!(read.code(), program.code());
+    assert_eq!(read.constants(), program.constants());
+    assert_eq!(read.function("f"), program.function("f"));
+    assert_eq!(read.global("x"), program.global("x"));
+    assert_eq!(read.slots(), program.slots());
+    assert!(!program.spans().is_empty());
+    assert_eq!(read.spans(), program.spans());
+    assert!(!program.locals().is_empty());
+    assert_eq!(read.locals(), program.locals());
+
+    assert_eq!(
+        artifact::read(b"nope").err(),
+        Some("unexpected end of artifact".to_string())
+    );
+    assert_eq!(
+        artifact::read(&bytes[..bytes.len() - 1]).err(),
+        Some("unexpected end of artifact".to_string())
+    );
+    // The first instruction jumps over `f`, make it jump out of the code.
+    let mut corrupt = bytes.clone();
+    assert_eq!(corrupt[14], 10);
+    corrupt[15..19].copy_from_slice(&1000i32.to_le_bytes());
+    assert_eq!(
+        artifact::read(&corrupt).err(),
+        Some("jump out of bounds at 0".to_string())
+    );
+}
+
+#[test]
+fn test_builds_without_debug_info() {
+    let src = "fn f(a, b) { return a + b; } x = f(1, \"a\");";
+    let compile = |debug_info| {
+        let ast = Parser::default().parse(Tokenizer::new(src).collect());
+        let options = CompileOptions {
+            debug_info,
+            ..CompileOptions::default()
+        };
+        Compiler::with_options(options).compile(ast).unwrap()
+    };
+    let (debug, release) = (compile(true), compile(false));
+    assert_eq!(debug.code(), release.code());
+    assert!(release.spans().is_empty());
+    assert!(release.locals().is_empty());
+    assert!(artifact::write(&release).len() < artifact::write(&debug).len());
+
+    let mut vm = VM::new();
+    vm.load(Arc::new(debug));
+    assert!(vm.run().is_err());
+    assert!(vm.span().is_some());
+    vm.load(Arc::new(release));
+    assert!(vm.run().is_err());
+    assert_eq!(vm.span(), None);
+}
@@ -0,0 +1,331 @@
+use std::sync::Arc;
+
+use reaper::compiler::OptLevel;
+use reaper::driver::compile_source;
+use reaper::parser::{
+    BinaryExpressionKind, Expression, Literal, Parser, Statement, UnaryExpressionKind,
+};
+use reaper::tokenizer::Tokenizer;
+use reaper::vm::VM;
+
+const CASES: u64 = 200;
+
+/// Instructions a generated program may run, they can loop forever.
+const FUEL: usize = 10_000;
+
+/// xorshift64*, so failures reproduce from the seed in the message.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as usize % n
+    }
+
+    fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[self.below(choices.len())]
+    }
+}
+
+/// Operators by precedence, loosest first, as the parser's loops see them.
+const COMPARISONS: [&str; 4] = ["<", "<=", ">", ">="];
+
+const LEVELS: [&[&str]; 4] = [&["==", "!="], &COMPARISONS, &["+", "-", "++"], &["*", "/"]];
+
+const NAMES: [&str; 5] = ["a", "f", "len", "math.sqrt", "x_1"];
+
+/// A random expression of at least the precedence of `level`, as source
+/// and as the s-expression shape of what it should parse into.
+fn expression(rng: &mut Rng, level: usize, depth: usize) -> (String, String) {
+    if depth == 0 {
+        return primary(rng);
+    }
+    match level {
+        0..=3 if rng.below(3) == 0 => {
+            // Left associative, the right operand binds tighter. Comparisons
+            // can't be chained, both of their operands bind tighter.
+            let lhs_level = if LEVELS[level] == COMPARISONS {
+                level + 1
+            } else {
+                level
+            };
+            let (lhs, lhs_shape) = expression(rng, lhs_level, depth - 1);
+            let (rhs, rhs_shape) = expression(rng, level + 1, depth - 1);
+            let op = rng.pick(LEVELS[level]);
+            (
+                format!("{} {} {}", lhs, op, rhs),
+                format!("({} {} {})", op, lhs_shape, rhs_shape),
+            )
+        }
+        0..=3 => expression(rng, level + 1, depth),
+        4 if rng.below(4) == 0 => {
+            let (expr, shape) = expression(rng, 4, depth - 1);
+            let op = rng.pick(&["!", "-"]);
+            (format!("{}{}", op, expr), format!("({} {})", op, shape))
+        }
+        _ => {
+            let (mut expr, mut shape) = primary(rng);
+            for _ in 0..rng.below(3) {
+                if rng.below(2) == 0 {
+                    let args: Vec<_> = (0..rng.below(3))
+                        .map(|_| expression(rng, 0, depth - 1))
+                        .collect();
+                    let sources: Vec<_> = args.iter().map(|(a, _)| a.as_str()).collect();
+                    let shapes: String = args.iter().map(|(_, s)| format!(" {}", s)).collect();
+                    expr = format!("{}({})", expr, sources.join(", "));
+                    shape = format!("(call {}{})", shape, shapes);
+                } else {
+                    let (index, index_shape) = expression(rng, 0, depth - 1);
+                    expr = format!("{}[{}]", expr, index);
+                    shape = format!("(index {} {})", shape, index_shape);
+                }
+            }
+            (expr, shape)
+        }
+    }
+}
+
+fn primary(rng: &mut Rng) -> (String, String) {
+    let src = match rng.below(5) {
+        0 => rng.below(100).to_string(),
+        1 => format!("{}.5", rng.below(10)),
+        2 => format!(
+            "{}\"{}\"",
+            rng.pick(&["", "b"]),
+            rng.pick(&["", "x", "abc"])
+        ),
+        3 => rng.pick(&["true", "false", "null"]).to_string(),
+        _ => rng.pick(&NAMES).to_string(),
+    };
+    (src.clone(), src)
+}
+
+/// A random expression the type checker accepts as a number. The
+/// variables `a` and `b` and the functions `f` and `g` of two arguments
+/// are defined wherever it's used.
+fn number(rng: &mut Rng, depth: usize) -> String {
+    match rng.below(if depth == 0 { 2 } else { 4 }) {
+        0 => rng.pick(&["0", "1", "2.5"]).to_string(),
+        1 => rng.pick(&["a", "b"]).to_string(),
+        2 => format!(
+            "{} {} {}",
+            number(rng, depth - 1),
+            rng.pick(&["+", "-", "*", "/"]),
+            number(rng, depth - 1)
+        ),
+        _ => format!(
+            "{}({}, {})",
+            rng.pick(&["f", "g"]),
+            number(rng, depth - 1),
+            number(rng, depth - 1)
+        ),
+    }
+}
+
+/// A random expression the type checker accepts as a condition.
+fn condition(rng: &mut Rng, depth: usize) -> String {
+    match rng.below(if depth == 0 { 1 } else { 4 }) {
+        0 => rng.pick(&["true", "false"]).to_string(),
+        1 => format!("!{}", condition(rng, 0)),
+        _ => format!(
+            "{} {} {}",
+            number(rng, depth - 1),
+            rng.pick(&["<", "<=", ">", ">=", "==", "!="]),
+            number(rng, depth - 1)
+        ),
+    }
+}
+
+fn shape(expr: &Expression) -> String {
+    match expr {
+        Expression::Literal(l) => match &l.value {
+            Literal::Num(n) => n.to_string(),
+            Literal::Bool(b) => b.to_string(),
+            Literal::String(s) => format!("\"{}\"", s),
+            Literal::Bytes(b) => format!("b\"{}\"", String::from_utf8_lossy(b)),
+            Literal::Null => "null".to_string(),
+        },
+        Expression::Variable(v) => v.value.clone(),
+        Expression::Binary(b) => {
+            let op = match b.kind {
+                BinaryExpressionKind::Add => "+",
+                BinaryExpressionKind::Sub => "-",
+                BinaryExpressionKind::Mul => "*",
+                BinaryExpressionKind::Div => "/",
+                BinaryExpressionKind::Less => "<",
+                BinaryExpressionKind::LessEqual => "<=",
+                BinaryExpressionKind::Greater => ">",
+                BinaryExpressionKind::GreaterEqual => ">=",
+                BinaryExpressionKind::Eq => "==",
+                BinaryExpressionKind::NotEq => "!=",
+                BinaryExpressionKind::Strcat => "++",
+            };
+            format!("({} {} {})", op, shape(&b.lhs), shape(&b.rhs))
+        }
+        Expression::Call(c) => {
+            let args: String = c
+                .arguments
+                .iter()
+                .map(|a| format!(" {}", shape(a)))
+                .collect();
+            format!("(call {}{})", shape(&c.callee), args)
+        }
+        Expression::Unary(u) => {
+            let op = match u.kind {
+                UnaryExpressionKind::Not => "!",
+                UnaryExpressionKind::Neg => "-",
+            };
+            format!("({} {})", op, shape(&u.expr))
+        }
+        Expression::Index(i) => format!("(index {} {})", shape(&i.expr), shape(&i.index)),
+        e => panic!("not generated: {:?}", e),
+    }
+}
+
+/// Where a generated statement goes, which decides what it may be.
+#[derive(Clone, Copy)]
+struct Context {
+    /// Directly in a function body, for `return` and `defer`.
+    function: bool,
+    /// Anywhere in a loop, for `break`.
+    in_loop: bool,
+}
+
+const SCRIPT: Context = Context {
+    function: false,
+    in_loop: false,
+};
+
+fn statement(rng: &mut Rng, depth: usize, context: Context) -> String {
+    let nested = Context {
+        function: false,
+        ..context
+    };
+    let body = Context {
+        function: false,
+        in_loop: true,
+    };
+    let expr = |rng: &mut Rng| number(rng, depth);
+    match rng.below(if depth == 0 { 3 } else { 10 }) {
+        0 => format!("{} = {};", rng.pick(&["a", "b"]), expr(rng)),
+        1 => format!("{};", expr(rng)),
+        2 if context.function => format!("return {};", expr(rng)),
+        2 if context.in_loop => rng.pick(&["break;", "continue;"]).to_string(),
+        2 => format!("print {};", expr(rng)),
+        3 => format!(
+            "if ({}) {} else {}",
+            condition(rng, depth),
+            statement(rng, depth - 1, nested),
+            statement(rng, depth - 1, nested)
+        ),
+        4 => format!(
+            "while ({}) {}",
+            condition(rng, depth),
+            statement(rng, depth - 1, body)
+        ),
+        5 => format!(
+            "do {} while ({});",
+            statement(rng, depth - 1, body),
+            condition(rng, depth)
+        ),
+        6 => format!(
+            "for (a = {}; {}; a = {}) {}",
+            expr(rng),
+            condition(rng, depth),
+            expr(rng),
+            statement(rng, depth - 1, body)
+        ),
+        7 => {
+            let body: Vec<_> = (0..rng.below(4))
+                .map(|_| statement(rng, depth - 1, nested))
+                .collect();
+            format!("{{ {} }}", body.join(" "))
+        }
+        8 if context.function => format!("defer {};", expr(rng)),
+        _ => {
+            let body: Vec<_> = (0..rng.below(4))
+                .map(|_| {
+                    let function = Context {
+                        function: true,
+                        in_loop: false,
+                    };
+                    statement(rng, depth - 1, function)
+                })
+                .collect();
+            format!(
+                "fn {}(a, b) {{ {} }}",
+                rng.pick(&["f", "g"]),
+                body.join(" ")
+            )
+        }
+    }
+}
+
+#[test]
+fn test_printed_expressions_parse_back() {
+    for seed in 0..CASES {
+        let mut rng = Rng::new(seed);
+        let (src, expected) = expression(&mut rng, 0, 4);
+        let mut parser = Parser::new();
+        let ast = parser.parse(Tokenizer::new(&format!("{};", src)).collect());
+        assert!(
+            parser.errors().is_empty(),
+            "seed {}: {}: {:?}",
+            seed,
+            src,
+            parser.errors()
+        );
+        let [Statement::Expression(statement)] = &ast[..] else {
+            panic!("seed {}: {}: {:?}", seed, src, ast);
+        };
+        assert_eq!(
+            shape(&statement.expression),
+            expected,
+            "seed {}: {}",
+            seed,
+            src
+        );
+    }
+}
+
+#[test]
+fn test_programs_compile_and_run_without_panics() {
+    for seed in 0..CASES {
+        let mut rng = Rng::new(seed);
+        let mut src =
+            vec!["a = 0; b = 1; fn f(a, b) { return a; } fn g(a, b) { return b; }".to_string()];
+        src.extend((0..1 + rng.below(5)).map(|_| statement(&mut rng, 3, SCRIPT)));
+        let src = src.join("\n");
+        let program = compile_source(&src, OptLevel::O2)
+            .unwrap_or_else(|e| panic!("seed {}: {}: {}", seed, src, e));
+        // Runtime errors are fine, as long as they are errors and not
+        // panics.
+        let result = std::panic::catch_unwind(|| {
+            let mut vm = VM::new();
+            vm.load(Arc::new(program));
+            vm.run_for(FUEL);
+        });
+        assert!(result.is_ok(), "seed {}: {}", seed, src);
+    }
+}
+
+/// Anything is reported as errors or compiles, nothing panics.
+#[test]
+fn test_token_soup_is_reported() {
+    let tokens = [
+        "a", "1", "\"s\"", "b\"s\"", "true", "(", ")", "{", "}", "[", "]", ";", ",", "=", "==",
+        "+", "++", "!", "<", "<=", ">", ">=", "..", ".", ":", "fn", "if", "elif", "else", "while",
+        "for", "do", "break", "continue", "return", "defer", "print", "is",
+    ];
+    for seed in 0..CASES {
+        let mut rng = Rng::new(seed);
+        let src: Vec<_> = (0..rng.below(30)).map(|_| rng.pick(&tokens)).collect();
+        let _ = compile_source(&src.join(" "), OptLevel::O0);
+    }
+}
@@ -0,0 +1,187 @@
+use reaper::parser::Parser;
+use reaper::resolver::{DeclarationKind, Resolution, Resolver};
+use reaper::tokenizer::Tokenizer;
+
+fn resolve(src: &str) -> Resolution {
+    let mut ast = Parser::default().parse(Tokenizer::new(src).collect());
+    Resolver::new().resolve(&mut ast)
+}
+
+#[test]
+fn test_slots_and_references() {
+    let src = "fn f(a, b) { c = a; { d = b; } e = c; return e; } print f(1, 2);";
+    let resolution = resolve(src);
+    assert!(resolution.errors.is_empty());
+
+    let slots: Vec<_> = resolution
+        .declarations
+        .iter()
+        .map(|d| (d.name.as_str(), d.kind, d.slot))
+        .collect();
+    assert_eq!(
+        slots,
+        [
+            ("f", DeclarationKind::Function, None),
+            ("a", DeclarationKind::Parameter, Some(0)),
+            ("b", DeclarationKind::Parameter, Some(1)),
+            ("c", DeclarationKind::Local, Some(2)),
+            ("d", DeclarationKind::Local, Some(3)),
+            // `d` went out of scope, its slot is reused.
+            ("e", DeclarationKind::Local, Some(3)),
+        ]
+    );
+
+    let a: Vec<_> = resolution
+        .references_to(1)
+        .map(|r| &src[r.span.start..r.span.end])
+        .collect();
+    assert_eq!(a, ["a"]);
+    assert_eq!(resolution.references_to(0).count(), 1);
+}
+
+#[test]
+fn test_nested_functions_have_their_own_slots() {
+    let src = "x = 1; { y = 2; fn g(p) { { q = p; } r = p; return r; } z = g(3); } w = 4;";
+    let mut ast = Parser::default().parse(Tokenizer::new(src).collect());
+    let resolution = Resolver::new().resolve(&mut ast);
+    assert!(resolution.errors.is_empty());
+
+    let slots: Vec<_> = resolution
+        .declarations
+        .iter()
+        .filter_map(|d| Some((d.name.as_str(), d.slot?)))
+        .collect();
+    assert_eq!(
+        slots,
+        [
+            ("x", 0),
+            ("y", 1),
+            ("p", 0),
+            ("q", 1),
+            ("r", 1),
+            // The function's slots don't count for the block around it.
+            ("z", 2),
+            ("w", 1),
+        ]
+    );
+    assert_eq!(resolution.slots, 3);
+}
+
+#[test]
+fn test_undefined_names() {
+    let resolution = resolve("fn f() { { x = 1; } print x; } g();");
+    let messages: Vec<_> = resolution.errors.iter().map(|e| &e.message).collect();
+    assert_eq!(
+        messages,
+        ["undefined variable 'x'", "undefined function 'g'"]
+    );
+}
+
+#[test]
+fn test_invalid_assignment_targets() {
+    let resolution = resolve("fn f() { return 1; } f[0] = 1; f()[0] = 2; x = 1; x[0] = 3; 1 = x;");
+    let messages: Vec<_> = resolution.errors.iter().map(|e| &e.message).collect();
+    assert_eq!(
+        messages,
+        [
+            "invalid assignment target",
+            "invalid assignment target",
+            "invalid assignment target"
+        ]
+    );
+}
+
+#[test]
+fn test_for_loops_scope_their_initializer() {
+    let resolution = resolve(
+        "fn f() { n = 0; for (i = 0; i < 2; i = i + 1) { x = i; } for (n = 1;;) {} return i; }",
+    );
+    let slots: Vec<_> = resolution
+        .declarations
+        .iter()
+        .map(|d| (d.name.as_str(), d.slot))
+        .collect();
+    // `n` exists already, the second loop assigns it.
+    assert_eq!(
+        slots,
+        [("f", None), ("n", Some(0)), ("i", Some(1)), ("x", Some(2))]
+    );
+    let messages: Vec<_> = resolution.errors.iter().map(|e| &e.message).collect();
+    assert_eq!(messages, ["undefined variable 'i'"]);
+}
+
+#[test]
+fn test_continue_needs_a_loop() {
+    let src = "continue; a: for (;;) { fn f() { continue; } continue b; continue a; }";
+    let resolution = resolve(src);
+    let messages: Vec<_> = resolution.errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        messages,
+        [
+            "'continue' outside of a loop at byte 0",
+            "'continue' outside of a loop at byte 33",
+            "undefined loop label 'b' at byte 45",
+        ]
+    );
+}
+
+#[test]
+fn test_return_needs_a_function() {
+    let src = "return 1; if (true) { return 2; } fn f() { if (true) { return 3; } return 4; }";
+    let resolution = resolve(src);
+    let messages: Vec<_> = resolution.errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        messages,
+        [
+            "'return' outside of a function at byte 0",
+            "'return' outside of a function at byte 22",
+        ]
+    );
+}
+
+#[test]
+fn test_namespaced_builtins() {
+    let resolution =
+        resolve("print math.sqrt(4); print math.cbrt(8); print re_match(\"a\", \"a\");");
+    let messages: Vec<_> = resolution.errors.iter().map(|e| &e.message).collect();
+    assert_eq!(
+        messages,
+        [
+            "undefined function 'math.cbrt'",
+            "undefined function 're_match'"
+        ]
+    );
+}
+
+#[test]
+fn test_defer_only_in_function_bodies() {
+    let src = "defer print 1;
+        fn f(x) { defer x; if (x) defer x; while (x) { defer x; } if (x) { defer x; } fn g() { defer 1; } }";
+    let resolution = resolve(src);
+    let messages: Vec<_> = resolution.errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        messages,
+        [
+            "'defer' not directly in a function body at byte 0",
+            "'defer' not directly in a function body at byte 49",
+            "'defer' not directly in a function body at byte 70",
+            "'defer' not directly in a function body at byte 90",
+        ]
+    );
+}
+
+#[test]
+fn test_break_needs_a_loop() {
+    let src = "break; a: while (true) { fn f() { break a; } break b; do break a; while (true); }
+        c: for (;;) break c;";
+    let resolution = resolve(src);
+    let messages: Vec<_> = resolution.errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        messages,
+        [
+            "'break' outside of a loop at byte 0",
+            "'break' outside of a loop at byte 34",
+            "undefined loop label 'b' at byte 45",
+        ]
+    );
+}
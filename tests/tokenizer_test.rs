@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use reaper::compiler::Compiler;
+use reaper::parser::Parser;
+use reaper::tokenizer::{Keywords, Token, TokenKind, Tokenizer, TriviaKind};
+use reaper::{Object, VM};
+
+const SRC: &str = "// answer\nfn main() {\n    print 42; // inline\n}\n";
+
+#[test]
+fn test_trivia_is_skipped_by_default() {
+    let kinds: Vec<TokenKind> = Tokenizer::new(SRC).map(|t| t.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::Fn,
+            TokenKind::Identifier,
+            TokenKind::LeftParen,
+            TokenKind::RightParen,
+            TokenKind::LeftBrace,
+            TokenKind::Print,
+            TokenKind::Number,
+            TokenKind::Semicolon,
+            TokenKind::RightBrace,
+            TokenKind::Eof,
+        ]
+    );
+}
+
+#[test]
+fn test_byte_strings() {
+    let tokens: Vec<_> = Tokenizer::new(r#"b"ab" b "c" bb"d""#)
+        .map(|t| (t.kind, t.value))
+        .collect();
+    assert_eq!(
+        tokens,
+        [
+            (TokenKind::Bytes, "ab".to_string()),
+            (TokenKind::Identifier, "b".to_string()),
+            (TokenKind::String, "c".to_string()),
+            (TokenKind::Identifier, "bb".to_string()),
+            (TokenKind::String, "d".to_string()),
+            (TokenKind::Eof, "".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_numbers_have_no_sign() {
+    let kinds: Vec<TokenKind> = Tokenizer::new("a-1 -2.5").map(|t| t.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::Identifier,
+            TokenKind::Minus,
+            TokenKind::Number,
+            TokenKind::Minus,
+            TokenKind::Number,
+            TokenKind::Eof,
+        ]
+    );
+}
+
+#[test]
+fn test_comparison_operators() {
+    let kinds: Vec<TokenKind> = Tokenizer::new("a<=b>=c>d<e").map(|t| t.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::Identifier,
+            TokenKind::LessEqual,
+            TokenKind::Identifier,
+            TokenKind::GreaterEqual,
+            TokenKind::Identifier,
+            TokenKind::Greater,
+            TokenKind::Identifier,
+            TokenKind::Less,
+            TokenKind::Identifier,
+            TokenKind::Eof,
+        ]
+    );
+}
+
+/// The source text of `tokens` and their trivia, in order.
+fn reproduce(src: &str, tokens: &[Token]) -> String {
+    let mut out = String::new();
+    for token in tokens {
+        for trivia in &token.leading_trivia {
+            out.push_str(&trivia.text);
+        }
+        out.push_str(&src[token.span.start..token.span.end]);
+    }
+    out
+}
+
+#[test]
+fn test_trivia_round_trip() {
+    let tokens: Vec<_> = Tokenizer::with_trivia(SRC).collect();
+    assert_eq!(reproduce(SRC, &tokens), SRC);
+
+    assert_eq!(tokens[0].leading_trivia[0].kind, TriviaKind::Comment);
+    assert_eq!(tokens[0].leading_trivia[0].text, "// answer");
+    let closing = &tokens[tokens.len() - 2];
+    assert!(closing
+        .leading_trivia
+        .iter()
+        .any(|t| t.kind == TriviaKind::Comment && t.text == "// inline"));
+}
+
+#[test]
+fn test_unknown_characters_are_kept() {
+    for src in ["x = 1 @ 2;", "print \"abc;\n", "b\"a\" \" é # $ \\", "@"] {
+        let tokens: Vec<_> = Tokenizer::with_trivia(src).collect();
+        assert_eq!(reproduce(src, &tokens), src);
+    }
+
+    let tokens: Vec<_> = Tokenizer::new("1 @ \"x")
+        .map(|t| (t.kind, t.value))
+        .collect();
+    assert_eq!(
+        tokens,
+        [
+            (TokenKind::Number, "1".to_string()),
+            (TokenKind::Unknown, "@".to_string()),
+            (TokenKind::Unknown, "\"".to_string()),
+            (TokenKind::Identifier, "x".to_string()),
+            (TokenKind::Eof, "".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_keyword_prefixes_are_identifiers() {
+    let kinds: Vec<_> = Tokenizer::new("deferred printer nullable defer")
+        .map(|t| t.kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::Identifier,
+            TokenKind::Identifier,
+            TokenKind::Identifier,
+            TokenKind::Defer,
+            TokenKind::Eof
+        ]
+    );
+}
+
+#[test]
+fn test_peeking() {
+    let mut tokens = Tokenizer::with_trivia("x = { a: 1 }; // done");
+    assert_eq!(tokens.peek().unwrap().kind, TokenKind::Identifier);
+    // `{` followed by an identifier and `:` starts a map, not a block.
+    assert_eq!(tokens.peek_n(2).unwrap().kind, TokenKind::LeftBrace);
+    assert_eq!(tokens.peek_n(4).unwrap().kind, TokenKind::Colon);
+    assert_eq!(tokens.next().unwrap().value, "x");
+    assert_eq!(tokens.peek_n(3).unwrap().kind, TokenKind::Colon);
+
+    let rest: Vec<_> = tokens.map(|t| t.kind).collect();
+    assert_eq!(rest.len(), 8);
+    assert_eq!(rest[7], TokenKind::Eof);
+
+    let mut tokens = Tokenizer::with_trivia("a // b");
+    assert!(tokens.peek_n(2).is_none());
+    let eof = tokens.peek_n(1).unwrap();
+    assert_eq!(eof.leading_trivia[1].text, "// b");
+    assert_eq!(tokens.count(), 2);
+}
+
+#[test]
+fn test_keyword_table() {
+    let keywords = Keywords::default()
+        .rename("fn", "funktion")
+        .rename("return", "ergebnis")
+        .rename("true", "wahr")
+        .alias("if", "wenn");
+    let src = "funktion f(x) { wenn (x) ergebnis 1; ergebnis 2; } y = f(wahr); fn = 3;";
+    let ast = Parser::default().parse(Tokenizer::new(src).with_keywords(keywords).collect());
+    let program = Compiler::default().compile(ast).unwrap();
+    let mut vm = VM::new();
+    vm.load(Arc::new(program));
+    vm.run().unwrap();
+    assert_eq!(vm.global("y"), Some(&Object::Number(1.0)));
+    assert_eq!(vm.global("fn"), Some(&Object::Number(3.0)));
+}
+
+#[test]
+#[should_panic(expected = "'print' is already a keyword")]
+fn test_keyword_table_clashes() {
+    let _ = Keywords::default().alias("fn", "print");
+}
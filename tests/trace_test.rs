@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use reaper::compiler::{Compiler, Opcode};
+use reaper::parser::Parser;
+use reaper::program::Program;
+use reaper::tokenizer::Tokenizer;
+use reaper::trace::Recorder;
+use reaper::{Object, VM};
+
+fn compile(src: &str) -> Arc<Program> {
+    let ast = Parser::default().parse(Tokenizer::new(src).collect());
+    Arc::new(Compiler::default().compile(ast).unwrap())
+}
+
+#[test]
+fn test_last_steps_are_kept() {
+    let program = compile("fn f(a) { return a[3]; } s = \"abc\"; n = 1 + 2; f(s);");
+    let mut vm = VM::new();
+    vm.load(program.clone());
+    let mut recorder = Recorder::new(4);
+    let e = recorder.run(&mut vm).unwrap_err();
+    assert_eq!(e.message, "String index 3 out of range for length 3.");
+
+    let steps = recorder.steps();
+    assert_eq!(steps.len(), 4);
+    let failing = steps.back().unwrap();
+    assert_eq!(failing.opcode, Opcode::Index);
+    assert_eq!(failing.ip, vm.ip());
+    // The operands were taken before the error.
+    assert_eq!(failing.before, [Object::from("abc"), Object::Number(3.0)]);
+    assert_eq!(failing.after, []);
+
+    let dump = recorder.dump(&program);
+    assert!(dump.starts_with("last 4 of "));
+    assert!(dump.ends_with(&format!(
+        "{:>5} Index  [String(\"abc\"), Number(3.0)] -> []\n",
+        vm.ip()
+    )));
+
+    let mut vm = VM::new();
+    vm.load(program);
+    let mut recorder = Recorder::new(0);
+    assert!(recorder.run(&mut vm).is_err());
+    assert!(recorder.steps().is_empty());
+}
+
+#[test]
+fn test_replay() {
+    let program = compile(
+        "fn twice(x) { y = x * 2; return y; }
+        a = 1;
+        while (a < 10) { a = twice(a); }
+        b = a;",
+    );
+    let mut vm = VM::new();
+    vm.load(program.clone());
+
+    // The stack before every instruction, stepped without recording.
+    let mut states = vec![];
+    let mut reference = VM::new();
+    reference.load(program);
+    loop {
+        states.push((reference.ip(), reference.stack().to_vec()));
+        if reference.step() != reaper::StepResult::Continue {
+            break;
+        }
+    }
+
+    let mut recorder = Recorder::new(usize::MAX);
+    recorder.run(&mut vm).unwrap();
+    assert_eq!(recorder.steps().len(), states.len());
+    let mut replay = recorder.replay(&vm);
+    assert_eq!(replay.ip(), None);
+    assert_eq!(replay.stack(), vm.stack());
+    for (ip, stack) in states.iter().rev() {
+        let step = replay.back().unwrap();
+        assert_eq!(step.ip, *ip);
+        assert_eq!(replay.ip(), Some(*ip));
+        assert_eq!(replay.stack(), stack);
+    }
+    assert!(replay.back().is_none());
+    for (ip, stack) in &states {
+        assert_eq!(replay.stack(), stack);
+        assert_eq!(replay.forward().unwrap().ip, *ip);
+    }
+    assert!(replay.forward().is_none());
+    assert_eq!(replay.stack(), vm.stack());
+}
+
+#[test]
+fn test_flight_recorder_option() {
+    let dir = std::env::temp_dir().join(format!("reaper-trace-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("fail.reap");
+    std::fs::write(&script, "s = \"abc\";\nx = s[3];\n").unwrap();
+    let mut cmd = assert_cmd::Command::cargo_bin("reaper").unwrap();
+    let output = cmd
+        .args(["--flight-recorder=2", script.to_str().unwrap()])
+        .assert()
+        .failure();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    let lines: Vec<_> = stderr.lines().collect();
+    assert!(lines[0].starts_with("last 2 of "), "{}", stderr);
+    assert!(lines[2].contains(" Index  [String(\"abc\"), Number(3.0)] -> []"));
+    assert!(lines[3].starts_with("String index 3 out of range"));
+}
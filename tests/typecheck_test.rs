@@ -0,0 +1,138 @@
+use reaper::parser::Parser;
+use reaper::tokenizer::Tokenizer;
+use reaper::typecheck::TypeChecker;
+
+fn check(src: &str) -> Vec<String> {
+    let ast = Parser::default().parse(Tokenizer::new(src).collect());
+    TypeChecker::new()
+        .check(&ast)
+        .iter()
+        .map(|e| e.message.clone())
+        .collect()
+}
+
+#[test]
+fn test_unannotated_code_is_dynamic() {
+    assert!(check("fn f(a, b) { return a + b; } print f(\"x\", true);").is_empty());
+}
+
+#[test]
+fn test_annotation_mismatches() {
+    let src = r#"
+        fn add(a: num, b: num): num {
+            a = "one";
+            return a < b;
+        }
+        fn f(s: str, x: text) {
+            print add(s, 1) ++ s;
+            if (add(1, 2)) print s;
+        }
+        add(1);
+    "#;
+    assert_eq!(
+        check(src),
+        [
+            "'a' expects num, found str",
+            "return expects num, found bool",
+            "unknown type 'text'",
+            "argument of 'add' expects num, found str",
+            "operator '++' expects str, found num",
+            "condition expects bool, found num",
+            "function 'add' takes 2 arguments, found 1",
+        ]
+    );
+}
+
+#[test]
+fn test_builtin_signatures() {
+    let src = r#"
+        fn f(pattern: str, n: num): list {
+            print re.match(pattern, n);
+            print re.replace(pattern, "x");
+            return re.find_all(pattern, "x");
+        }
+        print len(f("a", 1)) ++ "x";
+    "#;
+    assert_eq!(
+        check(src),
+        [
+            "argument of 're.match' expects str, found num",
+            "function 're.replace' takes 3 arguments, found 2",
+            "operator '++' expects str, found num",
+        ]
+    );
+}
+
+#[test]
+fn test_type_tests() {
+    let src = "fn f(x) { if (x is num) return x + 1; return x is text; } print 1 is bool < 2;";
+    assert_eq!(
+        check(src),
+        [
+            "unknown type 'text'",
+            "operator '<' expects num, found bool"
+        ]
+    );
+}
+
+#[test]
+fn test_operators_report_once() {
+    let src = r#"print "a" < "b"; print 1 + "b"; print true - 1;"#;
+    assert_eq!(
+        check(src),
+        [
+            "operator '<' expects num, found str",
+            "operator '+' expects num, found str",
+            "operator '-' expects num, found bool",
+        ]
+    );
+}
+
+#[test]
+fn test_byte_strings() {
+    let src = r#"
+        fn f(b: bytes): num { return b[0] + len(b[0..1] ++ b); }
+        print f(b"ab") ++ "x";
+        print f("ab");
+        print b"a" ++ "b";
+    "#;
+    assert_eq!(
+        check(src),
+        [
+            "operator '++' expects str, found num",
+            "argument of 'f' expects bytes, found str",
+            "operator '++' expects bytes, found str",
+        ]
+    );
+}
+
+#[test]
+fn test_negation() {
+    let src = "fn f(n: num): bool { return -n; } print -true; print !-1;";
+    assert_eq!(
+        check(src),
+        [
+            "return expects bool, found num",
+            "operator '-' expects num, found bool",
+            "operator '!' expects bool, found num",
+        ]
+    );
+}
+
+#[test]
+fn test_sets() {
+    let src = r#"
+        fn f(s: set, x): set {
+            if (set.contains(s, x) is set) return s;
+            return set.insert(set.of(s), len(s));
+        }
+        print f(set.new(), 1) ++ "x";
+    "#;
+    assert_eq!(
+        check(src),
+        [
+            "argument of 'set.of' expects list, found set",
+            "operator '++' expects str, found set",
+        ]
+    );
+}
@@ -0,0 +1,32 @@
+use reaper::util::{json_string, Location, SourceManager};
+
+#[test]
+fn test_locations() {
+    let mut sources = SourceManager::new();
+    let first = sources.add("a.reap", "print 1;\nprint \"äö\" ++ x;\n\n".to_string());
+    let second = sources.add("b.reap", "x".to_string());
+    assert_ne!(first, second);
+
+    let source = sources.get(first);
+    assert_eq!(source.name(), "a.reap");
+    let at = |line, column| Location { line, column };
+    assert_eq!(source.location(0), at(1, 1));
+    assert_eq!(source.location(8), at(1, 9));
+    assert_eq!(source.location(9), at(2, 1));
+    // `x` follows two two-byte characters.
+    let x = source.text().find('x').unwrap();
+    assert_eq!(source.location(x), at(2, 15));
+    assert_eq!(source.location(source.text().len()), at(4, 1));
+    assert_eq!(source.location(1000), at(4, 1));
+
+    assert_eq!(source.line(2), Some("print \"äö\" ++ x;"));
+    assert_eq!(source.line(3), Some(""));
+    assert_eq!(source.line(0), None);
+    assert_eq!(sources.get(second).line(1), Some("x"));
+}
+
+#[test]
+fn test_json_string() {
+    assert_eq!(json_string("plain"), "\"plain\"");
+    assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+}
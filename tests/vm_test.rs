@@ -0,0 +1,1477 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use reaper::builtins::{Capabilities, Capability};
+use reaper::compiler::{Compiler, Opcode};
+use reaper::heap::LimitedHeap;
+use reaper::native::Native;
+use reaper::parser::Parser;
+use reaper::program::Program;
+use reaper::tokenizer::Tokenizer;
+use reaper::vm::{Indexing, SizeLimits, State, StepResult, VM};
+use reaper::Object;
+
+fn compile(src: &str) -> Arc<Program> {
+    let ast = Parser::default().parse(Tokenizer::new(src).collect());
+    Arc::new(Compiler::default().compile(ast).unwrap())
+}
+
+#[test]
+fn test_step_until_done() {
+    let program = compile("x = 1; while (x < 100) { x = x * 2; }");
+    let mut vm = VM::new();
+    vm.load(program);
+    let mut steps = 0;
+    loop {
+        match vm.step() {
+            StepResult::Continue => steps += 1,
+            StepResult::Done => break,
+            StepResult::Trapped(e) => panic!("{}", e),
+        }
+    }
+    assert!(steps > 7);
+    assert_eq!(vm.step(), StepResult::Done);
+}
+
+#[test]
+fn test_run_for_budget_and_traps() {
+    let program = compile("x = 0; while (true) { x = x + 1; }");
+    let mut vm = VM::new();
+    vm.load(program);
+    assert_eq!(vm.run_for(1000), StepResult::Continue);
+    assert_eq!(vm.run_for(1000), StepResult::Continue);
+
+    let program = compile(r#"x = 1; y = x ++ "a";"#);
+    let mut vm = VM::new();
+    vm.load(program);
+    match vm.run_for(1000) {
+        StepResult::Trapped(e) => assert_eq!(
+            e.message,
+            "Can only concatenate two strings or two byte strings, found num and str."
+        ),
+        result => panic!("unexpected {:?}", result),
+    }
+}
+
+#[test]
+fn test_vm_moves_to_another_thread() {
+    let program =
+        compile("fn fib(n) { if (n < 2) return n; return fib(n-1)+fib(n-2); } x = fib(15);");
+    let mut vm = VM::new();
+    vm.load(program);
+    let result = std::thread::spawn(move || vm.run_for(1_000_000)).join();
+    assert_eq!(result.unwrap(), StepResult::Done);
+}
+
+#[test]
+fn test_program_shared_between_vms() {
+    let program =
+        compile("fn fib(n) { if (n < 2) return n; return fib(n-1)+fib(n-2); } x = fib(15);");
+    assert!(program.function("fib").is_some());
+    assert!(program.function("x").is_none());
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let program = Arc::clone(&program);
+            std::thread::spawn(move || {
+                let mut vm = VM::new();
+                vm.load(program);
+                vm.run_for(1_000_000)
+            })
+        })
+        .collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), StepResult::Done);
+    }
+}
+
+#[test]
+fn test_string_index_out_of_range_traps() {
+    for (src, message) in [
+        (
+            r#"s = "äbc"; t = s[3];"#,
+            "String index 3 out of range for length 3.",
+        ),
+        (
+            r#"s = "äbc"; t = s[2..4];"#,
+            "String slice 2..4 out of range for length 3.",
+        ),
+        (
+            r#"s = "äbc"; t = s[2..1];"#,
+            "String slice 2..1 out of range for length 3.",
+        ),
+        (
+            r#"s = "äbc"; t = s[0.5];"#,
+            "String index 0.5 is not a whole number.",
+        ),
+    ] {
+        let mut vm = VM::new();
+        vm.load(compile(src));
+        match vm.run_for(1000) {
+            StepResult::Trapped(e) => assert_eq!(e.message, message),
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn test_builtin_errors_trap() {
+    for (src, message) in [
+        (
+            r#"x = re.match("(", "a");"#,
+            "Invalid regex: regex parse error:\n    (\n    ^\nerror: unclosed group",
+        ),
+        (
+            r#"x = len(1 < 2);"#,
+            "Argument of 'len' must be a string, a byte string, a list or a set.",
+        ),
+        (
+            r#"x = re.find_all("a", "a")[1];"#,
+            "List index 1 out of range for length 1.",
+        ),
+    ] {
+        let mut vm = VM::new();
+        vm.load(compile(src));
+        match vm.run_for(1000) {
+            StepResult::Trapped(e) => assert_eq!(e.message, message),
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn test_deterministic_clock() {
+    let program = compile(r#"x = time.format(time.now(), "%Y-%m-%d %H:%M:%S");"#);
+    let mut vm = VM::new();
+    vm.set_deterministic(true);
+    vm.load(program);
+    assert_eq!(vm.run_for(1000), StepResult::Done);
+    assert_eq!(
+        vm.global("x"),
+        Some(&Object::from("1970-01-01 00:00:00".to_string()))
+    );
+}
+
+#[test]
+fn test_measure() {
+    let program = compile(
+        "fn work() { i = 0; while (i < 1000) { i = i + 1; } }
+        ms = measure(work);",
+    );
+    let mut vm = VM::new();
+    vm.load(program.clone());
+    vm.run().unwrap();
+    assert!(matches!(vm.global("ms"), Some(&Object::Number(ms)) if ms > 0.0));
+
+    vm.set_deterministic(true);
+    vm.load(program);
+    vm.run().unwrap();
+    assert_eq!(vm.global("ms"), Some(&Object::Number(0.0)));
+}
+
+#[test]
+fn test_time_builtins_need_capability() {
+    let program = compile(r#"x = time.format(time.now(), "%Y");"#);
+    let mut vm = VM::new();
+    vm.set_capabilities(Capabilities::all().without(Capability::Time));
+    vm.load(program.clone());
+    match vm.run_for(1000) {
+        StepResult::Trapped(e) => assert_eq!(e.message, "'time.now' needs the 'time' capability."),
+        result => panic!("unexpected {:?}", result),
+    }
+    vm.set_capabilities(Capabilities::none().with(Capability::Time));
+    vm.load(program);
+    assert_eq!(vm.run_for(1000), StepResult::Done);
+
+    for (src, message) in [
+        (
+            r#"x = time.parse("2023-02-29", "%Y-%m-%d");"#,
+            "'2023-02-29' does not match the time format '%Y-%m-%d'.",
+        ),
+        (r#"x = time.format(0, "%Q");"#, "Unknown time format '%Q'."),
+    ] {
+        let mut vm = VM::new();
+        vm.load(compile(src));
+        match vm.run_for(1000) {
+            StepResult::Trapped(e) => assert_eq!(e.message, message),
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn test_code_is_position_independent() {
+    let program = compile(
+        "fn fib(n) { if (n < 2) return n; return fib(n-1)+fib(n-2); }
+         x = 0; i = 0; while (i < 10) { x = x + fib(i); i = i + 1; }",
+    );
+    let mut code = vec![Opcode::Null, Opcode::Pop, Opcode::Null, Opcode::Pop];
+    code.extend_from_slice(&program.code()[..program.code().len() - 1]);
+    let globals = program.globals().map(|(n, s)| (n.to_string(), s)).collect();
+    let moved = Program::new(code, program.constants().to_vec(), HashMap::new())
+        .unwrap()
+        .with_globals(program.slots(), globals);
+
+    let mut vm = VM::new();
+    vm.load(Arc::new(moved));
+    assert_eq!(vm.run_for(100_000), StepResult::Done);
+    assert_eq!(vm.global("x"), Some(&Object::Number(88.0)));
+}
+
+#[test]
+fn test_calling_non_functions_traps() {
+    for (src, message) in [
+        ("x = 1; y = x(2);", "Can only call functions."),
+        (
+            "fn f(a) { return a; } g = f; y = g(1, 2);",
+            "Function 'f' takes 1 arguments, found 2.",
+        ),
+    ] {
+        let mut vm = VM::new();
+        vm.load(compile(src));
+        match vm.run_for(1000) {
+            StepResult::Trapped(e) => assert_eq!(e.message, message),
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn test_index_assignment_traps() {
+    for (src, message) in [
+        (
+            "l = re.find_all(\".\", \"ab\"); l[2] = \"c\";",
+            "List index 2 out of range for length 2.",
+        ),
+        (
+            "s = \"ab\"; s[5] = \"c\";",
+            "String index 5 out of range for length 2.",
+        ),
+        (
+            "s = \"ab\"; s[0] = 1;",
+            "Can only store strings into strings.",
+        ),
+        (
+            "x = 1; x[0] = 2;",
+            "Can only index strings, byte strings and lists.",
+        ),
+    ] {
+        let mut vm = VM::new();
+        vm.load(compile(src));
+        match vm.run_for(1000) {
+            StepResult::Trapped(e) => assert_eq!(e.message, message),
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn test_frames_after_a_trap() {
+    let program = compile(
+        "fn inner(a) { b = a[5]; return b; }
+        fn outer(n) { g = inner; return g(n); }
+        s = \"abc\"; x = outer(s);",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    assert!(matches!(vm.run_for(1000), StepResult::Trapped(_)));
+
+    let frames = vm.frames();
+    let functions: Vec<_> = frames
+        .iter()
+        .map(|f| f.function.map(|f| f.name.as_str()))
+        .collect();
+    // `outer` is called by name, `inner` through a function object.
+    assert_eq!(functions, [None, Some("outer"), Some("inner")]);
+
+    let script = &frames[0];
+    let s = script.names.iter().position(|n| *n == Some("s")).unwrap();
+    assert_eq!(script.values[s], Object::from("abc".to_string()));
+    assert_eq!(
+        frames[2].values[..2],
+        [Object::from("abc".to_string()), Object::Null]
+    );
+    assert_eq!(frames[1].names[..2], [Some("n"), Some("g")]);
+    // `b` isn't stored yet.
+    assert_eq!(frames[2].names[..2], [Some("a"), None]);
+    assert_eq!(frames[2].base, frames[1].base + 2);
+    assert_eq!(vm.stack().len(), frames[2].base + frames[2].values.len());
+}
+
+#[test]
+fn test_negation() {
+    let program = compile(
+        "x = 3;
+        y = -x;
+        z = - -x;
+        w = x-1;
+        product = -x * 2;
+        difference = 2 - -1;
+        less = -2 < -1;",
+    );
+    // Negative numbers are constants, other operands are negated.
+    assert!(program.constants().contains(&Object::Number(-2.0)));
+    assert_eq!(
+        program
+            .code()
+            .iter()
+            .filter(|op| **op == Opcode::Neg)
+            .count(),
+        4
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    assert_eq!(vm.global("y"), Some(&Object::Number(-3.0)));
+    assert_eq!(vm.global("z"), Some(&Object::Number(3.0)));
+    assert_eq!(vm.global("w"), Some(&Object::Number(2.0)));
+    assert_eq!(vm.global("product"), Some(&Object::Number(-6.0)));
+    assert_eq!(vm.global("difference"), Some(&Object::Number(3.0)));
+    assert_eq!(vm.global("less"), Some(&Object::Bool(true)));
+
+    let mut vm = VM::new();
+    vm.load(compile("fn f(x) { return -x; } f(\"a\");"));
+    assert_eq!(
+        vm.run().unwrap_err().message,
+        "Operand of '-' must be a number, found str."
+    );
+}
+
+#[test]
+fn test_inequality() {
+    let program = compile("a = 1 != 2; b = \"x\" != \"x\"; c = null != false;");
+    assert!(program.code().contains(&Opcode::Neq));
+    assert!(!program
+        .code()
+        .windows(2)
+        .any(|ops| ops == [Opcode::Eq, Opcode::Not]));
+    let mut vm = VM::new();
+    vm.load(program);
+    assert_eq!(vm.run_for(1000), StepResult::Done);
+    assert_eq!(vm.global("a"), Some(&Object::Bool(true)));
+    assert_eq!(vm.global("b"), Some(&Object::Bool(false)));
+    assert_eq!(vm.global("c"), Some(&Object::Bool(true)));
+}
+
+#[test]
+fn test_local_names() {
+    let program = compile(
+        "fn f(a) {
+            if (a < 1) { b = 1; return b; }
+            c = 2;
+            return c;
+        }
+        x = f(2);",
+    );
+    let local = |name: &str| {
+        let local = program.locals().iter().find(|l| l.name == name).unwrap();
+        (local.function.as_deref(), local.slot, local.live.clone())
+    };
+    let (function, slot, live) = local("b");
+    assert_eq!((function, slot), (Some("f"), 1));
+    // `b` and `c` share the slot.
+    let (_, c_slot, c_live) = local("c");
+    assert_eq!(c_slot, 1);
+    assert!(live.end <= c_live.start);
+    assert_eq!(program.local_name(Some("f"), live.start, 1), Some("b"));
+    assert_eq!(program.local_name(Some("f"), c_live.start, 1), Some("c"));
+    assert_eq!(program.local_name(None, c_live.start, 1), None);
+    let x = program.global("x").unwrap();
+    let end = program.code().len() - 1;
+    assert_eq!(program.local_name(None, end, x), Some("x"));
+}
+
+#[test]
+fn test_assignment_order() {
+    let program = compile(
+        "fn swap_diff(x, y) { t = x; x = y; y = t; return x - y; }
+        fn first(a, b) { return a; }
+        a = 1; b = 2;
+        a = b = a + b;
+        c = swap_diff(1, 5);
+        d = 0;
+        e = first(d = 5, d);
+        f = d + first(d = 10, 0);
+        g = 1;
+        { h = g; g = h + 1; { i = g; g = i * 10; } }",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    let global = |name| vm.global(name).cloned();
+    assert_eq!(global("a"), Some(Object::Number(3.0)));
+    assert_eq!(global("b"), Some(Object::Number(3.0)));
+    assert_eq!(global("c"), Some(Object::Number(4.0)));
+    assert_eq!(global("e"), Some(Object::Number(5.0)));
+    // `d` is read before the call assigns it.
+    assert_eq!(global("f"), Some(Object::Number(15.0)));
+    assert_eq!(global("d"), Some(Object::Number(10.0)));
+    assert_eq!(global("g"), Some(Object::Number(20.0)));
+    // Only the globals are left.
+    assert_eq!(vm.stack().len(), vm.program().unwrap().slots());
+}
+
+#[test]
+fn test_function_values() {
+    let program = compile(
+        "fn fib(n) { return n; }
+        fn other(n) { return n; }
+        f = fib;
+        a = f == fib;
+        b = f == other;
+        fn fib(n) { return 0; }
+        c = f == fib;
+        d = f;",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    let global = |name| vm.global(name).cloned().unwrap();
+    assert_eq!(format!("{:?}", global("f")), "Function(<fn fib/1>)");
+    assert_eq!(global("a"), Object::Bool(true));
+    assert_eq!(global("b"), Object::Bool(false));
+    // The same name, but another function.
+    assert_eq!(global("c"), Object::Bool(false));
+    assert_eq!(global("d"), global("f"));
+}
+
+#[test]
+fn test_heap_limit() {
+    let program = compile("s = \"ab\"; while (true) { s = s ++ s; }");
+    let mut vm = VM::new();
+    vm.set_heap(Box::new(LimitedHeap::new(1000)));
+    vm.load(program);
+    let e = vm.run().unwrap_err();
+    assert_eq!(e.message, "Memory limit of 1000 bytes exceeded.");
+    // 4 + 8 + ... + 256, the constant is not counted and 512 didn't fit.
+    assert_eq!(vm.heap().allocated(), 508);
+    assert_eq!(
+        vm.global("s"),
+        Some(&Object::String("ab".repeat(128).into()))
+    );
+
+    let program = compile("s = \"ab\"; t = s[0] ++ s[0..2];");
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    assert_eq!(vm.heap().allocated(), 1 + 2 + 3);
+}
+
+#[test]
+fn test_size_limits() {
+    let limits = SizeLimits {
+        string_bytes: 10,
+        list_items: 3,
+    };
+    for (src, message) in [
+        (
+            "s = \"ab\"; while (true) { s = s ++ s; }",
+            "String of 16 bytes exceeds the limit of 10 bytes.",
+        ),
+        (
+            "s = \"abcdefghij\"; s[0] = \"xy\";",
+            "String of 11 bytes exceeds the limit of 10 bytes.",
+        ),
+        (
+            "l = list.new(); while (true) { l = list.push(l, 1); }",
+            "List of 4 items exceeds the limit of 3 items.",
+        ),
+        (
+            "l = list.unshift(list.push(list.push(list.new(), 1), 2), 3); l = list.unshift(l, 4);",
+            "List of 4 items exceeds the limit of 3 items.",
+        ),
+        (
+            "l = re.find_all(\"[a-z]\", \"abcd\");",
+            "List of 4 items exceeds the limit of 3 items.",
+        ),
+        (
+            "s = re.replace(\"a\", \"aaaa\", \"xyz\");",
+            "String of 12 bytes exceeds the limit of 10 bytes.",
+        ),
+    ] {
+        let mut vm = VM::new();
+        vm.set_size_limits(limits);
+        vm.load(compile(src));
+        assert_eq!(vm.run().unwrap_err().message, message, "{}", src);
+    }
+
+    // Up to the limits, and strings in the program aren't checked.
+    let program = compile(
+        "s = \"abcdefghijkl\"; t = s[1..11]; u = \"ab\" ++ \"cdefghij\"; l = re.find_all(\"[a-z]\", \"abc\");",
+    );
+    let mut vm = VM::new();
+    vm.set_size_limits(limits);
+    vm.load(program);
+    vm.run().unwrap();
+    assert_eq!(vm.global("u"), Some(&Object::from("abcdefghij")));
+}
+
+#[test]
+fn test_native_objects() {
+    struct Entity {
+        id: u32,
+    }
+
+    let program = compile(
+        "e = null;
+        while (e == null) {}
+        fn id(x) { return x; }
+        f = id(e);
+        same = f == e;
+        other = e == null;",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    assert_eq!(vm.run_for(100), StepResult::Continue);
+    vm.set_global("e", Native::new("entity", Entity { id: 7 }).into())
+        .unwrap();
+    vm.run().unwrap();
+    let global = |name| vm.global(name).cloned().unwrap();
+    assert_eq!(global("same"), Object::Bool(true));
+    assert_eq!(global("other"), Object::Bool(false));
+    let f = Native::try_from(global("f")).unwrap();
+    assert_eq!(f.downcast::<Entity>().unwrap().id, 7);
+    assert_eq!(format!("{:?}", global("f")), "Native(<entity>)");
+    // Equal only to itself.
+    assert_ne!(global("f"), Native::new("entity", Entity { id: 7 }).into());
+
+    let program = compile("e = null; while (e == null) {} x = e + 1;");
+    vm.load(program);
+    vm.run_for(100);
+    vm.set_global("e", Native::new("entity", Entity { id: 7 }).into())
+        .unwrap();
+    assert_eq!(
+        vm.run().unwrap_err().message,
+        "Operands of '+' must be numbers, found entity and num."
+    );
+}
+
+#[test]
+fn test_events() {
+    let program = compile(
+        "fn count(dt) { return dt + 1; }
+        fn twice(dt) { return dt * 2; }
+        on(\"tick\", count);
+        on(\"tick\", twice);
+        x = 1;",
+    );
+    let mut vm = VM::new();
+    vm.register_event("tick");
+    vm.register_event("quit");
+    vm.load(program.clone());
+    vm.run().unwrap();
+    let tick = vm.emit("tick", &[Object::Number(3.0)]).unwrap();
+    assert_eq!(tick, [Object::Number(4.0), Object::Number(6.0)]);
+    assert_eq!(vm.emit("quit", &[]).unwrap(), []);
+    assert_eq!(vm.global("x"), Some(&Object::Number(1.0)));
+
+    // A failing handler leaves the VM as it was.
+    let stack = vm.stack().to_vec();
+    let e = vm.emit("tick", &[Object::Bool(true)]).unwrap_err();
+    assert_eq!(
+        e.message,
+        "Operands of '+' must be numbers, found bool and num."
+    );
+    assert_eq!(vm.stack(), stack);
+    let e = vm.emit("tick", &[]).unwrap_err();
+    assert_eq!(e.message, "Function 'count' takes 1 arguments, found 0.");
+    assert_eq!(vm.emit("tick", &[Object::Number(0.0)]).unwrap().len(), 2);
+
+    // In the middle of a run.
+    let program = compile(
+        "fn f(x) { return x; }
+        on(\"tick\", f);
+        i = 0;
+        while (i < 10) { i = i + 1; }",
+    );
+    vm.load(program);
+    assert_eq!(vm.run_for(20), StepResult::Continue);
+    let i = vm.global("i").cloned();
+    assert_eq!(vm.emit("tick", &[Object::Null]).unwrap(), [Object::Null]);
+    assert_eq!(vm.global("i").cloned(), i);
+    vm.run().unwrap();
+    assert_eq!(vm.global("i"), Some(&Object::Number(10.0)));
+
+    let e = vm.emit("close", &[]).unwrap_err();
+    assert_eq!(e.message, "Unknown event 'close'.");
+    vm.load(compile("fn f() {} on(\"close\", f);"));
+    assert_eq!(vm.run().unwrap_err().message, "Unknown event 'close'.");
+}
+
+#[test]
+fn test_memoize() {
+    let src = "fn fib(n) {
+            if (n < 2) { return n; }
+            return fib(n - 1) + fib(n - 2);
+        }
+        fn square(x) { return x * x; }
+        MEMO
+        a = fib(20);
+        f = square;
+        b = f(3) + f(3);";
+    let run = |src: &str| {
+        let mut vm = VM::new();
+        vm.load(compile(src));
+        let count = vm.run_counted().unwrap();
+        (vm, count)
+    };
+    let (plain, plain_count) = run(&src.replace("MEMO", ""));
+    let (memo, memo_count) = run(&src.replace("MEMO", "memoize(fib); f = memoize(square);"));
+    assert_eq!(memo.global("a"), Some(&Object::Number(6765.0)));
+    assert_eq!(memo.global("a"), plain.global("a"));
+    assert_eq!(memo.global("b"), Some(&Object::Number(18.0)));
+    assert!(memo_count * 100 < plain_count);
+
+    // Loading a program forgets which functions are memoized.
+    let mut vm = memo;
+    vm.load(compile(&src.replace("MEMO", "")));
+    assert_eq!(vm.run_counted().unwrap(), plain_count);
+
+    vm.load(compile("memoize(1);"));
+    let e = vm.run().unwrap_err();
+    assert_eq!(e.message, "Argument of 'memoize' must be a function.");
+    vm.load(compile("fn f(g) { return g; } memoize(f); f(f);"));
+    let e = vm.run().unwrap_err();
+    assert_eq!(
+        e.message,
+        "Can't use fn as a key, only numbers, strings, byte strings, bools, null and lists of them."
+    );
+}
+
+#[test]
+fn test_sets() {
+    let program = compile(
+        "s = set.insert(set.insert(set.new(), 1), \"a\");
+        s = set.insert(s, 1);
+        t = set.of(re.find_all(\"[a-c]\", \"abcab\"));
+        n = len(t);
+        has = set.contains(t, \"c\");
+        union = set.union(s, t);
+        both = set.intersect(s, t);
+        fewer = set.remove(t, \"a\");
+        same = set.insert(clone(s), 0) == set.insert(set.union(set.new(), s), 0);
+        shared = s; shared = set.insert(shared, 2);",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    let items = |name: &str| match vm.global(name) {
+        Some(Object::Set(s)) => {
+            let mut items: Vec<_> = s.values().iter().map(|o| format!("{:?}", o)).collect();
+            items.sort();
+            items
+        }
+        other => panic!("{:?}", other),
+    };
+    // Inserting into `shared` inserts into `s`, it's the same set.
+    assert_eq!(items("s"), ["Number(1.0)", "Number(2.0)", "String(\"a\")"]);
+    assert_eq!(vm.global("n"), Some(&Object::Number(3.0)));
+    assert_eq!(vm.global("has"), Some(&Object::Bool(true)));
+    assert_eq!(items("union").len(), 4);
+    assert_eq!(items("both"), ["String(\"a\")"]);
+    assert_eq!(items("fewer"), ["String(\"b\")", "String(\"c\")"]);
+    assert_eq!(vm.global("same"), Some(&Object::Bool(true)));
+
+    for (src, message) in [
+        (
+            "fn f() {} set.insert(set.new(), f);",
+            "Can't use fn as a key, only numbers, strings, byte strings, bools, null and lists of them.",
+        ),
+        (
+            "set.insert(set.new(), set.new());",
+            "Can't use set as a key, only numbers, strings, byte strings, bools, null and lists of them.",
+        ),
+        (
+            "x = re.find_all(\"a\", \"a\"); set.union(x, x);",
+            "Argument of 'set.union' must be a set.",
+        ),
+    ] {
+        vm.load(compile(src));
+        assert_eq!(vm.run().unwrap_err().message, message, "{}", src);
+    }
+}
+
+#[test]
+fn test_list_queue() {
+    // Breadth first through a binary tree numbered from 1, a queue.
+    let program = compile(
+        "q = list.push(list.new(), 1);
+        order = list.new();
+        while (0 < len(q)) {
+            n = list.peek(q);
+            q = list.shift(q);
+            order = list.push(order, n);
+            if (n < 4) { q = list.push(q, n * 2); q = list.push(q, n * 2 + 1); }
+        }
+        stack = list.unshift(list.unshift(list.new(), 1), 2);
+        same = stack;
+        stack = list.shift(stack);
+        sizes = list.push(list.push(list.new(), 0), 0);
+        sizes = list.push(sizes, len(sizes));",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    let numbers = |ns: &[f64]| Object::from(ns.to_vec());
+    assert_eq!(
+        vm.global("order"),
+        Some(&numbers(&[1., 2., 3., 4., 5., 6., 7.]))
+    );
+    assert_eq!(vm.global("stack"), Some(&numbers(&[1.])));
+    assert_eq!(vm.global("same"), Some(&numbers(&[1.])));
+    assert_eq!(vm.global("sizes"), Some(&numbers(&[0., 0., 2.])));
+
+    // Lists grow in place, also shared ones.
+    let size = std::mem::size_of::<Object>();
+    let program = compile(
+        "q = list.new(); i = 0;
+        while (i < 100) { q = list.push(q, i); i = i + 1; }
+        same = q; q = list.push(q, i);",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    assert_eq!(vm.heap().allocated(), (100 + 1) * size);
+
+    for (src, message) in [
+        ("list.shift(list.new());", "Can't shift an empty list."),
+        ("list.peek(list.new());", "Can't peek at an empty list."),
+        (
+            "fn f(x) { return list.push(x, 1); } f(1);",
+            "List of 'list.push' must be a list.",
+        ),
+    ] {
+        vm.load(compile(src));
+        assert_eq!(vm.run().unwrap_err().message, message, "{}", src);
+    }
+}
+
+#[test]
+fn test_aggregates() {
+    let program = compile(
+        "l = list.new();
+        for (i = 1; i < 5; i = i + 1) { l = list.push(l, i * i); }
+        l = list.push(l, 0 / 0);
+        min = math.min(l);
+        max = math.max(l);
+        sum = math.sum(l);",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    // NaN is skipped by min and max, but not by sums.
+    assert_eq!(vm.global("min"), Some(&Object::Number(1.0)));
+    assert_eq!(vm.global("max"), Some(&Object::Number(16.0)));
+    assert!(matches!(vm.global("sum"), Some(Object::Number(n)) if n.is_nan()));
+
+    let program = compile(
+        "l = list.push(list.push(list.push(list.new(), 1), 2), 4);
+        sum = math.sum(l);
+        avg = math.avg(l);
+        empty = math.sum(list.new());",
+    );
+    vm.load(program);
+    vm.run().unwrap();
+    assert_eq!(vm.global("sum"), Some(&Object::Number(7.0)));
+    assert_eq!(vm.global("avg"), Some(&Object::Number(7.0 / 3.0)));
+    assert_eq!(vm.global("empty"), Some(&Object::Number(0.0)));
+
+    for (src, message) in [
+        (
+            "math.min(list.new());",
+            "List of 'math.min' must not be empty.",
+        ),
+        (
+            "math.avg(list.new());",
+            "List of 'math.avg' must not be empty.",
+        ),
+        (
+            "math.max(re.find_all(\"a\", \"a\"));",
+            "Item 0 of the list of 'math.max' must be a number, found str.",
+        ),
+        (
+            "fn f(x) { return math.sum(x); } f(1);",
+            "Argument of 'math.sum' must be a list.",
+        ),
+    ] {
+        let mut vm = VM::new();
+        vm.load(compile(src));
+        assert_eq!(vm.run().unwrap_err().message, message, "{}", src);
+    }
+}
+
+#[test]
+fn test_comparisons() {
+    let program = compile(
+        "nan = 0 / 0;
+        le = 1 <= 1;
+        gt = 2 > 1;
+        ge = 1 >= 2;
+        nan_le = nan <= 1;
+        nan_ge = nan >= nan;",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    assert_eq!(vm.global("le"), Some(&Object::Bool(true)));
+    assert_eq!(vm.global("gt"), Some(&Object::Bool(true)));
+    assert_eq!(vm.global("ge"), Some(&Object::Bool(false)));
+    // NaN is neither less nor greater than anything, nor equal to it.
+    assert_eq!(vm.global("nan_le"), Some(&Object::Bool(false)));
+    assert_eq!(vm.global("nan_ge"), Some(&Object::Bool(false)));
+
+    let mut vm = VM::new();
+    vm.load(compile("fn f(x) { return x >= 1; } f(\"a\");"));
+    assert_eq!(
+        vm.run().unwrap_err().message,
+        "Operands of '>=' must be numbers, found str and num."
+    );
+}
+
+#[test]
+fn test_number_conversions() {
+    let program = compile(
+        "hex = num.parse_int(\"FF\", 16);
+        big = num.parse_int(\"zz\", 36);
+        signed = num.parse_int(\"+12\", 10);
+        large = num.parse_int(\"99999999999999999999\", 10);
+        huge = num.parse_int(\"-100000000000000000000000000000000\", 16);
+        float = num.parse_float(\" -0.25 \");
+        fixed = num.to_fixed(3.14159, 3);
+        rounded = num.to_fixed(0.125, 2);
+        whole = num.to_fixed(1234.5, 0);
+        odd = num.to_fixed(3.5, 0);
+        below = num.to_fixed(2.675, 2);",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    assert_eq!(vm.global("hex"), Some(&Object::Number(255.0)));
+    assert_eq!(vm.global("big"), Some(&Object::Number(1295.0)));
+    assert_eq!(vm.global("signed"), Some(&Object::Number(12.0)));
+    // Above 2^53, rounded once rather than at each digit.
+    assert_eq!(vm.global("large"), Some(&Object::Number(1e20)));
+    // Too long for integers, carried on in floats.
+    assert_eq!(vm.global("huge"), Some(&Object::Number(-(2f64.powi(128)))));
+    assert_eq!(vm.global("float"), Some(&Object::Number(-0.25)));
+    assert_eq!(vm.global("fixed"), Some(&Object::from("3.142")));
+    // Ties go to the even digit.
+    assert_eq!(vm.global("rounded"), Some(&Object::from("0.12")));
+    assert_eq!(vm.global("whole"), Some(&Object::from("1234")));
+    assert_eq!(vm.global("odd"), Some(&Object::from("4")));
+    // Not a tie, 2.675 is a little less in binary.
+    assert_eq!(vm.global("below"), Some(&Object::from("2.67")));
+
+    for (src, message) in [
+        (
+            "num.parse_int(\"12a\", 10);",
+            "'12a' is not a whole number in base 10.",
+        ),
+        (
+            "num.parse_int(\"-\", 10);",
+            "'-' is not a whole number in base 10.",
+        ),
+        (
+            "num.parse_int(\"1\", 37);",
+            "Radix of 'num.parse_int' must be a whole number from 2 to 36.",
+        ),
+        (
+            "num.parse_int(\"1\", 2.5);",
+            "Radix of 'num.parse_int' must be a whole number from 2 to 36.",
+        ),
+        ("num.parse_float(\"inf\");", "'inf' is not a number."),
+        ("num.parse_float(\"1.5x\");", "'1.5x' is not a number."),
+        ("num.parse_float(\"\");", "'' is not a number."),
+        (
+            "num.to_fixed(1, 0 - 1);",
+            "Digits of 'num.to_fixed' must be a whole number from 0 to 100.",
+        ),
+        (
+            "fn f(x) { return num.to_fixed(x, 2); } f(\"1\");",
+            "Number of 'num.to_fixed' must be a number.",
+        ),
+    ] {
+        let mut vm = VM::new();
+        vm.load(compile(src));
+        assert_eq!(vm.run().unwrap_err().message, message, "{}", src);
+    }
+}
+
+#[test]
+fn test_byte_strings() {
+    let program = compile(
+        r#"b = b"hi!";
+        first = b[0];
+        last = b[0 - 1];
+        b[1] = 73;
+        slice = b[0..2];
+        joined = b ++ bytes(2);
+        n = len(joined);
+        hex = bytes.to_hex(joined);
+        parsed = bytes.from_hex("48Ff");
+        text = bytes.to_str(b);
+        same = bytes.from_str("hI!") == b;"#,
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    assert_eq!(vm.global("first"), Some(&Object::Number(104.0)));
+    assert_eq!(vm.global("last"), Some(&Object::Number(33.0)));
+    assert_eq!(
+        vm.global("slice"),
+        Some(&Object::Bytes(b"hI".to_vec().into()))
+    );
+    assert_eq!(vm.global("n"), Some(&Object::Number(5.0)));
+    assert_eq!(vm.global("hex"), Some(&Object::from("6849210000")));
+    assert_eq!(
+        vm.global("parsed"),
+        Some(&Object::Bytes(vec![0x48, 0xff].into()))
+    );
+    assert_eq!(vm.global("text"), Some(&Object::from("hI!")));
+    assert_eq!(vm.global("same"), Some(&Object::Bool(true)));
+    let b: Vec<u8> = vm.global("b").unwrap().clone().try_into().unwrap();
+    assert_eq!(b, b"hI!");
+
+    for (src, message) in [
+        (
+            r#"b = b"a"; b[0] = 256;"#,
+            "Can only store whole numbers from 0 to 255 into byte strings.",
+        ),
+        (
+            r#"x = b"a"[1];"#,
+            "Byte string index 1 out of range for length 1.",
+        ),
+        (
+            r#"x = b"abc"[2..1];"#,
+            "Byte string slice 2..1 out of range for length 3.",
+        ),
+        (
+            r#"x = bytes.from_hex("abc");"#,
+            "'abc' is not hex, two digits per byte.",
+        ),
+        (
+            r#"x = bytes.from_hex("zz");"#,
+            "'zz' is not hex, two digits per byte.",
+        ),
+        (
+            r#"x = bytes.to_str(bytes.from_hex("41ff"));"#,
+            "Argument of 'bytes.to_str' is not UTF-8, byte 1 is invalid.",
+        ),
+        (
+            "x = bytes(0.5);",
+            "Length of 'bytes' must be a whole number of at least 0.",
+        ),
+        (
+            r#"fn f(x) { return x ++ b"a"; } f("a");"#,
+            "Can only concatenate two strings or two byte strings, found str and bytes.",
+        ),
+    ] {
+        let mut vm = VM::new();
+        vm.load(compile(src));
+        assert_eq!(vm.run().unwrap_err().message, message, "{}", src);
+    }
+
+    let mut vm = VM::new();
+    vm.set_size_limits(SizeLimits {
+        string_bytes: 4,
+        list_items: usize::MAX,
+    });
+    vm.load(compile("x = bytes(3) ++ bytes(2);"));
+    assert_eq!(
+        vm.run().unwrap_err().message,
+        "String of 5 bytes exceeds the limit of 4 bytes."
+    );
+}
+
+#[test]
+fn test_encodings() {
+    let program = compile(
+        r#"b64 = bytes.to_base64("Man") ++ " " ++ bytes.to_base64(b"Ma") ++ " " ++ bytes.to_base64("M");
+        empty = bytes.to_base64(b"");
+        binary = bytes.to_base64(bytes.from_hex("00fffe"));
+        decoded = bytes.to_str(bytes.from_base64("aGVsbG8gd29ybGQ="));
+        unpadded = bytes.from_base64("TWE") == b"Ma";
+        hex = bytes.to_hex("hé");"#,
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    assert_eq!(vm.global("b64"), Some(&Object::from("TWFu TWE= TQ==")));
+    assert_eq!(vm.global("empty"), Some(&Object::from("")));
+    assert_eq!(vm.global("binary"), Some(&Object::from("AP/+")));
+    assert_eq!(vm.global("decoded"), Some(&Object::from("hello world")));
+    assert_eq!(vm.global("unpadded"), Some(&Object::Bool(true)));
+    assert_eq!(vm.global("hex"), Some(&Object::from("68c3a9")));
+
+    for (src, message) in [
+        (r#"x = bytes.from_base64("TW-u");"#, "'TW-u' is not base64."),
+        (
+            r#"x = bytes.from_base64("TWFuT");"#,
+            "'TWFuT' is not base64.",
+        ),
+        (r#"x = bytes.from_base64("TQ=");"#, "'TQ=' is not base64."),
+        (r#"x = bytes.from_base64("T===");"#, "'T===' is not base64."),
+        (
+            "x = bytes.to_base64(1);",
+            "Argument of 'bytes.to_base64' must be a string or a byte string.",
+        ),
+    ] {
+        let mut vm = VM::new();
+        vm.load(compile(src));
+        assert_eq!(vm.run().unwrap_err().message, message, "{}", src);
+    }
+}
+
+#[test]
+fn test_csv() {
+    // Script strings can't contain quotes or line breaks, `'` and `|` stand
+    // in for them.
+    let prelude = r#"fn csv_text(s, nl) { return re.replace("'", re.replace("[|]", s, nl), bytes.to_str(bytes.from_hex("22"))); }
+        nl = bytes.to_str(bytes.from_hex("0a"));
+        crlf = bytes.to_str(bytes.from_hex("0d0a"));"#;
+    let program = compile(&format!(
+        r#"{}
+        rows = csv.parse(csv_text("name,note|ann,'says ''hi'', twice'|bob,|'multi|line',x", nl));
+        text = csv.stringify(rows);
+        again = csv.parse(text) == rows;
+        numbers = csv.stringify(list.push(list.new(), list.push(list.push(list.new(), 1), 2.5)));
+        lines = len(csv.parse(csv_text("a,b|c,d|", crlf)));
+        empty = len(csv.parse(""));"#,
+        prelude
+    ));
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    let row = |fields: &[&str]| Object::from(fields.to_vec());
+    assert_eq!(
+        vm.global("rows"),
+        Some(&Object::from(vec![
+            row(&["name", "note"]),
+            row(&["ann", "says \"hi\", twice"]),
+            row(&["bob", ""]),
+            row(&["multi\nline", "x"]),
+        ]))
+    );
+    assert_eq!(
+        vm.global("text"),
+        Some(&Object::from(
+            "name,note\nann,\"says \"\"hi\"\", twice\"\nbob,\n\"multi\nline\",x\n"
+        ))
+    );
+    assert_eq!(vm.global("again"), Some(&Object::Bool(true)));
+    assert_eq!(vm.global("numbers"), Some(&Object::from("1,2.5\n")));
+    assert_eq!(vm.global("lines"), Some(&Object::Number(2.0)));
+    assert_eq!(vm.global("empty"), Some(&Object::Number(0.0)));
+
+    for (src, message) in [
+        (
+            r#"x = csv.parse(csv_text("a,'b", nl));"#,
+            "Quoted field in row 0 of 'csv.parse' is never closed.",
+        ),
+        (
+            r#"x = csv.parse(csv_text("a|'a'b,c", nl));"#,
+            "Quoted field in row 1 of 'csv.parse' is followed by more than a comma or a line break.",
+        ),
+        (
+            "x = csv.stringify(list.push(list.new(), 1));",
+            "Row 0 of 'csv.stringify' must be a list.",
+        ),
+        (
+            "x = csv.stringify(list.push(list.new(), list.push(list.new(), null)));",
+            "Field 0 of row 0 of 'csv.stringify' must be a string or a number, found null.",
+        ),
+    ] {
+        let mut vm = VM::new();
+        vm.load(compile(&format!("{}\n{}", prelude, src)));
+        assert_eq!(vm.run().unwrap_err().message, message, "{}", src);
+    }
+}
+
+#[test]
+fn test_file_builtins() {
+    let path = std::env::temp_dir().join(format!("reaper-fs-{}.bin", std::process::id()));
+    let program = compile(&format!(
+        r#"fs.write_bytes("{0}", bytes.from_hex("00ff10"));
+        read = fs.read_bytes("{0}");"#,
+        path.display()
+    ));
+    let mut vm = VM::new();
+    vm.set_capabilities(Capabilities::all().without(Capability::Files));
+    vm.load(program.clone());
+    assert_eq!(
+        vm.run().unwrap_err().message,
+        "'fs.write_bytes' needs the 'files' capability."
+    );
+    assert!(!path.exists());
+
+    vm.set_capabilities(Capabilities::none().with(Capability::Files));
+    vm.load(program);
+    vm.run().unwrap();
+    assert_eq!(
+        vm.global("read"),
+        Some(&Object::Bytes(vec![0x00, 0xff, 0x10].into()))
+    );
+    assert_eq!(std::fs::read(&path).unwrap(), [0x00, 0xff, 0x10]);
+    std::fs::remove_file(&path).unwrap();
+
+    let mut vm = VM::new();
+    vm.load(compile(&format!(
+        r#"x = fs.read_bytes("{}");"#,
+        path.display()
+    )));
+    let message = vm.run().unwrap_err().message;
+    assert!(
+        message.starts_with(&format!("Can't read '{}': ", path.display())),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn test_digests() {
+    let program = compile(
+        r#"md5_empty = digest.md5("");
+        md5 = digest.md5("The quick brown fox jumps over the lazy dog");
+        sha256_empty = digest.sha256(b"");
+        sha256 = digest.sha256("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq");
+        crc32 = digest.crc32(b"123456789");
+        same = digest.crc32("héllo") == digest.crc32(bytes.from_str("héllo"));"#,
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    for (name, digest) in [
+        ("md5_empty", "d41d8cd98f00b204e9800998ecf8427e"),
+        ("md5", "9e107d9d372bb6826bd81d3542a419d6"),
+        (
+            "sha256_empty",
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        ),
+        (
+            "sha256",
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+        ),
+    ] {
+        assert_eq!(vm.global(name), Some(&Object::from(digest)), "{}", name);
+    }
+    assert_eq!(vm.global("crc32"), Some(&Object::Number(3421780262.0)));
+    assert_eq!(vm.global("same"), Some(&Object::Bool(true)));
+
+    let mut vm = VM::new();
+    vm.load(compile("x = digest.sha256(1);"));
+    assert_eq!(
+        vm.run().unwrap_err().message,
+        "Argument of 'digest.sha256' must be a string or a byte string."
+    );
+}
+
+#[test]
+fn test_sort_by() {
+    let program = compile(
+        "fn shorter(a, b) { return len(a) < len(b); }
+        fn longer(a, b) { return len(b) < len(a); }
+        words = re.find_all(\"[a-z]+\", \"pear fig apple kiwi banana plum\");
+        sorted = sort_by(clone(words), shorter);
+        reversed = sort_by(clone(words), longer);
+        shortest = min_by(words, shorter);
+        longest = max_by(words, shorter);
+        same = words;
+        words = sort_by(words, shorter);",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    let strings = |s: &str| Object::from(s.split(' ').collect::<Vec<_>>());
+    // Words of the same length stay in order.
+    let sorted = strings("fig pear kiwi plum apple banana");
+    assert_eq!(vm.global("sorted"), Some(&sorted));
+    assert_eq!(vm.global("words"), Some(&sorted));
+    // Sorted in place, every value holding the list sees it sorted.
+    assert_eq!(vm.global("same"), Some(&sorted));
+    assert_eq!(
+        vm.global("reversed"),
+        Some(&strings("banana apple pear kiwi plum fig"))
+    );
+    assert_eq!(vm.global("shortest"), Some(&Object::from("fig")));
+    assert_eq!(vm.global("longest"), Some(&Object::from("banana")));
+
+    for (src, message) in [
+        (
+            "fn f(a, b) { return 1; } sort_by(re.find_all(\"a\", \"aa\"), f);",
+            "Comparator of 'sort_by' must return a bool, found num.",
+        ),
+        (
+            "fn f(a, b) { return a < b; } min_by(re.find_all(\"a\", \"aa\"), f);",
+            "Operands of '<' must be numbers, found str and str.",
+        ),
+        (
+            "fn f(a, b) { return true; } max_by(list.new(), f);",
+            "List of 'max_by' must not be empty.",
+        ),
+        (
+            "sort_by(list.new(), 1);",
+            "Comparator of 'sort_by' must be a function.",
+        ),
+    ] {
+        vm.load(compile(src));
+        assert_eq!(vm.run().unwrap_err().message, message, "{}", src);
+    }
+}
+
+#[test]
+fn test_values_are_shared() {
+    let program = compile(
+        "fn change(l) { l[0] = 9; }
+        a = list.push(list.push(list.new(), 1), 2);
+        b = a;
+        b[0] = 3;
+        first = a[0];
+        change(a);
+        pushed = list.push(b, 4);
+        c = clone(a);
+        c[0] = 5;
+        s = \"ab\";
+        t = s;
+        t[0] = \"x\";
+        e = null;
+        while (e == null) {}
+        f = clone(e);
+        native = f == e;",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    assert_eq!(vm.run_for(200), StepResult::Continue);
+    vm.set_global("e", Native::new("entity", 7u32).into())
+        .unwrap();
+    vm.run().unwrap();
+    let numbers = |ns: &[f64]| Object::from(ns.to_vec());
+    let global = |name| vm.global(name).cloned().unwrap();
+    assert_eq!(global("first"), Object::Number(3.));
+    // One list behind `a`, `b` and `pushed`, changed by the function too.
+    assert_eq!(global("a"), numbers(&[9., 2., 4.]));
+    assert_eq!(global("b"), numbers(&[9., 2., 4.]));
+    let (Object::List(a), Object::List(pushed)) = (global("a"), global("pushed")) else {
+        panic!("not lists");
+    };
+    assert!(Arc::ptr_eq(&a, &pushed));
+    assert_eq!(global("c"), numbers(&[5., 2., 4.]));
+    // Strings are copied.
+    assert_eq!(global("s"), Object::from("ab"));
+    assert_eq!(global("t"), Object::from("xb"));
+    assert_eq!(global("native"), Object::Bool(true));
+}
+
+#[test]
+fn test_list_holding_itself() {
+    let program = compile(
+        "a = list.push(list.push(list.new(), 1), 2);
+        a[1] = list.push(list.push(list.new(), a), a);
+        d = clone(a);
+        same = d == a;
+        d[0] = 3;
+        differs = d == a;
+        e = d[1][0];
+        e[0] = 4;",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    let allocated = vm.heap().allocated();
+    vm.run().unwrap();
+    let global = |name| vm.global(name).cloned().unwrap();
+    assert_eq!(
+        format!("{:?}", global("a")),
+        "List([Number(1.0), List([List([...]), List([...])])])"
+    );
+    assert_eq!(global("same"), Object::Bool(true));
+    assert_eq!(global("differs"), Object::Bool(false));
+    // The copy holds itself twice, where the original did, not the original.
+    let (Object::List(a), Object::List(d)) = (global("a"), global("d")) else {
+        panic!("not lists");
+    };
+    assert_eq!(a.items()[0], Object::Number(1.));
+    assert_eq!(d.items()[0], Object::Number(4.));
+    let Object::List(inner) = d.items()[1].clone() else {
+        panic!("not a list");
+    };
+    for item in inner.items().iter() {
+        assert!(matches!(item, Object::List(l) if Arc::ptr_eq(l, &d)));
+    }
+    // Two lists of two and two copies of them.
+    let size = std::mem::size_of::<Object>();
+    assert_eq!(vm.heap().allocated() - allocated, 8 * size);
+
+    for (src, message) in [
+        (
+            "a = list.push(list.new(), 1); a[0] = a; set.of(a);",
+            "Can't use a list holding itself as a key.",
+        ),
+        (
+            "a = list.push(list.new(), 1); a[0] = a; a = freeze(a); a[0] = 1;",
+            "Can't change a frozen list.",
+        ),
+    ] {
+        vm.load(compile(src));
+        assert_eq!(vm.run().unwrap_err().message, message, "{}", src);
+    }
+}
+
+#[test]
+fn test_freeze() {
+    let program = compile(
+        "a = list.push(list.push(list.new(), 1), list.push(list.new(), 2));
+        a = freeze(a);
+        b = clone(a);
+        b[1][0] = 5;
+        same = a == clone(a);
+        s = freeze(set.of(list.new()));
+        t = set.insert(set.union(s, s), 1);",
+    );
+    let mut vm = VM::new();
+    vm.load(program);
+    vm.run().unwrap();
+    let global = |name| vm.global(name).cloned().unwrap();
+    let Object::List(a) = global("a") else {
+        panic!("not a list");
+    };
+    assert!(a.is_frozen() && matches!(&a.items()[1], Object::List(l) if l.is_frozen()));
+    let Object::List(b) = global("b") else {
+        panic!("not a list");
+    };
+    assert!(!b.is_frozen());
+    assert_eq!(global("same"), Object::Bool(true));
+    assert_eq!(
+        format!("{:?}", global("t")),
+        "Set({Number(1.0)})".to_string()
+    );
+
+    for (src, message) in [
+        ("a[0] = 2;", "Can't change a frozen list."),
+        ("a[1][0] = 2;", "Can't change a frozen list."),
+        ("a = list.push(a, 2);", "Can't change a frozen list."),
+        (
+            "fn f(x, y) { return true; } a = sort_by(a, f);",
+            "Can't change a frozen list.",
+        ),
+        ("s = set.remove(s, 1);", "Can't change a frozen set."),
+    ] {
+        let program = compile(&format!(
+            "a = null; s = null; while (a == null) {{}} {}",
+            src
+        ));
+        vm.load(program);
+        vm.run_for(100);
+        let a = Object::from(vec![Object::Number(1.), vec![2.].into()]);
+        a.freeze();
+        vm.set_global("a", a).unwrap();
+        let s = reaper::set::Set::new();
+        s.insert(&Object::Number(1.)).unwrap();
+        s.freeze();
+        vm.set_global("s", Object::Set(Arc::new(s))).unwrap();
+        assert_eq!(vm.run().unwrap_err().message, message, "{}", src);
+    }
+}
+
+#[test]
+fn test_lifecycle() {
+    let mut vm = VM::new();
+    assert_eq!(vm.state(), &State::Empty);
+    vm.load(compile(
+        "x = null; while (x == null) {} y = x; x = null; z = re.match(\"a+\", \"aa\");",
+    ));
+    assert_eq!(vm.state(), &State::Ready);
+    assert_eq!(vm.run_for(100), StepResult::Continue);
+    assert_eq!(vm.state(), &State::Suspended);
+    vm.set_global("x", Object::Number(3.0)).unwrap();
+    vm.resume().unwrap();
+    assert_eq!(vm.state(), &State::Done);
+    assert_eq!(vm.global("y"), Some(&Object::Number(3.0)));
+    let allocated = vm.heap().allocated();
+
+    // Resuming a program that ended does nothing, running it starts over
+    // with the globals `null` and the heap kept.
+    vm.resume().unwrap();
+    assert_eq!(vm.step(), StepResult::Done);
+    assert_eq!(vm.global("y"), Some(&Object::Number(3.0)));
+    vm.reset();
+    assert_eq!(vm.state(), &State::Ready);
+    assert_eq!(vm.global("y"), Some(&Object::Null));
+    vm.set_global("y", Object::Number(4.0)).unwrap();
+    assert_eq!(vm.run_for(100), StepResult::Continue);
+    vm.set_global("x", Object::Number(5.0)).unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.global("y"), Some(&Object::Number(5.0)));
+    assert_eq!(vm.heap().allocated(), 2 * allocated);
+
+    // A trapped program keeps failing with its error until it is reset.
+    vm.load(compile("x = 1; y = len(x);"));
+    let e = vm.run().unwrap_err();
+    assert_eq!(vm.state(), &State::Trapped(e.clone()));
+    let ip = vm.ip();
+    assert_eq!(vm.resume(), Err(e.clone()));
+    assert_eq!(vm.run_for(10), StepResult::Trapped(e.clone()));
+    assert_eq!(vm.ip(), ip);
+    vm.reset();
+    assert_eq!(vm.global("x"), Some(&Object::Null));
+    assert_eq!(vm.run(), Err(e.clone()));
+    assert_eq!(vm.global("x"), Some(&Object::Number(1.0)));
+    assert_eq!(vm.run_counted(), Err(e));
+}
+
+#[test]
+fn test_indexing() {
+    let src = r#"
+        l = re.find_all("[a-z]", "abcd");
+        last = l[0 - 1];
+        tail = l[0 - 2..len(l)];
+        l[0 - 4] = "x";
+        s = "äbc";
+        c = s[0 - 3];
+        s[0 - 1] = "d";
+        i = l[1.9];
+    "#;
+    let mut vm = VM::new();
+    vm.load(compile(src));
+    let e = vm.run().unwrap_err();
+    assert_eq!(e.message, "List index 1.9 is not a whole number.");
+    let global = |vm: &VM, name| vm.global(name).cloned().unwrap();
+    assert_eq!(global(&vm, "last"), Object::from("d"));
+    assert_eq!(global(&vm, "tail"), Object::from(vec!["c", "d"]));
+    assert_eq!(global(&vm, "l"), Object::from(vec!["x", "b", "c", "d"]));
+    assert_eq!(global(&vm, "c"), Object::from("ä"));
+    assert_eq!(global(&vm, "s"), Object::from("äbd"));
+
+    vm.set_indexing(Indexing {
+        from_end: false,
+        truncate: true,
+    });
+    // Run again from the start, without counting from the end.
+    assert_eq!(
+        vm.run().unwrap_err().message,
+        "List index -1 out of range for length 4."
+    );
+    vm.load(compile(
+        "l = re.find_all(\"[a-z]\", \"ab\"); i = l[1.9]; j = l[0 / 0];",
+    ));
+    let e = vm.run().unwrap_err();
+    assert_eq!(e.message, "List index NaN is not a whole number.");
+    assert_eq!(global(&vm, "i"), Object::from("b"));
+
+    for (src, message) in [
+        (
+            "l = list.new(); x = l[0 - 1];",
+            "List index -1 out of range for length 0.",
+        ),
+        (
+            "l = list.push(list.new(), 1); l[0 - 2] = 1;",
+            "List index -2 out of range for length 1.",
+        ),
+        (
+            "s = \"ab\"; t = s[0 - 3..2];",
+            "String index -3 out of range for length 2.",
+        ),
+    ] {
+        let mut vm = VM::new();
+        vm.load(compile(src));
+        assert_eq!(vm.run().unwrap_err().message, message, "{}", src);
+    }
+}